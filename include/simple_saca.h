@@ -0,0 +1,43 @@
+/* C interface of simple-saca, built with
+ * `cargo rustc --release --lib --crate-type cdylib --features ffi`
+ * and linked against target/release/libsimple_saca.so. */
+#ifndef SIMPLE_SACA_H
+#define SIMPLE_SACA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SacaIndex SacaIndex;
+
+/* Suffix array of the `len` bases at `seq`, with buckets of `k`-mers (0: automatic),
+ * built on `threads` threads. Entries are little-endian integers of `entry_bytes`
+ * bytes: 4, 5 or 6, 8 for uint64_t, or 0 for the narrowest that fits.
+ * Returns NULL on invalid arguments or failure. */
+SacaIndex *saca_build(const uint8_t *seq, size_t len, size_t k, size_t threads,
+                      size_t entry_bytes);
+
+void saca_free(SacaIndex *index);
+
+/* Number of entries, one per base. */
+size_t saca_len(const SacaIndex *index);
+
+/* Width in bytes of the entries at saca_get_ptr. */
+size_t saca_entry_bytes(const SacaIndex *index);
+
+/* The entries, valid until the index is freed. */
+const uint8_t *saca_get_ptr(const SacaIndex *index);
+
+/* Entries [*start, *end) whose suffixes start with the `len` bytes at `pattern`.
+ * Returns their number, or SIZE_MAX for patterns longer than 124 bases. */
+size_t saca_query_range(const SacaIndex *index, const uint8_t *pattern, size_t len,
+                        size_t *start, size_t *end);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
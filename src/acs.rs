@@ -0,0 +1,137 @@
+use rayon::prelude::*;
+
+use crate::compare::*;
+use crate::generalized::*;
+
+/// Average common substring (Ulitsky et al. 2006) between two collections of
+/// sequences, an alignment-free measure of their similarity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AverageCommonSubstring {
+    /// Mean over all positions of `a` of the longest substring starting there that
+    /// occurs in `b`, up to the context length.
+    pub a_in_b: f64,
+    /// The same for the positions of `b` in `a`.
+    pub b_in_a: f64,
+    /// Total length of the sequences of `a`.
+    pub len_a: usize,
+    /// Total length of the sequences of `b`.
+    pub len_b: usize,
+}
+
+impl AverageCommonSubstring {
+    /// `ln(|b|) / a_in_b - 2 ln(|a|) / |a|`: close to 0 for identical collections, and
+    /// growing with their divergence.
+    pub fn distance_a_to_b(&self) -> f64 {
+        let (n, m) = (self.len_a as f64, self.len_b as f64);
+        m.ln() / self.a_in_b - 2.0 * n.ln() / n
+    }
+
+    pub fn distance_b_to_a(&self) -> f64 {
+        let (n, m) = (self.len_b as f64, self.len_a as f64);
+        m.ln() / self.b_in_a - 2.0 * n.ln() / n
+    }
+
+    /// Mean of both directed distances, for building distance matrices.
+    pub fn distance(&self) -> f64 {
+        (self.distance_a_to_b() + self.distance_b_to_a()) / 2.0
+    }
+}
+
+/// Average common substring of the sequences `a` and `b`, from one generalized suffix
+/// array over both with buckets of `k` bases and a context of `ctx`, so that matches
+/// longer than `ctx` count as `ctx`.
+///
+/// Every position's longest match in the other collection is the minimum LCP up to the
+/// nearest entry of that collection, before or after it in the array, found in one
+/// pass in each direction.
+pub fn average_common_substring<const BYTES: usize>(
+    a: &[&[u8]],
+    b: &[&[u8]],
+    ctx: usize,
+    k: usize,
+) -> AverageCommonSubstring {
+    let records = a.iter().chain(b).copied().collect::<Vec<_>>();
+    let gsa = GeneralizedSuffixArray::<BYTES>::new(&records, ctx, k);
+    let (idxs, dict) = (gsa.suffix_array().idxs(), gsa.dict());
+    let docs = gsa.doc_array();
+    let docs = docs.docs();
+    let suffixes = PackedSuffixes::new(gsa.text());
+
+    // The LCP of every entry with the one before, ending at the ends of their records.
+    let lcp = (0..idxs.len())
+        .into_par_iter()
+        .map(|i| {
+            if i == 0 {
+                return 0;
+            }
+            let (p, q) = (idxs[i - 1].get_usize(), idxs[i].get_usize());
+            let max = ctx
+                .min(dict.end(docs[i - 1] as usize) - p)
+                .min(dict.end(docs[i] as usize) - q);
+            suffixes.lcp_simd(p, q, max)
+        })
+        .collect::<Vec<_>>();
+    let side = |i: usize| ((docs[i] as usize) >= a.len()) as usize;
+
+    // `run[s]` is the minimum LCP back to the last entry of side `s`.
+    let mut matches = vec![0; idxs.len()];
+    let mut run = [0; 2];
+    for i in 0..idxs.len() {
+        run = run.map(|r| r.min(lcp[i]));
+        matches[i] = run[1 - side(i)];
+        run[side(i)] = ctx;
+    }
+    let mut run = [0; 2];
+    for i in (0..idxs.len()).rev() {
+        matches[i] = matches[i].max(run[1 - side(i)]);
+        run[side(i)] = ctx;
+        run = run.map(|r| r.min(lcp[i]));
+    }
+
+    let mut sums = [0; 2];
+    for (i, &m) in matches.iter().enumerate() {
+        sums[side(i)] += m;
+    }
+    let len_a = a.iter().map(|s| s.len()).sum::<usize>();
+    let len_b = b.iter().map(|s| s.len()).sum::<usize>();
+    AverageCommonSubstring {
+        a_in_b: sums[0] as f64 / len_a.max(1) as f64,
+        b_in_a: sums[1] as f64 / len_b.max(1) as f64,
+        len_a,
+        len_b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sum over the positions of `x` of the longest substring starting there that
+    /// occurs in one of `ys`.
+    fn naive(xs: &[&[u8]], ys: &[&[u8]]) -> usize {
+        let occurs = |s: &[u8]| ys.iter().any(|y| y.windows(s.len()).any(|w| w == s));
+        xs.iter()
+            .flat_map(|x| {
+                (0..x.len()).map(move |i| {
+                    (1..=x.len() - i)
+                        .take_while(|&l| occurs(&x[i..i + l]))
+                        .count()
+                })
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_acs() {
+        let a: [&[u8]; 2] = [b"ACGTTGCAGATTACAGGATCCAGT", b"TTGACCATGA"];
+        let b: [&[u8]; 3] = [b"GATTACAGGTTGCAGA", b"CCATGATTT", b"ACGTAC"];
+        let acs = average_common_substring::<5>(&a, &b, 124, 2);
+        assert_eq!((acs.len_a, acs.len_b), (34, 31));
+        assert_eq!(acs.a_in_b, naive(&a, &b) as f64 / 34.0);
+        assert_eq!(acs.b_in_a, naive(&b, &a) as f64 / 31.0);
+
+        let same = average_common_substring::<5>(&a, &a, 124, 2);
+        assert_eq!(same.a_in_b, naive(&a, &a) as f64 / 34.0);
+        assert!(same.distance() < acs.distance());
+    }
+}
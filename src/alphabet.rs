@@ -0,0 +1,234 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+#[inline(always)]
+pub fn complement(b: u8) -> u8 {
+    match b {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        b'U' => b'A',
+        b'u' => b'a',
+        _ => b,
+    }
+}
+
+pub fn revcomp(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().map(|&b| complement(b)).collect()
+}
+
+#[inline(always)]
+pub fn is_acgt(b: u8) -> bool {
+    matches!(
+        b,
+        b'A' | b'C' | b'G' | b'T' | b'U' | b'a' | b'c' | b'g' | b't' | b'u'
+    )
+}
+
+/// Nucleotide alphabet of an input text.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Alphabet {
+    #[default]
+    Dna,
+    Rna,
+    /// Both `T` and `U` occur.
+    Mixed,
+}
+
+/// Normalization applied to the input before indexing. The packed construction
+/// already treats `U` as `T` and ignores case, so this only affects the stored text.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Folding {
+    /// Keep the input as is.
+    #[default]
+    None,
+    /// Convert to upper case.
+    Case,
+    /// Convert to upper case and `U` to `T`.
+    Dna,
+}
+
+impl FromStr for Folding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "case" => Ok(Self::Case),
+            "dna" => Ok(Self::Dna),
+            _ => Err(format!("unknown folding {s}, expected none, case or dna")),
+        }
+    }
+}
+
+pub fn fold(seq: &mut [u8], folding: Folding) {
+    match folding {
+        Folding::None => {}
+        Folding::Case => seq.make_ascii_uppercase(),
+        Folding::Dna => seq.iter_mut().for_each(|b| {
+            *b = match b.to_ascii_uppercase() {
+                b'U' => b'T',
+                c => c,
+            }
+        }),
+    }
+}
+
+/// Handling of input bytes other than `ACGTU` in either case, such as `N`, gaps and
+/// IUPAC codes, which the 2-bit packing cannot represent.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NonAcgt {
+    /// Remove them, joining the bases around them.
+    #[default]
+    Strip,
+    /// Refuse the input.
+    Error,
+    /// Remove them, but sort as if every run of them and every record end was one
+    /// symbol larger than `T`, so that no match spans them.
+    Symbol,
+}
+
+impl FromStr for NonAcgt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strip" => Ok(Self::Strip),
+            "error" => Ok(Self::Error),
+            "symbol" => Ok(Self::Symbol),
+            _ => Err(format!(
+                "unknown non-ACGT policy {s}, expected strip, error or symbol"
+            )),
+        }
+    }
+}
+
+/// Append the bases of `seq` to `out` and the position in `out` of every removed run
+/// of other bytes to `breaks`. Returns the number of removed bytes, or under
+/// `NonAcgt::Error` the offset in `seq` of the first one.
+pub fn push_acgt(
+    out: &mut Vec<u8>,
+    breaks: &mut Vec<usize>,
+    seq: &[u8],
+    policy: NonAcgt,
+) -> Result<usize, usize> {
+    let mut removed = 0;
+    for (i, &b) in seq.iter().enumerate() {
+        if is_acgt(b) {
+            out.push(b);
+        } else if policy == NonAcgt::Error {
+            return Err(i);
+        } else {
+            if breaks.last() != Some(&out.len()) {
+                breaks.push(out.len());
+            }
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Alphabet and case of the original input, kept alongside the index so that
+/// folded texts can be reported in their original form.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AlphabetInfo {
+    pub alphabet: Alphabet,
+    /// Sorted, disjoint runs of lower case (soft-masked) bases.
+    pub lower: Vec<Range<usize>>,
+}
+
+impl AlphabetInfo {
+    pub fn scan(seq: &[u8]) -> Self {
+        let has_t = seq.iter().any(|&b| b == b'T' || b == b't');
+        let has_u = seq.iter().any(|&b| b == b'U' || b == b'u');
+        let alphabet = match (has_t, has_u) {
+            (_, false) => Alphabet::Dna,
+            (false, true) => Alphabet::Rna,
+            (true, true) => Alphabet::Mixed,
+        };
+
+        let mut lower = Vec::<Range<usize>>::new();
+        for (i, b) in seq.iter().enumerate() {
+            if b.is_ascii_lowercase() {
+                match lower.last_mut() {
+                    Some(r) if r.end == i => r.end += 1,
+                    _ => lower.push(i..i + 1),
+                }
+            }
+        }
+
+        Self { alphabet, lower }
+    }
+
+    /// Add the info of a text appended at `offset`.
+    pub fn extend(&mut self, other: &Self, offset: usize) {
+        if self.alphabet != other.alphabet {
+            self.alphabet = Alphabet::Mixed;
+        }
+        self.lower
+            .extend(other.lower.iter().map(|r| r.start + offset..r.end + offset));
+    }
+
+    pub fn is_lower(&self, pos: usize) -> bool {
+        let i = self.lower.partition_point(|r| r.end <= pos);
+        self.lower.get(i).is_some_and(|r| r.start <= pos)
+    }
+
+    pub fn lower_count(&self) -> usize {
+        self.lower.iter().map(|r| r.len()).sum()
+    }
+
+    /// Restore the original case of the folded text `seq` starting at `offset`.
+    pub fn restore_case(&self, seq: &mut [u8], offset: usize) {
+        let i = self.lower.partition_point(|r| r.end <= offset);
+        for r in &self.lower[i..] {
+            if r.start >= offset + seq.len() {
+                break;
+            }
+            let start = r.start.max(offset) - offset;
+            let end = r.end.min(offset + seq.len()) - offset;
+            seq[start..end].make_ascii_lowercase();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alphabet_info() {
+        let mut seq = b"ACguuACUaa".to_vec();
+        let info = AlphabetInfo::scan(&seq);
+        assert_eq!(info.alphabet, Alphabet::Rna);
+        assert_eq!(info.lower, [2..5, 8..10]);
+        assert_eq!(info.lower_count(), 5);
+        assert!(info.is_lower(4) && !info.is_lower(5));
+
+        fold(&mut seq, Folding::Dna);
+        assert_eq!(seq, b"ACGTTACTAA");
+        info.restore_case(&mut seq[3..9], 3);
+        assert_eq!(seq, b"ACGttACTaA");
+
+        let mut dna = AlphabetInfo::scan(b"acgt");
+        dna.extend(&info, 4);
+        assert_eq!(dna.alphabet, Alphabet::Mixed);
+        assert_eq!(dna.lower, [0..4, 6..9, 12..14]);
+
+        let (mut out, mut breaks) = (b"AC".to_vec(), vec![]);
+        assert_eq!(
+            push_acgt(&mut out, &mut breaks, b"NNgt-ARNa", NonAcgt::Strip),
+            Ok(5)
+        );
+        assert_eq!((&out[..], &breaks[..]), (&b"ACgtAa"[..], &[2, 4, 5][..]));
+        assert_eq!(
+            push_acgt(&mut out, &mut breaks, b"ACNT", NonAcgt::Error),
+            Err(2)
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use crate::compact_vec::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// What all artifacts are derived from.
+#[derive(Copy, Clone)]
+pub struct ArtifactInput<'a> {
+    /// The text, padded with `ctx` `A`s.
+    pub bytes: &'a [u8],
+    pub dict: &'a SeqDict,
+    pub ctx: usize,
+    pub k: usize,
+    pub bucket_threads: usize,
+}
+
+/// A computed artifact, see `Artifacts::get` for its type.
+pub type ArtifactValue = Box<dyn Any + Send + Sync>;
+
+/// One output derived from the text or from other artifacts, computed by a `Pipeline`.
+pub trait Artifact<const BYTES: usize>: Send + Sync {
+    /// Name, unique within a pipeline.
+    fn name(&self) -> &'static str;
+
+    /// Names of the artifacts that `compute` reads.
+    fn deps(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Compute the artifact; `done` holds at least its dependencies.
+    fn compute(&self, input: &ArtifactInput, done: &Artifacts) -> ArtifactValue;
+}
+
+/// The artifacts shipped with the crate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    /// `"sa"`: the `SuffixArray<BYTES>` from `new_packed_with_ctx`.
+    Sa,
+    /// `"isa"`: its inverse, a `CompactVec<BYTES>`.
+    Isa,
+    /// `"lcp"`: its LCP array over the padding (`lcp_padded`), a `CompactVec<BYTES>`.
+    Lcp,
+    /// `"bwt"`: its Burrows-Wheeler transform, a `Vec<u8>`.
+    Bwt,
+    /// `"docs"`: its `DocArray`.
+    Docs,
+    /// `"mappability"`: `unique_kmers` of length `k` as a `Vec<bool>`, read off the LCP
+    /// array.
+    Mappability { k: usize },
+}
+
+impl<const BYTES: usize> Artifact<BYTES> for Builtin {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Sa => "sa",
+            Self::Isa => "isa",
+            Self::Lcp => "lcp",
+            Self::Bwt => "bwt",
+            Self::Docs => "docs",
+            Self::Mappability { .. } => "mappability",
+        }
+    }
+
+    fn deps(&self) -> &'static [&'static str] {
+        match self {
+            Self::Sa => &[],
+            Self::Isa | Self::Lcp | Self::Bwt | Self::Docs => &["sa"],
+            Self::Mappability { .. } => &["sa", "lcp"],
+        }
+    }
+
+    fn compute(&self, input: &ArtifactInput, done: &Artifacts) -> ArtifactValue {
+        let sa = || done.get::<SuffixArray<BYTES>>("sa").unwrap();
+        match *self {
+            Self::Sa => Box::new(SuffixArray::<BYTES>::new_packed_with_ctx(
+                &input.bytes[..input.bytes.len() - input.ctx],
+                input.ctx,
+                input.k,
+                input.bucket_threads,
+            )),
+            Self::Isa => Box::new(sa().inverse()),
+            Self::Lcp => Box::new(sa().lcp_padded(input.bytes)),
+            Self::Bwt => Box::new(sa().bwt(input.bytes)),
+            Self::Docs => Box::new(DocArray::new(sa(), input.dict)),
+            Self::Mappability { k } => {
+                assert!(k > 0 && k <= input.ctx);
+                let idxs = sa().idxs();
+                let lcp = done.get::<CompactVec<BYTES>>("lcp").unwrap();
+                let mut unique = vec![true; input.bytes.len()];
+                for (i, l) in lcp.iter().enumerate().skip(1) {
+                    if l.get_usize() >= k {
+                        unique[idxs[i - 1].get_usize()] = false;
+                        unique[idxs[i].get_usize()] = false;
+                    }
+                }
+                Box::new(unique)
+            }
+        }
+    }
+}
+
+/// Artifacts computed by a `Pipeline`, by name.
+#[derive(Default)]
+pub struct Artifacts {
+    values: HashMap<&'static str, ArtifactValue>,
+}
+
+impl Artifacts {
+    /// The artifact `name`, if it was computed and has type `T`.
+    pub fn get<T: 'static>(&self, name: &str) -> Option<&T> {
+        self.values.get(name)?.downcast_ref()
+    }
+
+    /// Move the artifact `name` out, if it was computed and has type `T`.
+    pub fn take<T: 'static>(&mut self, name: &str) -> Option<T> {
+        if !self.values.get(name)?.is::<T>() {
+            return None;
+        }
+        Some(*self.values.remove(name)?.downcast().unwrap())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+}
+
+/// A set of artifacts, computed on request in dependency order.
+pub struct Pipeline<const BYTES: usize> {
+    artifacts: Vec<Box<dyn Artifact<BYTES>>>,
+}
+
+impl<const BYTES: usize> Default for Pipeline<BYTES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BYTES: usize> Pipeline<BYTES> {
+    /// The builtin artifacts, except `mappability`, which needs its `k`.
+    pub fn new() -> Self {
+        Self {
+            artifacts: Vec::new(),
+        }
+        .with(Builtin::Sa)
+        .with(Builtin::Isa)
+        .with(Builtin::Lcp)
+        .with(Builtin::Bwt)
+        .with(Builtin::Docs)
+    }
+
+    /// Add `artifact`, replacing any of the same name.
+    pub fn with(mut self, artifact: impl Artifact<BYTES> + 'static) -> Self {
+        self.artifacts.retain(|a| a.name() != artifact.name());
+        self.artifacts.push(Box::new(artifact));
+        self
+    }
+
+    fn find(&self, name: &str) -> &dyn Artifact<BYTES> {
+        self.artifacts
+            .iter()
+            .find(|a| a.name() == name)
+            .unwrap_or_else(|| panic!("Unknown artifact {name}"))
+            .as_ref()
+    }
+
+    /// The `requested` artifacts and everything they depend on, dependencies first.
+    pub fn schedule(&self, requested: &[&str]) -> Vec<&'static str> {
+        fn visit<const BYTES: usize>(
+            pipeline: &Pipeline<BYTES>,
+            name: &str,
+            visiting: &mut Vec<&'static str>,
+            order: &mut Vec<&'static str>,
+        ) {
+            let artifact = pipeline.find(name);
+            if order.contains(&artifact.name()) {
+                return;
+            }
+            assert!(
+                !visiting.contains(&artifact.name()),
+                "Artifact {name} depends on itself"
+            );
+            visiting.push(artifact.name());
+            for dep in artifact.deps() {
+                visit(pipeline, dep, visiting, order);
+            }
+            visiting.pop();
+            order.push(artifact.name());
+        }
+
+        let mut order = Vec::new();
+        for name in requested {
+            visit(self, name, &mut Vec::new(), &mut order);
+        }
+        order
+    }
+
+    /// Compute the `requested` artifacts. Each intermediate is computed once, shared by
+    /// everything depending on it, and dropped once no artifact still to be computed
+    /// needs it.
+    pub fn compute(&self, input: &ArtifactInput, requested: &[&str]) -> Artifacts {
+        let order = self.schedule(requested);
+        let mut uses = HashMap::<&str, usize>::new();
+        for name in &order {
+            for dep in self.find(name).deps() {
+                *uses.entry(*dep).or_default() += 1;
+            }
+        }
+
+        let mut done = Artifacts::default();
+        for name in order {
+            let artifact = self.find(name);
+            let value = artifact.compute(input, &done);
+            done.values.insert(name, value);
+            for dep in artifact.deps() {
+                let left = uses.get_mut(dep).unwrap();
+                *left -= 1;
+                if *left == 0 && !requested.contains(dep) {
+                    done.values.remove(dep);
+                }
+            }
+        }
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Number of distinct 3-mers, counting how often it is computed.
+    struct Distinct(&'static AtomicUsize);
+
+    impl Artifact<5> for Distinct {
+        fn name(&self) -> &'static str {
+            "distinct"
+        }
+
+        fn deps(&self) -> &'static [&'static str] {
+            &["lcp"]
+        }
+
+        fn compute(&self, _input: &ArtifactInput, done: &Artifacts) -> ArtifactValue {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            let lcp = done.get::<CompactVec<5>>("lcp").unwrap();
+            Box::new(lcp.iter().filter(|l| l.get_usize() < 3).count())
+        }
+    }
+
+    #[test]
+    fn test_pipeline() {
+        const CTX: usize = 124;
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let seq = b"ACGTTGCAGATTACAGGATCCA".repeat(10);
+        let mut dict = SeqDict::new();
+        dict.push("a", seq.len());
+        dict.pad(CTX);
+        let b = pad_text(&seq, CTX);
+        let input = ArtifactInput {
+            bytes: &b,
+            dict: &dict,
+            ctx: CTX,
+            k: 3,
+            bucket_threads: 2,
+        };
+
+        let pipeline = Pipeline::<5>::new()
+            .with(Builtin::Mappability { k: 30 })
+            .with(Distinct(&CALLS));
+        let requested = ["mappability", "distinct", "bwt"];
+        assert_eq!(
+            pipeline.schedule(&requested),
+            ["sa", "lcp", "mappability", "distinct", "bwt"]
+        );
+        let mut done = pipeline.compute(&input, &requested);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert!(!done.contains("lcp"));
+
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        assert!(done.get::<Vec<u8>>("mappability").is_none());
+        assert_eq!(
+            done.take::<Vec<bool>>("mappability").unwrap(),
+            sa.unique_kmers(&b, 30)
+        );
+        assert!(!done.contains("mappability"));
+        assert_eq!(done.get::<Vec<u8>>("bwt").unwrap(), &sa.bwt(&b));
+        let distinct = b.windows(3).take(seq.len()).collect::<HashSet<_>>().len();
+        assert_eq!(done.get::<usize>("distinct"), Some(&distinct));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::bundle::*;
+use crate::coords::*;
+use crate::seq_dict::*;
+
+/// Split intervals over the concatenated text at sequence boundaries and write them
+/// as BED records in the coordinates of `set_coords`, dropping parts that fall in gaps. Intervals
+/// must be sorted and non-overlapping.
+pub fn write_bed(mut w: impl Write, dict: &SeqDict, intervals: &[Range<usize>]) -> io::Result<()> {
+    let coords = coords();
+    let mut id = 0;
+
+    for r in intervals {
+        while id < dict.len() && dict.end(id) <= r.start {
+            id += 1;
+        }
+
+        let mut i = id;
+        while i < dict.len() && dict.start(i) < r.end {
+            let start = r.start.max(dict.start(i));
+            let end = r.end.min(dict.end(i));
+            let offset = dict.start(i);
+            let (start, end) = coords.interval(start - offset..end - offset);
+            writeln!(w, "{}\t{start}\t{end}", dict.name(i))?;
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Read 0-based, half-open BED intervals as ranges over the concatenated text. Records of sequences not in `dict` are skipped, and intervals are
+/// clipped to their sequence.
+pub fn read_bed(r: impl BufRead, dict: &SeqDict) -> io::Result<Vec<Range<usize>>> {
+    let ids = (0..dict.len())
+        .map(|id| (dict.name(id), id))
+        .collect::<HashMap<_, _>>();
+    let mut res = Vec::new();
+
+    for (line_no, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with(['#']) || line.starts_with("track") {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let (Some(name), Some(start), Some(end)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid_data(format!(
+                "invalid BED record on line {}",
+                line_no + 1
+            )));
+        };
+        let parse = |v: &str| {
+            v.trim().parse::<usize>().map_err(|_| {
+                invalid_data(format!("invalid BED coordinate on line {}", line_no + 1))
+            })
+        };
+        let Some(&id) = ids.get(name) else {
+            continue;
+        };
+        let len = dict.seq_len(id);
+        let (start, end) = (parse(start)?.min(len), parse(end)?.min(len));
+        if start < end {
+            res.push(dict.start(id) + start..dict.start(id) + end);
+        }
+    }
+    Ok(res)
+}
+
+/// What to do with hits that overlap a masked interval.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MaskMode {
+    /// Leave them out.
+    #[default]
+    Drop,
+    /// Keep them, marked as masked.
+    Flag,
+}
+
+impl FromStr for MaskMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Self::Drop),
+            "flag" => Ok(Self::Flag),
+            _ => Err(format!("unknown mask mode {s}, expected drop or flag")),
+        }
+    }
+}
+
+/// Sorted, disjoint masked intervals over the concatenated text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mask {
+    intervals: Vec<Range<usize>>,
+}
+
+impl Mask {
+    /// Sort and merge overlapping or touching intervals.
+    pub fn new(mut intervals: Vec<Range<usize>>) -> Self {
+        intervals.retain(|r| !r.is_empty());
+        intervals.sort_unstable_by_key(|r| r.start);
+        let mut merged = Vec::<Range<usize>>::with_capacity(intervals.len());
+        for r in intervals {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+        Self { intervals: merged }
+    }
+
+    pub fn intervals(&self) -> &[Range<usize>] {
+        &self.intervals
+    }
+
+    /// Total number of masked positions.
+    pub fn masked_len(&self) -> usize {
+        self.intervals.iter().map(|r| r.len()).sum()
+    }
+
+    /// Whether any position of `range` is masked.
+    pub fn overlaps(&self, range: Range<usize>) -> bool {
+        let i = self.intervals.partition_point(|r| r.end <= range.start);
+        self.intervals
+            .get(i)
+            .is_some_and(|r| r.start < range.end && !range.is_empty())
+    }
+
+    /// Drop or flag the `hits` whose `span` overlaps a masked interval. Hits are
+    /// returned with whether they are masked, which is always false when dropping.
+    pub fn apply<T>(
+        &self,
+        hits: Vec<T>,
+        mode: MaskMode,
+        span: impl Fn(&T) -> Range<usize>,
+    ) -> Vec<(T, bool)> {
+        hits.into_iter()
+            .map(|h| {
+                let masked = self.overlaps(span(&h));
+                (h, masked)
+            })
+            .filter(|&(_, masked)| mode == MaskMode::Flag || !masked)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask() {
+        let mut dict = SeqDict::new();
+        dict.push("a", 100);
+        dict.push("b", 50);
+        let bed = b"track name=mask\nb\t10\t20\nc\t0\t5\na\t90\t200\nb\t15\t30\n";
+        let intervals = read_bed(&bed[..], &dict).unwrap();
+        assert_eq!(intervals, [110..120, 90..100, 115..130]);
+        assert!(read_bed(&b"a\t1\n"[..], &dict).is_err());
+
+        let mask = Mask::new(intervals);
+        assert_eq!(mask.intervals(), [90..100, 110..130]);
+        assert_eq!(mask.masked_len(), 30);
+        assert!(mask.overlaps(85..91));
+        assert!(!mask.overlaps(100..110));
+        assert!(!mask.overlaps(115..115));
+
+        let hits = vec![80, 95, 105, 128];
+        let flagged = mask.apply(hits.clone(), MaskMode::Flag, |&p| p..p + 6);
+        assert_eq!(flagged, [(80, false), (95, true), (105, true), (128, true)]);
+        let dropped = mask.apply(hits, MaskMode::Drop, |&p| p..p + 5);
+        assert_eq!(dropped, [(80, false), (105, false)]);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::platform::{Advice, Mmap};
+use crate::query::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACABKT";
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Write the array with an offset table of its k-mer buckets ahead of the entries,
+    /// together with the sequence `seq`, for `MappedBuckets`.
+    pub fn write_bucketed(&self, seq: &[u8], path: impl AsRef<Path>) -> io::Result<()> {
+        assert!(self.is_lexicographic());
+        assert!(!self.bucket_ends().is_empty());
+        let mut offsets = Vec::with_capacity((self.bucket_ends().len() + 1) * 8);
+        offsets.extend_from_slice(&0u64.to_le_bytes());
+        for e in self.bucket_ends().iter() {
+            offsets.extend_from_slice(&(e.get_usize() as u64).to_le_bytes());
+        }
+        write_sections(
+            path.as_ref(),
+            MAGIC,
+            BYTES,
+            self.k(),
+            self.ctx(),
+            &[
+                ("offsets", &offsets),
+                ("sa", self.idxs().as_bytes()),
+                ("text", seq),
+            ],
+        )
+    }
+}
+
+/// A suffix array file written by `write_bucketed`, mapped read-only. Only the pages of
+/// the buckets (and text) a query looks at are read, so resident memory follows the
+/// query workload instead of the index size.
+pub struct MappedBuckets<const BYTES: usize> {
+    map: Mmap,
+    header: Header,
+}
+
+impl<const BYTES: usize> MappedBuckets<BYTES> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let len = f.metadata()?.len() as usize;
+        let header = Header::read(&mut f.by_ref(), MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "bucketed suffix array has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+        for name in ["offsets", "sa", "text"] {
+            let s = header
+                .section(name)
+                .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+            if s.offset + s.len > len as u64 {
+                return Err(invalid_data("section extends past the end of the file"));
+            }
+        }
+        if header.section("offsets").unwrap().len != ((1u64 << (2 * header.k)) + 1) * 8 {
+            return Err(invalid_data("offset table does not match k"));
+        }
+
+        Ok(Self {
+            map: Mmap::map(&f, len)?,
+            header,
+        })
+    }
+
+    fn section_range(&self, name: &str) -> io::Result<Range<usize>> {
+        let s = self
+            .header
+            .section(name)
+            .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+        Ok(s.offset as usize..(s.offset + s.len) as usize)
+    }
+
+    fn section(&self, name: &str) -> &[u8] {
+        &self.map.as_slice()[self.section_range(name).unwrap()]
+    }
+
+    /// Advise the OS how the named sections (`offsets`, `sa`, `text`) will be accessed.
+    pub fn advise(&self, sections: &[&str], advice: Advice) -> io::Result<()> {
+        for name in sections {
+            self.map.advise(self.section_range(name)?, advice)?;
+        }
+        Ok(())
+    }
+
+    /// Make the named sections resident before the first queries arrive.
+    pub fn warmup(&self, sections: &[&str]) -> io::Result<()> {
+        for name in sections {
+            self.map.warmup(self.section_range(name)?)?;
+        }
+        Ok(())
+    }
+
+    /// Make the suffix array entries of the buckets in `kmers` resident.
+    pub fn warmup_buckets(&self, kmers: Range<usize>) -> io::Result<()> {
+        let sa = self.section_range("sa")?;
+        let start = sa.start + self.offset(kmers.start) * BYTES;
+        let end = sa.start + self.offset(kmers.end) * BYTES;
+        self.map.warmup(start..end)
+    }
+
+    pub fn k(&self) -> usize {
+        self.header.k
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.header.ctx
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        1 << (2 * self.k())
+    }
+
+    pub fn text(&self) -> &[u8] {
+        self.section("text")
+    }
+
+    /// First suffix array entry of `kmer`'s bucket, or the length for `num_buckets`.
+    fn offset(&self, kmer: usize) -> usize {
+        let table = &self.section("offsets")[kmer * 8..kmer * 8 + 8];
+        u64::from_le_bytes(table.try_into().unwrap()) as usize
+    }
+
+    /// Entries of the buckets `kmers`, which are stored contiguously.
+    fn entries(&self, kmers: Range<usize>) -> (usize, &[Int<BYTES>]) {
+        let start = self.offset(kmers.start);
+        let end = self.offset(kmers.end);
+        let sa = self.section("sa");
+        // `Int` is a byte array, so any offset is suitably aligned.
+        let ints = unsafe {
+            std::slice::from_raw_parts(
+                sa.as_ptr().add(start * BYTES) as *const Int<BYTES>,
+                end - start,
+            )
+        };
+        (start, ints)
+    }
+
+    pub fn bucket_range(&self, kmer: usize) -> Range<usize> {
+        self.offset(kmer)..self.offset(kmer + 1)
+    }
+
+    pub fn bucket(&self, kmer: usize) -> &[Int<BYTES>] {
+        self.entries(kmer..kmer + 1).1
+    }
+
+    /// Range of suffix array entries whose suffixes start with `pattern`, searching only
+    /// the buckets of k-mers starting with the pattern.
+    pub fn range(&self, pattern: &[u8]) -> Range<usize> {
+        assert!(pattern.len() <= self.ctx());
+        let k = self.k();
+        let prefix = pattern
+            .iter()
+            .take(k)
+            .fold(0, |kmer, &b| kmer << 2 | base_code(b) as usize);
+        let free = 2 * k.saturating_sub(pattern.len());
+        let kmers = prefix << free..(prefix + 1) << free;
+
+        let (start, entries) = self.entries(kmers);
+        let range = pattern_range(entries, self.text(), pattern);
+        start + range.start..start + range.end
+    }
+
+    /// Text positions of the occurrences of `pattern`, in suffix array order.
+    pub fn locate(&self, pattern: &[u8]) -> Vec<usize> {
+        let range = self.range(pattern);
+        let (_, entries) = self.entries(0..self.num_buckets());
+        entries[range].iter().map(|i| i.get_usize()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped_buckets() {
+        const CTX: usize = 124;
+        let text = b"ACGTTGCAGATTACAGGT".repeat(10);
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.buckets", std::process::id()));
+        sa.write_bucketed(&text, &path).unwrap();
+        let mapped = MappedBuckets::<5>::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mapped.num_buckets(), 64);
+        mapped.warmup(&["offsets", "text"]).unwrap();
+        mapped.warmup_buckets(8..16).unwrap();
+        mapped.advise(&["sa"], Advice::Random).unwrap();
+        for kmer in 0..64 {
+            assert_eq!(mapped.bucket_range(kmer), sa.bucket_range(kmer));
+            assert_eq!(mapped.bucket(kmer), &sa.idxs()[sa.bucket_range(kmer)]);
+        }
+        for pattern in [&b"GATTA"[..], b"GG", b"T", b"ACG", b"CCC", b""] {
+            assert_eq!(
+                mapped.range(pattern),
+                sa.range(&text, pattern),
+                "{pattern:?}"
+            );
+        }
+        assert_eq!(mapped.locate(b"TTAC").len(), 10);
+    }
+}
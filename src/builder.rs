@@ -0,0 +1,264 @@
+use std::str::FromStr;
+
+use crate::alphabet::*;
+use crate::effort::*;
+use crate::generalized::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+use crate::sweep::*;
+
+/// Construction parameters chosen at run time, e.g. from command line flags or a
+/// configuration string, instead of as const generics. `build` dispatches to the
+/// kernel specialized for the chosen context.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuffixArrayBuilder {
+    k: usize,
+    ctx: usize,
+    schedule: Schedule,
+    non_acgt: NonAcgt,
+    folding: Folding,
+    effort: Effort,
+    ties: TieOrder,
+}
+
+impl Default for SuffixArrayBuilder {
+    fn default() -> Self {
+        Self {
+            k: 10,
+            ctx: 248,
+            schedule: Schedule::default(),
+            non_acgt: NonAcgt::Strip,
+            folding: Folding::None,
+            effort: Effort::Bounded,
+            ties: TieOrder::Ascending,
+        }
+    }
+}
+
+/// A suffix array with the text it was built on.
+pub struct BuiltIndex<const BYTES: usize> {
+    pub sa: SuffixArray<BYTES>,
+    /// The folded bases of all records, without other bytes, padded by the context.
+    pub text: Vec<u8>,
+    /// The records in `text`, named by their index.
+    pub dict: SeqDict,
+}
+
+impl SuffixArrayBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bases to bucket suffixes on, at most 16.
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Number of bases to sort suffixes on, one of `SUPPORTED_CTX`.
+    pub fn ctx(mut self, ctx: usize) -> Self {
+        self.ctx = ctx;
+        self
+    }
+
+    /// Threads to count and scatter buckets on. The default of 0 takes the threads of
+    /// the rayon pool the build runs in, see `build_in`.
+    pub fn bucket_threads(mut self, bucket_threads: usize) -> Self {
+        self.schedule.bucket_threads = bucket_threads;
+        self
+    }
+
+    /// Chunks per bucket thread in the count and scatter phases, see `Schedule`.
+    pub fn chunks_per_thread(mut self, chunks_per_thread: usize) -> Self {
+        self.schedule.chunks_per_thread = chunks_per_thread;
+        self
+    }
+
+    /// Smallest number of entries to sort per task, see `Schedule`.
+    pub fn min_task_entries(mut self, min_task_entries: usize) -> Self {
+        self.schedule.min_task_entries = min_task_entries;
+        self
+    }
+
+    pub fn non_acgt(mut self, non_acgt: NonAcgt) -> Self {
+        self.non_acgt = non_acgt;
+        self
+    }
+
+    pub fn folding(mut self, folding: Folding) -> Self {
+        self.folding = folding;
+        self
+    }
+
+    pub fn effort(mut self, effort: Effort) -> Self {
+        self.effort = effort;
+        self
+    }
+
+    pub fn tie_order(mut self, ties: TieOrder) -> Self {
+        self.ties = ties;
+        self
+    }
+
+    /// Set the parameter `key`, named like the command line flag, from its string form.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value {value} for {key}"))
+        }
+        match key {
+            "k" => self.k = parse(key, value)?,
+            "ctx" => self.ctx = parse(key, value)?,
+            "bucket-threads" => self.schedule.bucket_threads = parse(key, value)?,
+            "chunks-per-thread" => self.schedule.chunks_per_thread = parse(key, value)?,
+            "min-task-entries" => self.schedule.min_task_entries = parse(key, value)?,
+            "non-acgt" => self.non_acgt = value.parse()?,
+            "fold" => self.folding = value.parse()?,
+            "effort" => self.effort = value.parse()?,
+            "tie-order" => self.ties = value.parse()?,
+            _ => return Err(format!("unknown parameter {key}")),
+        }
+        Ok(())
+    }
+
+    /// Check that the parameters can be built with.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=16).contains(&self.k) {
+            return Err(format!("k must be between 1 and 16, not {}", self.k));
+        }
+        if !SUPPORTED_CTX.contains(&self.ctx) {
+            return Err(format!(
+                "context length {} is not supported, expected one of {SUPPORTED_CTX:?}",
+                self.ctx
+            ));
+        }
+        if self.non_acgt == NonAcgt::Symbol && self.effort != Effort::Bounded {
+            return Err("non-ACGT symbols only support bounded effort".to_string());
+        }
+        Ok(())
+    }
+
+    /// Fold `records`, handle their non-ACGT bytes, and build the suffix array of their
+    /// concatenation.
+    pub fn build<const BYTES: usize>(
+        &self,
+        records: &[&[u8]],
+    ) -> Result<BuiltIndex<BYTES>, String> {
+        self.validate()?;
+        let mut text =
+            Vec::with_capacity(records.iter().map(|r| r.len()).sum::<usize>() + self.ctx);
+        let mut dict = SeqDict::new();
+        let mut breaks = Vec::new();
+        for (id, record) in records.iter().enumerate() {
+            let start = text.len();
+            let removed =
+                push_acgt(&mut text, &mut breaks, record, self.non_acgt).map_err(|i| {
+                    format!(
+                        "record {id} has non-ACGT byte {:?} at {i}",
+                        record[i] as char
+                    )
+                })?;
+            dict.push_with_ns(id.to_string(), text.len() - start, removed);
+        }
+        fold(&mut text, self.folding);
+
+        let (ctx, k, threads) = (self.ctx, self.k, self.schedule.clone());
+        let sa = if self.non_acgt == NonAcgt::Symbol {
+            let sentinels = Sentinels {
+                order: SentinelOrder::Larger,
+                kind: SentinelKind::Shared,
+            };
+            let fragments = dict.split_at(&breaks);
+            SuffixArray::new_generalized_with_ctx(&text, &fragments, ctx, k, sentinels)
+        } else if self.effort == Effort::Bounded {
+            SuffixArray::new_packed_ordered_with_ctx(&text, ctx, k, threads, self.ties)
+        } else {
+            SuffixArray::new_with_effort_with_ctx(&text, ctx, k, threads, self.effort)
+        };
+        text.resize(text.len() + ctx, b'A');
+        dict.pad(ctx);
+        Ok(BuiltIndex { sa, text, dict })
+    }
+
+    /// `build` with every phase on the threads of `pool` instead of the global pool.
+    pub fn build_in<const BYTES: usize>(
+        &self,
+        pool: &rayon::ThreadPool,
+        records: &[&[u8]],
+    ) -> Result<BuiltIndex<BYTES>, String> {
+        pool.install(|| self.build(records))
+    }
+}
+
+/// Comma separated `key=value` pairs, e.g. `k=12,ctx=496,non-acgt=symbol`, for the
+/// keys of `SuffixArrayBuilder::set`, on top of the defaults.
+impl FromStr for SuffixArrayBuilder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut builder = Self::default();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got {pair}"))?;
+            builder.set(key.trim(), value.trim())?;
+        }
+        builder.validate()?;
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let records: [&[u8]; 2] = [b"ACGTTGCANNGATTACA", b"acgtacgtgg"];
+        let builder = "k=3, ctx=124, bucket-threads=2, chunks-per-thread=3, fold=case"
+            .parse::<SuffixArrayBuilder>()
+            .unwrap();
+        assert_eq!(
+            builder,
+            SuffixArrayBuilder::new()
+                .k(3)
+                .ctx(124)
+                .bucket_threads(2)
+                .chunks_per_thread(3)
+                .folding(Folding::Case)
+        );
+
+        let built = builder.build::<5>(&records).unwrap();
+        let seq = b"ACGTTGCAGATTACAACGTACGTGG";
+        assert_eq!(built.text, pad_text(seq, 124));
+        assert_eq!(built.dict.n_count(0), 2);
+        let expected = SuffixArray::<5>::new_packed::<124>(seq, 3, 2);
+        assert_eq!(built.sa.idxs(), expected.idxs());
+
+        let exact = builder.clone().effort(Effort::Exact).build::<5>(&records);
+        assert!(exact.unwrap().sa.is_exact());
+        let symbol = builder.clone().non_acgt(NonAcgt::Symbol);
+        assert_eq!(symbol.build::<5>(&records).unwrap().sa.idxs().len(), 25);
+        assert!(builder
+            .clone()
+            .non_acgt(NonAcgt::Error)
+            .build::<5>(&records)
+            .is_err());
+        assert!(builder.clone().ctx(100).build::<5>(&records).is_err());
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let pooled = SuffixArrayBuilder::new()
+            .k(3)
+            .ctx(124)
+            .folding(Folding::Case)
+            .build_in::<5>(&pool, &records)
+            .unwrap();
+        assert_eq!(pooled.sa.idxs(), expected.idxs());
+        assert_eq!(pool.install(|| pool_bucket_threads(0)), 2);
+        assert!("k=3,depth=2".parse::<SuffixArrayBuilder>().is_err());
+    }
+}
@@ -0,0 +1,635 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::alphabet::*;
+use crate::compact_vec::*;
+use crate::fai::*;
+use crate::fingerprint::*;
+use crate::manifest::*;
+use crate::memory::*;
+use crate::metadata::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACABDL";
+/// Format version written by this crate.
+pub(crate) const VERSION: u32 = 1;
+/// Oldest format version that is still read, see `migrate_bundle`.
+pub(crate) const MIN_VERSION: u32 = 1;
+
+/// A suffix array saved together with everything needed to query and extend it:
+/// the padded text it was built on, the sequence dictionary, the document array,
+/// the alphabet and case of the original input, and optionally a manifest of how it
+/// was built.
+///
+/// File layout (all integers little-endian):
+/// magic, version `u32`, `BYTES` `u32`, `k` `u64`, `ctx` `u64`, section count `u64`,
+/// then per section an 8-byte tag, offset `u64` and length `u64`, then the section data.
+pub struct Bundle<const BYTES: usize> {
+    sa: SuffixArray<BYTES>,
+    text: Vec<u8>,
+    docs: DocArray,
+    alphabet: AlphabetInfo,
+    fai: Option<Vec<FaiRecord>>,
+    manifest: Option<Manifest>,
+}
+
+pub(crate) struct SectionEntry {
+    pub(crate) tag: [u8; 8],
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+}
+
+pub(crate) struct Header {
+    pub(crate) version: u32,
+    pub(crate) bytes: usize,
+    pub(crate) k: usize,
+    pub(crate) ctx: usize,
+    pub(crate) sections: Vec<SectionEntry>,
+}
+
+pub(crate) fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn tag(name: &str) -> [u8; 8] {
+    let mut res = [0u8; 8];
+    res[..name.len()].copy_from_slice(name.as_bytes());
+    res
+}
+
+pub(crate) fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+pub(crate) fn read_magic(r: &mut impl Read) -> io::Result<[u8; 8]> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    Ok(magic)
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+impl Header {
+    pub(crate) fn read(r: &mut impl Read, expected_magic: &[u8; 8]) -> io::Result<Self> {
+        if &read_magic(r)? != expected_magic {
+            return Err(invalid_data(format!(
+                "expected a {} file",
+                String::from_utf8_lossy(expected_magic)
+            )));
+        }
+        Self::read_after_magic(r)
+    }
+
+    pub(crate) fn read_after_magic(r: &mut impl Read) -> io::Result<Self> {
+        let version = read_u32(r)?;
+        if version > VERSION {
+            return Err(invalid_data(format!(
+                "bundle version {version} was written by a newer simple-saca, which reads up to {VERSION}"
+            )));
+        }
+        if version < MIN_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported bundle version {version}"
+            )));
+        }
+
+        let bytes = read_u32(r)? as usize;
+        let k = read_u64(r)? as usize;
+        let ctx = read_u64(r)? as usize;
+        let num_sections = read_u64(r)?;
+        let sections = (0..num_sections)
+            .map(|_| {
+                let mut tag = [0u8; 8];
+                r.read_exact(&mut tag)?;
+                Ok(SectionEntry {
+                    tag,
+                    offset: read_u64(r)?,
+                    len: read_u64(r)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            version,
+            bytes,
+            k,
+            ctx,
+            sections,
+        })
+    }
+
+    pub(crate) fn section(&self, name: &str) -> Option<&SectionEntry> {
+        self.sections.iter().find(|s| s.tag == tag(name))
+    }
+
+    /// Tags and lengths of the sections, in file order.
+    pub(crate) fn section_lens(&self) -> Vec<(String, u64)> {
+        self.sections
+            .iter()
+            .map(|s| {
+                let tag = String::from_utf8_lossy(&s.tag);
+                (tag.trim_end_matches('\0').to_string(), s.len)
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn write_sections(
+    path: &Path,
+    magic: &[u8; 8],
+    bytes: usize,
+    k: usize,
+    ctx: usize,
+    sections: &[(&str, &[u8])],
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_sections_to(&mut w, magic, bytes, k, ctx, sections)?;
+    w.flush()
+}
+
+/// Like `write_sections`, into any writer.
+pub(crate) fn write_sections_to(
+    w: &mut impl Write,
+    magic: &[u8; 8],
+    bytes: usize,
+    k: usize,
+    ctx: usize,
+    sections: &[(&str, &[u8])],
+) -> io::Result<()> {
+    let lens = sections
+        .iter()
+        .map(|(name, data)| (*name, data.len() as u64))
+        .collect::<Vec<_>>();
+    write_header(w, magic, bytes, k, ctx, &lens)?;
+    for (_, data) in sections {
+        w.write_all(data)?;
+    }
+    Ok(())
+}
+
+/// Write the header for sections of the given names and lengths, whose data must follow
+/// in the same order. Lets large sections be streamed instead of held in memory.
+pub(crate) fn write_header(
+    w: &mut impl Write,
+    magic: &[u8; 8],
+    bytes: usize,
+    k: usize,
+    ctx: usize,
+    sections: &[(&str, u64)],
+) -> io::Result<()> {
+    w.write_all(magic)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&(bytes as u32).to_le_bytes())?;
+    w.write_all(&(k as u64).to_le_bytes())?;
+    w.write_all(&(ctx as u64).to_le_bytes())?;
+    w.write_all(&(sections.len() as u64).to_le_bytes())?;
+
+    let mut offset = (8 + 4 + 4 + 8 + 8 + 8 + sections.len() * 24) as u64;
+    for &(name, len) in sections {
+        w.write_all(&tag(name))?;
+        w.write_all(&offset.to_le_bytes())?;
+        w.write_all(&len.to_le_bytes())?;
+        offset += len;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_section(
+    f: &mut (impl Read + Seek),
+    header: &Header,
+    name: &str,
+) -> io::Result<Vec<u8>> {
+    let s = header
+        .section(name)
+        .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+    let end = f.seek(SeekFrom::End(0))?;
+    if s.offset.checked_add(s.len).is_none_or(|e| e > end) {
+        return Err(invalid_data(format!("section {name} is out of bounds")));
+    }
+    let mut res = vec![0u8; s.len as usize];
+    f.seek(SeekFrom::Start(s.offset))?;
+    f.read_exact(&mut res)?;
+    Ok(res)
+}
+
+/// Read a section of `BYTES`-byte entries, which must hold a whole number of them.
+pub(crate) fn read_entries<const BYTES: usize>(
+    f: &mut (impl Read + Seek),
+    header: &Header,
+    name: &str,
+) -> io::Result<CompactVec<BYTES>> {
+    let bytes = read_section(f, header, name)?;
+    if bytes.len() % BYTES != 0 {
+        return Err(invalid_data(format!(
+            "section {name} is not a whole number of {BYTES}-byte entries"
+        )));
+    }
+    Ok(CompactVec::from_bytes(&bytes))
+}
+
+pub(crate) fn decode_ties(ties: &[u8]) -> io::Result<TieOrder> {
+    match ties {
+        [0] => Ok(TieOrder::Ascending),
+        [1] => Ok(TieOrder::Descending),
+        _ => Err(invalid_data("invalid tie order")),
+    }
+}
+
+pub(crate) fn encode_dict(dict: &SeqDict) -> Vec<u8> {
+    let mut res = Vec::new();
+    res.extend_from_slice(&(dict.len() as u64).to_le_bytes());
+    for id in 0..dict.len() {
+        res.extend_from_slice(&(dict.name(id).len() as u64).to_le_bytes());
+        res.extend_from_slice(dict.name(id).as_bytes());
+        for v in [dict.start(id), dict.seq_len(id), dict.n_count(id)] {
+            res.extend_from_slice(&(v as u64).to_le_bytes());
+        }
+    }
+    res.extend_from_slice(&(dict.total_len() as u64).to_le_bytes());
+    res
+}
+
+pub(crate) fn encode_alphabet(info: &AlphabetInfo) -> Vec<u8> {
+    let mut res = vec![info.alphabet as u8];
+    res.extend_from_slice(&(info.lower.len() as u64).to_le_bytes());
+    for r in &info.lower {
+        res.extend_from_slice(&(r.start as u64).to_le_bytes());
+        res.extend_from_slice(&(r.end as u64).to_le_bytes());
+    }
+    res
+}
+
+pub(crate) fn decode_alphabet(mut data: &[u8]) -> io::Result<AlphabetInfo> {
+    let r = &mut data;
+    let mut alphabet = [0u8];
+    r.read_exact(&mut alphabet)?;
+    let alphabet = match alphabet[0] {
+        0 => Alphabet::Dna,
+        1 => Alphabet::Rna,
+        2 => Alphabet::Mixed,
+        a => return Err(invalid_data(format!("unknown alphabet {a}"))),
+    };
+    let lower = (0..read_u64(r)?)
+        .map(|_| Ok(read_u64(r)? as usize..read_u64(r)? as usize))
+        .collect::<io::Result<_>>()?;
+    Ok(AlphabetInfo { alphabet, lower })
+}
+
+/// A length-prefixed UTF-8 name, checking the length against the data left.
+pub(crate) fn read_name(r: &mut &[u8]) -> io::Result<String> {
+    let len = read_u64(r)?;
+    if len > r.len() as u64 {
+        return Err(invalid_data("truncated name"));
+    }
+    let (name, rest) = r.split_at(len as usize);
+    *r = rest;
+    String::from_utf8(name.to_vec()).map_err(|_| invalid_data("invalid sequence name"))
+}
+
+pub(crate) fn decode_dict(mut data: &[u8]) -> io::Result<SeqDict> {
+    let r = &mut data;
+    let mut dict = SeqDict::new();
+
+    for _ in 0..read_u64(r)? {
+        let name = read_name(r)?;
+        let start = read_u64(r)? as usize;
+        let len = read_u64(r)? as usize;
+        let ns = read_u64(r)? as usize;
+        let gap = start
+            .checked_sub(dict.total_len())
+            .filter(|_| start.checked_add(len).is_some())
+            .ok_or_else(|| invalid_data("overlapping sequences"))?;
+        dict.pad(gap);
+        dict.push_with_ns(name, len, ns);
+    }
+    let total_len = read_u64(r)? as usize;
+    let gap = total_len
+        .checked_sub(dict.total_len())
+        .ok_or_else(|| invalid_data("sequences exceed the text"))?;
+    dict.pad(gap);
+
+    Ok(dict)
+}
+
+impl<const BYTES: usize> Bundle<BYTES> {
+    /// `text` must be the padded text `sa` was built on, with `dict` covering the padding as gaps.
+    pub fn new(mut sa: SuffixArray<BYTES>, text: Vec<u8>, dict: &SeqDict) -> Self {
+        assert_eq!(text.len(), dict.total_len());
+        assert!(sa.is_lexicographic());
+        sa.record_fingerprint(&text);
+        let docs = DocArray::new(&sa, dict);
+        let alphabet = AlphabetInfo::scan(&text);
+        Self {
+            sa,
+            text,
+            docs,
+            alphabet,
+            fai: None,
+            manifest: None,
+        }
+    }
+
+    /// Record the alphabet of the input before it was folded into `text`.
+    pub fn with_alphabet(mut self, alphabet: AlphabetInfo) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    /// Record the `.fai` index of the FASTA the text was read from.
+    pub fn with_fai(mut self, fai: Vec<FaiRecord>) -> Self {
+        assert_eq!(fai.len(), self.dict().len());
+        self.fai = Some(fai);
+        self
+    }
+
+    /// Record how the bundle was built.
+    pub fn with_manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    /// Heap memory held by the bundle: the array, the text, and the document array and
+    /// case mask as auxiliary memory.
+    pub fn memory_usage(&self) -> MemoryBreakdown {
+        let sa = self.sa.memory_usage();
+        MemoryBreakdown {
+            text: self.text.capacity(),
+            auxiliary: sa.auxiliary
+                + std::mem::size_of_val(self.docs.docs())
+                + self.alphabet.lower.capacity() * std::mem::size_of::<std::ops::Range<usize>>(),
+            ..sa
+        }
+    }
+
+    pub fn suffix_array(&self) -> &SuffixArray<BYTES> {
+        &self.sa
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    pub fn dict(&self) -> &SeqDict {
+        self.docs.dict()
+    }
+
+    pub fn docs(&self) -> &DocArray {
+        &self.docs
+    }
+
+    pub fn alphabet(&self) -> &AlphabetInfo {
+        &self.alphabet
+    }
+
+    pub fn fai(&self) -> Option<&[FaiRecord]> {
+        self.fai.as_deref()
+    }
+
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Call `f` with the tags and contents of the sections this bundle is written as.
+    fn with_sections<R>(&self, f: impl FnOnce(&[(&str, &[u8])]) -> R) -> R {
+        let docs = self
+            .docs
+            .docs()
+            .iter()
+            .flat_map(|d| d.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let dict = encode_dict(self.dict());
+        let alphabet = encode_alphabet(&self.alphabet);
+        let fingerprint = self.sa.fingerprint().unwrap();
+        let ties = [self.sa.tie_order() as u8];
+        let fai = self.fai.as_deref().map(encode_fai);
+        let manifest = self.manifest.as_ref().map(Manifest::encode);
+        let mut sections = vec![
+            ("sa", self.sa.idxs().as_bytes()),
+            ("buckets", self.sa.bucket_ends().as_bytes()),
+            ("text", &self.text),
+            ("dict", &dict),
+            ("docs", &docs),
+            ("alphabet", &alphabet),
+            ("fprint", &fingerprint.0),
+            ("ties", &ties),
+        ];
+        if let Some(fai) = &fai {
+            sections.push(("fai", fai));
+        }
+        if let Some(manifest) = &manifest {
+            sections.push(("manifest", manifest));
+        }
+        f(&sections)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_into(&mut w)?;
+        w.flush()
+    }
+
+    /// Write the bundle to `w`, in the same format as `write`.
+    pub fn write_into(&self, w: &mut impl Write) -> io::Result<()> {
+        self.with_sections(|sections| {
+            write_sections_to(w, MAGIC, BYTES, self.sa.k(), self.sa.ctx(), sections)
+        })
+    }
+
+    /// What `Metadata::read` reports for this bundle once written.
+    pub fn metadata(&self) -> Metadata {
+        let sections = self.with_sections(|sections| {
+            sections
+                .iter()
+                .map(|(name, data)| (name.to_string(), data.len() as u64))
+                .collect()
+        });
+        Metadata {
+            kind: IndexKind::Bundle,
+            version: VERSION,
+            entry_bytes: BYTES,
+            k: self.sa.k(),
+            ctx: self.sa.ctx(),
+            dict: Some(self.dict().clone()),
+            fingerprint: self.sa.fingerprint(),
+            manifest: self.manifest.clone(),
+            sections,
+        }
+    }
+
+    /// Read a bundle written by `write`. The fingerprint is not checked against the
+    /// text, see `read_verified`.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(&mut File::open(path)?)
+    }
+
+    /// `read`, then check that the text matches the recorded fingerprint, which hashes
+    /// all of it.
+    pub fn read_verified(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bundle = Self::read(path)?;
+        bundle.verify_fingerprint()?;
+        Ok(bundle)
+    }
+
+    /// Check that the text still matches the fingerprint recorded at build time.
+    pub fn verify_fingerprint(&self) -> io::Result<()> {
+        if !self.sa.matches_text(&self.text) {
+            return Err(invalid_data("text does not match the index fingerprint"));
+        }
+        Ok(())
+    }
+
+    /// Read a bundle written by `write_into` or `write` from `f`.
+    pub fn read_from(f: &mut (impl Read + Seek)) -> io::Result<Self> {
+        let header = Header::read(f, MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "bundle has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+
+        let idxs = read_entries(f, &header, "sa")?;
+        let bucket_ends = read_entries(f, &header, "buckets")?;
+        let text = read_section(f, &header, "text")?;
+        let dict = decode_dict(&read_section(f, &header, "dict")?)?;
+        let docs = read_section(f, &header, "docs")?
+            .chunks_exact(4)
+            .map(|d| SeqId::from_le_bytes(d.try_into().unwrap()))
+            .collect();
+        let alphabet = match header.section("alphabet") {
+            Some(_) => decode_alphabet(&read_section(f, &header, "alphabet")?)?,
+            None => AlphabetInfo::scan(&text),
+        };
+
+        let ties = match header.section("ties") {
+            Some(_) => decode_ties(&read_section(f, &header, "ties")?)?,
+            None => TieOrder::Ascending,
+        };
+        let mut sa = SuffixArray::from_parts(idxs, bucket_ends, header.k, header.ctx, false)
+            .with_tie_order(ties);
+        if header.section("fprint").is_some() {
+            let fingerprint = read_section(f, &header, "fprint")?;
+            sa.set_fingerprint(Some(Fingerprint(
+                fingerprint
+                    .try_into()
+                    .map_err(|_| invalid_data("invalid fingerprint"))?,
+            )));
+        } else {
+            sa.record_fingerprint(&text);
+        }
+
+        let fai = match header.section("fai") {
+            Some(_) => Some(decode_fai(&read_section(f, &header, "fai")?)?),
+            None => None,
+        };
+        let manifest = match header.section("manifest") {
+            Some(_) => Some(Manifest::decode(&read_section(f, &header, "manifest")?)?),
+            None => None,
+        };
+
+        Ok(Self {
+            sa,
+            text,
+            docs: DocArray::from_parts(docs, dict),
+            alphabet,
+            fai,
+            manifest,
+        })
+    }
+
+    /// Index `seq` on its own and merge it into the bundle as a new sequence `name`,
+    /// instead of rebuilding the whole suffix array.
+    pub fn append(
+        &mut self,
+        name: impl Into<String>,
+        seq: &[u8],
+        bucket_threads: impl Into<Schedule>,
+    ) {
+        let name = name.into();
+        if let Some(manifest) = &mut self.manifest {
+            manifest.push("append", &name);
+        }
+        let ctx = self.sa.ctx();
+        let sa = SuffixArray::new_packed_ordered_with_ctx(
+            seq,
+            ctx,
+            self.sa.k(),
+            bucket_threads,
+            self.sa.tie_order(),
+        );
+        let text = pad_text(seq, ctx);
+        let merged = SuffixArray::merge(&self.sa, &sa, &self.text, &text);
+
+        let mut dict = self.dict().clone();
+        dict.push(name, seq.len());
+        dict.pad(ctx);
+
+        self.alphabet
+            .extend(&AlphabetInfo::scan(seq), self.text.len());
+        self.text.extend_from_slice(&text);
+        self.docs = DocArray::new(&merged, &dict);
+        self.sa = merged;
+        self.sa.record_fingerprint(&self.text);
+        // The appended sequence is not part of the indexed FASTA file.
+        self.fai = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_append() {
+        const CTX: usize = 124;
+        let seq = b"ACGTACGTTT";
+        let mut dict = SeqDict::new();
+        dict.push("a", seq.len());
+        dict.pad(CTX);
+
+        let sa = SuffixArray::<5>::new_packed::<CTX>(seq, 2, 1);
+        let text = pad_text(seq, CTX);
+        let mut bundle = Bundle::new(sa, text, &dict).with_manifest(Manifest::new().with("k", 2));
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.bundle", std::process::id()));
+        bundle.write(&path).unwrap();
+
+        let mut read = Bundle::<5>::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.suffix_array().idxs(), bundle.suffix_array().idxs());
+        assert_eq!(read.dict(), bundle.dict());
+        assert_eq!(
+            read.suffix_array().fingerprint(),
+            bundle.suffix_array().fingerprint()
+        );
+        assert_eq!(read.manifest(), bundle.manifest());
+        assert!(read.verify_fingerprint().is_ok());
+        read.text[9] = b'C';
+        assert!(read.verify_fingerprint().is_err());
+        read.text[9] = b'T';
+
+        bundle.append("b", b"GTTACGTT", 1);
+        read.append("b", b"GUUacguu", 1);
+        assert_eq!(read.suffix_array().idxs(), bundle.suffix_array().idxs());
+        assert_eq!(read.suffix_array().idxs().len(), 18);
+        assert_eq!(read.dict().locate(10 + CTX), Some((1, 0)));
+        assert_eq!(read.docs().docs().iter().filter(|&&d| d == 1).count(), 8);
+        assert_eq!(read.alphabet().alphabet, Alphabet::Mixed);
+        assert_eq!(read.alphabet().lower_count(), 5);
+        assert!(read.alphabet().is_lower(10 + CTX + 3));
+        assert_eq!(read.manifest().unwrap().get("append"), Some("b"));
+
+        let (sa, text, docs) = (read.suffix_array(), read.text(), read.docs());
+        assert_eq!(sa.documents_with(text, docs, b"GTT"), [0, 1]);
+        assert_eq!(sa.documents_with(text, docs, b"GTTT"), [0]);
+        assert_eq!(sa.documents_with(text, docs, b"TTAC"), [1]);
+    }
+}
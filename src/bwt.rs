@@ -0,0 +1,98 @@
+use rayon::prelude::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::packed_text::*;
+use crate::suffix_array::*;
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Burrows-Wheeler transform of the text `T` of the suffix array, i.e. the first
+    /// `idxs().len()` bases of `bytes`, with a sentinel `$` appended that sorts first.
+    /// Any padding after `T` is ignored. The array must be in full lexicographic order,
+    /// e.g. from `new_exact` or `new_pfp`.
+    ///
+    /// The first character precedes the suffix `$` and is the last base of `T`; the
+    /// suffix at position 0 is preceded by the `$`. The BWT of the empty text is `$`.
+    pub fn bwt(&self, bytes: &[u8]) -> Vec<u8> {
+        assert!(self.is_lexicographic());
+        let n = self.idxs().len();
+        if n == 0 {
+            return vec![b'$'];
+        }
+        let mut res = Vec::with_capacity(n + 1);
+        res.push(bytes[n - 1]);
+        res.par_extend(self.idxs().par_iter().map(|i| match i.get_usize() {
+            0 => b'$',
+            i => bytes[i - 1],
+        }));
+        res
+    }
+
+    /// `bwt`, packed to 2 bits per base, with the sentinel stored as `A`. Returns the
+    /// packed BWT and the position of the sentinel in it. Words of 32 bases are filled
+    /// in parallel, in the same pass that finds the sentinel.
+    pub fn bwt_packed(&self, bytes: &[u8]) -> (PackedText, usize) {
+        assert!(self.is_lexicographic());
+        let idxs = self.idxs();
+        let n = idxs.len();
+        let dollar = AtomicUsize::new(0);
+        let bwt = PackedText::from_fn(n + 1, |r| match r {
+            0 if n == 0 => 0,
+            0 => base_code(bytes[n - 1]),
+            r => match idxs[r - 1].get_usize() {
+                0 => {
+                    dollar.store(r, Ordering::Relaxed);
+                    0
+                }
+                i => base_code(bytes[i - 1]),
+            },
+        });
+        (bwt, dollar.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compact_vec::*;
+
+    #[test]
+    fn test_bwt() {
+        const CTX: usize = 124;
+        let text = b"ACGTTGCAGATTACAGATTACA".repeat(9);
+        let mut b = text.clone();
+        b.resize(b.len() + CTX, b'A');
+        let s = SuffixArray::<5>::new_exact::<CTX>(&text, 3, 2);
+
+        // Naive BWT over all rotations of `T$`.
+        let mut t = text.clone();
+        t.push(b'$');
+        let mut rotations = (0..t.len()).collect::<Vec<_>>();
+        let key = |i: usize| {
+            let rank = |c: u8| if c == b'$' { 0 } else { 1 + base_code(c) };
+            t[i..]
+                .iter()
+                .chain(&t[..i])
+                .map(|&c| rank(c))
+                .collect::<Vec<_>>()
+        };
+        rotations.sort_by_key(|&i| key(i));
+        let naive = rotations
+            .iter()
+            .map(|&i| t[(i + t.len() - 1) % t.len()])
+            .collect::<Vec<_>>();
+        assert_eq!(s.bwt(&b), naive);
+
+        let (packed, dollar) = s.bwt_packed(&b);
+        assert_eq!(naive[dollar], b'$');
+        let mut decoded = packed.decode(0..packed.len());
+        decoded[dollar] = b'$';
+        assert_eq!(decoded, naive);
+
+        let empty =
+            SuffixArray::<5>::from_parts(CompactVec::new(0), CompactVec::new(64), 3, CTX, false);
+        assert_eq!(empty.bwt(&[]), b"$");
+        let (packed, dollar) = empty.bwt_packed(&[]);
+        assert_eq!((packed.len(), dollar), (1, 0));
+    }
+}
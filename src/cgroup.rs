@@ -0,0 +1,108 @@
+use std::fs;
+
+/// CPU and memory limits of the control group of this process, as set by container
+/// runtimes and batch schedulers. Both cgroup v2 and v1 are read.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CgroupLimits {
+    /// CPU quota in cores, possibly fractional.
+    pub cpus: Option<f64>,
+    /// Memory limit in bytes.
+    pub memory: Option<usize>,
+    /// Memory currently charged to the group in bytes.
+    pub memory_used: Option<usize>,
+}
+
+/// Parse a v2 `cpu.max` file, `$MAX $PERIOD` or `max $PERIOD`.
+fn parse_cpu_max(s: &str) -> Option<f64> {
+    let mut it = s.split_whitespace();
+    let quota = it.next()?.parse::<f64>().ok()?;
+    let period = it.next()?.parse::<f64>().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// Parse a v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us` pair, where a quota of -1
+/// means unlimited.
+fn parse_cfs(quota: &str, period: &str) -> Option<f64> {
+    let quota = quota.trim().parse::<i64>().ok()?;
+    let period = period.trim().parse::<i64>().ok()?;
+    (quota > 0 && period > 0).then(|| quota as f64 / period as f64)
+}
+
+/// Parse a memory limit, which is `max` (v2) or a huge number close to `i64::MAX` (v1)
+/// when unlimited.
+fn parse_memory(s: &str) -> Option<usize> {
+    let bytes = s.trim().parse::<u64>().ok()?;
+    (bytes < 1 << 60).then_some(bytes as usize)
+}
+
+fn read(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok()
+}
+
+impl CgroupLimits {
+    /// Read the limits of the cgroup mounted at `/sys/fs/cgroup`. Missing files, as on
+    /// hosts without cgroups or other operating systems, give no limit.
+    pub fn detect() -> Self {
+        const V2: &str = "/sys/fs/cgroup";
+        const V1_CPU: &str = "/sys/fs/cgroup/cpu";
+        const V1_MEM: &str = "/sys/fs/cgroup/memory";
+        let cpus = read(&format!("{V2}/cpu.max"))
+            .and_then(|s| parse_cpu_max(&s))
+            .or_else(|| {
+                let quota = read(&format!("{V1_CPU}/cpu.cfs_quota_us"))?;
+                let period = read(&format!("{V1_CPU}/cpu.cfs_period_us"))?;
+                parse_cfs(&quota, &period)
+            });
+        let memory = read(&format!("{V2}/memory.max"))
+            .or_else(|| read(&format!("{V1_MEM}/memory.limit_in_bytes")))
+            .and_then(|s| parse_memory(&s));
+        let memory_used = read(&format!("{V2}/memory.current"))
+            .or_else(|| read(&format!("{V1_MEM}/memory.usage_in_bytes")))
+            .and_then(|s| parse_memory(&s));
+        Self {
+            cpus,
+            memory,
+            memory_used,
+        }
+    }
+
+    /// Number of threads worth running: the CPU quota rounded up, at most the number
+    /// of CPUs this process may run on, and at least 1.
+    pub fn threads(&self) -> usize {
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.cpus
+            .map_or(parallelism, |c| (c.ceil() as usize).min(parallelism))
+            .max(1)
+    }
+
+    /// Memory left before the group hits its limit, if it has one.
+    pub fn memory_available(&self) -> Option<usize> {
+        Some(self.memory?.saturating_sub(self.memory_used.unwrap_or(0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_cpu_max("400000 100000\n"), Some(4.0));
+        assert_eq!(parse_cpu_max("150000 100000"), Some(1.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cfs("-1\n", "100000\n"), None);
+        assert_eq!(parse_cfs("200000\n", "100000\n"), Some(2.0));
+        assert_eq!(parse_memory("max\n"), None);
+        assert_eq!(parse_memory("9223372036854771712\n"), None);
+        assert_eq!(parse_memory("4294967296\n"), Some(1 << 32));
+
+        let limits = CgroupLimits {
+            cpus: Some(2.5),
+            memory: Some(1000),
+            memory_used: Some(400),
+        };
+        assert!((1..=3).contains(&limits.threads()));
+        assert_eq!(limits.memory_available(), Some(600));
+        assert_eq!(CgroupLimits::default().memory_available(), None);
+    }
+}
@@ -0,0 +1,141 @@
+use rayon::prelude::*;
+
+use crate::compact_vec::*;
+use crate::suffix_array::*;
+
+/// The suffix array ranks grouped by text locality: ranks ordered by the block of
+/// `block` text positions their suffix starts in, and increasing within a block. A
+/// consumer that needs both orders, such as a position to rank map, scans the blocks
+/// instead of jumping through the text in suffix array order.
+pub struct TextClusters<const BYTES: usize> {
+    block: usize,
+    /// Start of every block in `ranks`, followed by the number of ranks.
+    starts: Vec<usize>,
+    ranks: CompactVec<BYTES>,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Group the ranks of the array by text blocks of `block` positions. Chunks of the
+    /// array are counted and scattered in parallel, so this costs about as much as one
+    /// bucketing pass.
+    pub fn text_clusters(&self, block: usize) -> TextClusters<BYTES> {
+        assert!(block > 0);
+        let idxs = self.idxs();
+        let end = idxs
+            .par_iter()
+            .map(|i| i.get_usize() + 1)
+            .max()
+            .unwrap_or(0);
+        let blocks = end.div_ceil(block);
+        let chunks = rayon::current_num_threads();
+        let chunk_size = idxs.len().div_ceil(chunks).max(1);
+
+        let mut counts = idxs
+            .par_chunks(chunk_size)
+            .map(|chunk| {
+                let mut counts = vec![0usize; blocks];
+                chunk
+                    .iter()
+                    .for_each(|i| counts[i.get_usize() / block] += 1);
+                counts
+            })
+            .collect::<Vec<_>>();
+
+        // Within a block, earlier chunks hold smaller ranks.
+        let mut starts = Vec::with_capacity(blocks + 1);
+        let mut sum = 0;
+        for b in 0..blocks {
+            starts.push(sum);
+            for counts in &mut counts {
+                let c = counts[b];
+                counts[b] = sum;
+                sum += c;
+            }
+        }
+        starts.push(sum);
+
+        let mut ranks = CompactVec::<BYTES>::new(idxs.len());
+        let ranks_ptr = MutPtr(ranks.as_mut_ptr());
+        idxs.par_chunks(chunk_size)
+            .zip(counts)
+            .enumerate()
+            .for_each(|(c, (chunk, mut fill))| {
+                let ptr = ranks_ptr;
+                for (j, i) in chunk.iter().enumerate() {
+                    let b = i.get_usize() / block;
+                    unsafe { (*ptr.0.add(fill[b])).set_usize(c * chunk_size + j) };
+                    fill[b] += 1;
+                }
+            });
+
+        TextClusters {
+            block,
+            starts,
+            ranks,
+        }
+    }
+}
+
+impl<const BYTES: usize> TextClusters<BYTES> {
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// Ranks of the suffixes starting in text block `b`, increasing.
+    pub fn ranks(&self, b: usize) -> &[Int<BYTES>] {
+        &self.ranks[self.starts[b]..self.starts[b + 1]]
+    }
+
+    /// All ranks, block after block: the secondary permutation of the array.
+    pub fn permutation(&self) -> &CompactVec<BYTES> {
+        &self.ranks
+    }
+
+    /// The inverse suffix array of `sa`, the array these clusters were built from, which
+    /// must contain every position of its text. Blocks are filled in parallel, each
+    /// writing only its own range of positions.
+    pub fn inverse(&self, sa: &SuffixArray<BYTES>) -> CompactVec<BYTES> {
+        let idxs = sa.idxs();
+        assert_eq!(idxs.len(), self.ranks.len());
+        let mut res = CompactVec::<BYTES>::new(idxs.len());
+        res.par_chunks_mut(self.block)
+            .enumerate()
+            .for_each(|(b, out)| {
+                for r in self.ranks(b) {
+                    let r = r.get_usize();
+                    out[idxs[r].get_usize() - b * self.block].set_usize(r);
+                }
+            });
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_clusters() {
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACAGGGATTTCCAG".repeat(30);
+        let n = b.len();
+        let s = SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2);
+        let idxs = s.idxs().to_usize_vec();
+
+        let clusters = s.text_clusters(64);
+        assert_eq!(clusters.num_blocks(), n.div_ceil(64));
+        for blk in 0..clusters.num_blocks() {
+            let ranks = clusters.ranks(blk).iter().map(|r| r.get_usize());
+            let expected = (0..n).filter(|&r| idxs[r] / 64 == blk);
+            assert!(ranks.eq(expected));
+        }
+
+        let mut inverse = vec![0; n];
+        idxs.iter().enumerate().for_each(|(r, &p)| inverse[p] = r);
+        assert_eq!(clusters.inverse(&s).to_usize_vec(), inverse);
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
 pub struct Int<const BYTES: usize>([u8; BYTES]);
@@ -14,12 +16,22 @@ impl<const BYTES: usize> Int<BYTES> {
 
     #[inline(always)]
     pub fn set_usize(&mut self, val: usize) {
+        debug_assert!(
+            BYTES >= 8 || val >> (8 * BYTES) == 0,
+            "{val} does not fit in {BYTES} bytes"
+        );
         unsafe {
             std::ptr::copy_nonoverlapping(&val as *const _ as _, self.0.as_mut_ptr(), BYTES);
         }
     }
 }
 
+/// The raw little-endian bytes of `ints`.
+pub fn int_bytes<const BYTES: usize>(ints: &[Int<BYTES>]) -> &[u8] {
+    // `Int` is a transparent byte array, so the entries are contiguous bytes.
+    unsafe { std::slice::from_raw_parts(ints.as_ptr() as *const u8, ints.len() * BYTES) }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct CompactVec<const BYTES: usize> {
     data: Vec<Int<BYTES>>,
@@ -37,6 +49,65 @@ impl<const BYTES: usize> CompactVec<BYTES> {
     pub fn to_usize_vec(&self) -> Vec<usize> {
         self.data.iter().map(|i| i.get_usize()).collect()
     }
+
+    /// Bytes allocated for the entries, including spare capacity.
+    pub fn heap_size(&self) -> usize {
+        self.data.capacity() * BYTES
+    }
+
+    /// Little-endian `BYTES`-wide integers, back to back.
+    pub fn as_bytes(&self) -> &[u8] {
+        int_bytes(&self.data)
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.data.as_mut_ptr() as *mut u8,
+                self.data.len() * BYTES,
+            )
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len() % BYTES, 0);
+        let mut res = Self::new(bytes.len() / BYTES);
+        res.as_bytes_mut().copy_from_slice(bytes);
+        res
+    }
+
+    /// Copy the entries `src` to start at `dest`, like `slice::copy_within`, as one
+    /// `memmove` of the raw bytes. The ranges may overlap.
+    pub fn copy_within(&mut self, src: Range<usize>, dest: usize) {
+        self.as_bytes_mut()
+            .copy_within(src.start * BYTES..src.end * BYTES, dest * BYTES);
+    }
+
+    /// Copy the entries of `src`, e.g. a range of another vector, to start at `dest`.
+    pub fn copy_from(&mut self, dest: usize, src: &[Int<BYTES>]) {
+        self.as_bytes_mut()[dest * BYTES..(dest + src.len()) * BYTES]
+            .copy_from_slice(int_bytes(src));
+    }
+
+    /// Keep only the first `len` entries, like `Vec::truncate`.
+    pub fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+    }
+
+    /// Set every entry to `val`, doubling the filled prefix with each copy.
+    pub fn fill_usize(&mut self, val: usize) {
+        let Some(first) = self.data.first_mut() else {
+            return;
+        };
+        first.set_usize(val);
+        let bytes = self.as_bytes_mut();
+        let mut filled = BYTES;
+        while filled < bytes.len() {
+            let len = filled.min(bytes.len() - filled);
+            bytes.copy_within(..len, filled);
+            filled += len;
+        }
+    }
 }
 
 impl<const BYTES: usize> std::ops::Deref for CompactVec<BYTES> {
@@ -52,3 +123,32 @@ impl<const BYTES: usize> std::ops::DerefMut for CompactVec<BYTES> {
         &mut self.data
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_ops() {
+        let mut v = CompactVec::<5>::new(7);
+        v.fill_usize(1 << 33 | 7);
+        assert!(v.iter().all(|i| i.get_usize() == 1 << 33 | 7));
+
+        v.iter_mut().enumerate().for_each(|(j, i)| i.set_usize(j));
+        v.copy_within(0..4, 2);
+        assert_eq!(v.to_usize_vec(), [0, 1, 0, 1, 2, 3, 6]);
+
+        let mut w = CompactVec::<5>::new(4);
+        w.copy_from(1, &v[4..7]);
+        assert_eq!(w.to_usize_vec(), [0, 2, 3, 6]);
+        assert_eq!(int_bytes(&w[1..2]), &[2, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_overflow() {
+        let mut v = CompactVec::<4>::new(1);
+        v[0].set_usize(1 << 32);
+    }
+}
@@ -0,0 +1,166 @@
+use std::cmp::Ordering;
+
+use crate::simd::*;
+use crate::suffix_array::*;
+
+/// A DNA text packed for fast comparison of its suffixes, using the SIMD kernels of
+/// the suffix array construction when the CPU supports them. Bases other than ACGT are
+/// packed as in construction, so they compare equal to one of ACGT.
+pub struct PackedSuffixes {
+    packed: RevPacked,
+    len: usize,
+    simd: bool,
+}
+
+impl PackedSuffixes {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            packed: RevPacked::new(bytes),
+            len: bytes.len(),
+            simd: simd_supported(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of equal bases of the suffixes at `i` and `j`, up to `max_len` and the
+    /// end of the text. Suffixes at or past the end of the text are empty.
+    pub fn lcp_simd(&self, i: usize, j: usize, max_len: usize) -> usize {
+        let rest = self.len.saturating_sub(i.max(j));
+        let max_len = max_len.min(rest);
+        // Block loads must stay within the text, so the tail is compared base by base.
+        let simd_max = if self.simd {
+            max_len.min(rest.saturating_sub(124))
+        } else {
+            0
+        };
+        let mut l = if simd_max > 0 {
+            unsafe { self.packed.lcp(i, j, simd_max) }
+        } else {
+            0
+        };
+        if l == simd_max {
+            while l < max_len && self.packed.get(i + l) == self.packed.get(j + l) {
+                l += 1;
+            }
+        }
+        l
+    }
+
+    /// Compare the suffixes at `i` and `j` on at most `max_len` bases. A suffix that
+    /// ends before `max_len` bases sorts before the suffixes it is a prefix of.
+    pub fn cmp_suffixes(&self, i: usize, j: usize, max_len: usize) -> Ordering {
+        let l = self.lcp_simd(i, j, max_len);
+        if l == max_len {
+            return Ordering::Equal;
+        }
+        match (i + l < self.len, j + l < self.len) {
+            (true, true) => self.packed.get(i + l).cmp(&self.packed.get(j + l)),
+            (a, b) => a.cmp(&b),
+        }
+    }
+}
+
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn lcp_bytes_simd(a: &[u8], b: &[u8]) -> usize {
+    let n = a.len().min(b.len());
+    let mut l = 0;
+    while l + 32 <= n {
+        let x = V::load(a.as_ptr().add(l));
+        let y = V::load(b.as_ptr().add(l));
+        let neq_mask = !x.eq_mask8(y);
+        if neq_mask != 0 {
+            return l + neq_mask.trailing_zeros() as usize;
+        }
+        l += 32;
+    }
+    l + lcp_bytes_scalar(&a[l..n], &b[l..n])
+}
+
+fn lcp_bytes_scalar(a: &[u8], b: &[u8]) -> usize {
+    let n = a.len().min(b.len());
+    let mut l = 0;
+    while l + 8 <= n {
+        let x = u64::from_le_bytes(a[l..l + 8].try_into().unwrap());
+        let y = u64::from_le_bytes(b[l..l + 8].try_into().unwrap());
+        if x != y {
+            return l + ((x ^ y).trailing_zeros() / 8) as usize;
+        }
+        l += 8;
+    }
+    l + a[l..n]
+        .iter()
+        .zip(&b[l..n])
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+/// Number of equal bytes of the suffixes of `bytes` at `i` and `j`, up to `max_len` and
+/// the end of the text, using SIMD when available.
+pub fn lcp_simd(bytes: &[u8], i: usize, j: usize, max_len: usize) -> usize {
+    let suffix = |x: usize| {
+        let s = bytes.get(x..).unwrap_or_default();
+        &s[..s.len().min(max_len)]
+    };
+    let (a, b) = (suffix(i), suffix(j));
+    if simd_supported() {
+        unsafe { lcp_bytes_simd(a, b) }
+    } else {
+        lcp_bytes_scalar(a, b)
+    }
+}
+
+/// Compare the suffixes of `bytes` at `i` and `j` on at most `max_len` bytes, with a
+/// suffix that ends first sorting first.
+pub fn cmp_suffixes(bytes: &[u8], i: usize, j: usize, max_len: usize) -> Ordering {
+    let l = lcp_simd(bytes, i, j, max_len);
+    if l == max_len {
+        return Ordering::Equal;
+    }
+    bytes.get(i + l).cmp(&bytes.get(j + l))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_compare() {
+        let mut b = pseudo_dna(700);
+        b.extend_from_within(50..450);
+        let packed = PackedSuffixes::new(&b);
+
+        for (i, j) in [(50, 700), (60, 710), (0, 1), (5, 1090), (700, 1099), (3, 3)] {
+            for max_len in [0, 1, 31, 124, 300, 2000] {
+                let naive = b[i..]
+                    .iter()
+                    .zip(&b[j..])
+                    .take(max_len)
+                    .take_while(|(x, y)| x == y)
+                    .count();
+                assert_eq!(lcp_simd(&b, i, j, max_len), naive, "{i} {j} {max_len}");
+                assert_eq!(packed.lcp_simd(i, j, max_len), naive, "{i} {j} {max_len}");
+
+                let end = |x: usize| (x + max_len).min(b.len());
+                let correct = b[i..end(i)].cmp(&b[j..end(j)]);
+                assert_eq!(cmp_suffixes(&b, i, j, max_len), correct);
+                assert_eq!(packed.cmp_suffixes(i, j, max_len), correct);
+            }
+        }
+        assert_eq!(lcp_simd(&b, 50, 700, usize::MAX), 400);
+        for (i, j) in [(b.len(), 3), (3, b.len() + 5), (b.len() + 1, b.len() + 2)] {
+            assert_eq!(lcp_simd(&b, i, j, 10), 0);
+            assert_eq!(packed.lcp_simd(i, j, 10), 0);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACALCP";
+
+/// Byte code that marks an LCP of at least its value, stored in the escapes.
+const ESCAPE: u8 = u8::MAX;
+/// Entries per block of the sampled escape offsets.
+const BLOCK: usize = 256;
+
+/// An LCP array in about one byte per entry, since almost all LCPs are small: values
+/// below 255 are stored as a byte, and larger ones as an escape byte with the excess
+/// as a LEB128 varint in a separate stream. The varint offset of every block of 256
+/// entries is sampled, so random access decodes at most one block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedLcp {
+    codes: Vec<u8>,
+    escapes: Vec<u8>,
+    blocks: Vec<u64>,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// `lcp(bytes)`, coded as a `CompressedLcp`.
+    pub fn compressed_lcp(&self, bytes: &[u8]) -> CompressedLcp {
+        CompressedLcp::new(self.lcp(bytes).iter().map(|l| l.get_usize()))
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut x: usize) {
+    while x >= 0x80 {
+        out.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut x = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*pos];
+        *pos += 1;
+        x |= ((b & 0x7f) as usize) << shift;
+        if b < 0x80 {
+            return x;
+        }
+        shift += 7;
+    }
+}
+
+impl CompressedLcp {
+    pub fn new(lcp: impl IntoIterator<Item = usize>) -> Self {
+        let mut codes = Vec::new();
+        let mut escapes = Vec::new();
+        let mut blocks = Vec::new();
+        for (i, l) in lcp.into_iter().enumerate() {
+            if i % BLOCK == 0 {
+                blocks.push(escapes.len() as u64);
+            }
+            if l < ESCAPE as usize {
+                codes.push(l as u8);
+            } else {
+                codes.push(ESCAPE);
+                push_varint(&mut escapes, l - ESCAPE as usize);
+            }
+        }
+        Self {
+            codes,
+            escapes,
+            blocks,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Size of the codes, escapes and block samples in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.codes.len() + self.escapes.len() + 8 * self.blocks.len()
+    }
+
+    pub fn get(&self, i: usize) -> usize {
+        let code = self.codes[i];
+        if code < ESCAPE {
+            return code as usize;
+        }
+        let block = i / BLOCK;
+        let skip = self.codes[block * BLOCK..i]
+            .iter()
+            .filter(|&&c| c == ESCAPE)
+            .count();
+        let mut pos = self.blocks[block] as usize;
+        for _ in 0..skip {
+            read_varint(&self.escapes, &mut pos);
+        }
+        ESCAPE as usize + read_varint(&self.escapes, &mut pos)
+    }
+
+    /// All entries in order, decoded sequentially.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut pos = 0;
+        self.codes.iter().map(move |&code| {
+            if code < ESCAPE {
+                code as usize
+            } else {
+                ESCAPE as usize + read_varint(&self.escapes, &mut pos)
+            }
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let blocks = self
+            .blocks
+            .iter()
+            .flat_map(|b| b.to_le_bytes())
+            .collect::<Vec<_>>();
+        write_sections(
+            path.as_ref(),
+            MAGIC,
+            1,
+            0,
+            0,
+            &[
+                ("codes", &self.codes),
+                ("escapes", &self.escapes),
+                ("blocks", &blocks),
+            ],
+        )
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let header = Header::read(&mut f, MAGIC)?;
+        let codes = read_section(&mut f, &header, "codes")?;
+        let escapes = read_section(&mut f, &header, "escapes")?;
+        let blocks = read_section(&mut f, &header, "blocks")?
+            .chunks_exact(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        if blocks.len() != codes.len().div_ceil(BLOCK)
+            || blocks.windows(2).any(|w| w[0] > w[1])
+            || blocks.last().is_some_and(|&b| b as usize > escapes.len())
+        {
+            return Err(invalid_data("block offsets do not match the codes"));
+        }
+        let escaped = codes.iter().filter(|&&c| c == ESCAPE).count();
+        if escapes.iter().filter(|&&b| b < 0x80).count() != escaped {
+            return Err(invalid_data("escapes do not match the codes"));
+        }
+        Ok(Self {
+            codes,
+            escapes,
+            blocks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_lcp() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCAGATTACA".repeat(100);
+        seq.extend_from_slice(b"GGG");
+        seq.extend_from_slice(&b"ACGTTGCAGATTACA".repeat(30));
+        let b = pad_text(&seq, CTX);
+        let sa = SuffixArray::<5>::new_exact::<CTX>(&seq, 3, 2);
+        let lcp = sa.lcp(&b).to_usize_vec();
+        assert!(lcp.iter().any(|&l| l > 1000));
+
+        let c = sa.compressed_lcp(&b);
+        assert_eq!(c.len(), lcp.len());
+        assert_eq!(c.iter().collect::<Vec<_>>(), lcp);
+        for (i, &l) in lcp.iter().enumerate() {
+            assert_eq!(c.get(i), l);
+        }
+        // Smaller than the 5-byte entries even though most LCPs here are escaped.
+        assert!(c.size_in_bytes() < 5 * lcp.len());
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.lcp", std::process::id()));
+        c.write(&path).unwrap();
+        assert_eq!(CompressedLcp::read(&path).unwrap(), c);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How positions are written: counted from 0 or 1, and with interval ends exclusive or
+/// inclusive. Internally, all positions are 0-based and intervals half-open.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coords {
+    pub one_based: bool,
+    pub end_inclusive: bool,
+}
+
+impl Coords {
+    /// 0-based, end-exclusive, as in BED and PAF.
+    pub const BED: Self = Self {
+        one_based: false,
+        end_inclusive: false,
+    };
+    /// 1-based, end-inclusive, as in GFF, SAM and VCF.
+    pub const GFF: Self = Self {
+        one_based: true,
+        end_inclusive: true,
+    };
+
+    /// The written form of position `pos`.
+    pub fn position(&self, pos: usize) -> usize {
+        pos + self.one_based as usize
+    }
+
+    /// The written start and end of the half-open interval `range`.
+    pub fn interval(&self, range: Range<usize>) -> (usize, usize) {
+        let end = (range.end + self.one_based as usize).saturating_sub(self.end_inclusive as usize);
+        (self.position(range.start), end)
+    }
+
+    /// The half-open interval written as `start` and `end`, if those are valid.
+    pub fn parse_interval(&self, start: usize, end: usize) -> Option<Range<usize>> {
+        let start = start.checked_sub(self.one_based as usize)?;
+        let end = (end + self.end_inclusive as usize).checked_sub(self.one_based as usize)?;
+        (start <= end).then_some(start..end)
+    }
+
+    fn to_bits(self) -> u8 {
+        self.one_based as u8 | (self.end_inclusive as u8) << 1
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            one_based: bits & 1 != 0,
+            end_inclusive: bits & 2 != 0,
+        }
+    }
+}
+
+impl FromStr for Coords {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, end) = match s {
+            "bed" | "paf" => return Ok(Self::BED),
+            "gff" | "sam" | "vcf" => return Ok(Self::GFF),
+            _ => s.split_once('-').ok_or_else(|| {
+                format!("unknown coordinates {s}, expected bed, gff or e.g. 1-half-open")
+            })?,
+        };
+        let one_based = match base {
+            "0" => false,
+            "1" => true,
+            _ => return Err(format!("unknown base {base}, expected 0 or 1")),
+        };
+        let end_inclusive = match end {
+            "half-open" => false,
+            "closed" => true,
+            _ => return Err(format!("unknown end {end}, expected half-open or closed")),
+        };
+        Ok(Self {
+            one_based,
+            end_inclusive,
+        })
+    }
+}
+
+impl fmt::Display for Coords {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let end = if self.end_inclusive {
+            "closed"
+        } else {
+            "half-open"
+        };
+        write!(f, "{}-{end}", self.one_based as u8)
+    }
+}
+
+static COORDS: AtomicU8 = AtomicU8::new(0);
+
+/// Write positions in `coords` in all following BED, bedGraph and PAF output and query
+/// results, and read `--region`s in them. BED inputs such as masks stay 0-based and
+/// half-open, as the format defines. Defaults to `Coords::BED`.
+pub fn set_coords(coords: Coords) {
+    COORDS.store(coords.to_bits(), Ordering::Relaxed);
+}
+
+pub fn coords() -> Coords {
+    Coords::from_bits(COORDS.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coords() {
+        assert_eq!(Coords::BED.interval(10..20), (10, 20));
+        assert_eq!(Coords::GFF.interval(10..20), (11, 20));
+        assert_eq!(Coords::GFF.position(0), 1);
+        let closed0 = "0-closed".parse::<Coords>().unwrap();
+        assert_eq!(closed0.interval(10..20), (10, 19));
+        let open1 = "1-half-open".parse::<Coords>().unwrap();
+        assert_eq!(open1.interval(10..20), (11, 21));
+
+        for c in [Coords::BED, Coords::GFF, closed0, open1] {
+            let (start, end) = c.interval(10..20);
+            assert_eq!(c.parse_interval(start, end), Some(10..20));
+            assert_eq!(c.to_string().parse::<Coords>(), Ok(c));
+            assert_eq!(Coords::from_bits(c.to_bits()), c);
+        }
+        assert_eq!(Coords::GFF.parse_interval(0, 5), None);
+        assert_eq!("sam".parse::<Coords>(), Ok(Coords::GFF));
+        assert!("2-closed".parse::<Coords>().is_err());
+    }
+}
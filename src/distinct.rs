@@ -0,0 +1,197 @@
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+use crate::alphabet::*;
+use crate::compact_vec::*;
+use crate::query::*;
+use crate::suffix_array::*;
+
+/// The distinct `ctx`-base prefixes of the suffixes of every bucket, as runs of suffix
+/// array entries sharing one, with the number of entries in each run.
+///
+/// Patterns of up to `ctx` bases are counted by a binary search over the runs of their
+/// bucket, without reading the suffix array. Repetitive texts have far fewer runs than
+/// entries, so the runs also stay in cache much longer.
+pub struct DistinctPrefixes<const BYTES: usize> {
+    /// Text position of the first suffix of every run.
+    heads: CompactVec<BYTES>,
+    /// Suffix array index of the first entry of every run, followed by the length of
+    /// the array.
+    starts: CompactVec<BYTES>,
+    /// Index of the first run of every bucket, followed by the number of runs.
+    bucket_starts: Vec<usize>,
+    k: usize,
+    ctx: usize,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Collapse the entries of every bucket into runs with the same `ctx`-base prefix
+    /// in the padded text `bytes`, buckets in parallel.
+    pub fn distinct_prefixes(&self, bytes: &[u8]) -> DistinctPrefixes<BYTES> {
+        assert!(self.is_lexicographic());
+        assert!(!self.bucket_ends().is_empty());
+        let packed = RevPacked::new(bytes);
+        let ctx = self.ctx();
+        let runs = (0..self.bucket_ends().len())
+            .into_par_iter()
+            .map(|kmer| {
+                let range = self.bucket_range(kmer);
+                let bucket = &self.idxs()[range.clone()];
+                let mut runs = Vec::new();
+                for (i, pos) in bucket.iter().enumerate() {
+                    let pos = pos.get_usize();
+                    let same = i > 0
+                        && unsafe {
+                            simd_cmp_packed_lce(
+                                &packed,
+                                bucket[i - 1].get_usize(),
+                                &packed,
+                                pos,
+                                ctx,
+                            )
+                            .is_eq()
+                        };
+                    if !same {
+                        runs.push((pos, range.start + i));
+                    }
+                }
+                runs
+            })
+            .collect::<Vec<_>>();
+
+        let len = runs.iter().map(|r| r.len()).sum();
+        let mut heads = CompactVec::<BYTES>::new(len);
+        let mut starts = CompactVec::<BYTES>::new(len + 1);
+        let mut bucket_starts = Vec::with_capacity(runs.len() + 1);
+        let mut r = 0;
+        for bucket in &runs {
+            bucket_starts.push(r);
+            for &(head, start) in bucket {
+                heads[r].set_usize(head);
+                starts[r].set_usize(start);
+                r += 1;
+            }
+        }
+        bucket_starts.push(r);
+        starts[len].set_usize(self.idxs().len());
+        DistinctPrefixes {
+            heads,
+            starts,
+            bucket_starts,
+            k: self.k(),
+            ctx,
+        }
+    }
+}
+
+impl<const BYTES: usize> DistinctPrefixes<BYTES> {
+    /// Number of runs, i.e. of distinct prefixes.
+    pub fn len(&self) -> usize {
+        self.heads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_empty()
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.ctx
+    }
+
+    /// Text position of the first suffix of run `r`, whose prefix all suffixes of the
+    /// run share.
+    pub fn head(&self, r: usize) -> usize {
+        self.heads[r].get_usize()
+    }
+
+    /// Suffix array entries of run `r`.
+    pub fn entries(&self, r: usize) -> Range<usize> {
+        self.starts[r].get_usize()..self.starts[r + 1].get_usize()
+    }
+
+    /// Number of suffixes in run `r`.
+    pub fn multiplicity(&self, r: usize) -> usize {
+        self.entries(r).len()
+    }
+
+    /// Runs of the bucket of `kmer`.
+    pub fn bucket_runs(&self, kmer: usize) -> Range<usize> {
+        self.bucket_starts[kmer]..self.bucket_starts[kmer + 1]
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        (self.heads.len() + self.starts.len()) * BYTES + self.bucket_starts.len() * 8
+    }
+
+    /// Runs whose prefixes start with `pattern`, of at most `ctx` bases. Patterns of at
+    /// least `k` bases of ACGT only search the runs of their bucket.
+    pub fn run_range(&self, seq: &[u8], pattern: &[u8]) -> Range<usize> {
+        assert!(pattern.len() <= self.ctx);
+        let kmer = pattern
+            .get(..self.k)
+            .filter(|p| p.iter().all(|&b| is_acgt(b)));
+        let runs = match kmer {
+            Some(kmer) => self.bucket_runs(
+                kmer.iter()
+                    .fold(0, |x, &b| (x << 2) | base_code(b) as usize),
+            ),
+            None => 0..self.len(),
+        };
+        let r = pattern_range(&self.heads[runs.clone()], seq, pattern);
+        runs.start + r.start..runs.start + r.end
+    }
+
+    /// `SuffixArray::range` of `pattern`, from the runs alone.
+    pub fn range(&self, seq: &[u8], pattern: &[u8]) -> Range<usize> {
+        let r = self.run_range(seq, pattern);
+        self.starts[r.start].get_usize()..self.starts[r.end].get_usize()
+    }
+
+    /// Number of occurrences of `pattern`.
+    pub fn count(&self, seq: &[u8], pattern: &[u8]) -> usize {
+        self.range(seq, pattern).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distinct_prefixes() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCAGATTACAGGATCCA".repeat(40);
+        seq.extend_from_slice(b"GATTACATTTGGCA");
+        let b = pad_text(&seq, CTX);
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        let distinct = sa.distinct_prefixes(&b);
+        assert!(distinct.len() < sa.idxs().len() / 3);
+        let total = (0..distinct.len())
+            .map(|r| distinct.multiplicity(r))
+            .sum::<usize>();
+        assert_eq!(total, sa.idxs().len());
+
+        for pattern in [
+            &b"GATTACA"[..],
+            b"GA",
+            b"T",
+            b"",
+            b"CCAACG",
+            b"GATTACATTTGG",
+            b"GGG",
+            b"GANTACA",
+            &b"ACGTTGCAGATTACAGGATCCA".repeat(5)[..],
+        ] {
+            assert_eq!(
+                distinct.range(&seq, pattern),
+                sa.range(&seq, pattern),
+                "{}",
+                String::from_utf8_lossy(pattern)
+            );
+        }
+        assert_eq!(distinct.count(&seq, b"GATTACA"), 41);
+        let r = distinct.run_range(&seq, b"GATTACAT");
+        assert_eq!((r.len(), distinct.multiplicity(r.start)), (1, 1));
+    }
+}
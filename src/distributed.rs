@@ -0,0 +1,246 @@
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::kmers::*;
+use crate::perf::*;
+use crate::sorter::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACAPRT";
+
+/// The sorted buckets of a contiguous range of k-mers, built by one worker of a
+/// distributed construction. Buckets outside `kmers` are empty.
+pub struct PartialSuffixArray<const BYTES: usize> {
+    pub kmers: Range<usize>,
+    pub sa: SuffixArray<BYTES>,
+}
+
+/// Number of suffixes of `seq` per k-mer bucket, with the k-mers of the last suffixes
+/// continuing into padding `A`s.
+pub fn kmer_bucket_sizes(seq: &[u8], k: usize) -> Vec<usize> {
+    const CHUNK: usize = 1 << 20;
+    let n = seq.len();
+    let mask = (1usize << (2 * k)) - 1;
+    let base = |i: usize| seq.get(i).map_or(0, |&b| base_code(b) as usize);
+
+    (0..n.div_ceil(CHUNK))
+        .into_par_iter()
+        .fold(
+            || vec![0; 1 << (2 * k)],
+            |mut counts, c| {
+                let range = c * CHUNK..((c + 1) * CHUNK).min(n);
+                let mut kmer =
+                    (range.start..range.start + k - 1).fold(0, |kmer, i| kmer << 2 | base(i));
+                for i in range {
+                    kmer = (kmer << 2 | base(i + k - 1)) & mask;
+                    counts[kmer] += 1;
+                }
+                counts
+            },
+        )
+        .reduce(
+            || vec![0; 1 << (2 * k)],
+            |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            },
+        )
+}
+
+/// Split all k-mers into at most `workers` contiguous ranges holding about the same
+/// number of suffixes, returning each range with its number of suffixes. There must be
+/// at least one worker.
+pub fn plan_kmer_ranges(bucket_sizes: &[usize], workers: usize) -> Vec<(Range<usize>, usize)> {
+    assert!(workers > 0, "cannot plan k-mer ranges for 0 workers");
+    let total = bucket_sizes.iter().sum::<usize>();
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut sum = 0;
+
+    for (kmer, &size) in bucket_sizes.iter().enumerate() {
+        sum += size;
+        let target = total * (res.len() + 1) / workers;
+        if sum >= target && res.len() + 1 < workers {
+            let prev = res.iter().map(|(_, s)| s).sum::<usize>();
+            res.push((start..kmer + 1, sum - prev));
+            start = kmer + 1;
+        }
+    }
+    let prev = res.iter().map(|(_, s)| s).sum::<usize>();
+    res.push((start..bucket_sizes.len(), sum - prev));
+    res
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Sort only the suffixes of `seq` whose k-mer is in `kmers`, as one worker of a
+    /// distributed construction. Each worker needs the full text.
+    pub fn new_packed_kmer_range<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        kmers: Range<usize>,
+    ) -> PartialSuffixArray<BYTES> {
+        assert!(kmers.end <= 1 << (2 * k));
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &packed,
+                0..seq.len(),
+                SpacedSeed::contiguous(k),
+                schedule,
+                Selection::kmers(kmers.clone()),
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+
+        PartialSuffixArray {
+            kmers,
+            sa: Self::from_parts(idxs, bucket_ends, k, CTX, false)
+                .with_residual_ties(residual_ties),
+        }
+    }
+
+    pub fn new_packed_kmer_range_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        kmers: Range<usize>,
+    ) -> PartialSuffixArray<BYTES> {
+        match ctx {
+            124 => Self::new_packed_kmer_range::<124>(seq, k, bucket_threads, kmers),
+            248 => Self::new_packed_kmer_range::<248>(seq, k, bucket_threads, kmers),
+            496 => Self::new_packed_kmer_range::<496>(seq, k, bucket_threads, kmers),
+            992 => Self::new_packed_kmer_range::<992>(seq, k, bucket_threads, kmers),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    /// Concatenate the partial suffix arrays of k-mer ranges that together cover all
+    /// k-mers exactly once.
+    pub fn from_partials(mut parts: Vec<PartialSuffixArray<BYTES>>) -> Self {
+        parts.sort_by_key(|p| p.kmers.start);
+        let (k, ctx) = (parts[0].sa.k(), parts[0].sa.ctx());
+        let num_kmers = 1 << (2 * k);
+        assert!(parts.iter().all(|p| p.sa.k() == k && p.sa.ctx() == ctx));
+        assert_eq!(parts[0].kmers.start, 0);
+        assert!(parts.windows(2).all(|w| w[0].kmers.end == w[1].kmers.start));
+        assert_eq!(parts.last().unwrap().kmers.end, num_kmers);
+
+        let len = parts.iter().map(|p| p.sa.idxs().len()).sum();
+        let mut idxs = CompactVec::<BYTES>::new(len);
+        let mut bucket_ends = CompactVec::<BYTES>::new(num_kmers);
+        let mut base = 0;
+        for p in &parts {
+            let n = p.sa.idxs().len();
+            idxs.copy_from(base, p.sa.idxs());
+            for kmer in p.kmers.clone() {
+                bucket_ends[kmer].set_usize(base + p.sa.bucket_ends()[kmer].get_usize());
+            }
+            base += n;
+        }
+
+        // Buckets do not span parts, so neither do ties.
+        let residual_ties = parts
+            .iter()
+            .map(|p| p.sa.residual_ties())
+            .sum::<Option<usize>>();
+        let sa = Self::from_parts(idxs, bucket_ends, k, ctx, false);
+        match residual_ties {
+            Some(ties) => sa.with_residual_ties(ties),
+            None => sa,
+        }
+    }
+}
+
+impl<const BYTES: usize> PartialSuffixArray<BYTES> {
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let kmers = [self.kmers.start as u64, self.kmers.end as u64]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        write_sections(
+            path.as_ref(),
+            MAGIC,
+            BYTES,
+            self.sa.k(),
+            self.sa.ctx(),
+            &[
+                ("kmers", &kmers),
+                ("sa", self.sa.idxs().as_bytes()),
+                ("buckets", self.sa.bucket_ends().as_bytes()),
+            ],
+        )
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let header = Header::read(&mut f, MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "partial suffix array has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+
+        let kmers = read_section(&mut f, &header, "kmers")?;
+        let r = &mut &kmers[..];
+        let kmers = read_u64(r)? as usize..read_u64(r)? as usize;
+        let idxs = CompactVec::from_bytes(&read_section(&mut f, &header, "sa")?);
+        let bucket_ends = CompactVec::from_bytes(&read_section(&mut f, &header, "buckets")?);
+
+        Ok(Self {
+            kmers,
+            sa: SuffixArray::from_parts(idxs, bucket_ends, header.k, header.ctx, false),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distributed() {
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACAGGGATTTCCAG".repeat(30);
+        let k = 3;
+
+        let sizes = kmer_bucket_sizes(&b, k);
+        assert_eq!(sizes.iter().sum::<usize>(), b.len());
+        let plan = plan_kmer_ranges(&sizes, 3);
+        assert_eq!(plan.len(), 3);
+
+        let parts = plan
+            .iter()
+            .enumerate()
+            .map(|(w, (kmers, size))| {
+                let part = SuffixArray::<5>::new_packed_kmer_range::<CTX>(&b, k, 2, kmers.clone());
+                assert_eq!(part.sa.idxs().len(), *size);
+                let path = std::env::temp_dir()
+                    .join(format!("simple-saca-{}-{w}.partial", std::process::id()));
+                part.write(&path).unwrap();
+                let read = PartialSuffixArray::<5>::read(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                read
+            })
+            .collect();
+
+        let s = SuffixArray::<5>::from_partials(parts);
+        let t = SuffixArray::<5>::new_packed::<CTX>(&b, k, 2);
+        assert_eq!(s.idxs(), t.idxs());
+        assert_eq!(s.bucket_ends(), t.bucket_ends());
+    }
+}
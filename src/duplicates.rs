@@ -0,0 +1,131 @@
+use rayon::prelude::*;
+
+use crate::alphabet::*;
+use crate::generalized::*;
+use crate::query::*;
+use crate::sweep::SUPPORTED_CTX;
+
+/// The reads with one sequence, and the longer reads starting with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateReads {
+    /// Indices of the reads with this sequence, in increasing order.
+    pub reads: Vec<usize>,
+    /// Indices of the reads of which this sequence is a proper prefix, in increasing
+    /// order.
+    pub extended_by: Vec<usize>,
+}
+
+impl DuplicateReads {
+    /// Number of reads with this sequence.
+    pub fn multiplicity(&self) -> usize {
+        self.reads.len()
+    }
+}
+
+/// Every sequence that occurs more than once in `reads`, or as a proper prefix of a
+/// longer read, in sorted order. Empty reads and reads with bytes other than `ACGTU`
+/// are skipped, and reads can be at most 992 bases long.
+///
+/// Only the read starts of the generalized suffix array are used: they list the reads
+/// sorted, each followed by its duplicates and then the reads it is a prefix of.
+pub fn find_duplicates<const BYTES: usize>(reads: &[&[u8]], k: usize) -> Vec<DuplicateReads> {
+    let reads = reads
+        .iter()
+        .map(|&r| {
+            if r.iter().all(|&b| is_acgt(b)) {
+                r
+            } else {
+                &r[..0]
+            }
+        })
+        .collect::<Vec<_>>();
+    let max_len = reads.iter().map(|r| r.len()).max().unwrap_or(0);
+    // Reads are sorted on their full length, so prefixes come before extensions.
+    let ctx = *SUPPORTED_CTX
+        .iter()
+        .find(|&&ctx| ctx >= max_len)
+        .unwrap_or_else(|| panic!("reads of {max_len} bases are not supported"));
+    let gsa = GeneralizedSuffixArray::<BYTES>::new(&reads, ctx, k);
+    let order = (0..gsa.len())
+        .into_par_iter()
+        .filter_map(|i| {
+            let (id, offset) = gsa.entry(i);
+            (offset == 0).then_some(id)
+        })
+        .collect::<Vec<_>>();
+
+    let starts_with = |a: usize, b: usize| cmp_pattern(reads[a], 0, reads[b]).is_eq();
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < order.len() {
+        let read = order[i];
+        let len = reads[read].len();
+        let mut end = i + 1;
+        while end < order.len() && reads[order[end]].len() == len && starts_with(order[end], read) {
+            end += 1;
+        }
+        // Distinct sentinels order equal reads by index.
+        let dups = order[i..end].to_vec();
+        let mut ext = end;
+        while ext < order.len() && starts_with(order[ext], read) {
+            ext += 1;
+        }
+        let mut extended_by = order[end..ext].to_vec();
+        extended_by.sort_unstable();
+        if dups.len() > 1 || !extended_by.is_empty() {
+            res.push(DuplicateReads {
+                reads: dups,
+                extended_by,
+            });
+        }
+        i = end;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates() {
+        let reads: [&[u8]; 11] = [
+            b"ACGTAC",
+            b"GATTACA",
+            b"ACGTAC",
+            b"ACG",
+            b"GATTACAGG",
+            b"acgtac",
+            b"TTT",
+            b"",
+            b"ACGTACG",
+            b"TTT",
+            b"ACNTAC",
+        ];
+        let dups = find_duplicates::<5>(&reads, 2);
+        let expected = [
+            (vec![3], vec![0, 2, 5, 8]),
+            (vec![0, 2, 5], vec![8]),
+            (vec![1], vec![4]),
+            (vec![6, 9], vec![]),
+        ];
+        assert_eq!(dups.len(), expected.len());
+        for (d, (reads, extended_by)) in dups.iter().zip(expected) {
+            assert_eq!((&d.reads, &d.extended_by), (&reads, &extended_by));
+        }
+        assert_eq!(dups[1].multiplicity(), 3);
+
+        let long = b"ACGT".repeat(100);
+        let reads = [&long[..], &long[..200], &long[..], &long[..399]];
+        let dups = find_duplicates::<5>(&reads, 4);
+        let expected = [
+            (vec![1], vec![0, 2, 3]),
+            (vec![3], vec![0, 2]),
+            (vec![0, 2], vec![]),
+        ];
+        assert_eq!(dups.len(), expected.len());
+        for (d, (reads, extended_by)) in dups.iter().zip(expected) {
+            assert_eq!((&d.reads, &d.extended_by), (&reads, &extended_by));
+        }
+    }
+}
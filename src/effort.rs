@@ -0,0 +1,137 @@
+use crate::perf::*;
+use crate::suffix_array::*;
+
+/// How much work construction puts in, trading build time for what the array supports
+/// downstream.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Effort {
+    /// Suffixes sorted on the bounded context only, see `new_packed`.
+    #[default]
+    Bounded,
+    /// Suffixes tied over the context ordered by their full suffix, see `new_exact`.
+    Exact,
+    /// `Exact`, with the LCP array computed and kept alongside.
+    ExactLcp,
+}
+
+impl std::str::FromStr for Effort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounded" => Ok(Self::Bounded),
+            "exact" => Ok(Self::Exact),
+            "exact-lcp" => Ok(Self::ExactLcp),
+            _ => Err(format!(
+                "unknown effort {s}, expected bounded, exact or exact-lcp"
+            )),
+        }
+    }
+}
+
+/// What a suffix array can be used for, see `SuffixArray::capabilities`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Suffixes are in lexicographic order over the context, so patterns up to the
+    /// context length can be searched.
+    pub lexicographic: bool,
+    /// Suffixes are in lexicographic order in full, so the array is an exact suffix
+    /// array and e.g. the BWT can be derived from it.
+    pub exact: bool,
+    /// The LCP array is available without another pass over the text.
+    pub lcp: bool,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Build the suffix array of `seq` at the given effort level. Check the result's
+    /// `capabilities` rather than the effort asked for, which it may exceed.
+    pub fn new_with_effort<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        effort: Effort,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        if effort == Effort::Bounded {
+            return Self::new_packed_ordered::<CTX>(seq, k, schedule, TieOrder::Ascending);
+        }
+        let mut sa = Self::new_exact::<CTX>(seq, k, schedule);
+        if effort == Effort::ExactLcp {
+            let phase = Phase::start("LCP array");
+            let lcp = sa.lcp(&pad_text(seq, CTX));
+            sa.set_lcp_array(Some(lcp));
+            phase.finish();
+        }
+        sa
+    }
+
+    pub fn new_with_effort_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        effort: Effort,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_with_effort::<124>(seq, k, bucket_threads, effort),
+            248 => Self::new_with_effort::<248>(seq, k, bucket_threads, effort),
+            496 => Self::new_with_effort::<496>(seq, k, bucket_threads, effort),
+            992 => Self::new_with_effort::<992>(seq, k, bucket_threads, effort),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        let lexicographic = self.is_lexicographic();
+        Capabilities {
+            lexicographic,
+            exact: lexicographic && self.is_exact(),
+            lcp: self.lcp_array().is_some(),
+        }
+    }
+
+    /// The effort level whose capabilities this array has.
+    pub fn effort(&self) -> Effort {
+        match self.capabilities() {
+            Capabilities {
+                exact: true,
+                lcp: true,
+                ..
+            } => Effort::ExactLcp,
+            Capabilities { exact: true, .. } => Effort::Exact,
+            _ => Effort::Bounded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effort() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCA".repeat(40);
+
+        let bounded = SuffixArray::<5>::new_with_effort::<CTX>(&seq, 3, 2, Effort::Bounded);
+        assert_eq!(
+            bounded.capabilities(),
+            Capabilities {
+                lexicographic: true,
+                exact: false,
+                lcp: false
+            }
+        );
+
+        let exact = SuffixArray::<5>::new_with_effort::<CTX>(&seq, 3, 2, Effort::Exact);
+        assert_eq!(exact.effort(), Effort::Exact);
+        assert!(exact.lcp_array().is_none());
+
+        let full = SuffixArray::<5>::new_with_effort_with_ctx(&seq, CTX, 3, 2, Effort::ExactLcp);
+        assert_eq!(full.effort(), Effort::ExactLcp);
+        assert_eq!(full.idxs(), exact.idxs());
+        assert_eq!(full.lcp_array(), Some(&exact.lcp(&pad_text(&seq, CTX))));
+        assert_eq!("exact-lcp".parse(), Ok(Effort::ExactLcp));
+    }
+}
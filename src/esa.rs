@@ -0,0 +1,456 @@
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+use crate::compact_vec::*;
+use crate::query::*;
+use crate::suffix_array::*;
+
+/// Marks undefined child table links.
+const NONE: usize = usize::MAX;
+
+/// Intervals of at least this many entries are traversed in parallel.
+const PAR_INTERVAL: usize = 1 << 14;
+
+/// The entries `start..end` of a suffix array, whose suffixes share their first `lcp`
+/// bases: a node of the suffix tree. Leaves have a single entry and an `lcp` of the
+/// context length.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LcpInterval {
+    pub lcp: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LcpInterval {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.end - self.start <= 1
+    }
+}
+
+/// Enhanced suffix array (Abouelhoda, Kurtz and Ohlebusch 2004): the suffix array with
+/// its LCP array and child table, traversed top-down like a suffix tree.
+///
+/// The child table stores in one entry per suffix the `up`, `down` and `next` links
+/// between the indices of every LCP interval where the LCP equals that of the interval,
+/// so the children of an interval are found in constant time each. LCPs are capped at
+/// the context length, up to which the suffixes are sorted, so that they fit in 16
+/// bits, and count the padding at the end of the text.
+pub struct Esa<'a, const BYTES: usize> {
+    sa: &'a SuffixArray<BYTES>,
+    lcp: Vec<u16>,
+    child: CompactVec<BYTES>,
+    cap: usize,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// `Esa::new` with the LCP array of `bytes` over the padding.
+    pub fn esa(&self, bytes: &[u8]) -> Esa<'_, BYTES> {
+        Esa::new(self, &self.lcp_padded(bytes))
+    }
+}
+
+/// `up`, `down` and `next` links of the child table over `v(0..len)`, whose first and
+/// last values are sentinels below all others, or `NONE` where undefined.
+fn child_links(len: usize, v: impl Fn(usize) -> isize) -> [Vec<usize>; 3] {
+    let (mut up, mut down, mut next) = (vec![NONE; len], vec![NONE; len], vec![NONE; len]);
+    let mut stack = vec![0];
+    for (i, up) in up.iter_mut().enumerate().skip(1) {
+        let mut last = NONE;
+        while v(i) < v(*stack.last().unwrap()) {
+            last = stack.pop().unwrap();
+            let top = *stack.last().unwrap();
+            if v(i) <= v(top) && v(top) < v(last) {
+                down[top] = last;
+            }
+        }
+        *up = last;
+        let top = *stack.last().unwrap();
+        if v(top) == v(i) && i < len - 1 {
+            next[top] = i;
+        }
+        stack.push(i);
+    }
+    [up, down, next]
+}
+
+impl<'a, const BYTES: usize> Esa<'a, BYTES> {
+    /// Build from `sa` and its LCP array, see `SuffixArray::lcp_padded`.
+    ///
+    /// Entries with an LCP below the bucket length `k` split the array into its
+    /// buckets, whose child tables are built in parallel; only the links between
+    /// bucket boundaries are built sequentially, from one entry per bucket.
+    pub fn new(sa: &'a SuffixArray<BYTES>, lcp: &CompactVec<BYTES>) -> Self {
+        let n = sa.idxs().len();
+        assert_eq!(lcp.len(), n);
+        let cap = sa.ctx();
+        assert!(cap <= u16::MAX as usize);
+        let lcp = lcp
+            .par_iter()
+            .map(|l| l.get_usize().min(cap) as u16)
+            .collect::<Vec<_>>();
+        let mut child = CompactVec::<BYTES>::new(n);
+        let lcp_at = |i: usize| {
+            if i == 0 || i >= n {
+                -1
+            } else {
+                lcp[i] as isize
+            }
+        };
+
+        // Boundaries have a smaller LCP than every entry between them, so intervals
+        // either lie between two boundaries or start and end at one.
+        let k = sa.k() as u16;
+        let mut bounds = vec![0];
+        bounds.extend(
+            (1..n)
+                .into_par_iter()
+                .filter(|&i| lcp[i] < k)
+                .collect::<Vec<_>>(),
+        );
+        bounds.push(n);
+        let segments = bounds.len() - 1;
+
+        // The leftmost minimum between every two consecutive boundaries.
+        let seg_min = (0..segments)
+            .into_par_iter()
+            .map(|j| {
+                (bounds[j] + 1..bounds[j + 1])
+                    .min_by_key(|&i| (lcp[i], i))
+                    .unwrap_or(NONE)
+            })
+            .collect::<Vec<_>>();
+        let [up, down, next] = child_links(bounds.len(), |j| lcp_at(bounds[j]));
+        let link = |l: usize| if l == NONE { NONE } else { bounds[l] };
+        let g_up = |j: usize| match up[j] {
+            NONE => seg_min[j - 1],
+            l => bounds[l],
+        };
+        let g_down = |j: usize| match down[j] {
+            NONE => seg_min[j],
+            l => bounds[l],
+        };
+
+        let mut slices = Vec::with_capacity(segments);
+        let mut rest = &mut child[..];
+        for j in 0..segments {
+            let (slice, tail) = std::mem::take(&mut rest).split_at_mut(bounds[j + 1] - bounds[j]);
+            slices.push(slice);
+            rest = tail;
+        }
+        slices.into_par_iter().enumerate().for_each(|(j, cells)| {
+            let (b, e) = (bounds[j], bounds[j + 1]);
+            let cell = if lcp_at(b) > lcp_at(b + 1) {
+                g_up(j + 1)
+            } else if next[j] != NONE {
+                link(next[j])
+            } else {
+                g_down(j)
+            };
+            if let Some(c) = cells.first_mut() {
+                c.set_usize(cell.min(n));
+            }
+            if e - b < 2 {
+                return;
+            }
+
+            let len = e - b + 1;
+            let [up, down, next] = child_links(len, |x| {
+                if x == 0 || x == len - 1 {
+                    -1
+                } else {
+                    lcp[b + x] as isize
+                }
+            });
+            for x in 1..len - 1 {
+                let cell = if x == len - 2 {
+                    g_up(j + 1)
+                } else if lcp[b + x] > lcp[b + x + 1] {
+                    b + up[x + 1]
+                } else if next[x] != NONE {
+                    b + next[x]
+                } else if down[x] != NONE {
+                    b + down[x]
+                } else {
+                    n
+                };
+                cells[x].set_usize(cell);
+            }
+        });
+
+        Self {
+            sa,
+            lcp,
+            child,
+            cap,
+        }
+    }
+
+    /// The largest LCP, that of the leaves.
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    pub fn suffix_array(&self) -> &SuffixArray<BYTES> {
+        self.sa
+    }
+
+    /// Bytes used by the LCP array and the child table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.lcp.len() * 2 + self.child.len() * BYTES
+    }
+
+    fn lcp_at(&self, i: usize) -> isize {
+        if i == 0 || i >= self.lcp.len() {
+            -1
+        } else {
+            self.lcp[i] as isize
+        }
+    }
+
+    /// The child table link of entry `i` if it points forward to an entry with an LCP
+    /// for which `f` holds.
+    fn forward(&self, i: usize, f: impl Fn(isize, isize) -> bool) -> Option<usize> {
+        let c = self.child[i].get_usize();
+        (c > i && c < self.lcp.len() && f(self.lcp_at(c), self.lcp_at(i))).then_some(c)
+    }
+
+    /// The first index of `start..end` whose LCP is that of the interval.
+    fn first_l_index(&self, start: usize, end: usize) -> usize {
+        if self.lcp_at(end - 1) > self.lcp_at(end) {
+            let up = self.child[end - 1].get_usize();
+            if start < up && up < end {
+                return up;
+            }
+        }
+        self.forward(start, |c, i| c > i).unwrap()
+    }
+
+    fn interval(&self, start: usize, end: usize) -> LcpInterval {
+        let lcp = if end - start <= 1 {
+            self.cap
+        } else {
+            self.lcp_at(self.first_l_index(start, end)) as usize
+        };
+        LcpInterval { lcp, start, end }
+    }
+
+    /// The interval of all suffixes.
+    pub fn root(&self) -> LcpInterval {
+        self.interval(0, self.lcp.len())
+    }
+
+    /// The child intervals of `iv` in order, or none for a leaf.
+    pub fn children(&self, iv: LcpInterval) -> Vec<LcpInterval> {
+        if iv.is_leaf() {
+            return Vec::new();
+        }
+        let mut res = Vec::new();
+        let (mut start, mut i) = (iv.start, self.first_l_index(iv.start, iv.end));
+        loop {
+            res.push(self.interval(start, i));
+            start = i;
+            match self.forward(i, |c, i| c == i) {
+                Some(next) if next < iv.end => i = next,
+                _ => break,
+            }
+        }
+        res.push(self.interval(start, iv.end));
+        res
+    }
+
+    /// Text position of the suffix at entry `i`.
+    fn pos(&self, i: usize) -> usize {
+        self.sa.idxs()[i].get_usize()
+    }
+
+    /// The child of `iv` whose suffixes continue with `base` after the `lcp` bases
+    /// they share.
+    pub fn child(&self, bytes: &[u8], iv: LcpInterval, base: u8) -> Option<LcpInterval> {
+        self.children(iv)
+            .into_iter()
+            .find(|c| base_code(bytes[self.pos(c.start) + iv.lcp]) == base_code(base))
+    }
+
+    /// The interval of the suffixes starting with `pattern`, found top-down from the
+    /// root, or `None` if it does not occur or is longer than the `cap` sorted bases.
+    pub fn find(&self, bytes: &[u8], pattern: &[u8]) -> Option<LcpInterval> {
+        if pattern.len() > self.cap {
+            return None;
+        }
+        let (mut iv, mut depth) = (self.root(), 0);
+        loop {
+            let end = iv.lcp.min(pattern.len());
+            let pos = self.pos(iv.start);
+            if !cmp_pattern(bytes, pos + depth, &pattern[depth..end]).is_eq() {
+                return None;
+            }
+            if end == pattern.len() {
+                return Some(iv);
+            }
+            depth = end;
+            iv = self.child(bytes, iv, pattern[depth])?;
+        }
+    }
+
+    /// Every maximal repeat of at least `min_len` bases: the internal intervals whose
+    /// suffixes are not all preceded by the same base, ordered by start and then
+    /// length. Repeats of `cap` bases may continue beyond it.
+    pub fn maximal_repeats(&self, bytes: &[u8], min_len: usize) -> Vec<LcpInterval> {
+        let mut res = Vec::new();
+        self.maximal_below(bytes, self.root(), min_len.max(1), &mut res);
+        res.par_sort_unstable_by_key(|iv| (iv.start, iv.lcp));
+        res
+    }
+
+    /// Push the maximal repeats of `iv` and below to `out`, and return the base before
+    /// all suffixes of `iv`, if there is one. Large intervals are split over threads.
+    fn maximal_below(
+        &self,
+        bytes: &[u8],
+        iv: LcpInterval,
+        min_len: usize,
+        out: &mut Vec<LcpInterval>,
+    ) -> Option<u8> {
+        if iv.is_leaf() {
+            let pos = self.pos(iv.start);
+            return (pos > 0).then(|| base_code(bytes[pos - 1]));
+        }
+        let children = self.children(iv);
+        let left = if iv.end - iv.start >= PAR_INTERVAL {
+            let below = children
+                .into_par_iter()
+                .map(|c| {
+                    let mut out = Vec::new();
+                    let left = self.maximal_below(bytes, c, min_len, &mut out);
+                    (left, out)
+                })
+                .collect::<Vec<_>>();
+            let mut lefts = Vec::with_capacity(below.len());
+            for (left, repeats) in below {
+                lefts.push(left);
+                out.extend(repeats);
+            }
+            lefts
+        } else {
+            children
+                .into_iter()
+                .map(|c| self.maximal_below(bytes, c, min_len, out))
+                .collect()
+        };
+        let left = left
+            .into_iter()
+            .reduce(|a, b| if a == b { a } else { None })
+            .flatten();
+        if left.is_none() && iv.lcp >= min_len {
+            out.push(iv);
+        }
+        left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_esa() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCAGATTACAGGATCCA".repeat(12);
+        seq.extend_from_slice(b"GATTACATTTGGCACCGTAGATTACC");
+        let n = seq.len();
+        let b = pad_text(&seq, CTX);
+        for k in [0, 3] {
+            let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, k.max(1), 2);
+            let sa = if k == 0 {
+                SuffixArray::from_idxs(sa.idxs().clone(), CTX)
+            } else {
+                sa
+            };
+            let esa = sa.esa(&b);
+            let lcp = sa.lcp_padded(&b).to_usize_vec();
+
+            // Every internal interval has its LCP as the minimum inside, and a smaller
+            // one at both ends; its children partition it.
+            let mut stack = vec![esa.root()];
+            let mut internal = 0;
+            while let Some(iv) = stack.pop() {
+                if iv.is_leaf() {
+                    continue;
+                }
+                internal += 1;
+                let inner = lcp[iv.start + 1..iv.end].iter().map(|&l| l.min(CTX));
+                assert_eq!(inner.min(), Some(iv.lcp), "{iv:?}");
+                assert!(iv.start == 0 || lcp[iv.start] < iv.lcp);
+                assert!(iv.end == n || lcp[iv.end] < iv.lcp);
+                let children = esa.children(iv);
+                assert!(children.len() >= 2);
+                assert_eq!(children[0].start, iv.start);
+                assert_eq!(children.last().unwrap().end, iv.end);
+                assert!(children.windows(2).all(|w| w[0].end == w[1].start));
+                stack.extend(children);
+            }
+            // Internal intervals correspond to the entries with no equal LCP before the
+            // next smaller one.
+            let c = |i: usize| lcp[i].min(CTX);
+            let naive = (1..n)
+                .filter(|&i| {
+                    (1..i)
+                        .rev()
+                        .find(|&j| c(j) <= c(i))
+                        .is_none_or(|j| c(j) != c(i))
+                })
+                .count();
+            assert_eq!(internal, naive);
+
+            for pattern in [
+                &b"GATTACA"[..],
+                b"GA",
+                b"T",
+                b"",
+                b"CCAACG",
+                b"GATTACATTTGG",
+                b"GGG",
+                &b"ACGTTGCAGATTACAGGATCCA".repeat(5)[..],
+            ] {
+                let range = sa.range(&seq, pattern);
+                let found = esa.find(&b, pattern).map(|iv| iv.range());
+                assert_eq!(
+                    found,
+                    (!range.is_empty()).then_some(range),
+                    "{}",
+                    String::from_utf8_lossy(pattern)
+                );
+            }
+
+            assert_eq!(esa.find(&b, &seq[..CTX + 1]), None);
+
+            let root = esa.root();
+            let a = esa.child(&b, root, b'A').unwrap();
+            assert_eq!(a.range(), sa.range(&seq, b"A"));
+
+            let repeats = esa.maximal_repeats(&b, 6);
+            let text = |iv: &LcpInterval| {
+                let pos = sa.idxs()[iv.start].get_usize();
+                &b[pos..pos + iv.lcp]
+            };
+            assert!(repeats
+                .iter()
+                .any(|r| text(r) == b"AGATTAC" && r.range().len() == 14));
+            assert!(!repeats.iter().any(|r| text(r) == b"GATTAC"));
+            for r in &repeats {
+                assert!(r.lcp >= 6);
+                let left = r
+                    .range()
+                    .map(|i| sa.idxs()[i].get_usize())
+                    .map(|p| (p > 0).then(|| b[p - 1]))
+                    .collect::<Vec<_>>();
+                assert!(left.contains(&None) || left.iter().any(|&c| c != left[0]));
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::compact_vec::*;
+use crate::perf::*;
+use crate::suffix_array::*;
+
+/// Compare the suffixes `a` and `b` of `seq` followed by padding `A`s in full, a
+/// shorter suffix being smaller. Suffixes that start in the padding are only padding.
+fn cmp_suffixes(seq: &[u8], a: usize, b: usize) -> Ordering {
+    let suffix = |i: usize| seq.get(i..).unwrap_or(&[]).iter().map(|&c| base_code(c));
+    suffix(a).cmp(suffix(b)).then(b.cmp(&a))
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// An exact suffix array of `seq`: `new_packed`, with suffixes that are tied over
+    /// the context then ordered by `resolve_ties`.
+    pub fn new_exact<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let mut sa = Self::new_packed_ordered::<CTX>(seq, k, schedule, TieOrder::Ascending);
+        let phase = Phase::start("Resolve ties beyond the context");
+        sa.resolve_ties_packed(&RevPacked::with_padding(seq, CTX), seq);
+        phase.finish();
+        sa
+    }
+
+    pub fn new_exact_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_exact::<124>(seq, k, bucket_threads),
+            248 => Self::new_exact::<248>(seq, k, bucket_threads),
+            496 => Self::new_exact::<496>(seq, k, bucket_threads),
+            992 => Self::new_exact::<992>(seq, k, bucket_threads),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    /// Order suffixes that are tied over the full context by their complete suffix,
+    /// so that the array becomes an exact suffix array of `bytes`.
+    ///
+    /// Instead of extending the SIMD comparison arbitrarily far, tied suffixes `a` and
+    /// `b` that are known to be equal over `h` bases are ordered by the already known
+    /// ranks of `a + h` and `b + h` (prefix doubling over the tied groups only).
+    /// Suffixes whose shifted position is not in the array are compared directly.
+    ///
+    /// Like `lcp`, this takes the text padded with `pad_text`.
+    pub fn resolve_ties(&mut self, bytes: &[u8]) {
+        let seq = &bytes[..bytes.len() - self.ctx()];
+        self.resolve_ties_packed(&RevPacked::new(bytes), seq);
+    }
+
+    /// `resolve_ties` for the sequence `seq` that is packed with its padding in `packed`.
+    fn resolve_ties_packed(&mut self, packed: &RevPacked, seq: &[u8]) {
+        let n = self.idxs().len();
+        let ctx = self.ctx();
+
+        let mut groups = {
+            let idxs = self.idxs();
+            let tied = (1..n)
+                .into_par_iter()
+                .map(|i| unsafe {
+                    packed.lcp(idxs[i - 1].get_usize(), idxs[i].get_usize(), ctx) >= ctx
+                })
+                .collect::<Vec<_>>();
+            tied_groups(&tied)
+        };
+
+        let mut rank = CompactVec::<BYTES>::new(n);
+        {
+            let rank_ptr = MutPtr(rank.as_mut_ptr());
+            let idxs = self.idxs();
+            let group_start = group_starts(n, &groups);
+            idxs.par_iter().enumerate().for_each(|(i, pos)| {
+                let ptr = rank_ptr;
+                unsafe { (*ptr.0.add(pos.get_usize())).set_usize(group_start[i]) };
+            });
+        }
+
+        let mut h = ctx;
+        while !groups.is_empty() {
+            let idxs_ptr = MutPtr(self.idxs_mut().as_mut_ptr());
+            let ranks = &rank;
+            let key_cmp = |a: usize, b: usize| {
+                if a + h < n && b + h < n {
+                    ranks[a + h].get_usize().cmp(&ranks[b + h].get_usize())
+                } else {
+                    cmp_suffixes(seq, a + h, b + h)
+                }
+            };
+
+            let new_groups = groups
+                .par_iter()
+                .flat_map_iter(|g| {
+                    let ptr = idxs_ptr;
+                    let slice =
+                        unsafe { std::slice::from_raw_parts_mut(ptr.0.add(g.start), g.len()) };
+                    slice.sort_unstable_by(|a, b| key_cmp(a.get_usize(), b.get_usize()));
+
+                    let tied = slice
+                        .windows(2)
+                        .map(|w| key_cmp(w[0].get_usize(), w[1].get_usize()).is_eq())
+                        .collect::<Vec<_>>();
+                    tied_groups(&tied)
+                        .into_iter()
+                        .map(move |r| r.start + g.start..r.end + g.start)
+                })
+                .collect::<Vec<_>>();
+
+            // Entries of resolved groups get their own rank, entries still tied share one.
+            let rank_ptr = MutPtr(rank.as_mut_ptr());
+            let idxs = self.idxs();
+            groups.par_iter().for_each(|g| {
+                let ptr = rank_ptr;
+                let mut sub = new_groups.partition_point(|r| r.start < g.start);
+                for i in g.clone() {
+                    while sub < new_groups.len() && new_groups[sub].end <= i {
+                        sub += 1;
+                    }
+                    let r = match new_groups.get(sub) {
+                        Some(r) if r.start <= i => r.start,
+                        _ => i,
+                    };
+                    unsafe { (*ptr.0.add(idxs[i].get_usize())).set_usize(r) };
+                }
+            });
+
+            groups = new_groups;
+            h *= 2;
+        }
+        self.set_exact(true);
+    }
+}
+
+/// Ranges of at least two consecutive entries where `tied[i]` says entries `i` and
+/// `i + 1` are equal.
+fn tied_groups(tied: &[bool]) -> Vec<Range<usize>> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < tied.len() {
+        if tied[i] {
+            let start = i;
+            while i < tied.len() && tied[i] {
+                i += 1;
+            }
+            res.push(start..i + 1);
+        } else {
+            i += 1;
+        }
+    }
+    res
+}
+
+fn group_starts(n: usize, groups: &[Range<usize>]) -> Vec<usize> {
+    let mut res = (0..n).collect::<Vec<_>>();
+    for g in groups {
+        res[g.clone()].fill(g.start);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_ties() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCA".repeat(40);
+        seq.extend_from_slice(b"GATTACA");
+        seq.extend_from_slice(&b"ACGTTGCA".repeat(40));
+
+        let mut s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        s.resolve_ties(&pad_text(&seq, CTX));
+
+        let mut correct = (0..seq.len()).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| seq[x..].cmp(&seq[y..]));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+
+        let exact = SuffixArray::<5>::new_exact_with_ctx(&seq, CTX, 3, 2);
+        assert_eq!(exact.idxs().to_usize_vec(), correct);
+    }
+}
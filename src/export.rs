@@ -0,0 +1,108 @@
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::suffix_array::*;
+
+/// Entries converted at a time by `write_raw`.
+const RAW_BLOCK: usize = 1 << 20;
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// The entries as `u32`, converted in parallel. Panics if a position does not fit.
+    pub fn to_u32_vec(&self) -> Vec<u32> {
+        self.idxs()
+            .par_iter()
+            .map(|i| u32::try_from(i.get_usize()).expect("position does not fit in a u32"))
+            .collect()
+    }
+
+    /// The entries as `u64`, converted in parallel, as 64-bit divsufsort and libsais
+    /// return them.
+    pub fn to_u64_vec(&self) -> Vec<u64> {
+        self.idxs()
+            .par_iter()
+            .map(|i| i.get_usize() as u64)
+            .collect()
+    }
+
+    /// Write the entries to `path` as little-endian integers of `width` bytes, 1 to 8,
+    /// back to back and without a header: entry `i` is bytes `i * width..(i + 1) *
+    /// width`. At the stored width the entries are written as they are; otherwise they
+    /// are converted in blocks, in parallel. Fails with `InvalidInput` if a position
+    /// does not fit in `width` bytes.
+    pub fn write_raw(&self, path: impl AsRef<Path>, width: usize) -> io::Result<()> {
+        if !(1..=8).contains(&width) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("entries of {width} bytes are not supported"),
+            ));
+        }
+        if width < BYTES {
+            let max = self.idxs().par_iter().map(|i| i.get_usize()).max();
+            if max.is_some_and(|m| m >> (8 * width) != 0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("positions do not fit in {width} bytes"),
+                ));
+            }
+        }
+
+        let mut w = BufWriter::new(File::create(path)?);
+        if width == BYTES {
+            w.write_all(self.idxs().as_bytes())?;
+            return w.flush();
+        }
+        let mut buf = Vec::new();
+        for block in self.idxs().chunks(RAW_BLOCK) {
+            buf.resize(block.len() * width, 0);
+            buf.par_chunks_mut(width).zip(block).for_each(|(out, i)| {
+                out.copy_from_slice(&(i.get_usize() as u64).to_le_bytes()[..width]);
+            });
+            w.write_all(&buf)?;
+        }
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACAGGATCCA".repeat(30);
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        let idxs = sa.idxs().to_usize_vec();
+        assert_eq!(
+            sa.to_u32_vec(),
+            idxs.iter().map(|&i| i as u32).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            sa.to_u64_vec(),
+            idxs.iter().map(|&i| i as u64).collect::<Vec<_>>()
+        );
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.raw", std::process::id()));
+        for width in [2, 4, 5, 8] {
+            sa.write_raw(&path, width).unwrap();
+            let raw = std::fs::read(&path).unwrap();
+            assert_eq!(raw.len(), idxs.len() * width);
+            let read = raw
+                .chunks_exact(width)
+                .map(|c| {
+                    let mut x = [0u8; 8];
+                    x[..width].copy_from_slice(c);
+                    u64::from_le_bytes(x) as usize
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(read, idxs, "width {width}");
+        }
+        let err = sa.write_raw(&path, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(sa.write_raw(&path, 9).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::packed_text::*;
+use crate::perf::*;
+use crate::report::*;
+use crate::sa_file::MAGIC;
+use crate::stages::*;
+use crate::suffix_array::*;
+
+/// Suffixes whose k-mers are computed in parallel before being spilled in text order.
+const SPILL_CHUNK: usize = 1 << 20;
+
+/// Split all k-mers into contiguous ranges of at most `max_suffixes` suffixes each. A
+/// single bucket larger than that gets a range of its own.
+pub fn plan_external_partitions(bucket_sizes: &[usize], max_suffixes: usize) -> Vec<Range<usize>> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut sum = 0;
+    for (kmer, &size) in bucket_sizes.iter().enumerate() {
+        if sum + size > max_suffixes && kmer > start {
+            res.push(start..kmer);
+            start = kmer;
+            sum = 0;
+        }
+        sum += size;
+    }
+    res.push(start..bucket_sizes.len());
+    res
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Build the suffix array of the sequence `seq` out of core, and write it to
+    /// `path` in the format of `write_to`.
+    ///
+    /// The k-mer buckets are split into partitions of about `budget` bytes of entries
+    /// (including the scratch space of sorting), and one pass over the text spills the
+    /// positions of every partition to its own file in `tmp_dir`. Partitions are then
+    /// read back one at a time, scattered into their buckets, sorted, and appended to
+    /// `path`. Only the packed text, the bucket counts and one partition are in memory.
+    pub fn write_external<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        budget: usize,
+        tmp_dir: &Path,
+        path: &Path,
+    ) -> io::Result<()> {
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+        write_external_rev::<CTX, BYTES>(&packed, seq.len(), k, budget, tmp_dir, path)
+    }
+
+    /// Like `write_external`, for a text that was packed while it was read, e.g. by
+    /// `read_fasta_stream`, so that the bytes are never in memory.
+    pub fn write_external_packed<const CTX: usize>(
+        text: &PackedText,
+        k: usize,
+        budget: usize,
+        tmp_dir: &Path,
+        path: &Path,
+    ) -> io::Result<()> {
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::from_text(text, CTX);
+        phase.finish();
+        write_external_rev::<CTX, BYTES>(&packed, text.len(), k, budget, tmp_dir, path)
+    }
+
+    pub fn write_external_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        budget: usize,
+        tmp_dir: &Path,
+        path: &Path,
+    ) -> io::Result<()> {
+        match ctx {
+            124 => Self::write_external::<124>(seq, k, budget, tmp_dir, path),
+            248 => Self::write_external::<248>(seq, k, budget, tmp_dir, path),
+            496 => Self::write_external::<496>(seq, k, budget, tmp_dir, path),
+            992 => Self::write_external::<992>(seq, k, budget, tmp_dir, path),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    pub fn write_external_packed_with_ctx(
+        text: &PackedText,
+        ctx: usize,
+        k: usize,
+        budget: usize,
+        tmp_dir: &Path,
+        path: &Path,
+    ) -> io::Result<()> {
+        match ctx {
+            124 => Self::write_external_packed::<124>(text, k, budget, tmp_dir, path),
+            248 => Self::write_external_packed::<248>(text, k, budget, tmp_dir, path),
+            496 => Self::write_external_packed::<496>(text, k, budget, tmp_dir, path),
+            992 => Self::write_external_packed::<992>(text, k, budget, tmp_dir, path),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+}
+
+/// Spill files that are removed when dropped, so that none are left behind when the
+/// build fails halfway.
+struct SpillFiles(Vec<PathBuf>);
+
+impl Drop for SpillFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            // Files of sorted partitions are already gone.
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Build the suffix array of the `n` suffixes of `packed` out of core, see
+/// `write_external`.
+fn write_external_rev<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    n: usize,
+    k: usize,
+    budget: usize,
+    tmp_dir: &Path,
+    path: &Path,
+) -> io::Result<()> {
+    // The k-mers are read from the packed text, so the bytes are not needed.
+    let chunks = bucket_chunks(n, rayon::current_num_threads());
+    let mut counts = count_keys::<BYTES>(&chunks, 1 << (2 * k), |i| unsafe {
+        packed.load_k(i, k) as usize
+    });
+    let bucket_ends = prefix_sum(&mut counts);
+    drop(counts);
+    let sizes = bucket_sizes(&bucket_ends);
+
+    // Sorting a bucket takes a scratch buffer of half its size.
+    let parts = plan_external_partitions(&sizes, budget * 2 / (3 * BYTES));
+    build_log!("\tPartitions: {}", parts.len());
+    let spill_files = SpillFiles(
+        (0..parts.len())
+            .map(|p| tmp_dir.join(format!("simple-saca-{}-{p}.spill", std::process::id())))
+            .collect(),
+    );
+
+    let phase = Phase::start("Spill partitions");
+    spill::<BYTES>(packed, n, k, &parts, &spill_files.0)?;
+    phase.finish();
+
+    let mut w = BufWriter::new(File::create(path)?);
+    let sections = [
+        ("buckets", bucket_ends.as_bytes().len() as u64),
+        ("flags", 1),
+        ("ties", 1),
+        ("sa", (n * BYTES) as u64),
+    ];
+    write_header(&mut w, MAGIC, BYTES, k, CTX, &sections)?;
+    w.write_all(bucket_ends.as_bytes())?;
+    w.write_all(&[0])?;
+    w.write_all(&[TieOrder::Ascending as u8])?;
+
+    let phase = Phase::start("Sort partitions");
+    for (kmers, spill_path) in parts.into_iter().zip(&spill_files.0) {
+        let part = sort_partition::<CTX, BYTES>(packed, k, kmers, &sizes, spill_path)?;
+        std::fs::remove_file(spill_path)?;
+        w.write_all(part.as_bytes())?;
+    }
+    phase.finish();
+    w.flush()
+}
+
+/// The size of every bucket, from the ends of the buckets.
+fn bucket_sizes<const BYTES: usize>(bucket_ends: &CompactVec<BYTES>) -> Vec<usize> {
+    let mut prev = 0;
+    bucket_ends
+        .iter()
+        .map(|e| {
+            let size = e.get_usize() - prev;
+            prev = e.get_usize();
+            size
+        })
+        .collect()
+}
+
+/// Write the positions `0..n` of every partition to its spill file, in text order.
+fn spill<const BYTES: usize>(
+    packed: &RevPacked,
+    n: usize,
+    k: usize,
+    parts: &[Range<usize>],
+    paths: &[PathBuf],
+) -> io::Result<()> {
+    let mut part_of = vec![0u32; 1 << (2 * k)];
+    for (p, kmers) in parts.iter().enumerate() {
+        part_of[kmers.clone()].fill(p as u32);
+    }
+    let mut writers = paths
+        .iter()
+        .map(|path| Ok(BufWriter::new(File::create(path)?)))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    for c in 0..n.div_ceil(SPILL_CHUNK) {
+        let range = c * SPILL_CHUNK..((c + 1) * SPILL_CHUNK).min(n);
+        let kmers = range
+            .clone()
+            .into_par_iter()
+            .map(|i| unsafe { packed.load_k(i, k) })
+            .collect::<Vec<_>>();
+        for (i, kmer) in range.zip(kmers) {
+            writers[part_of[kmer as usize] as usize].write_all(&i.to_le_bytes()[..BYTES])?;
+        }
+    }
+    writers.iter_mut().try_for_each(|w| w.flush())
+}
+
+/// Read the spilled positions of the k-mers `kmers` into their buckets and sort them.
+fn sort_partition<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    k: usize,
+    kmers: Range<usize>,
+    sizes: &[usize],
+    spill_path: &Path,
+) -> io::Result<CompactVec<BYTES>> {
+    let mut fill = Vec::with_capacity(kmers.len());
+    let mut sum = 0;
+    for &size in &sizes[kmers.clone()] {
+        fill.push(sum);
+        sum += size;
+    }
+    let mut part = CompactVec::<BYTES>::new(sum);
+
+    let mut r = BufReader::new(File::open(spill_path)?);
+    let mut entry = [0u8; 8];
+    for _ in 0..sum {
+        r.read_exact(&mut entry[..BYTES])?;
+        let i = usize::from_le_bytes(entry);
+        let kmer = unsafe { packed.load_k(i, k) } as usize;
+        part[fill[kmer - kmers.start]].set_usize(i);
+        fill[kmer - kmers.start] += 1;
+    }
+
+    let mut buckets = Vec::with_capacity(kmers.len());
+    let mut rest = &mut part[..];
+    for &size in &sizes[kmers] {
+        let (bucket, tail) = rest.split_at_mut(size);
+        buckets.push(bucket);
+        rest = tail;
+    }
+    let kernel = Kernel::detect(CTX);
+    buckets.into_par_iter().for_each(|bucket| {
+        sort_bucket::<CTX, BYTES>(kernel, packed, bucket, false, false, TieOrder::Ascending)
+    });
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributed::*;
+
+    #[test]
+    fn test_external() {
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACAGGGATTTCCAG".repeat(30);
+        let k = 3;
+
+        let sizes = kmer_bucket_sizes(&b, k);
+        let parts = plan_external_partitions(&sizes, 100);
+        assert!(parts.len() > 4);
+        assert!(parts.windows(2).all(|w| w[0].end == w[1].start));
+        assert_eq!(parts.last().unwrap().end, sizes.len());
+
+        let tmp = std::env::temp_dir();
+        let path = tmp.join(format!("simple-saca-{}-external.sa", std::process::id()));
+        SuffixArray::<5>::write_external::<CTX>(&b, k, 100 * 5 * 3 / 2, &tmp, &path).unwrap();
+        let s = SuffixArray::<5>::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let t = SuffixArray::<5>::new_packed::<CTX>(&b, k, 2);
+        assert_eq!(s.idxs(), t.idxs());
+        assert_eq!(s.bucket_ends(), t.bucket_ends());
+        assert_eq!((s.k(), s.ctx()), (k, CTX));
+
+        SuffixArray::<5>::write_external_packed::<CTX>(&PackedText::new(&b), k, 500, &tmp, &path)
+            .unwrap();
+        let u = SuffixArray::<5>::load(&path).unwrap();
+        assert_eq!((u.idxs(), u.bucket_ends()), (s.idxs(), s.bucket_ends()));
+        std::fs::remove_file(&path).unwrap();
+
+        // Writing to a directory fails after spilling, and the spill files are removed.
+        assert!(SuffixArray::<5>::write_external::<CTX>(&b, k, 500, &tmp, &tmp).is_err());
+        let prefix = format!("simple-saca-{}-", std::process::id());
+        assert!(!std::fs::read_dir(&tmp).unwrap().any(|e| {
+            let name = e.unwrap().file_name().into_string().unwrap_or_default();
+            name.starts_with(&prefix) && name.ends_with(".spill")
+        }));
+    }
+}
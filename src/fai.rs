@@ -0,0 +1,193 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::bundle::*;
+use crate::fingerprint::*;
+
+/// One line of a samtools `.fai` index, plus the MD5 of the sequence for `.dict` files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaiRecord {
+    pub name: String,
+    /// Length including Ns.
+    pub len: usize,
+    /// Byte offset of the first base in the file.
+    pub offset: u64,
+    pub line_bases: usize,
+    pub line_width: usize,
+    /// MD5 of the upper case sequence.
+    pub md5: [u8; 16],
+}
+
+/// Index an uncompressed FASTA file the way `samtools faidx` does.
+pub fn index_fasta(mut r: impl BufRead) -> io::Result<Vec<FaiRecord>> {
+    let mut res = Vec::<FaiRecord>::new();
+    let mut offset = 0u64;
+    let mut line = Vec::new();
+    let mut seq = Vec::new();
+    // Whether the last sequence line of the current record was shorter than the others.
+    let mut short_line = false;
+
+    let finish = |res: &mut Vec<FaiRecord>, seq: &mut Vec<u8>| {
+        if let Some(last) = res.last_mut() {
+            last.len = seq.len();
+            last.md5 = md5(seq);
+            seq.clear();
+        }
+    };
+
+    loop {
+        line.clear();
+        let n = r.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        let start = offset;
+        offset += n as u64;
+        let content = line.trim_ascii_end();
+
+        if let Some(header) = content.strip_prefix(b">") {
+            finish(&mut res, &mut seq);
+            let name = String::from_utf8_lossy(header);
+            res.push(FaiRecord {
+                name: name
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned(),
+                len: 0,
+                offset,
+                line_bases: 0,
+                line_width: 0,
+                md5: [0; 16],
+            });
+            short_line = false;
+            continue;
+        }
+
+        let Some(record) = res.last_mut() else {
+            if content.is_empty() {
+                continue;
+            }
+            return Err(invalid_data("not an uncompressed FASTA file"));
+        };
+        if content.is_empty() {
+            continue;
+        }
+        if record.line_bases == 0 {
+            record.line_bases = content.len();
+            record.line_width = n;
+        } else if short_line || content.len() > record.line_bases {
+            return Err(invalid_data(format!(
+                "different line length in sequence '{}' at byte {start}",
+                record.name
+            )));
+        }
+        short_line = content.len() < record.line_bases;
+        seq.extend(content.iter().map(|b| b.to_ascii_uppercase()));
+    }
+    finish(&mut res, &mut seq);
+
+    Ok(res)
+}
+
+pub fn write_fai(mut w: impl Write, records: &[FaiRecord]) -> io::Result<()> {
+    for r in records {
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}\t{}",
+            r.name, r.len, r.offset, r.line_bases, r.line_width
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a Picard/`samtools dict` style sequence dictionary, with the FASTA at `uri`.
+pub fn write_fasta_dict(
+    mut w: impl Write,
+    records: &[FaiRecord],
+    uri: Option<&str>,
+) -> io::Result<()> {
+    writeln!(w, "@HD\tVN:1.6\tSO:unsorted")?;
+    for r in records {
+        write!(w, "@SQ\tSN:{}\tLN:{}\tM5:", r.name, r.len)?;
+        r.md5.iter().try_for_each(|b| write!(w, "{b:02x}"))?;
+        match uri {
+            Some(uri) => writeln!(w, "\tUR:{uri}")?,
+            None => writeln!(w)?,
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn encode_fai(records: &[FaiRecord]) -> Vec<u8> {
+    let mut res = Vec::new();
+    res.extend_from_slice(&(records.len() as u64).to_le_bytes());
+    for r in records {
+        res.extend_from_slice(&(r.name.len() as u64).to_le_bytes());
+        res.extend_from_slice(r.name.as_bytes());
+        for v in [
+            r.len as u64,
+            r.offset,
+            r.line_bases as u64,
+            r.line_width as u64,
+        ] {
+            res.extend_from_slice(&v.to_le_bytes());
+        }
+        res.extend_from_slice(&r.md5);
+    }
+    res
+}
+
+pub(crate) fn decode_fai(mut data: &[u8]) -> io::Result<Vec<FaiRecord>> {
+    let r = &mut data;
+    (0..read_u64(r)?)
+        .map(|_| {
+            let name = read_name(r)?;
+            let len = read_u64(r)? as usize;
+            let offset = read_u64(r)?;
+            let line_bases = read_u64(r)? as usize;
+            let line_width = read_u64(r)? as usize;
+            let mut md5 = [0u8; 16];
+            r.read_exact(&mut md5)?;
+            Ok(FaiRecord {
+                name,
+                len,
+                offset,
+                line_bases,
+                line_width,
+                md5,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_fasta() {
+        let fasta = b">chr1 desc\nACGTN\nacgtA\nAC\n>chr2\r\nGGGG\r\nGG\r\n";
+        let records = index_fasta(&fasta[..]).unwrap();
+
+        let mut fai = Vec::new();
+        write_fai(&mut fai, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(fai).unwrap(),
+            "chr1\t12\t11\t5\t6\nchr2\t6\t33\t4\t6\n"
+        );
+        assert_eq!(records[0].md5, md5(b"ACGTNACGTAAC"));
+        assert_eq!(decode_fai(&encode_fai(&records)).unwrap(), records);
+
+        let mut dict = Vec::new();
+        write_fasta_dict(&mut dict, &records[1..], Some("ref.fa")).unwrap();
+        let dict = String::from_utf8(dict).unwrap();
+        assert_eq!(
+            dict,
+            "@HD\tVN:1.6\tSO:unsorted\n\
+             @SQ\tSN:chr2\tLN:6\tM5:f216144a92aa74c07e1e7f0ab792477d\tUR:ref.fa\n"
+        );
+
+        assert!(index_fasta(&b">a\nAC\nACGT\n"[..]).is_err());
+        assert!(index_fasta(&b"ACGT\n"[..]).is_err());
+    }
+}
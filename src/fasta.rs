@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::alphabet::*;
+use crate::bundle::*;
+use crate::platform::{Advice, Mmap};
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// The records of a FASTA or FASTQ file concatenated into one text, with bytes other
+/// than `ACGTU` handled by a `NonAcgt` policy.
+pub struct FastxInput {
+    /// The bases of all records, followed by `ctx` bases of padding once padded.
+    pub seq: Vec<u8>,
+    /// The records, named by their whole header line.
+    pub dict: SeqDict,
+    /// The positions in `seq` where runs of other bytes were removed.
+    pub breaks: Vec<usize>,
+    policy: NonAcgt,
+    ctx: usize,
+}
+
+impl FastxInput {
+    pub fn new(policy: NonAcgt) -> Self {
+        Self {
+            seq: Vec::new(),
+            dict: SeqDict::new(),
+            breaks: Vec::new(),
+            policy,
+            ctx: 0,
+        }
+    }
+
+    /// Append a record, e.g. from another parser.
+    pub fn push_record(&mut self, name: &str, seq: &[u8]) -> io::Result<()> {
+        self.push_lines(name, [seq])
+    }
+
+    fn push_lines<'a>(
+        &mut self,
+        name: &str,
+        lines: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<()> {
+        assert_eq!(self.ctx, 0, "records cannot be added after padding");
+        let start = self.seq.len();
+        let mut removed = 0;
+        let mut offset = 0;
+        for line in lines {
+            if line.iter().all(|&b| is_acgt(b)) {
+                self.seq.extend_from_slice(line);
+            } else {
+                removed +=
+                    push_acgt(&mut self.seq, &mut self.breaks, line, self.policy).map_err(|i| {
+                        invalid_data(format!(
+                            "{name} has non-ACGT byte {:?} at {}",
+                            line[i] as char,
+                            offset + i
+                        ))
+                    })?;
+            }
+            offset += line.len();
+        }
+        self.dict
+            .push_with_ns(name, self.seq.len() - start, removed);
+        Ok(())
+    }
+
+    /// Append `ctx` bases of padding, after which the text can be passed to analyses
+    /// such as `lcp`.
+    pub fn pad(&mut self, ctx: usize) {
+        assert_eq!(self.ctx, 0, "already padded");
+        self.seq.resize(self.seq.len() + ctx, b'A');
+        self.dict.pad(ctx);
+        self.ctx = ctx;
+    }
+
+    /// The padding added by `pad`, or 0.
+    pub fn ctx(&self) -> usize {
+        self.ctx
+    }
+
+    /// `SuffixArray::new_packed_with_ctx` of the text without its padding.
+    pub fn suffix_array<const BYTES: usize>(
+        &self,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> SuffixArray<BYTES> {
+        assert!(self.ctx > 0, "the input must be padded first");
+        let seq = &self.seq[..self.seq.len() - self.ctx];
+        SuffixArray::new_packed_with_ctx(seq, self.ctx, k, bucket_threads)
+    }
+}
+
+/// Position of the first `\n` in `bytes`, or its length, testing 8 bytes at a time.
+fn find_newline(bytes: &[u8]) -> usize {
+    const LO: u64 = u64::from_ne_bytes([0x01; 8]);
+    const HI: u64 = u64::from_ne_bytes([0x80; 8]);
+    let mut i = 0;
+    while i + 8 <= bytes.len() {
+        let word = u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap()) ^ (LO * b'\n' as u64);
+        // The lowest set bit is exact: borrows only carry into higher bytes.
+        let zero = word.wrapping_sub(LO) & !word & HI;
+        if zero != 0 {
+            return i + zero.trailing_zeros() as usize / 8;
+        }
+        i += 8;
+    }
+    i + bytes[i..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .unwrap_or(bytes.len() - i)
+}
+
+/// The lines of `data`, without `\n` or `\r\n`.
+fn lines(mut data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+        let end = find_newline(data);
+        let line = &data[..end];
+        data = &data[(end + 1).min(data.len())..];
+        Some(line.strip_suffix(b"\r").unwrap_or(line))
+    })
+}
+
+/// Parse FASTA with records over any number of lines, or FASTQ with one line per
+/// sequence, telling them apart by the first byte.
+pub fn parse_fastx(data: &[u8], policy: NonAcgt) -> io::Result<FastxInput> {
+    let mut input = FastxInput::new(policy);
+    let mut lines = lines(data).filter(|l| !l.is_empty()).peekable();
+    match lines.peek().map(|l| l[0]) {
+        None => {}
+        Some(b'>') => {
+            while let Some(header) = lines.next() {
+                let name = String::from_utf8_lossy(&header[1..]);
+                let mut seq = Vec::new();
+                while let Some(line) = lines.next_if(|l| l[0] != b'>') {
+                    seq.push(line);
+                }
+                input.push_lines(&name, seq)?;
+            }
+        }
+        Some(b'@') => {
+            while let Some(header) = lines.next() {
+                let name = String::from_utf8_lossy(
+                    header
+                        .strip_prefix(b"@")
+                        .ok_or_else(|| invalid_data("FASTQ record does not start with @"))?,
+                );
+                let (Some(seq), Some(plus), Some(qual)) =
+                    (lines.next(), lines.next(), lines.next())
+                else {
+                    return Err(invalid_data(format!("FASTQ record {name} is truncated")));
+                };
+                if plus[0] != b'+' || qual.len() != seq.len() {
+                    return Err(invalid_data(format!("FASTQ record {name} is malformed")));
+                }
+                input.push_lines(&name, [seq])?;
+            }
+        }
+        Some(_) => return Err(invalid_data("expected FASTA or FASTQ")),
+    }
+    Ok(input)
+}
+
+/// `parse_fastx` on the mapped file at `path`, which is read once front to back and
+/// never copied as a whole. Gzip-compressed files fail with `ErrorKind::Unsupported`.
+pub fn read_fastx(path: impl AsRef<Path>, policy: NonAcgt) -> io::Result<FastxInput> {
+    let f = File::open(path)?;
+    let len = f.metadata()?.len() as usize;
+    if len == 0 {
+        return Ok(FastxInput::new(policy));
+    }
+    let map = Mmap::map(&f, len)?;
+    map.advise(0..len, Advice::Sequential)?;
+    if map.as_slice().starts_with(&[0x1f, 0x8b]) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "gzip-compressed input cannot be mapped",
+        ));
+    }
+    parse_fastx(map.as_slice(), policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fastx() {
+        let fasta = b"\n>chr1 first\nACGTN\r\nacgu\n\n>chr2\nNNGGCC\n>empty\n>chr3\nGATTACAGATTACA";
+        let input = parse_fastx(fasta, NonAcgt::Strip).unwrap();
+        assert_eq!(input.seq, b"ACGTacguGGCCGATTACAGATTACA");
+        assert_eq!(input.dict.len(), 4);
+        assert_eq!(input.dict.name(0), "chr1 first");
+        assert_eq!(input.dict.seq_len(0), 8);
+        assert_eq!(input.dict.n_count(0), 1);
+        assert_eq!((input.dict.name(1), input.dict.n_count(1)), ("chr2", 2));
+        assert_eq!(input.dict.seq_len(2), 0);
+        assert_eq!(input.breaks, [4, 8]);
+        let err = parse_fastx(fasta, NonAcgt::Error).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("chr1 first has non-ACGT byte 'N' at 4"));
+
+        let fastq = b"@r1\nACGT\n+\nIIII\n@r2\nGGNA\n+r2\nIIII\n";
+        let input = parse_fastx(fastq, NonAcgt::Strip).unwrap();
+        assert_eq!(input.seq, b"ACGTGGA");
+        assert_eq!(input.dict.name(1), "r2");
+        assert!(parse_fastx(b"@r1\nACGT\n+\nIII\n", NonAcgt::Strip).is_err());
+        assert!(parse_fastx(b"@r1\nACGT\n", NonAcgt::Strip).is_err());
+        assert!(parse_fastx(b"ACGT\n", NonAcgt::Strip).is_err());
+
+        let long = b"ACGTTGCAGATTACA".repeat(7);
+        for i in 0..long.len() {
+            let mut line = long.clone();
+            line[i] = b'\n';
+            assert_eq!(find_newline(&line), i);
+        }
+        assert_eq!(find_newline(&long), long.len());
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.fa", std::process::id()));
+        std::fs::write(&path, [&b">a\n"[..], &long].concat()).unwrap();
+        let mut input = read_fastx(&path, NonAcgt::Strip).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(input.seq, long);
+        input.pad(124);
+        let sa = input.suffix_array::<5>(3, 2);
+        let expected = SuffixArray::<5>::new_packed::<124>(&long, 3, 2);
+        assert_eq!(sa.idxs(), expected.idxs());
+    }
+}
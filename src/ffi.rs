@@ -0,0 +1,184 @@
+//! C interface, see `include/simple_saca.h`. No function unwinds into C: invalid
+//! arguments and panics are reported as a null handle or `SIZE_MAX`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::tune::*;
+use crate::width::*;
+
+/// Context length of the suffix arrays built through the C interface.
+const CTX: usize = 124;
+
+/// A suffix array built for C callers, with the text it is queried on.
+pub struct SacaIndex {
+    sa: AnySuffixArray,
+    text: Vec<u8>,
+}
+
+impl SacaIndex {
+    fn new(seq: &[u8], k: usize, threads: usize, entry_bytes: usize) -> Option<Self> {
+        let threads = threads.max(1);
+        let width = match entry_bytes {
+            0 => crate::width::entry_bytes(seq.len()),
+            4..=6 | 8 => entry_bytes,
+            _ => return None,
+        };
+        let k = match k {
+            0 => auto_tune(seq.len(), width, usize::MAX, threads)?.k,
+            k => k,
+        };
+        let sa = AnySuffixArray::with_entry_bytes(seq, CTX, k, threads, width);
+        Some(Self {
+            sa,
+            text: seq.to_vec(),
+        })
+    }
+}
+
+/// Build the suffix array of the `len` bases at `seq` with buckets of `k`-mers, or an
+/// automatically chosen `k` when it is 0, on `threads` threads. Entries are stored as
+/// little-endian integers of `entry_bytes` bytes: 4, 5 or 6, or 8 for `u64`, or 0 for
+/// the narrowest of 4, 5 and 6 that fits. Returns null on invalid arguments or failure.
+///
+/// # Safety
+/// `seq` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn saca_build(
+    seq: *const u8,
+    len: usize,
+    k: usize,
+    threads: usize,
+    entry_bytes: usize,
+) -> *mut SacaIndex {
+    if seq.is_null() && len > 0 {
+        return ptr::null_mut();
+    }
+    let seq = if len == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(seq, len)
+    };
+    match catch_unwind(|| SacaIndex::new(seq, k, threads, entry_bytes)) {
+        Ok(Some(index)) => Box::into_raw(Box::new(index)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Free an index returned by `saca_build`. Null is ignored.
+///
+/// # Safety
+/// `index` must be null or a live handle from `saca_build`.
+#[no_mangle]
+pub unsafe extern "C" fn saca_free(index: *mut SacaIndex) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
+
+/// Number of entries, one per base.
+///
+/// # Safety
+/// `index` must be a live handle from `saca_build`.
+#[no_mangle]
+pub unsafe extern "C" fn saca_len(index: *const SacaIndex) -> usize {
+    (*index).sa.len()
+}
+
+/// Width in bytes of the entries at `saca_get_ptr`.
+///
+/// # Safety
+/// `index` must be a live handle from `saca_build`.
+#[no_mangle]
+pub unsafe extern "C" fn saca_entry_bytes(index: *const SacaIndex) -> usize {
+    (*index).sa.entry_bytes()
+}
+
+/// The `saca_len` entries, `saca_entry_bytes` little-endian bytes each, valid until
+/// the index is freed.
+///
+/// # Safety
+/// `index` must be a live handle from `saca_build`.
+#[no_mangle]
+pub unsafe extern "C" fn saca_get_ptr(index: *const SacaIndex) -> *const u8 {
+    (*index).sa.as_bytes().as_ptr()
+}
+
+/// Find the entries `[*start, *end)` whose suffixes start with the `len` bytes at
+/// `pattern`, and return their number. Patterns longer than 124 bases cannot be
+/// searched and return `SIZE_MAX`.
+///
+/// # Safety
+/// `index` must be a live handle from `saca_build`, `pattern` must point to `len`
+/// readable bytes, and `start` and `end` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn saca_query_range(
+    index: *const SacaIndex,
+    pattern: *const u8,
+    len: usize,
+    start: *mut usize,
+    end: *mut usize,
+) -> usize {
+    let index = &*index;
+    if len > CTX || (pattern.is_null() && len > 0) {
+        return usize::MAX;
+    }
+    let pattern = if len == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(pattern, len)
+    };
+    match catch_unwind(AssertUnwindSafe(|| index.sa.range(&index.text, pattern))) {
+        Ok(range) => {
+            *start = range.start;
+            *end = range.end;
+            range.len()
+        }
+        Err(_) => usize::MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_ffi() {
+        let seq = b"ACGTTGCAGATTACAGGATCCA".repeat(20);
+        let expected = SuffixArray::<4>::new_packed::<CTX>(&seq, 3, 2);
+        for (entry_bytes, width) in [(0, 4), (5, 5), (8, 8)] {
+            unsafe {
+                let index = saca_build(seq.as_ptr(), seq.len(), 3, 2, entry_bytes);
+                assert!(!index.is_null());
+                assert_eq!(saca_len(index), seq.len());
+                assert_eq!(saca_entry_bytes(index), width);
+                let raw = std::slice::from_raw_parts(saca_get_ptr(index), seq.len() * width);
+                let entries = raw
+                    .chunks_exact(width)
+                    .map(|e| {
+                        let mut b = [0; 8];
+                        b[..width].copy_from_slice(e);
+                        u64::from_le_bytes(b) as usize
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(entries, expected.idxs().to_usize_vec());
+
+                let (mut start, mut end) = (0, 0);
+                let n = saca_query_range(index, b"GATTA".as_ptr(), 5, &mut start, &mut end);
+                assert_eq!(n, 20);
+                assert_eq!(start..end, expected.range(&seq, b"GATTA"));
+                let long = [b'A'; CTX + 1];
+                assert_eq!(
+                    saca_query_range(index, long.as_ptr(), long.len(), &mut start, &mut end),
+                    usize::MAX
+                );
+                saca_free(index);
+            }
+        }
+        unsafe {
+            assert!(saca_build(seq.as_ptr(), seq.len(), 3, 2, 7).is_null());
+            assert!(saca_build(ptr::null(), 5, 3, 2, 0).is_null());
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use md5::Md5;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+
+use crate::suffix_array::*;
+
+/// Bytes of text hashed per leaf of the hash tree.
+const LEAF: usize = 1 << 20;
+
+/// SHA-256 of `data`.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// MD5 of `data`, as used for the `M5` tag of sequence dictionaries. Not for integrity
+/// checks, use `sha256` for those.
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    Md5::digest(data).into()
+}
+
+/// Hash of an indexed text together with the parameters the index was built with.
+///
+/// The text is hashed as a tree of SHA-256 digests of 1 MiB leaves, so that large
+/// genomes are hashed in parallel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    pub fn new(bytes: &[u8], k: usize, ctx: usize, entry_bytes: usize, canonical: bool) -> Self {
+        let mut root = bytes
+            .par_chunks(LEAF)
+            .flat_map_iter(sha256)
+            .collect::<Vec<_>>();
+        for v in [bytes.len(), k, ctx, entry_bytes, canonical as usize] {
+            root.extend_from_slice(&(v as u64).to_le_bytes());
+        }
+        Self(sha256(&root))
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Fingerprint of `bytes` with the build parameters of this index.
+    pub fn fingerprint_of(&self, bytes: &[u8]) -> Fingerprint {
+        Fingerprint::new(bytes, self.k(), self.ctx(), BYTES, self.is_canonical())
+    }
+
+    /// Record that this index was built on `bytes`.
+    pub fn record_fingerprint(&mut self, bytes: &[u8]) {
+        let fingerprint = self.fingerprint_of(bytes);
+        self.set_fingerprint(Some(fingerprint));
+    }
+
+    /// Whether this index was built on `bytes` with its current parameters. An index
+    /// without a recorded fingerprint matches no text.
+    pub fn matches_text(&self, bytes: &[u8]) -> bool {
+        self.fingerprint()
+            .is_some_and(|f| f == self.fingerprint_of(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let hex = |d: [u8; 32]| Fingerprint(d).to_string();
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(sha256(&[b'a'; 56])),
+            "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a"
+        );
+
+        let hex = |d: [u8; 16]| d.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex(md5(&b"ACGT".repeat(20))),
+            "ad01ff07883bde3965f91f71e009c5b0"
+        );
+
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACA".repeat(10);
+        let mut b = pad_text(&seq, CTX);
+        let mut s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 1);
+        assert!(!s.matches_text(&b));
+        s.record_fingerprint(&b);
+        assert!(s.matches_text(&b));
+        b[7] = b'C';
+        assert!(!s.matches_text(&b));
+    }
+}
@@ -0,0 +1,462 @@
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::packed_text::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACAFMI";
+/// Words between occurrence checkpoints.
+const OCC_WORDS: usize = 4;
+const LO_BITS: u64 = 0x5555_5555_5555_5555;
+
+/// Occurrences of `c` among the first `len` bases of `word`.
+#[inline(always)]
+fn count_in_word(word: u64, c: u8, len: usize) -> usize {
+    let x = word ^ (LO_BITS * c as u64);
+    let mut m = !(x | (x >> 1)) & LO_BITS;
+    if len < WORD {
+        m &= (1 << (2 * len)) - 1;
+    }
+    m.count_ones() as usize
+}
+
+fn words_to_bytes(words: &[u64]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+fn bytes_to_words(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks_exact(8)
+        .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+        .collect()
+}
+
+/// A self-contained FM-index of the padded text `T` with a sentinel `$` appended:
+/// the 2-bit packed BWT, every `sample_rate`-th suffix array and inverse suffix array
+/// entry, and optionally the packed text. Counting, locating and extracting text work
+/// without the original sequence.
+pub struct FmIndex<const BYTES: usize> {
+    bwt: PackedText,
+    occ: Vec<[usize; 4]>,
+    c: [usize; 4],
+    /// BWT position of the sentinel, stored as `A` in `bwt`.
+    dollar: usize,
+    /// Length of `T$`.
+    len: usize,
+    /// Length of `T` without the padding.
+    text_len: usize,
+    sample_rate: usize,
+    /// BWT positions whose suffix array entry is sampled.
+    marked: Vec<u64>,
+    marked_rank: Vec<usize>,
+    sa_samples: CompactVec<BYTES>,
+    isa_samples: CompactVec<BYTES>,
+    text: Option<PackedText>,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Build an FM-index over `bytes`, which must be padded with `A`. Ties over the
+    /// sorted context are resolved first, so the array is consumed.
+    pub fn into_fm_index(
+        mut self,
+        bytes: &[u8],
+        sample_rate: usize,
+        keep_text: bool,
+    ) -> FmIndex<BYTES> {
+        let ctx = self.ctx();
+        assert!(bytes[bytes.len() - ctx..]
+            .iter()
+            .all(|&b| base_code(b) == 0));
+        self.resolve_ties(bytes);
+
+        // The sentinel sorts first, then the padding suffixes `A^j$` by increasing `j`
+        // since every suffix of the text proper is longer than the padding.
+        let n = bytes.len();
+        let idxs = self.idxs();
+        let sa = |r: usize| {
+            if r <= ctx {
+                n - r
+            } else {
+                idxs[r - ctx - 1].get_usize()
+            }
+        };
+        let len = n + 1;
+
+        let dollar = ctx + 1 + idxs.iter().position(|i| i.get_usize() == 0).unwrap();
+        let bwt = PackedText::from_fn(len, |r| {
+            let p = sa(r);
+            if p == 0 {
+                0
+            } else {
+                base_code(bytes[p - 1])
+            }
+        });
+
+        let marked = (0..len.div_ceil(64))
+            .into_par_iter()
+            .map(|w| {
+                (w * 64..((w + 1) * 64).min(len))
+                    .filter(|&r| sa(r) % sample_rate == 0)
+                    .fold(0u64, |word, r| word | 1 << (r % 64))
+            })
+            .collect::<Vec<_>>();
+        let marked_rank = rank_words(&marked);
+
+        let mut sa_samples = CompactVec::<BYTES>::new(*marked_rank.last().unwrap());
+        let mut isa_samples = CompactVec::<BYTES>::new(len.div_ceil(sample_rate));
+        let sa_ptr = MutPtr(sa_samples.as_mut_ptr());
+        let isa_ptr = MutPtr(isa_samples.as_mut_ptr());
+        (0..len).into_par_iter().for_each(|r| {
+            let (sa_ptr, isa_ptr) = (sa_ptr, isa_ptr);
+            let p = sa(r);
+            if p % sample_rate == 0 {
+                let j = marked_rank[r / 64]
+                    + (marked[r / 64] & ((1 << (r % 64)) - 1)).count_ones() as usize;
+                unsafe {
+                    (*sa_ptr.0.add(j)).set_usize(p);
+                    (*isa_ptr.0.add(p / sample_rate)).set_usize(r);
+                }
+            }
+        });
+
+        let text = keep_text.then(|| PackedText::new(&bytes[..n - ctx]));
+
+        FmIndex::from_parts(
+            bwt,
+            dollar,
+            len,
+            n - ctx,
+            sample_rate,
+            marked,
+            sa_samples,
+            isa_samples,
+            text,
+        )
+    }
+}
+
+/// Number of set bits before each word, plus the total.
+fn rank_words(words: &[u64]) -> Vec<usize> {
+    let mut res = Vec::with_capacity(words.len() + 1);
+    let mut sum = 0;
+    for w in words {
+        res.push(sum);
+        sum += w.count_ones() as usize;
+    }
+    res.push(sum);
+    res
+}
+
+impl<const BYTES: usize> FmIndex<BYTES> {
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        bwt: PackedText,
+        dollar: usize,
+        len: usize,
+        text_len: usize,
+        sample_rate: usize,
+        marked: Vec<u64>,
+        sa_samples: CompactVec<BYTES>,
+        isa_samples: CompactVec<BYTES>,
+        text: Option<PackedText>,
+    ) -> Self {
+        let mut occ = Vec::with_capacity(bwt.words().len() / OCC_WORDS + 1);
+        let mut counts = [0; 4];
+        for (w, &word) in bwt.words().iter().enumerate() {
+            if w % OCC_WORDS == 0 {
+                occ.push(counts);
+            }
+            for (c, count) in counts.iter_mut().enumerate() {
+                *count += count_in_word(word, c as u8, (len - w * WORD).min(WORD));
+            }
+        }
+        occ.push(counts);
+
+        // `A` counts include the sentinel.
+        let c = [
+            1,
+            counts[0],
+            counts[0] + counts[1],
+            counts[0] + counts[1] + counts[2],
+        ];
+
+        Self {
+            bwt,
+            occ,
+            c,
+            dollar,
+            len,
+            text_len,
+            sample_rate,
+            marked_rank: rank_words(&marked),
+            marked,
+            sa_samples,
+            isa_samples,
+            text,
+        }
+    }
+
+    /// Length of the indexed text, without padding.
+    pub fn text_len(&self) -> usize {
+        self.text_len
+    }
+
+    /// The packed text, if it was kept.
+    pub fn text(&self) -> Option<&PackedText> {
+        self.text.as_ref()
+    }
+
+    /// Occurrences of the 2-bit base `c` in `bwt[..i]`, not counting the sentinel.
+    pub fn occ(&self, c: u8, i: usize) -> usize {
+        let block = i / (WORD * OCC_WORDS);
+        let mut r = self.occ[block][c as usize];
+        for w in block * OCC_WORDS..i / WORD {
+            r += count_in_word(self.bwt.words()[w], c, WORD);
+        }
+        if !i.is_multiple_of(WORD) {
+            r += count_in_word(self.bwt.words()[i / WORD], c, i % WORD);
+        }
+        if c == 0 && self.dollar < i {
+            r -= 1;
+        }
+        r
+    }
+
+    fn lf(&self, r: usize) -> usize {
+        let c = self.bwt.get(r);
+        self.c[c as usize] + self.occ(c, r)
+    }
+
+    fn is_marked(&self, r: usize) -> bool {
+        self.marked[r / 64] >> (r % 64) & 1 == 1
+    }
+
+    /// Text position of the suffix at BWT position `r`, found by walking back to the
+    /// nearest sampled suffix array entry.
+    pub fn sa(&self, mut r: usize) -> usize {
+        let mut steps = 0;
+        while !self.is_marked(r) {
+            r = self.lf(r);
+            steps += 1;
+        }
+        let j = self.marked_rank[r / 64]
+            + (self.marked[r / 64] & ((1 << (r % 64)) - 1)).count_ones() as usize;
+        self.sa_samples[j].get_usize() + steps
+    }
+
+    /// Range of BWT positions of suffixes starting with `pattern`, including hits that
+    /// run into the padding. Use `sa` to locate them.
+    pub fn backward_search(&self, pattern: &[u8]) -> Range<usize> {
+        let mut range = 0..self.len;
+        for &b in pattern.iter().rev() {
+            range = self.extend_left(range, b);
+            if range.is_empty() {
+                return 0..0;
+            }
+        }
+        range
+    }
+
+    /// One step of `backward_search`: the range of suffixes `b + P`, given the range of
+    /// suffixes starting with `P`. Empty for bases other than `ACGT`.
+    pub fn extend_left(&self, range: Range<usize>, b: u8) -> Range<usize> {
+        if !crate::alphabet::is_acgt(b) {
+            return 0..0;
+        }
+        let c = base_code(b);
+        self.c[c as usize] + self.occ(c, range.start)..self.c[c as usize] + self.occ(c, range.end)
+    }
+
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        let range = self.backward_search(pattern);
+        if range.is_empty() {
+            return 0;
+        }
+
+        // Subtract hits that run into or lie within the padding.
+        let tail_start = self.text_len.saturating_sub(pattern.len() - 1);
+        let mut tail = self.extract(tail_start..self.text_len);
+        tail.resize(self.len - 1 - tail_start, b'A');
+        let padded = tail
+            .windows(pattern.len())
+            .filter(|w| {
+                w.iter()
+                    .zip(pattern)
+                    .all(|(&a, &b)| base_code(a) == base_code(b))
+            })
+            .count();
+        range.len() - padded
+    }
+
+    /// Text positions of all occurrences of `pattern`, in suffix array order.
+    pub fn locate(&self, pattern: &[u8]) -> Vec<usize> {
+        self.backward_search(pattern)
+            .into_par_iter()
+            .map(|r| self.sa(r))
+            .filter(|&p| p + pattern.len() <= self.text_len)
+            .collect()
+    }
+
+    /// The bases of `range` of the text, as upper case `ACGT`.
+    pub fn extract(&self, range: Range<usize>) -> Vec<u8> {
+        assert!(range.end <= self.text_len);
+        if let Some(text) = &self.text {
+            return text.decode(range);
+        }
+
+        // Walk backwards from the first sampled position at or after the end.
+        let mut p = range.end.div_ceil(self.sample_rate) * self.sample_rate;
+        let mut r = if p >= self.len - 1 {
+            p = self.len - 1;
+            0
+        } else {
+            self.isa_samples[p / self.sample_rate].get_usize()
+        };
+
+        let mut res = vec![0u8; range.len()];
+        while p > range.start {
+            let c = self.bwt.get(r);
+            p -= 1;
+            if p < range.end {
+                res[p - range.start] = b"ACGT"[c as usize];
+            }
+            r = self.lf(r);
+        }
+        res
+    }
+
+    /// Bytes used by the index.
+    pub fn size_in_bytes(&self) -> usize {
+        (self.bwt.words().len()
+            + self.marked.len()
+            + self.text.as_ref().map_or(0, |t| t.words().len()))
+            * 8
+            + (self.occ.len() * 4 + self.marked_rank.len()) * std::mem::size_of::<usize>()
+            + (self.sa_samples.len() + self.isa_samples.len()) * BYTES
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let meta = [self.dollar, self.len, self.text_len, self.sample_rate]
+            .iter()
+            .flat_map(|&v| (v as u64).to_le_bytes())
+            .collect::<Vec<_>>();
+        let bwt = words_to_bytes(self.bwt.words());
+        let marked = words_to_bytes(&self.marked);
+        let text = self.text.as_ref().map(|t| words_to_bytes(t.words()));
+
+        let mut sections = vec![
+            ("meta", &meta[..]),
+            ("bwt", &bwt),
+            ("marked", &marked),
+            ("sa", self.sa_samples.as_bytes()),
+            ("isa", self.isa_samples.as_bytes()),
+        ];
+        if let Some(text) = &text {
+            sections.push(("text", text));
+        }
+        write_sections(path.as_ref(), MAGIC, BYTES, 0, 0, &sections)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let header = Header::read(&mut f, MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "index has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+
+        let meta = read_section(&mut f, &header, "meta")?;
+        let mut meta = &meta[..];
+        let mut next = || read_u64(&mut meta).map(|v| v as usize);
+        let (dollar, len, text_len, sample_rate) = (next()?, next()?, next()?, next()?);
+
+        let bwt =
+            PackedText::from_words(bytes_to_words(&read_section(&mut f, &header, "bwt")?), len);
+        let marked = bytes_to_words(&read_section(&mut f, &header, "marked")?);
+        let sa_samples = CompactVec::from_bytes(&read_section(&mut f, &header, "sa")?);
+        let isa_samples = CompactVec::from_bytes(&read_section(&mut f, &header, "isa")?);
+        let text = match header.section("text") {
+            Some(_) => Some(PackedText::from_words(
+                bytes_to_words(&read_section(&mut f, &header, "text")?),
+                text_len,
+            )),
+            None => None,
+        };
+
+        Ok(Self::from_parts(
+            bwt,
+            dollar,
+            len,
+            text_len,
+            sample_rate,
+            marked,
+            sa_samples,
+            isa_samples,
+            text,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_fm_index() {
+        const CTX: usize = 124;
+        let mut text = pseudo_dna(1000);
+        text.extend_from_within(200..600);
+        text.extend_from_slice(b"AAAAAAAA");
+        let b = pad_text(&text, CTX);
+
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+        let fm = sa.into_fm_index(&b, 8, false);
+        assert_eq!(fm.text_len(), text.len());
+        assert_eq!(fm.extract(0..text.len()), text);
+        assert_eq!(fm.extract(395..1403), &text[395..1403]);
+
+        let with_text = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1).into_fm_index(&b, 8, true);
+        assert_eq!(with_text.extract(395..1403), &text[395..1403]);
+        assert_eq!(
+            with_text.text().unwrap().decode_revcomp(0..20),
+            crate::alphabet::revcomp(&text[..20])
+        );
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.fm", std::process::id()));
+        fm.write(&path).unwrap();
+        let fm = FmIndex::<5>::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for pattern in [&b"ACG"[..], &text[300..340], b"AAAAA", &text[1390..1408]] {
+            let mut naive = (0..=text.len() - pattern.len())
+                .filter(|&i| &text[i..i + pattern.len()] == pattern)
+                .collect::<Vec<_>>();
+            let mut hits = fm.locate(pattern);
+            hits.sort();
+            naive.sort();
+            assert_eq!(hits, naive);
+            assert_eq!(fm.count(pattern), naive.len());
+
+            let range = fm.backward_search(pattern);
+            let mut located = range.clone().map(|r| fm.sa(r)).collect::<Vec<_>>();
+            located.sort();
+            located.retain(|&p| p + pattern.len() <= text.len());
+            assert_eq!(located, naive);
+            let last = fm.backward_search(&pattern[1..]);
+            assert_eq!(fm.extend_left(last, pattern[0]), range);
+        }
+        assert_eq!(
+            fm.occ(3, fm.len),
+            text.iter().filter(|&&c| c == b'T').count()
+        );
+    }
+}
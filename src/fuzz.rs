@@ -0,0 +1,177 @@
+//! Entry points of the fuzz targets in `fuzz/`. Each takes arbitrary bytes and panics
+//! only on a bug, so they also serve to check the crate against untrusted input.
+
+use std::io::Cursor;
+
+use crate::bundle::*;
+use crate::fai::*;
+use crate::manifest::*;
+use crate::seq_dict::*;
+use crate::simd::*;
+use crate::stream::*;
+use crate::suffix_array::*;
+
+/// Longest text the fuzz entry points build suffix arrays of.
+const MAX_TEXT: usize = 1 << 12;
+
+/// A DNA text with one base per byte of `data`.
+fn dna(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .take(MAX_TEXT)
+        .map(|&b| b"ACGT"[b as usize & 3])
+        .collect()
+}
+
+/// Compare the comparison kernels against plain loops. The first four bytes pick two
+/// suffixes and the rest are the text, as bases.
+pub fn fuzz_kernels(data: &[u8]) {
+    const CTX: usize = 248;
+    let Some((pick, rest)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let text = dna(rest);
+    if text.is_empty() {
+        return;
+    }
+    let a = u16::from_le_bytes([pick[0], pick[1]]) as usize % text.len();
+    let b = u16::from_le_bytes([pick[2], pick[3]]) as usize % text.len();
+    let padded = pad_text(&text, CTX);
+    let packed = RevPacked::with_padding(&text, CTX);
+
+    let naive_lcp = |max: usize| {
+        (0..max)
+            .take_while(|&i| {
+                padded
+                    .get(a + i)
+                    .is_some_and(|x| Some(x) == padded.get(b + i))
+            })
+            .count()
+    };
+    let naive_cmp = |ctx: usize, ties: TieOrder| {
+        let ord = padded[a..a + ctx].cmp(&padded[b..b + ctx]);
+        ord.then(match ties {
+            TieOrder::Ascending => a.cmp(&b),
+            TieOrder::Descending => b.cmp(&a),
+        })
+    };
+
+    for ties in [TieOrder::Ascending, TieOrder::Descending] {
+        let ord = unsafe { simd_cmp_packed::<124>(&packed, a, b, ties) };
+        assert_eq!(
+            ord,
+            naive_cmp(124, ties),
+            "124-base comparison of {a} and {b}"
+        );
+        let ord = unsafe { simd_cmp_packed::<248>(&packed, a, b, ties) };
+        assert_eq!(
+            ord,
+            naive_cmp(248, ties),
+            "248-base comparison of {a} and {b}"
+        );
+    }
+    for max in [1, 124, CTX] {
+        let lcp = unsafe { packed.lcp(a, b, max) };
+        assert_eq!(lcp, naive_lcp(max), "LCP of {a} and {b} up to {max}");
+    }
+    assert_eq!(
+        lcp_to_end(&packed, padded.len(), a, b),
+        naive_lcp(padded.len())
+    );
+    assert_eq!(
+        common_prefix(&padded[a..], &padded[b..]),
+        naive_lcp(padded.len())
+    );
+}
+
+/// Parse `data` as a bundle and a suffix array file, and decode it as each of the
+/// sections of a bundle, which must either fail or encode back to the same value. Then
+/// write and read back a bundle of `data` as bases.
+pub fn fuzz_formats(data: &[u8]) {
+    let _ = Bundle::<5>::read_from(&mut Cursor::new(data));
+    let _ = SuffixArray::<5>::read_from(&mut Cursor::new(data));
+    if let Ok(dict) = decode_dict(data) {
+        assert_eq!(decode_dict(&encode_dict(&dict)).unwrap(), dict);
+    }
+    if let Ok(fai) = decode_fai(data) {
+        assert_eq!(decode_fai(&encode_fai(&fai)).unwrap(), fai);
+    }
+    if let Ok(alphabet) = decode_alphabet(data) {
+        assert_eq!(
+            decode_alphabet(&encode_alphabet(&alphabet)).unwrap(),
+            alphabet
+        );
+    }
+    if let Ok(manifest) = Manifest::decode(data) {
+        assert_eq!(Manifest::decode(&manifest.encode()).unwrap(), manifest);
+    }
+
+    const CTX: usize = 124;
+    let seq = dna(data);
+    if seq.is_empty() {
+        return;
+    }
+    let mut dict = SeqDict::new();
+    dict.push("fuzz", seq.len());
+    dict.pad(CTX);
+    let sa = SuffixArray::<5>::new_packed_ordered::<CTX>(&seq, 2, 1, TieOrder::Ascending);
+    let bundle = Bundle::new(sa, pad_text(&seq, CTX), &dict);
+    let mut file = Cursor::new(Vec::new());
+    bundle.write_into(&mut file).unwrap();
+    file.set_position(0);
+    let read = Bundle::<5>::read_from(&mut file).unwrap();
+    assert_eq!(read.suffix_array().idxs(), bundle.suffix_array().idxs());
+    assert_eq!(read.text(), bundle.text());
+    assert_eq!(read.dict(), bundle.dict());
+    assert_eq!(read.docs().docs(), bundle.docs().docs());
+}
+
+/// Parse `data` as FASTA in one piece and byte by byte, which must agree, and index it
+/// the way `samtools faidx` does, which may fail but not panic.
+pub fn fuzz_fasta(data: &[u8]) {
+    let whole = read_fasta_stream(Cursor::new(data.to_vec()), data.len().max(1));
+    let bytewise = read_fasta_stream(Cursor::new(data.to_vec()), 1);
+    assert_eq!(whole.is_ok(), bytewise.is_ok());
+    if let (Ok(whole), Ok(bytewise)) = (whole, bytewise) {
+        assert_eq!(whole.dict, bytewise.dict);
+        assert_eq!(whole.packed, bytewise.packed);
+        assert_eq!(whole.dict.total_len(), whole.packed.len());
+    }
+
+    if let Ok(records) = index_fasta(data) {
+        assert!(records.iter().all(|r| r.offset <= data.len() as u64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzz_entry_points() {
+        let mut inputs = vec![
+            Vec::new(),
+            b">a\nACGT\n>b x\nNNacgu\n\n>c\n".to_vec(),
+            b"GATTACA\n>a\nAC\n".to_vec(),
+            b"ACGTTGCAGATTACA".repeat(30),
+        ];
+        // Arbitrary bytes from a fixed xorshift sequence.
+        let mut x = 0x9e3779b97f4a7c15u64;
+        for len in [3, 50, 300, 2000] {
+            inputs.push(
+                (0..len)
+                    .map(|_| {
+                        x ^= x << 13;
+                        x ^= x >> 7;
+                        x ^= x << 17;
+                        x as u8
+                    })
+                    .collect(),
+            );
+        }
+        for data in &inputs {
+            fuzz_kernels(data);
+            fuzz_formats(data);
+            fuzz_fasta(data);
+        }
+    }
+}
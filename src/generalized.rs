@@ -0,0 +1,349 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use crate::perf::*;
+use crate::seq_dict::*;
+use crate::stages::*;
+use crate::suffix_array::*;
+
+/// Where the end-of-record sentinel sorts relative to the bases.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SentinelOrder {
+    /// Smaller than `A`, as in most BWT tools.
+    #[default]
+    Smaller,
+    /// Larger than `T`.
+    Larger,
+}
+
+impl FromStr for SentinelOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smaller" => Ok(Self::Smaller),
+            "larger" => Ok(Self::Larger),
+            _ => Err(format!(
+                "unknown sentinel order {s}, expected smaller or larger"
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SentinelKind {
+    /// Record `i` ends in its own sentinel `$i`, with `$i < $j` for `i < j`.
+    #[default]
+    Distinct,
+    /// All records end in the same sentinel, and suffixes continue into the next record.
+    Shared,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sentinels {
+    pub order: SentinelOrder,
+    pub kind: SentinelKind,
+}
+
+/// The record of every position of the text of `dict`, or `DocArray::NONE` in gaps, so
+/// that the end of the record of a suffix is found without a search.
+fn record_array(dict: &SeqDict) -> Vec<SeqId> {
+    assert!(dict.len() < DocArray::NONE as usize);
+    let mut records = vec![DocArray::NONE; dict.total_len()];
+    let mut slices = Vec::with_capacity(dict.len());
+    let mut rest = &mut records[..];
+    let mut offset = 0;
+    for id in 0..dict.len() {
+        let (_, tail) = std::mem::take(&mut rest).split_at_mut(dict.start(id) - offset);
+        let (slice, tail) = tail.split_at_mut(dict.seq_len(id));
+        slices.push((id as SeqId, slice));
+        rest = tail;
+        offset = dict.end(id);
+    }
+    slices
+        .into_par_iter()
+        .for_each(|(id, slice)| slice.fill(id));
+    records
+}
+
+/// Compare the suffixes at `a` and `b` as if every record of `dict` was followed by a
+/// sentinel, up to `ctx` characters. Ties are broken by index.
+fn cmp_generalized(
+    packed: &RevPacked,
+    dict: &SeqDict,
+    records: &[SeqId],
+    sentinels: Sentinels,
+    ctx: usize,
+    a: usize,
+    b: usize,
+) -> Ordering {
+    let (mut id_a, mut a_i) = (records[a] as usize, a);
+    let (mut id_b, mut b_i) = (records[b] as usize, b);
+    let mut budget = ctx;
+
+    loop {
+        let rem_a = dict.end(id_a) - a_i;
+        let rem_b = dict.end(id_b) - b_i;
+        let max = rem_a.min(rem_b).min(budget);
+        let l = unsafe { packed.lcp(a_i, b_i, max) };
+        if l < max {
+            return packed.get(a_i + l).cmp(&packed.get(b_i + l));
+        }
+        if l == budget {
+            return a.cmp(&b);
+        }
+
+        // At least one of the suffixes reached its sentinel.
+        if rem_a != rem_b {
+            let ord = if rem_a < rem_b {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+            return match sentinels.order {
+                SentinelOrder::Smaller => ord,
+                SentinelOrder::Larger => ord.reverse(),
+            };
+        }
+        match sentinels.kind {
+            SentinelKind::Distinct => return id_a.cmp(&id_b).then(a.cmp(&b)),
+            SentinelKind::Shared => {
+                budget -= l + 1;
+                // The end of the text sorts before any further record.
+                match (id_a + 1 < dict.len(), id_b + 1 < dict.len()) {
+                    (true, true) if budget > 0 => {}
+                    (true, false) => return Ordering::Greater,
+                    (false, true) => return Ordering::Less,
+                    _ => return a.cmp(&b),
+                }
+                id_a += 1;
+                id_b += 1;
+                a_i = dict.start(id_a);
+                b_i = dict.start(id_b);
+            }
+        }
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Suffix array of the records of `dict` with a sentinel after each record, sorted
+    /// on up to `CTX` characters. Only positions inside records are included; gaps and
+    /// sentinels themselves get no entry. Like for `new_packed`, the packed text is
+    /// padded internally.
+    pub fn new_generalized<const CTX: usize>(
+        seq: &[u8],
+        dict: &SeqDict,
+        k: usize,
+        sentinels: Sentinels,
+    ) -> Self {
+        assert!(k <= 16);
+        assert!(dict.total_len() <= seq.len());
+
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+
+        let phase = Phase::start("Record of every position");
+        let records = record_array(dict);
+        phase.finish();
+
+        // Suffixes closer than `k` to their sentinel are bucketed as if the sentinel and
+        // everything after were `A` (or `T`), which keeps the buckets in order. Gaps
+        // between records go to one more bucket at the end, which is dropped.
+        let filler = match sentinels.order {
+            SentinelOrder::Smaller => 0,
+            SentinelOrder::Larger => u32::MAX,
+        };
+        let gaps = 1 << (2 * k);
+        let key = |p: usize| {
+            let id = records[p];
+            if id == DocArray::NONE {
+                return gaps;
+            }
+            let kmer = unsafe { packed.load_k(p, k) };
+            let rem = dict.end(id as usize) - p;
+            if rem >= k {
+                kmer as usize
+            } else {
+                let shift = 2 * (k - rem);
+                let mask = (1u32 << shift) - 1;
+                ((kmer & !mask) | (filler & mask)) as usize
+            }
+        };
+        let chunks = bucket_chunks(records.len(), Schedule::default());
+        let mut counts = count_keys::<BYTES>(&chunks, gaps + 1, key);
+        let mut bucket_ends = prefix_sum(&mut counts);
+        let mut sorted = scatter_keys(&chunks, &counts, &bucket_ends, key);
+        drop(counts);
+        bucket_ends.truncate(gaps);
+        sorted.truncate(bucket_ends.last().unwrap().get_usize());
+
+        sort_buckets(&mut sorted, &bucket_ends, Schedule::default(), |slice| {
+            slice.sort_unstable_by(|a, b| {
+                let (a, b) = (a.get_usize(), b.get_usize());
+                cmp_generalized(&packed, dict, &records, sentinels, CTX, a, b)
+            })
+        });
+
+        Self::from_parts(sorted, bucket_ends, k, CTX, false)
+    }
+
+    /// `new_generalized` with the context length chosen at runtime.
+    pub fn new_generalized_with_ctx(
+        seq: &[u8],
+        dict: &SeqDict,
+        ctx: usize,
+        k: usize,
+        sentinels: Sentinels,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_generalized::<124>(seq, dict, k, sentinels),
+            248 => Self::new_generalized::<248>(seq, dict, k, sentinels),
+            496 => Self::new_generalized::<496>(seq, dict, k, sentinels),
+            992 => Self::new_generalized::<992>(seq, dict, k, sentinels),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+}
+
+/// A generalized suffix array over several sequences, with their concatenated (and
+/// padded) text and dictionary. Suffixes end at a sentinel after their sequence.
+pub struct GeneralizedSuffixArray<const BYTES: usize> {
+    sa: SuffixArray<BYTES>,
+    text: Vec<u8>,
+    dict: SeqDict,
+}
+
+impl<const BYTES: usize> GeneralizedSuffixArray<BYTES> {
+    /// Index `seqs`, named by their index, with a distinct sentinel per sequence.
+    pub fn new(seqs: &[&[u8]], ctx: usize, k: usize) -> Self {
+        Self::with_sentinels(seqs, ctx, k, Sentinels::default())
+    }
+
+    pub fn with_sentinels(seqs: &[&[u8]], ctx: usize, k: usize, sentinels: Sentinels) -> Self {
+        let mut text = Vec::with_capacity(seqs.iter().map(|s| s.len()).sum::<usize>() + ctx);
+        let mut dict = SeqDict::new();
+        for (id, seq) in seqs.iter().enumerate() {
+            text.extend_from_slice(seq);
+            dict.push(id.to_string(), seq.len());
+        }
+        let sa = SuffixArray::new_generalized_with_ctx(&text, &dict, ctx, k, sentinels);
+        text.resize(text.len() + ctx, b'A');
+        dict.pad(ctx);
+        Self { sa, text, dict }
+    }
+
+    pub fn suffix_array(&self) -> &SuffixArray<BYTES> {
+        &self.sa
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    pub fn dict(&self) -> &SeqDict {
+        &self.dict
+    }
+
+    pub fn len(&self) -> usize {
+        self.sa.idxs().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sequence index and offset within it of suffix array entry `i`.
+    pub fn entry(&self, i: usize) -> (usize, usize) {
+        self.dict.locate(self.sa.idxs()[i].get_usize()).unwrap()
+    }
+
+    /// The sequence index of every entry, in suffix array order.
+    pub fn doc_array(&self) -> DocArray {
+        DocArray::new(&self.sa, &self.dict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::*;
+
+    /// Sort record positions by the text with explicit sentinel symbols.
+    fn naive(records: &[&[u8]], sentinels: Sentinels) -> Vec<usize> {
+        let mut text = Vec::<i64>::new();
+        let mut positions = Vec::new();
+        let mut pos = 0;
+        for (i, r) in records.iter().enumerate() {
+            for &b in r.iter() {
+                positions.push((text.len(), pos));
+                text.push(b"ACGT".iter().position(|&c| c == b).unwrap() as i64);
+                pos += 1;
+            }
+            let id = match sentinels.kind {
+                SentinelKind::Distinct => i as i64,
+                SentinelKind::Shared => 0,
+            };
+            text.push(match sentinels.order {
+                SentinelOrder::Smaller => id - 100,
+                SentinelOrder::Larger => id + 100,
+            });
+        }
+        positions.sort_by(|a, b| text[a.0..].cmp(&text[b.0..]));
+        positions.into_iter().map(|p| p.1).collect()
+    }
+
+    #[test]
+    fn test_generalized() {
+        const CTX: usize = 124;
+        let records: [&[u8]; 4] = [b"ACGTAC", b"AC", b"GTACGTTA", b"TAC"];
+        let b = records.concat();
+        let mut dict = SeqDict::new();
+        for (i, r) in records.iter().enumerate() {
+            dict.push(format!("r{i}"), r.len());
+        }
+
+        for order in [SentinelOrder::Smaller, SentinelOrder::Larger] {
+            for kind in [SentinelKind::Distinct, SentinelKind::Shared] {
+                let sentinels = Sentinels { order, kind };
+                let s = SuffixArray::<5>::new_generalized::<CTX>(&b, &dict, 3, sentinels);
+                assert_eq!(
+                    s.idxs().to_usize_vec(),
+                    naive(&records, sentinels),
+                    "{sentinels:?}"
+                );
+
+                let g = GeneralizedSuffixArray::<5>::with_sentinels(&records, CTX, 3, sentinels);
+                assert_eq!(g.suffix_array().idxs(), s.idxs());
+                let docs = g.doc_array();
+                for i in 0..g.len() {
+                    let (id, offset) = g.entry(i);
+                    assert_eq!(g.dict().start(id) + offset, s.idxs()[i].get_usize());
+                    assert_eq!(docs.docs()[i] as usize, id);
+                }
+            }
+        }
+
+        // Runs of non-ACGT bytes as one symbol larger than `T`.
+        let (mut b, mut breaks, mut dict) = (vec![], vec![], SeqDict::new());
+        for (name, r) in [("a", &b"ACGNNTACRGTA"[..]), ("b", b"TANACGT")] {
+            let start = b.len();
+            let removed = push_acgt(&mut b, &mut breaks, r, NonAcgt::Symbol).unwrap();
+            dict.push_with_ns(name, b.len() - start, removed);
+        }
+        let fragments = dict.split_at(&breaks);
+        assert_eq!(fragments.len(), 5);
+        let records = (0..fragments.len())
+            .map(|id| &b[fragments.start(id)..fragments.end(id)])
+            .collect::<Vec<_>>();
+        let sentinels = Sentinels {
+            order: SentinelOrder::Larger,
+            kind: SentinelKind::Shared,
+        };
+        let expected = naive(&records, sentinels);
+        let s = SuffixArray::<5>::new_generalized::<CTX>(&b, &fragments, 3, sentinels);
+        assert_eq!(s.idxs().to_usize_vec(), expected);
+    }
+}
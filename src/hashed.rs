@@ -0,0 +1,123 @@
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+use crate::compact_vec::*;
+use crate::stages::*;
+use crate::suffix_array::*;
+
+/// Widest hash prefix that seeds are bucketed on, `2^24` buckets.
+pub const MAX_KEY_BITS: usize = 24;
+
+/// Suffix array over a stream of `(hash, position)` seeds, e.g. minimizers, in text
+/// order. Unlike a `SuffixArray`, buckets are keyed by a hash prefix rather than a
+/// k-mer, and the context is counted in seeds rather than bases, so the two are kept
+/// apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashedSuffixArray<const BYTES: usize> {
+    idxs: CompactVec<BYTES>,
+    bucket_ends: CompactVec<BYTES>,
+    key_bits: usize,
+    seed_ctx: usize,
+}
+
+impl<const BYTES: usize> HashedSuffixArray<BYTES> {
+    /// Seeds are bucketed by the top `key_bits` bits of their hash and sorted by the
+    /// hashes of the next `CTX` seeds (a shorter context at the end sorts first), with
+    /// ties by seed index. Entries are the original positions of the seeds. The
+    /// counting and scattering are split over chunks like in `new_packed`.
+    pub fn new<const CTX: usize>(
+        seeds: &[(u64, usize)],
+        key_bits: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        assert!(
+            (1..=MAX_KEY_BITS).contains(&key_bits),
+            "key_bits must be in 1..={MAX_KEY_BITS}, not {key_bits}"
+        );
+        let n = seeds.len();
+        let key = |i: usize| (seeds[i].0 >> (64 - key_bits)) as usize;
+
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let chunks = bucket_chunks(n, &schedule);
+        let mut counts = count_keys(&chunks, 1 << key_bits, key);
+        let bucket_ends = prefix_sum(&mut counts);
+        let mut sorted = scatter_keys(&chunks, &counts, &bucket_ends, key);
+        drop(counts);
+
+        // Buckets are scattered in seed order and sorted stably, so ties stay in it.
+        let context = |i: usize| seeds[i..(i + CTX).min(n)].iter().map(|s| s.0);
+        sort_buckets_by(&mut sorted, &bucket_ends, schedule, |a, b| {
+            context(a).cmp(context(b))
+        });
+
+        sorted
+            .par_iter_mut()
+            .for_each(|e| e.set_usize(seeds[e.get_usize()].1));
+
+        Self {
+            idxs: sorted,
+            bucket_ends,
+            key_bits,
+            seed_ctx: CTX,
+        }
+    }
+
+    /// The seed positions, in sorted order.
+    pub fn idxs(&self) -> &CompactVec<BYTES> {
+        &self.idxs
+    }
+
+    pub fn len(&self) -> usize {
+        self.idxs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.idxs.is_empty()
+    }
+
+    /// The number of top hash bits that seeds are bucketed on.
+    pub fn key_bits(&self) -> usize {
+        self.key_bits
+    }
+
+    /// The number of seeds that suffixes are sorted on.
+    pub fn seed_ctx(&self) -> usize {
+        self.seed_ctx
+    }
+
+    /// The range of entries whose hash starts with the `key_bits` bits of `key`.
+    pub fn bucket_range(&self, key: usize) -> Range<usize> {
+        let start = if key == 0 {
+            0
+        } else {
+            self.bucket_ends[key - 1].get_usize()
+        };
+        start..self.bucket_ends[key].get_usize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashed() {
+        let hashes = [5u64, 3, 5, 3, 9, 5, 3, 1].map(|h| h << 60 | h);
+        let seeds = hashes
+            .iter()
+            .enumerate()
+            .map(|(i, &h)| (h, 10 * i + 7))
+            .collect::<Vec<_>>();
+        let s = HashedSuffixArray::<5>::new::<3>(&seeds, 2, 2);
+
+        let mut correct = (0..seeds.len()).collect::<Vec<_>>();
+        correct.sort_by_key(|&i| (&hashes[i..(i + 3).min(hashes.len())], i));
+        let correct = correct.iter().map(|&i| seeds[i].1).collect::<Vec<_>>();
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+        assert_eq!((s.key_bits(), s.seed_ctx()), (2, 3));
+        assert_eq!(s.bucket_range(0).len(), 4);
+        assert_eq!(s.bucket_range(1).len(), 3);
+    }
+}
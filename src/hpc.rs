@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::seq_dict::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACAHPC";
+
+/// Homopolymer-compressed text: every run of equal bases collapsed into a single base,
+/// with the raw position of every compressed base. Runs never cross sequence ends.
+pub struct Hpc<const BYTES: usize> {
+    pub seq: Vec<u8>,
+    pub dict: SeqDict,
+    /// Raw start of the run of every compressed base, followed by the raw length.
+    raw: CompactVec<BYTES>,
+}
+
+impl<const BYTES: usize> Hpc<BYTES> {
+    /// Compress the (unpadded) records of `seq` described by `dict`.
+    pub fn new(seq: &[u8], dict: &SeqDict) -> Self {
+        let mut hpc = Vec::new();
+        let mut starts = Vec::new();
+        let mut hpc_dict = SeqDict::new();
+        for id in 0..dict.len() {
+            let start = hpc.len();
+            for i in dict.start(id)..dict.end(id) {
+                if i == dict.start(id) || seq[i] != seq[i - 1] {
+                    hpc.push(seq[i]);
+                    starts.push(i);
+                }
+            }
+            hpc_dict.push_with_ns(dict.name(id), hpc.len() - start, dict.n_count(id));
+        }
+        starts.push(seq.len());
+
+        let mut raw = CompactVec::<BYTES>::new(starts.len());
+        raw.iter_mut().zip(starts).for_each(|(r, s)| r.set_usize(s));
+        Self {
+            seq: hpc,
+            dict: hpc_dict,
+            raw,
+        }
+    }
+
+    /// Raw position of the run of compressed position `pos`.
+    pub fn to_raw(&self, pos: usize) -> usize {
+        self.raw[pos].get_usize()
+    }
+
+    /// Length of the run of compressed position `pos`.
+    pub fn run_len(&self, pos: usize) -> usize {
+        self.raw[pos + 1].get_usize() - self.raw[pos].get_usize()
+    }
+
+    /// Raw range covered by the runs of the compressed `range`.
+    pub fn to_raw_range(&self, range: Range<usize>) -> Range<usize> {
+        if range.is_empty() {
+            return self.to_raw(range.start)..self.to_raw(range.start);
+        }
+        self.to_raw(range.start)..self.to_raw(range.end - 1) + self.run_len(range.end - 1)
+    }
+
+    /// Raw length over compressed length.
+    pub fn compression_ratio(&self) -> f64 {
+        self.raw[self.seq.len()].get_usize() as f64 / self.seq.len().max(1) as f64
+    }
+
+    /// Write the coordinate map and dictionary, to map positions of an index built on
+    /// the compressed text back to the raw text.
+    pub fn write_map(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let dict = encode_dict(&self.dict);
+        write_sections(
+            path.as_ref(),
+            MAGIC,
+            BYTES,
+            0,
+            0,
+            &[("raw", self.raw.as_bytes()), ("dict", &dict)],
+        )
+    }
+
+    /// Read a coordinate map written by `write_map`. The compressed text is not stored.
+    pub fn read_map(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let header = Header::read(&mut f, MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "coordinate map has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+        Ok(Self {
+            seq: Vec::new(),
+            dict: decode_dict(&read_section(&mut f, &header, "dict")?)?,
+            raw: CompactVec::from_bytes(&read_section(&mut f, &header, "raw")?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_hpc() {
+        const CTX: usize = 124;
+        let raw = b"AAACCGTTTTAACCCAAGT";
+        let mut dict = SeqDict::new();
+        dict.push("a", 10);
+        dict.push("b", 9);
+
+        let hpc = Hpc::<5>::new(raw, &dict);
+        assert_eq!(hpc.seq, b"ACGTACAGT");
+        assert_eq!((hpc.dict.seq_len(0), hpc.dict.seq_len(1)), (4, 5));
+        assert_eq!((hpc.to_raw(4), hpc.run_len(4)), (10, 2));
+        assert_eq!(hpc.to_raw_range(1..4), 3..10);
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.hpc", std::process::id()));
+        hpc.write_map(&path).unwrap();
+        let map = Hpc::<5>::read_map(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(map.to_raw_range(5..7), 12..17);
+
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&hpc.seq, 2, 1);
+        let hits = sa.idxs()[sa.range(&hpc.seq, b"CAG")]
+            .iter()
+            .map(|i| i.get_usize())
+            .collect::<Vec<_>>();
+        assert_eq!(hits, [5]);
+        assert_eq!(hpc.to_raw_range(5..8), 12..18);
+    }
+}
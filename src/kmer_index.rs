@@ -0,0 +1,203 @@
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::alphabet::*;
+use crate::bundle::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACAKMI";
+
+/// A table from every k-mer to its text positions, in a flat little-endian format that
+/// needs no knowledge of suffix arrays:
+///
+/// - the magic `SSACAKMI`,
+/// - `k` and the width of a position in bytes (4 or 8), as `u64`s,
+/// - `4^k + 1` offsets as `u64`s, where the positions of the k-mer with 2-bit code `x`
+///   (`A=0, C=1, G=2, T=3`, first base in the most significant bits) are entries
+///   `offsets[x]..offsets[x + 1]`,
+/// - the positions, in increasing order per k-mer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KmerIndex {
+    k: usize,
+    offsets: Vec<u64>,
+    positions: Vec<u64>,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// The k-mer position table of the padded text `bytes`, for a `k` of at most the
+    /// bucket length, from the bucket boundaries and the entries of each bucket. Only
+    /// k-mers that lie fully within the text are included.
+    pub fn kmer_index(&self, bytes: &[u8], k: usize) -> KmerIndex {
+        assert!(self.is_lexicographic());
+        assert!(!self.bucket_ends().is_empty());
+        assert!(
+            (1..=self.k()).contains(&k),
+            "k-mers of length {k} need buckets of at least that length, not {}",
+            self.k()
+        );
+        let n = bytes.len() - self.ctx();
+        // Buckets of longer k-mers sharing a prefix are adjacent, so every k-mer is a
+        // contiguous run of them.
+        let group = 1 << (2 * (self.k() - k));
+        let ranges = (0..1usize << (2 * k))
+            .map(|x| {
+                let start = self.bucket_range(x * group).start;
+                start..self.bucket_range((x + 1) * group - 1).end
+            })
+            .collect::<Vec<_>>();
+        let lists = ranges
+            .into_par_iter()
+            .map(|range| {
+                let mut positions = self.idxs()[range]
+                    .iter()
+                    .map(|i| i.get_usize())
+                    .filter(|&i| i + k <= n)
+                    .map(|i| i as u64)
+                    .collect::<Vec<_>>();
+                positions.sort_unstable();
+                positions
+            })
+            .collect::<Vec<_>>();
+
+        let mut offsets = Vec::with_capacity(lists.len() + 1);
+        offsets.push(0);
+        for list in &lists {
+            offsets.push(offsets.last().unwrap() + list.len() as u64);
+        }
+        KmerIndex {
+            k,
+            offsets,
+            positions: lists.concat(),
+        }
+    }
+}
+
+impl KmerIndex {
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Number of positions over all k-mers.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Positions of the k-mer with 2-bit code `kmer`, in increasing order.
+    pub fn positions(&self, kmer: usize) -> &[u64] {
+        &self.positions[self.offsets[kmer] as usize..self.offsets[kmer + 1] as usize]
+    }
+
+    /// Positions of the ASCII k-mer `kmer`, or none if it is not `k` bases of ACGT.
+    pub fn lookup(&self, kmer: &[u8]) -> &[u64] {
+        if kmer.len() != self.k || !kmer.iter().all(|&b| is_acgt(b)) {
+            return &[];
+        }
+        let code = kmer
+            .iter()
+            .fold(0, |x, &b| (x << 2) | base_code(b) as usize);
+        self.positions(code)
+    }
+
+    /// Width of a position in the file: 4 bytes if every position fits, 8 otherwise.
+    fn position_bytes(&self) -> usize {
+        if self.positions.iter().all(|&p| p <= u32::MAX as u64) {
+            4
+        } else {
+            8
+        }
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        let width = self.position_bytes();
+        w.write_all(MAGIC)?;
+        w.write_all(&(self.k as u64).to_le_bytes())?;
+        w.write_all(&(width as u64).to_le_bytes())?;
+        for &o in &self.offsets {
+            w.write_all(&o.to_le_bytes())?;
+        }
+        for &p in &self.positions {
+            w.write_all(&p.to_le_bytes()[..width])?;
+        }
+        w.flush()
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        if read_magic(&mut r)? != *MAGIC {
+            return Err(invalid_data("not a k-mer index"));
+        }
+        let k = read_u64(&mut r)? as usize;
+        if !(1..=16).contains(&k) {
+            return Err(invalid_data(format!("invalid k-mer length {k}")));
+        }
+        let width = read_u64(&mut r)? as usize;
+        if width != 4 && width != 8 {
+            return Err(invalid_data(format!("invalid position width {width}")));
+        }
+        let offsets = (0..(1usize << (2 * k)) + 1)
+            .map(|_| read_u64(&mut r))
+            .collect::<io::Result<Vec<_>>>()?;
+        if offsets[0] != 0 || offsets.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid_data("offsets are not increasing"));
+        }
+        let mut buf = vec![0u8; *offsets.last().unwrap() as usize * width];
+        r.read_exact(&mut buf)?;
+        let positions = buf
+            .chunks_exact(width)
+            .map(|c| {
+                let mut p = [0u8; 8];
+                p[..width].copy_from_slice(c);
+                u64::from_le_bytes(p)
+            })
+            .collect();
+        Ok(Self {
+            k,
+            offsets,
+            positions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmer_index() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACAGATTACACCG".repeat(3);
+        let b = pad_text(&seq, CTX);
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 4, 2);
+
+        for k in 1..=4 {
+            let index = sa.kmer_index(&b, k);
+            assert_eq!(index.len(), seq.len() - k + 1);
+            for x in 0..1 << (2 * k) {
+                let kmer = crate::kmers::decode_kmer(x, k);
+                let naive = (0..=seq.len() - k)
+                    .filter(|&i| seq[i..i + k] == kmer[..])
+                    .map(|i| i as u64)
+                    .collect::<Vec<_>>();
+                assert_eq!(index.positions(x), naive);
+                assert_eq!(index.lookup(&kmer), naive);
+            }
+        }
+
+        let index = sa.kmer_index(&b, 3);
+        assert_eq!(index.lookup(b"GAT"), [8, 15, 33, 40, 58, 65]);
+        assert!(index.lookup(b"GAN").is_empty());
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.kmi", std::process::id()));
+        index.write(&path).unwrap();
+        assert_eq!(KmerIndex::read(&path).unwrap(), index);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
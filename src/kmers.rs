@@ -0,0 +1,352 @@
+use rayon::prelude::*;
+
+use std::io::{self, Write};
+
+use crate::compact_vec::*;
+use crate::suffix_array::*;
+
+/// Number of largest buckets listed in the build report.
+pub(crate) const SKEW_TOP_N: usize = 5;
+/// Fraction of all suffixes in one bucket above which the bucketing is considered skewed.
+pub const SKEW_THRESHOLD: f64 = 0.01;
+/// Largest `k` the packed bucketing supports.
+const MAX_K: usize = 16;
+
+/// ASCII bases of a bucket k-mer, first base in the most significant bits.
+pub fn decode_kmer(kmer: usize, k: usize) -> Vec<u8> {
+    (0..k)
+        .map(|i| b"ACGT"[(kmer >> (2 * (k - 1 - i))) & 0b11])
+        .collect()
+}
+
+/// Reverse complement of a 2-bit packed k-mer, first base in the most significant bits.
+#[inline(always)]
+pub fn revcomp_kmer(kmer: u32, k: usize) -> u32 {
+    let mut x = !kmer;
+    x = ((x >> 2) & 0x3333_3333) | ((x & 0x3333_3333) << 2);
+    x = ((x >> 4) & 0x0f0f_0f0f) | ((x & 0x0f0f_0f0f) << 4);
+    x.swap_bytes() >> (32 - 2 * k)
+}
+
+#[inline(always)]
+pub fn canonical_kmer(kmer: u32, k: usize) -> u32 {
+    kmer.min(revcomp_kmer(kmer, k))
+}
+
+/// A bucket key of the bases at the care positions of a window of `span` bases, e.g.
+/// `1101101` takes 5 of 7 bases. Spreading the key out spreads the suffixes of
+/// low-complexity regions, whose contiguous k-mers collide, over more buckets.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpacedSeed {
+    /// `0b11` for every care base of a `load_k(_, span)` window, first base in the most
+    /// significant bits.
+    mask: u32,
+    span: usize,
+}
+
+impl SpacedSeed {
+    /// The contiguous seed of `k` bases, i.e. plain k-mer bucketing.
+    pub fn contiguous(k: usize) -> Self {
+        assert!(k > 0 && k <= MAX_K);
+        Self {
+            mask: u32::MAX >> (32 - 2 * k),
+            span: k,
+        }
+    }
+
+    /// Bases covered by the seed window.
+    pub fn span(&self) -> usize {
+        self.span
+    }
+
+    /// Number of care bases, i.e. the `k` of the buckets.
+    pub fn weight(&self) -> usize {
+        self.mask.count_ones() as usize / 2
+    }
+
+    pub fn is_contiguous(&self) -> bool {
+        self.weight() == self.span
+    }
+
+    /// Gather the care bases of a window of `span` bases into a key of `weight` bases.
+    #[inline(always)]
+    pub fn extract(&self, window: u32) -> u32 {
+        if self.is_contiguous() {
+            return window;
+        }
+        let mut key = 0;
+        let mut mask = self.mask;
+        while mask != 0 {
+            let shift = 31 - mask.leading_zeros() - 1;
+            key = key << 2 | (window >> shift) & 0b11;
+            mask &= !(0b11 << shift);
+        }
+        key
+    }
+
+    /// ASCII pattern of a key, with `-` at the don't-care positions.
+    pub fn decode(&self, key: usize) -> Vec<u8> {
+        let bases = decode_kmer(key, self.weight());
+        let mut bases = bases.into_iter();
+        (0..self.span)
+            .map(|i| {
+                if self.mask >> (2 * (self.span - 1 - i)) & 1 == 1 {
+                    bases.next().unwrap()
+                } else {
+                    b'-'
+                }
+            })
+            .collect()
+    }
+}
+
+impl std::str::FromStr for SpacedSeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > MAX_K {
+            return Err(format!("spaced seed {s} must span 1 to {MAX_K} bases"));
+        }
+        if !s.starts_with('1') || !s.ends_with('1') {
+            return Err(format!("spaced seed {s} must start and end with 1"));
+        }
+        let mut mask = 0;
+        for c in s.chars() {
+            mask = match c {
+                '1' => mask << 2 | 0b11,
+                '0' => mask << 2,
+                _ => {
+                    return Err(format!(
+                        "unknown character {c} in spaced seed {s}, expected 0 or 1"
+                    ))
+                }
+            };
+        }
+        Ok(Self {
+            mask,
+            span: s.len(),
+        })
+    }
+}
+
+impl std::fmt::Display for SpacedSeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in 0..self.span {
+            let care = self.mask >> (2 * (self.span - 1 - i)) & 1 == 1;
+            write!(f, "{}", if care { '1' } else { '0' })?;
+        }
+        Ok(())
+    }
+}
+
+/// The largest k-mer buckets, to judge how well a choice of `k` balances the sort.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BucketSkew {
+    pub k: usize,
+    /// `(kmer, size)` of the largest buckets, largest first.
+    pub top: Vec<(usize, usize)>,
+    pub total: usize,
+}
+
+impl BucketSkew {
+    pub fn new<const BYTES: usize>(
+        bucket_ends: &CompactVec<BYTES>,
+        k: usize,
+        top_n: usize,
+    ) -> Self {
+        let mut top = Vec::with_capacity(top_n + 1);
+        let mut prev = 0;
+        for (kmer, e) in bucket_ends.iter().enumerate() {
+            let size = e.get_usize() - prev;
+            prev = e.get_usize();
+            if top.len() < top_n || top.last().is_some_and(|&(_, s)| s < size) {
+                let i = top.partition_point(|&(_, s)| s >= size);
+                top.insert(i, (kmer, size));
+                top.truncate(top_n);
+            }
+        }
+        Self {
+            k,
+            top,
+            total: prev,
+        }
+    }
+
+    pub fn largest(&self) -> usize {
+        self.top.first().map_or(0, |&(_, s)| s)
+    }
+
+    /// Fraction of all suffixes in the largest bucket.
+    pub fn largest_fraction(&self) -> f64 {
+        self.largest() as f64 / self.total.max(1) as f64
+    }
+
+    /// Advice on how to reduce the skew, if the largest bucket exceeds `SKEW_THRESHOLD`.
+    pub fn recommendation(&self, canonical: bool) -> Option<String> {
+        let fraction = self.largest_fraction();
+        if fraction < SKEW_THRESHOLD {
+            return None;
+        }
+        let kmer = String::from_utf8_lossy(&decode_kmer(self.top[0].0, self.k)).into_owned();
+        let share = format!(
+            "Bucket {kmer} holds {:.1}% of all suffixes",
+            100.0 * fraction
+        );
+
+        Some(if self.k < MAX_K {
+            format!(
+                "{share}; a larger k (e.g. {}) splits it into up to {} buckets.",
+                (self.k + 2).min(MAX_K),
+                1 << (2 * ((self.k + 2).min(MAX_K) - self.k))
+            )
+        } else if canonical {
+            format!("{share}; canonical bucketing merges both strands of a k-mer, bucketing by forward k-mer halves repetitive buckets.")
+        } else {
+            format!("{share} at the largest k; consider masking the repeat.")
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KmerCountFormat {
+    /// One little-endian `u64` count per k-mer, in k-mer order.
+    Binary,
+    /// `kmer<TAB>count` lines for every k-mer that occurs, like `kmc_dump`.
+    Text,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Number of suffixes starting with `kmer`, taken from the bucket counts of construction.
+    pub fn kmer_count(&self, kmer: usize) -> usize {
+        self.bucket_range(kmer).len()
+    }
+
+    /// `(count, number of k-mers with that count)` pairs for all k-mers that occur,
+    /// sorted by count.
+    pub fn kmer_spectrum(&self) -> Vec<(usize, usize)> {
+        assert!(!self.bucket_ends().is_empty());
+        let mut counts = (0..self.bucket_ends().len())
+            .into_par_iter()
+            .map(|kmer| self.kmer_count(kmer))
+            .filter(|&c| c > 0)
+            .collect::<Vec<_>>();
+        counts.par_sort_unstable();
+
+        let mut res: Vec<(usize, usize)> = Vec::new();
+        for c in counts {
+            match res.last_mut() {
+                Some((last, n)) if *last == c => *n += 1,
+                _ => res.push((c, 1)),
+            }
+        }
+        res
+    }
+
+    pub fn write_kmer_spectrum(&self, mut w: impl Write) -> io::Result<()> {
+        for (count, n) in self.kmer_spectrum() {
+            writeln!(w, "{count}\t{n}")?;
+        }
+        Ok(())
+    }
+
+    pub fn write_kmer_counts(&self, mut w: impl Write, format: KmerCountFormat) -> io::Result<()> {
+        assert!(!self.bucket_ends().is_empty());
+
+        for kmer in 0..self.bucket_ends().len() {
+            let count = self.kmer_count(kmer);
+
+            match format {
+                KmerCountFormat::Binary => w.write_all(&(count as u64).to_le_bytes())?,
+                KmerCountFormat::Text if count > 0 => {
+                    w.write_all(&decode_kmer(kmer, self.k()))?;
+                    writeln!(w, "\t{count}")?;
+                }
+                KmerCountFormat::Text => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmer_spectrum() {
+        const CTX: usize = 124;
+        let s = SuffixArray::<5>::new_packed::<CTX>(b"ACGTACGTAC", 2, 1);
+        assert_eq!(s.kmer_count(0b00_01), 3);
+        // The last base is followed by the padding `A`s.
+        assert_eq!(s.kmer_count(0b01_00), 1);
+        assert_eq!(s.kmer_count(0b00_00), 0);
+        assert_eq!(s.kmer_spectrum(), [(1, 1), (2, 3), (3, 1)]);
+
+        let mut out = Vec::new();
+        s.write_kmer_counts(&mut out, KmerCountFormat::Text)
+            .unwrap();
+        assert_eq!(out, b"AC\t3\nCA\t1\nCG\t2\nGT\t2\nTA\t2\n");
+    }
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(revcomp_kmer(0b00_01_10, 3), 0b01_10_11);
+        assert_eq!(canonical_kmer(0b11_11, 2), 0b00_00);
+        assert_eq!(canonical_kmer(0b00_11, 2), 0b00_11);
+
+        const CTX: usize = 124;
+        let seq = b"AACGTTTGCA";
+        let b = pad_text(seq, CTX);
+        let s = SuffixArray::<5>::new_packed_canonical::<CTX>(seq, 2, 1);
+        assert!(s.is_canonical());
+        // AA, including the last A before the padding, + TT, TG + CA
+        assert_eq!(s.kmer_count(0b00_00), 4);
+        assert_eq!(s.kmer_count(0b01_00), 2);
+        assert_eq!(s.kmer_count(0b11_10), 0);
+        for (kmer, idxs) in s.buckets() {
+            let idxs = idxs.iter().map(|i| i.get_usize()).collect::<Vec<_>>();
+            let fwd = idxs
+                .iter()
+                .take_while(|&&i| b[i..i + 2] == decode_kmer(kmer as usize, 2))
+                .count();
+            assert!(idxs[fwd..].windows(2).all(|w| b[w[0]..] < b[w[1]..]));
+            assert!(idxs[..fwd].windows(2).all(|w| b[w[0]..] < b[w[1]..]));
+        }
+    }
+
+    #[test]
+    fn test_spaced_seed() {
+        let seed: SpacedSeed = "1101".parse().unwrap();
+        assert_eq!((seed.span(), seed.weight()), (4, 3));
+        assert_eq!(seed.to_string(), "1101");
+        assert_eq!(seed.extract(0b00_01_10_11), 0b00_01_11);
+        assert_eq!(seed.decode(0b00_01_11), b"AC-T");
+        assert!("0110".parse::<SpacedSeed>().is_err());
+        assert!("1x1".parse::<SpacedSeed>().is_err());
+        assert_eq!("111".parse::<SpacedSeed>(), Ok(SpacedSeed::contiguous(3)));
+
+        const CTX: usize = 124;
+        let seq = b"ACATAGACTTCAGAAC".repeat(3);
+        let b = pad_text(&seq, CTX);
+        let s = SuffixArray::<5>::new_packed_spaced::<CTX>(&seq, "101".parse().unwrap(), 1);
+        assert_eq!(s.k(), 2);
+        assert!(!s.is_lexicographic());
+        for (kmer, idxs) in s.buckets() {
+            let key = decode_kmer(kmer as usize, 2);
+            let idxs = idxs.iter().map(|i| i.get_usize()).collect::<Vec<_>>();
+            assert!(idxs.iter().all(|&i| [b[i], b[i + 2]] == key[..]));
+            assert!(idxs.windows(2).all(|w| b[w[0]..] < b[w[1]..]));
+        }
+        assert_eq!(s.idxs().len(), seq.len());
+    }
+
+    #[test]
+    fn test_bucket_skew() {
+        const CTX: usize = 124;
+        let s = SuffixArray::<5>::new_packed::<CTX>(b"AAAAAAAAAAAAAAAAAAAAACGTACGTTT", 2, 1);
+        let skew = BucketSkew::new(s.bucket_ends(), 2, 3);
+        assert_eq!(skew.top, [(0b00_00, 20), (0b00_01, 2), (0b01_10, 2)]);
+        assert_eq!(skew.total, 30);
+        assert!(skew.recommendation(false).unwrap().contains("larger k"));
+    }
+}
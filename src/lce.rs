@@ -0,0 +1,111 @@
+use rayon::prelude::*;
+
+use crate::compact_vec::*;
+use crate::suffix_array::*;
+
+/// Longest common extension of any two text positions in constant time: the minimum
+/// of the LCP array between their ranks, found with a sparse table.
+///
+/// Values are capped at the context length, up to which the suffixes are sorted, so
+/// that they fit in 16 bits: the table takes `2 n log n` bytes besides the inverse
+/// suffix array.
+pub struct LceIndex<const BYTES: usize> {
+    isa: CompactVec<BYTES>,
+    /// Level `l` holds the minimum of every `2^l` consecutive LCP entries.
+    table: Vec<Vec<u16>>,
+    cap: usize,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// `LceIndex::new` with the LCP array of `bytes` over the padding.
+    pub fn lce_index(&self, bytes: &[u8]) -> LceIndex<BYTES> {
+        LceIndex::new(self, &self.lcp_padded(bytes))
+    }
+}
+
+impl<const BYTES: usize> LceIndex<BYTES> {
+    /// Build from `sa` and its LCP array, see `SuffixArray::lcp_padded`.
+    pub fn new(sa: &SuffixArray<BYTES>, lcp: &CompactVec<BYTES>) -> Self {
+        assert_eq!(lcp.len(), sa.idxs().len());
+        let cap = sa.ctx();
+        assert!(cap <= u16::MAX as usize);
+        let mut table = vec![lcp
+            .par_iter()
+            .map(|l| l.get_usize().min(cap) as u16)
+            .collect::<Vec<_>>()];
+        let mut width = 1;
+        while 2 * width <= lcp.len() {
+            let prev = table.last().unwrap();
+            let level = (0..prev.len() - width)
+                .into_par_iter()
+                .map(|i| prev[i].min(prev[i + width]))
+                .collect();
+            table.push(level);
+            width *= 2;
+        }
+        Self {
+            isa: sa.inverse(),
+            table,
+            cap,
+        }
+    }
+
+    /// The largest value `lce` returns.
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Bytes used by the inverse suffix array and the sparse table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.isa.len() * BYTES + self.table.iter().map(|l| 2 * l.len()).sum::<usize>()
+    }
+
+    /// Length of the longest common prefix of the suffixes at text positions `i` and
+    /// `j`, up to `cap`.
+    pub fn lce(&self, i: usize, j: usize) -> usize {
+        let (ri, rj) = (self.isa[i].get_usize(), self.isa[j].get_usize());
+        if ri == rj {
+            return self.cap;
+        }
+        // The minimum of the LCPs of the adjacent entries strictly after the first rank
+        // up to and including the second.
+        let (lo, hi) = (ri.min(rj) + 1, ri.max(rj) + 1);
+        let level = (hi - lo).ilog2() as usize;
+        let t = &self.table[level];
+        t[lo].min(t[hi - (1 << level)]) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lce() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCAGATTACA".repeat(20);
+        seq.extend_from_slice(b"GGATCC");
+        seq.extend_from_slice(&b"ACGTTGCAGATTACA".repeat(3));
+        let n = seq.len();
+        let b = pad_text(&seq, CTX);
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        let lce = sa.lce_index(&b);
+        assert_eq!(lce.cap(), CTX);
+
+        let naive = |i: usize, j: usize| {
+            b[i..]
+                .iter()
+                .zip(&b[j..])
+                .take_while(|(x, y)| x == y)
+                .count()
+                .min(CTX)
+        };
+        for i in (0..n).step_by(7) {
+            for j in (0..n).step_by(5) {
+                assert_eq!(lce.lce(i, j), naive(i, j), "lce({i}, {j})");
+            }
+        }
+        assert_eq!(lce.lce(0, 15), CTX);
+        assert_eq!(lce.lce(3, 3), CTX);
+    }
+}
@@ -0,0 +1,150 @@
+use rayon::prelude::*;
+
+use crate::compact_vec::*;
+use crate::suffix_array::*;
+
+/// Text positions per parallel chunk of the Φ algorithm.
+const CHUNK: usize = 1 << 16;
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// The LCP array: entry `i > 0` is the length of the longest common prefix of the
+    /// suffixes at entries `i - 1` and `i`, up to the end of the text without its
+    /// padding, and entry 0 is 0.
+    ///
+    /// On an exact array (see `new_exact`) this is the exact LCP array, computed in
+    /// linear time with the Φ algorithm. Otherwise suffixes tied over the context are
+    /// only in position order, so values are capped at the context and every entry is
+    /// one SIMD comparison of its pair.
+    pub fn lcp(&self, bytes: &[u8]) -> CompactVec<BYTES> {
+        self.lcp_ending_at(bytes, bytes.len() - self.ctx())
+    }
+
+    /// Like `lcp`, but comparisons run on over the padding to the end of `bytes`, as
+    /// they do while sorting. Unlike `lcp`, these values agree with the order of the
+    /// array for the suffixes near the end of the text, which `Esa` and `LceIndex`
+    /// rely on.
+    pub fn lcp_padded(&self, bytes: &[u8]) -> CompactVec<BYTES> {
+        self.lcp_ending_at(bytes, bytes.len())
+    }
+
+    /// LCP array with comparisons stopping at `end`.
+    fn lcp_ending_at(&self, bytes: &[u8], end: usize) -> CompactVec<BYTES> {
+        assert!(self.is_lexicographic());
+        let packed = RevPacked::new(bytes);
+        let n = bytes.len() - self.ctx();
+        let idxs = self.idxs();
+        if self.is_exact() && idxs.len() == n {
+            return phi_lcp(&packed, bytes.len(), n, end, idxs);
+        }
+
+        let mut res = CompactVec::<BYTES>::new(idxs.len());
+        res.par_iter_mut().enumerate().skip(1).for_each(|(i, lcp)| {
+            let (a, b) = (idxs[i - 1].get_usize(), idxs[i].get_usize());
+            lcp.set_usize(lcp_up_to(&packed, end, a, b, self.ctx()));
+        });
+        res
+    }
+}
+
+/// LCP array of the complete exact suffix array `idxs` of a text of `n` bases, padded
+/// to `len`. The LCP of a suffix with its predecessor in the array is at most one less
+/// than that of the suffix before it (the Φ algorithm), so every chunk of text
+/// positions extends it from there. Comparisons run over the padding too, which keeps
+/// the order they are made in consistent, and the results are cut at `end`.
+fn phi_lcp<const BYTES: usize>(
+    packed: &RevPacked,
+    len: usize,
+    n: usize,
+    end: usize,
+    idxs: &[Int<BYTES>],
+) -> CompactVec<BYTES> {
+    // The predecessor of every position, or the position itself for the first entry.
+    let mut phi = CompactVec::<BYTES>::new(n);
+    let phi_ptr = MutPtr(phi.as_mut_ptr());
+    idxs.par_iter().enumerate().for_each(|(i, pos)| {
+        let ptr = phi_ptr;
+        let prev = &idxs[i.saturating_sub(1)];
+        unsafe { *ptr.0.add(pos.get_usize()) = prev.clone() };
+    });
+
+    // Overwrite every predecessor with the LCP of its position.
+    phi.par_chunks_mut(CHUNK)
+        .enumerate()
+        .for_each(|(chunk, entries)| {
+            let mut h = 0;
+            for (j, e) in entries.iter_mut().enumerate() {
+                let (i, p) = (chunk * CHUNK + j, e.get_usize());
+                if i == p {
+                    h = 0;
+                    e.set_usize(0);
+                    continue;
+                }
+                h += lcp_to_end(packed, len, i + h, p + h);
+                e.set_usize(h.min(end - i.max(p)));
+                h = h.saturating_sub(1);
+            }
+        });
+
+    let mut res = CompactVec::<BYTES>::new(idxs.len());
+    res.par_iter_mut()
+        .zip(idxs.par_iter())
+        .for_each(|(lcp, pos)| *lcp = phi[pos.get_usize()].clone());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcp() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCA".repeat(40);
+        seq.extend_from_slice(b"GATTACA");
+        seq.extend_from_slice(&b"ACGTTGCA".repeat(20));
+        let b = pad_text(&seq, CTX);
+        let n = seq.len();
+
+        let s = SuffixArray::<5>::new_exact::<CTX>(&seq, 3, 2);
+        let idxs = s.idxs().to_usize_vec();
+        let naive = std::iter::once(0)
+            .chain(idxs.windows(2).map(|w| {
+                b[w[0]..n]
+                    .iter()
+                    .zip(&b[w[1]..n])
+                    .take_while(|(x, y)| x == y)
+                    .count()
+            }))
+            .collect::<Vec<_>>();
+        let lcp = s.lcp(&b).to_usize_vec();
+        assert_eq!(lcp, naive);
+        assert!(lcp.iter().any(|&l| l > CTX));
+        let padded = idxs.windows(2).map(|w| {
+            b[w[0]..]
+                .iter()
+                .zip(&b[w[1]..])
+                .take_while(|(x, y)| x == y)
+                .count()
+        });
+        assert_eq!(
+            s.lcp_padded(&b).to_usize_vec(),
+            std::iter::once(0).chain(padded).collect::<Vec<_>>()
+        );
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        let bounded = s.lcp(&b).to_usize_vec();
+        let idxs = s.idxs().to_usize_vec();
+        let capped = idxs.windows(2).map(|w| {
+            b[w[0]..n]
+                .iter()
+                .zip(&b[w[1]..n])
+                .take_while(|(x, y)| x == y)
+                .count()
+                .min(CTX)
+        });
+        assert_eq!(
+            bounded,
+            std::iter::once(0).chain(capped).collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,3 +1,150 @@
+pub mod acs;
+pub mod alphabet;
+pub mod artifact;
+pub mod bed;
+pub mod bucketed;
+pub mod builder;
+pub mod bundle;
+pub mod bwt;
+pub mod cgroup;
+pub mod clustered;
 pub mod compact_vec;
+pub mod compare;
+pub mod compressed_lcp;
+pub mod coords;
+pub mod distinct;
+pub mod distributed;
+pub mod duplicates;
+pub mod effort;
+pub mod esa;
+pub mod exact;
+pub mod export;
+pub mod external;
+pub mod fai;
+pub mod fasta;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fingerprint;
+pub mod fm;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod generalized;
+pub mod hashed;
+pub mod hpc;
+pub mod kmer_index;
+pub mod kmers;
+pub mod lce;
+pub mod lcp;
+pub mod manifest;
+pub mod mappability;
+pub mod mem;
+pub mod memory;
+pub mod merge;
+pub mod metadata;
+pub mod migrate;
+pub mod overlap;
+pub mod packed_text;
+pub mod paf;
+pub mod pbwt;
+pub mod per_seq;
+pub mod perf;
+pub mod pfp;
+pub mod platform;
+pub mod prelude;
+pub mod prepared;
+pub mod query;
+pub mod rank_sample;
+pub mod relative;
+pub mod remote;
+pub mod repeats;
+pub mod report;
+pub mod sa_file;
+pub mod seeding;
+pub mod seq_dict;
+pub mod shard;
+pub mod shared;
+mod simd;
+pub mod sorter;
+pub mod sparse;
+pub mod stages;
+pub mod stats;
+pub mod stranded;
+pub mod stream;
 pub mod suffix_array;
+pub mod sweep;
+#[cfg(test)]
+mod test_util;
+pub mod translate;
+pub mod tune;
+pub mod verify;
+pub mod watchdog;
+pub mod width;
+pub use acs::*;
+pub use alphabet::*;
+pub use artifact::*;
+pub use bed::*;
+pub use bucketed::*;
+pub use builder::*;
+pub use bundle::*;
+pub use cgroup::*;
+pub use clustered::*;
+pub use compare::*;
+pub use compressed_lcp::*;
+pub use coords::*;
+pub use distinct::*;
+pub use distributed::*;
+pub use duplicates::*;
+pub use effort::*;
+pub use esa::*;
+pub use external::*;
+pub use fai::*;
+pub use fasta::*;
+#[cfg(feature = "ffi")]
+pub use ffi::*;
+pub use fingerprint::*;
+pub use fm::*;
+#[cfg(feature = "fuzz")]
+pub use fuzz::*;
+pub use generalized::*;
+pub use hashed::*;
+pub use hpc::*;
+pub use kmer_index::*;
+pub use kmers::*;
+pub use lce::*;
+pub use manifest::*;
+pub use mappability::*;
+pub use mem::*;
+pub use memory::*;
+pub use metadata::*;
+pub use migrate::*;
+pub use overlap::*;
+pub use packed_text::*;
+pub use paf::*;
+pub use pbwt::*;
+pub use per_seq::*;
+pub use pfp::*;
+pub use platform::*;
+pub use prepared::*;
+pub use query::*;
+pub use rank_sample::*;
+pub use relative::*;
+pub use remote::*;
+pub use report::*;
+pub use sa_file::*;
+pub use seeding::*;
+pub use seq_dict::*;
+pub use shard::*;
+pub use shared::*;
+pub use sorter::*;
+pub use sparse::*;
+pub use stages::*;
+pub use stats::*;
+pub use stranded::*;
+pub use stream::*;
 pub use suffix_array::*;
+pub use sweep::*;
+pub use translate::*;
+pub use tune::*;
+pub use verify::*;
+pub use watchdog::*;
+pub use width::*;
@@ -1,46 +1,520 @@
 use needletail::*;
 
-use clap::{self, Parser};
+use clap::{self, Parser, Subcommand};
+use rayon::prelude::*;
 
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use simple_saca::*;
 
 fn main() {
     let start = Instant::now();
     let start_mem = max_mem_usage_mb();
-    let args = Args::parse();
+    let mut args = Args::parse();
+    fit_to_cgroup(&mut args, &CgroupLimits::detect());
     eprintln!("{args:?}");
 
     rayon::ThreadPoolBuilder::new()
         .num_threads(args.threads)
         .build_global()
         .unwrap();
+    // Builds that take only a thread count report like the others.
+    let _observed = schedule(&args).observe();
+    set_coords(args.coords);
 
-    let seq = read_fasta(&args.fasta);
-    eprintln!("Sequence length: {}", seq.len());
-    let seq = seq
-        .into_iter()
-        .filter(|&b| b.to_ascii_uppercase() != b'N')
-        .collect::<Vec<_>>();
+    if let Some(Command::Info { index }) = &args.command {
+        print!("{}", Metadata::read(index).unwrap());
+        return;
+    }
+    if let Some(Command::Migrate { input, output }) = &args.command {
+        let migration = migrate_bundle(input, output).unwrap();
+        eprintln!(
+            "Migrated bundle from version {} to {}",
+            migration.from_version, migration.to_version
+        );
+        if !migration.added.is_empty() {
+            eprintln!("Added sections: {}", migration.added.join(", "));
+        }
+        return;
+    }
+    if let Some(Command::Pbwt {
+        panel,
+        queries,
+        min_sites,
+    }) = &args.command
+    {
+        run_pbwt(panel, queries, *min_sites);
+        return;
+    }
+    if let Some(Command::Dedup { reads }) = &args.command {
+        run_dedup(reads, args.k);
+        return;
+    }
+    let fasta = args.fasta.as_deref().unwrap();
+    if let Some(tmp_dir) = &args.external {
+        run_external(&args, fasta, tmp_dir);
+        let elapsed = start.elapsed().as_secs_f64();
+        eprintln!("Total run time (s): {elapsed}");
+        let mem = (max_mem_usage_mb() - start_mem).max(0.0);
+        eprintln!("Peak memory usage (MB): {mem}");
+        return;
+    }
+
+    let (mut seq, mut dict, packed, breaks) = if args.stream {
+        let input = read_fasta_streamed(fasta);
+        let seq = input.packed.decode(0..input.packed.len());
+        (seq, input.dict, Some(input.packed), vec![])
+    } else {
+        let (seq, dict, breaks) = read_fasta(fasta, args.non_acgt);
+        (seq, dict, None, breaks)
+    };
     eprintln!("Sequence length (no Ns): {}", seq.len());
+    let codec = dict.codec();
+    eprintln!(
+        "Sequences: {}, entry encoding: {} id bits + {} offset bits ({} bytes)",
+        dict.len(),
+        codec.id_bits(),
+        codec.offset_bits(),
+        codec.bytes()
+    );
+
+    let fai = args.fai.then(|| write_fai_files(fasta, &dict));
+
+    if args.hpc {
+        let hpc = Hpc::<5>::new(&seq, &dict);
+        eprintln!(
+            "Homopolymer-compressed length: {} (ratio {:.3})",
+            hpc.seq.len(),
+            hpc.compression_ratio()
+        );
+        if let Some(path) = &args.hpc_map {
+            hpc.write_map(path).unwrap();
+        }
+        (seq, dict) = (hpc.seq, hpc.dict);
+    }
+
+    let alphabet = AlphabetInfo::scan(&seq);
+    eprintln!(
+        "Alphabet: {:?}, lower case bases: {}",
+        alphabet.alphabet,
+        alphabet.lower_count()
+    );
+
+    if let Some(budget) = args.tune_memory {
+        // The input text stays in memory next to the construction.
+        let budget = (budget << 20).saturating_sub(seq.len());
+        let n = seq.len() + args.ctx;
+        match auto_tune(n, 5, budget, schedule(&args)) {
+            Some(t) => {
+                eprintln!(
+                    "Tuned: -k {} --bucket-threads {} (estimated memory {} MiB, expected bucket {})",
+                    t.k,
+                    t.bucket_threads,
+                    t.memory >> 20,
+                    t.expected_bucket(n)
+                );
+                (args.k, args.bucket_threads) = (t.k, t.bucket_threads);
+            }
+            None => panic!("The suffix array does not fit in {} MiB", budget >> 20),
+        }
+    }
+
+    if let Some(sample_len) = args.sweep {
+        let start = Instant::now();
+        let sweep = Sweep::run::<5>(
+            &seq,
+            sample_len,
+            &args.sweep_k,
+            &SUPPORTED_CTX,
+            schedule(&args),
+        );
+        eprintln!("Sweep sample length: {}", sweep.sample_len);
+        eprintln!("k\tctx\ttime (s)\tfull memory (MB)\tresidual ties");
+        for p in &sweep.points {
+            eprintln!(
+                "{}\t{}\t{:.3}\t{:.0}\t{}",
+                p.k,
+                p.ctx,
+                p.time,
+                p.memory as f64 / 1024.0 / 1024.0,
+                p.ties
+            );
+        }
+        let best = sweep.recommended().unwrap();
+        eprintln!("Recommended: -k {} --ctx {}", best.k, best.ctx);
+        (args.k, args.ctx) = (best.k, best.ctx);
+        let elapsed = start.elapsed().as_secs_f64();
+        eprintln!("Sweep run time (s): {elapsed}");
+    }
 
     let start_saca = Instant::now();
 
-    if args.divsufsort {
+    if let Some(path) = &args.append_to {
+        let mut bundle = Bundle::<5>::read(path).unwrap();
+        for id in 0..dict.len() {
+            let record = &seq[dict.start(id)..dict.end(id)];
+            bundle.append(dict.name(id), record, schedule(&args));
+        }
+        bundle.write(path).unwrap();
+        eprintln!(
+            "Suffix array length: {}",
+            bundle.suffix_array().idxs().len()
+        );
+    } else if args.divsufsort {
         use libdivsufsort_rs::divsufsort64;
         let suffix_array = divsufsort64(&seq).unwrap();
         eprintln!("Suffix array length: {}", suffix_array.len());
+    } else if let Some(workers) = args.plan_workers {
+        fold(&mut seq, args.fold);
+        let sizes = kmer_bucket_sizes(&seq, args.k);
+        for (kmers, size) in plan_kmer_ranges(&sizes, workers) {
+            println!("{}-{}\t{size}", kmers.start, kmers.end);
+        }
+    } else if args.translate {
+        let start = Instant::now();
+        let text = TranslatedText::new(&seq, &dict);
+        let sa = ProteinSuffixArray::<5>::new(&text, args.ctx);
+        eprintln!("Translated length: {}", text.protein.len());
+        let elapsed = start.elapsed().as_secs_f64();
+        eprintln!("Translated suffix array run time (s): {elapsed}");
+
+        if let Some(path) = &args.peptides {
+            let start = Instant::now();
+            let mut r = parse_fastx_file(path).unwrap();
+            let mut w = BufWriter::new(std::io::stdout().lock());
+            while let Some(record) = r.next() {
+                let record = record.unwrap();
+                let peptide = record.seq().to_ascii_uppercase();
+                let name = String::from_utf8_lossy(record.id());
+                for hit in sa.locate(&text, &dict, &peptide) {
+                    let strand = if hit.reverse { '-' } else { '+' };
+                    let target = dict.name(hit.seq);
+                    let (start, end) = coords().interval(hit.start..hit.end);
+                    writeln!(w, "{name}\t{target}\t{start}\t{end}\t{strand}").unwrap();
+                }
+            }
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Peptide query run time (s): {elapsed}");
+        }
+    } else if let Some(path) = &args.pfp_bwt {
+        fold(&mut seq, args.fold);
+        let suffix_array = SuffixArray::<5>::new_pfp(&seq, args.pfp_window, args.pfp_modulus);
+        eprintln!("Suffix array length: {}", suffix_array.idxs().len());
+        std::fs::write(path, suffix_array.bwt(&seq)).unwrap();
+    } else if let Some(kmers) = &args.worker_kmers {
+        fold(&mut seq, args.fold);
+        let part = SuffixArray::<5>::new_packed_kmer_range_with_ctx(
+            &seq,
+            args.ctx,
+            args.k,
+            schedule(&args),
+            kmers.clone(),
+        );
+        eprintln!("Partial suffix array length: {}", part.sa.idxs().len());
+        part.write(args.partial.as_ref().unwrap()).unwrap();
+    } else if let Some(path) = &args.per_seq {
+        fold(&mut seq, args.fold);
+        let (index, reports) =
+            PerSeqIndex::<5>::build(&seq, &dict, args.ctx, args.k, schedule(&args));
+        eprintln!("sequence\tlength\trun time (s)\tlargest bucket");
+        for r in &reports {
+            eprintln!(
+                "{}\t{}\t{:.3}\t{}",
+                r.name,
+                r.len,
+                r.stats.seconds(),
+                r.stats.max_bucket
+            );
+        }
+        index.write(path).unwrap();
+    } else if let Some(index_bytes) = args.index_bytes {
+        fold(&mut seq, args.fold);
+        let suffix_array = match index_bytes {
+            0 => AnySuffixArray::new(&seq, args.ctx, args.k, schedule(&args)),
+            b => AnySuffixArray::with_entry_bytes(&seq, args.ctx, args.k, schedule(&args), b),
+        };
+        seq.resize(seq.len() + args.ctx, b'A');
+        eprintln!(
+            "Suffix array length: {} ({}-byte entries)",
+            suffix_array.len(),
+            suffix_array.entry_bytes()
+        );
+        report_lcp_stats(&args, || suffix_array.stats(&seq, args.lcp_cap));
+        if let Some(path) = &args.save_sa {
+            suffix_array.write_to(path).unwrap();
+        }
+        if let Some(path) = &args.save_raw {
+            suffix_array.write_raw(path, args.raw_width).unwrap();
+        }
     } else {
-        let suffix_array = match args.ctx {
-            124 => SuffixArray::<5>::new_packed::<124>(&seq, args.k, args.bucket_threads),
-            248 => SuffixArray::<5>::new_packed::<248>(&seq, args.k, args.bucket_threads),
-            496 => SuffixArray::<5>::new_packed::<496>(&seq, args.k, args.bucket_threads),
-            992 => SuffixArray::<5>::new_packed::<992>(&seq, args.k, args.bucket_threads),
-            _ => panic!("Context length of {} is not supported!", args.ctx),
+        fold(&mut seq, args.fold);
+        let suffix_array = if !args.merge_partials.is_empty() {
+            let parts = args
+                .merge_partials
+                .iter()
+                .map(|path| PartialSuffixArray::<5>::read(path).unwrap())
+                .collect();
+            SuffixArray::from_partials(parts)
+        } else if let Some(region) = &args.region {
+            let range = parse_region(region, &dict);
+            eprintln!("Region: {}..{}", range.start, range.end);
+            SuffixArray::<5>::new_packed_range_with_ctx(
+                &seq,
+                range,
+                args.ctx,
+                args.k,
+                schedule(&args),
+            )
+        } else if let Some(order) = args.sentinel {
+            let kind = if args.shared_sentinel {
+                SentinelKind::Shared
+            } else {
+                SentinelKind::Distinct
+            };
+            let sentinels = Sentinels { order, kind };
+            SuffixArray::<5>::new_generalized_with_ctx(&seq, &dict, args.ctx, args.k, sentinels)
+        } else if args.non_acgt == NonAcgt::Symbol {
+            let sentinels = Sentinels {
+                order: SentinelOrder::Larger,
+                kind: SentinelKind::Shared,
+            };
+            let fragments = dict.split_at(&breaks);
+            SuffixArray::<5>::new_generalized_with_ctx(
+                &seq, &fragments, args.ctx, args.k, sentinels,
+            )
+        } else if args.canonical {
+            SuffixArray::<5>::new_packed_canonical_with_ctx(&seq, args.ctx, args.k, schedule(&args))
+        } else if let Some(seed) = args.spaced_seed {
+            SuffixArray::<5>::new_packed_spaced_with_ctx(&seq, args.ctx, seed, schedule(&args))
+        } else if let Some(packed) = &packed {
+            SuffixArray::<5>::new_packed_text_with_ctx(packed, args.ctx, args.k, schedule(&args))
+        } else if args.exact {
+            SuffixArray::<5>::new_exact_with_ctx(&seq, args.ctx, args.k, schedule(&args))
+        } else if args.key_cache {
+            SuffixArray::<5>::new_packed_key_cached_with_ctx(
+                &seq,
+                args.ctx,
+                args.k,
+                schedule(&args),
+                args.tie_order,
+            )
+        } else {
+            SuffixArray::<5>::new_packed_ordered_with_ctx(
+                &seq,
+                args.ctx,
+                args.k,
+                schedule(&args),
+                args.tie_order,
+            )
         };
+        // Analyses of the whole text read past its end.
+        seq.resize(seq.len() + args.ctx, b'A');
+        dict.pad(args.ctx);
         eprintln!("Suffix array length: {}", suffix_array.idxs().len());
+        eprintln!("Suffix array memory: {}", suffix_array.memory_usage());
+
+        if args.verify {
+            let start = Instant::now();
+            match suffix_array.verify(&seq) {
+                Ok(()) => eprintln!("Verification passed"),
+                Err(e) => panic!("Verification failed: {e}"),
+            }
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Verification run time (s): {elapsed}");
+        }
+
+        if let Some(b) = args.relative_bytes {
+            let (size, wide) = match b {
+                2 => relative_size(&suffix_array.to_relative::<2>()),
+                3 => relative_size(&suffix_array.to_relative::<3>()),
+                4 => relative_size(&suffix_array.to_relative::<4>()),
+                _ => panic!("Relative entries of {b} bytes are not supported!"),
+            };
+            eprintln!(
+                "Bucket-relative entries: {size} bytes, {wide} of {} buckets full width",
+                suffix_array.bucket_ends().len()
+            );
+        }
+
+        if let Some(path) = &args.kmer_spectrum {
+            let w = BufWriter::new(File::create(path).unwrap());
+            suffix_array.write_kmer_spectrum(w).unwrap();
+        }
+
+        if let Some(path) = &args.kmer_counts {
+            let w = BufWriter::new(File::create(path).unwrap());
+            suffix_array
+                .write_kmer_counts(w, KmerCountFormat::Text)
+                .unwrap();
+        }
+
+        if let Some(path) = &args.repeats_bed {
+            let regions = suffix_array.repeat_regions(&seq, args.repeat_min_lcp);
+            let w = BufWriter::new(File::create(path).unwrap());
+            write_bed(w, &dict, &regions).unwrap();
+        }
+
+        if let Some(path) = &args.mappability {
+            let unique = suffix_array.unique_kmers(&seq, args.mappability_k);
+            let w = BufWriter::new(File::create(path).unwrap());
+            write_mappability(w, &dict, &unique, args.mappability_k).unwrap();
+        }
+
+        if let Some(query) = &args.query {
+            let start = Instant::now();
+            let out = BufWriter::new(File::create(args.paf.as_ref().unwrap()).unwrap());
+            write_query_paf(&suffix_array, &seq, &dict, query, &args, out);
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Query run time (s): {elapsed}");
+        }
+
+        if let Some(path) = &args.overlaps {
+            let start = Instant::now();
+            let overlaps = suffix_array.overlaps(&seq, &dict, args.min_overlap);
+            let w = BufWriter::new(File::create(path).unwrap());
+            write_overlaps_paf(w, &overlaps, &dict).unwrap();
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Overlaps: {}", overlaps.len());
+            eprintln!("Overlap run time (s): {elapsed}");
+        }
+
+        report_lcp_stats(&args, || suffix_array.stats(&seq, args.lcp_cap));
+
+        if let Some(path) = &args.repeat_windows {
+            let start = Instant::now();
+            let windows = suffix_array.repeat_windows(
+                &seq,
+                &dict,
+                args.repeat_window_size,
+                args.repeat_window_k,
+            );
+            let mut w = BufWriter::new(File::create(path).unwrap());
+            for r in &windows {
+                let (start, end) = coords().interval(r.start..r.end);
+                writeln!(
+                    w,
+                    "{}\t{}\t{}\t{:.2}\t{:.4}\t{}\t{:.4}\t{:.4}",
+                    dict.name(r.seq),
+                    start,
+                    end,
+                    r.mean_lcp,
+                    r.saturated_fraction,
+                    r.longest_saturated_run,
+                    r.distinct_kmer_fraction,
+                    r.bwt_run_density
+                )
+                .unwrap();
+            }
+            let mut top = windows.iter().collect::<Vec<_>>();
+            top.sort_by(|a, b| b.mean_lcp.total_cmp(&a.mean_lcp));
+            eprintln!("Most repetitive windows (mean LCP, saturated fraction, longest run):");
+            for r in top.iter().take(10) {
+                eprintln!(
+                    "{}:{}-{}\t{:.2}\t{:.4}\t{}",
+                    dict.name(r.seq),
+                    r.start,
+                    r.end,
+                    r.mean_lcp,
+                    r.saturated_fraction,
+                    r.longest_saturated_run
+                );
+            }
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Repeat windows run time (s): {elapsed}");
+        }
+
+        if let Some(max_k) = args.entropy {
+            let start = Instant::now();
+            let entropies = suffix_array.order_k_entropies(&seq, max_k);
+            for (k, h) in entropies.iter().enumerate() {
+                eprintln!("H_{k}: {h:.4} bits/base");
+            }
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Entropy run time (s): {elapsed}");
+        }
+
+        if let Some(k) = args.genome_stats {
+            let stats = suffix_array.genome_stats(&seq, &dict, k);
+            eprintln!("Sequence\tBases\tN fraction\t{k}-mers\tUnique {k}-mer fraction");
+            for (id, s) in stats.iter().enumerate() {
+                eprintln!(
+                    "{}\t{}\t{:.4}\t{}\t{:.4}",
+                    dict.name(id),
+                    s.bases,
+                    s.n_fraction(),
+                    s.kmers,
+                    s.unique_fraction()
+                );
+            }
+        }
+
+        if let Some(dir) = &args.split_bundles {
+            let start = Instant::now();
+            std::fs::create_dir_all(dir).unwrap();
+            let shards = suffix_array.split_by_seq(&seq, &dict);
+            let num_shards = shards.len();
+            for shard in shards {
+                let path = dir.join(format!("{}.bundle", shard.name));
+                shard.into_bundle().write(path).unwrap();
+            }
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Per-sequence bundles: {num_shards}");
+            eprintln!("Split run time (s): {elapsed}");
+        }
+
+        if let Some(path) = &args.rank_sample {
+            let sample = suffix_array.sample_ranks(seq.len() - args.ctx, args.rank_sample_rate);
+            sample.write(path).unwrap();
+        }
+
+        if let Some(path) = &args.bucketed {
+            suffix_array.write_bucketed(&seq, path).unwrap();
+        }
+
+        if let Some(path) = &args.kmer_index {
+            let kmer_k = args.kmer_index_k.unwrap_or(args.k);
+            suffix_array.kmer_index(&seq, kmer_k).write(path).unwrap();
+        }
+
+        if let Some(path) = &args.save_sa {
+            suffix_array.write_to(path).unwrap();
+        }
+        if let Some(path) = &args.save_raw {
+            suffix_array.write_raw(path, args.raw_width).unwrap();
+        }
+
+        if let Some(path) = &args.save_lcp {
+            let lcp = suffix_array.compressed_lcp(&seq);
+            lcp.write(path).unwrap();
+            eprintln!("Compressed LCP size (bytes): {}", lcp.size_in_bytes());
+        }
+
+        if let Some(path) = &args.self_index {
+            let start = Instant::now();
+            let fm = suffix_array.into_fm_index(&seq, args.sa_sample_rate, args.self_index_text);
+            fm.write(path).unwrap();
+            let elapsed = start.elapsed().as_secs_f64();
+            eprintln!("Self-index size (bytes): {}", fm.size_in_bytes());
+            eprintln!("Self-index run time (s): {elapsed}");
+        } else if let Some(path) = &args.bundle {
+            let manifest = build_manifest(&args, fasta, &suffix_array);
+            let mut bundle = Bundle::new(suffix_array, seq, &dict)
+                .with_alphabet(alphabet)
+                .with_manifest(manifest);
+            if let Some(fai) = fai {
+                bundle = bundle.with_fai(fai);
+            }
+            bundle.write(path).unwrap();
+            eprintln!(
+                "Text fingerprint: {}",
+                bundle.suffix_array().fingerprint().unwrap()
+            );
+        }
     }
 
     let elapsed_saca = start_saca.elapsed().as_secs_f64();
@@ -52,43 +526,447 @@ fn main() {
     eprintln!("Peak memory usage (MB): {mem}");
 }
 
-fn read_fasta(path: &Path) -> Vec<u8> {
-    let mut r = parse_fastx_file(path).unwrap();
-    let mut seq = Vec::new();
+fn build_manifest<const BYTES: usize>(
+    args: &Args,
+    fasta: &Path,
+    sa: &SuffixArray<BYTES>,
+) -> Manifest {
+    let mut manifest = Manifest::new()
+        .with("command", std::env::args().collect::<Vec<_>>().join(" "))
+        .with("input", fasta.display());
+    // Streamed input from stdin cannot be read again.
+    if fasta != Path::new("-") {
+        manifest.push("input_sha256", hash_file(fasta).unwrap());
+    }
+    manifest
+        .with("threads", args.threads)
+        .with("bucket_threads", args.bucket_threads)
+        .with("chunks_per_thread", args.chunks_per_thread)
+        .with("min_task_entries", args.min_task_entries.unwrap())
+        .with("entry_bytes", BYTES)
+        .with("k", sa.k())
+        .with("ctx", sa.ctx())
+        .with("tie_order", format!("{:?}", sa.tie_order()))
+        .with("exact", args.exact)
+        .with("fold", format!("{:?}", args.fold))
+}
+
+/// Read all records, removing bytes other than `ACGTU` according to `policy`. Also
+/// returns the positions where runs of them were removed.
+fn read_fasta(path: &Path, policy: NonAcgt) -> (Vec<u8>, SeqDict, Vec<usize>) {
+    let input = match read_fastx(path, policy) {
+        // Compressed input is decompressed by needletail and normalized the same way.
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+            let mut input = FastxInput::new(policy);
+            let mut r = parse_fastx_file(path).unwrap();
+            while let Some(record) = r.next() {
+                let record = record.unwrap();
+                let name = String::from_utf8_lossy(record.id());
+                input.push_record(&name, &record.seq()).unwrap();
+            }
+            input
+        }
+        input => input.unwrap(),
+    };
+
+    let ns = (0..input.dict.len())
+        .map(|id| input.dict.n_count(id))
+        .sum::<usize>();
+    eprintln!("Sequence length: {}", input.seq.len() + ns);
+    eprintln!("Removed non-ACGT runs: {}", input.breaks.len());
+    (input.seq, input.dict, input.breaks)
+}
+
+/// Build out of core for `--external`. The FASTA is always streamed into its packed
+/// text, so that the bytes of the input are never in memory.
+fn run_external(args: &Args, fasta: &Path, tmp_dir: &Path) {
+    let input = read_fasta_streamed(fasta);
+    eprintln!("Sequence length (no Ns): {}", input.packed.len());
+    if args.fai {
+        write_fai_files(fasta, &input.dict);
+    }
+
+    let start_saca = Instant::now();
+    SuffixArray::<5>::write_external_packed_with_ctx(
+        &input.packed,
+        args.ctx,
+        args.k,
+        args.memory_budget << 20,
+        tmp_dir,
+        args.save_sa.as_ref().unwrap(),
+    )
+    .unwrap();
+    let elapsed_saca = start_saca.elapsed().as_secs_f64();
+    eprintln!("Suffix array construction run time (s): {elapsed_saca}");
+}
+
+fn read_fasta_streamed(path: &Path) -> StreamedInput {
+    const CHUNK_SIZE: usize = 1 << 20;
+    let start = Instant::now();
+    let input = if path == Path::new("-") {
+        read_fasta_stream(std::io::stdin(), CHUNK_SIZE)
+    } else {
+        read_fasta_stream(File::open(path).unwrap(), CHUNK_SIZE)
+    }
+    .unwrap();
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!("Streamed reading and packing run time (s): {elapsed}");
+    input
+}
+
+/// Write `<fasta>.fai` and the `.dict` next to the FASTA, and check that they agree
+/// with the sequences that were read.
+fn write_fai_files(fasta: &Path, dict: &SeqDict) -> Vec<FaiRecord> {
+    let start = Instant::now();
+    let records = index_fasta(std::io::BufReader::new(File::open(fasta).unwrap())).unwrap();
+    assert_eq!(records.len(), dict.len(), "fai and sequence counts differ");
+    for (id, r) in records.iter().enumerate() {
+        assert_eq!(
+            r.len,
+            dict.seq_len(id) + dict.n_count(id),
+            "length of {} differs from the fai",
+            r.name
+        );
+    }
+
+    let mut fai_path = fasta.as_os_str().to_owned();
+    fai_path.push(".fai");
+    write_fai(BufWriter::new(File::create(fai_path).unwrap()), &records).unwrap();
+    let uri = std::fs::canonicalize(fasta).unwrap();
+    let uri = format!("file:{}", uri.display());
+    let w = BufWriter::new(File::create(fasta.with_extension("dict")).unwrap());
+    write_fasta_dict(w, &records, Some(&uri)).unwrap();
+
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!("Fai and dict run time (s): {elapsed}");
+    records
+}
+
+fn write_query_paf<const BYTES: usize>(
+    suffix_array: &SuffixArray<BYTES>,
+    seq: &[u8],
+    dict: &SeqDict,
+    query: &Path,
+    args: &Args,
+    mut out: impl Write,
+) {
+    let mut r = parse_fastx_file(query).unwrap();
+    let mask = args.mask_bed.as_ref().map(|path| {
+        let r = std::io::BufReader::new(File::open(path).unwrap());
+        let mask = Mask::new(read_bed(r, dict).unwrap());
+        eprintln!("Masked bases: {}", mask.masked_len());
+        mask
+    });
 
     while let Some(record) = r.next() {
         let record = record.unwrap();
-        seq.extend_from_slice(&record.seq());
+        let name = String::from_utf8_lossy(record.id()).into_owned();
+        let fwd = record.seq();
+        let rc = revcomp(&fwd);
+
+        let filter = HitFilter {
+            min_len: args.min_mem_len,
+            max_freq: args.max_occ,
+            max_hits: args.max_hits,
+        };
+        let order = if args.text_order {
+            HitOrder::Text
+        } else {
+            HitOrder::SuffixArray
+        };
+        let [fwd_hits, rc_hits] = [&fwd[..], &rc[..]].map(|q| match &mask {
+            Some(mask) => suffix_array.mems_masked(seq, q, filter, order, mask, args.mask_mode),
+            None => suffix_array
+                .mems_filtered(seq, q, filter, order)
+                .into_iter()
+                .map(|m| (m, false))
+                .collect(),
+        });
+        let (fwd_hits, rc_hits) = if args.keep_strand_duplicates {
+            (fwd_hits, rc_hits)
+        } else {
+            merge_strands(fwd_hits, rc_hits, fwd.len(), |h| h.0)
+        };
+
+        for (strand, q, hits) in [('+', &fwd[..], fwd_hits), ('-', &rc[..], rc_hits)] {
+            let (mems, masked): (Vec<_>, Vec<_>) = hits.into_iter().unzip();
+            let masked = mask.as_ref().map(|_| &masked[..]);
+            write_paf(&mut out, &name, q.len(), strand, &mems, masked, dict).unwrap();
+        }
     }
+}
 
-    seq
+/// Text range of `NAME[:START-END]`, with coordinates as set by `--coords` within the
+/// sequence after removing Ns.
+fn parse_region(region: &str, dict: &SeqDict) -> Range<usize> {
+    let (name, range) = match region.rsplit_once(':') {
+        Some((name, range)) if parse_kmer_range(range).is_ok() => {
+            (name, Some(parse_kmer_range(range).unwrap()))
+        }
+        _ => (region, None),
+    };
+    let id = (0..dict.len())
+        .find(|&id| dict.name(id) == name)
+        .unwrap_or_else(|| panic!("Unknown sequence {name}"));
+    let range = match range {
+        Some(r) => coords()
+            .parse_interval(r.start, r.end)
+            .unwrap_or_else(|| panic!("Region {region} is empty or invalid")),
+        None => 0..dict.seq_len(id),
+    };
+    assert!(
+        range.end <= dict.seq_len(id),
+        "Region {region} is out of bounds"
+    );
+    dict.start(id) + range.start..dict.start(id) + range.end
 }
 
-fn max_mem_usage_mb() -> f64 {
-    let rusage = unsafe {
-        let mut rusage = std::mem::MaybeUninit::uninit();
-        libc::getrusage(libc::RUSAGE_SELF, rusage.as_mut_ptr());
-        rusage.assume_init()
+fn parse_kmer_range(s: &str) -> Result<Range<usize>, String> {
+    let (start, end) = s.split_once('-').ok_or("expected START-END")?;
+    let parse = |v: &str| v.parse::<usize>().map_err(|e| e.to_string());
+    Ok(parse(start)?..parse(end)?)
+}
+
+fn parse_workers(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>().map_err(|e| e.to_string())? {
+        0 => Err("expected at least one worker".to_string()),
+        n => Ok(n),
+    }
+}
+
+fn relative_size<const B: usize, const BYTES: usize>(r: &RelativeIdxs<B, BYTES>) -> (usize, usize) {
+    (r.size_in_bytes(), r.wide_buckets())
+}
+
+fn run_pbwt(panel: &Path, queries: &Path, min_sites: usize) {
+    let read =
+        |path: &Path| read_haplotypes(std::io::BufReader::new(File::open(path).unwrap())).unwrap();
+    let start = Instant::now();
+    let pbwt = Pbwt::new(read(panel));
+    eprintln!("Haplotypes: {}", pbwt.num_haplotypes());
+    eprintln!("Sites: {}", pbwt.num_sites());
+    eprintln!("PBWT run time (s): {}", start.elapsed().as_secs_f64());
+
+    let start = Instant::now();
+    let matches = read(queries)
+        .par_iter()
+        .map(|query| pbwt.set_maximal_matches(query, min_sites))
+        .collect::<Vec<_>>();
+    eprintln!("Query run time (s): {}", start.elapsed().as_secs_f64());
+
+    let mut w = BufWriter::new(std::io::stdout().lock());
+    for (q, matches) in matches.iter().enumerate() {
+        for m in matches {
+            writeln!(w, "{q}\t{}\t{}\t{}", m.haplotype, m.start, m.end).unwrap();
+        }
+    }
+}
+
+fn run_dedup(path: &Path, k: usize) {
+    let (seq, dict, _) = read_fasta(path, NonAcgt::Strip);
+    // Reads that lost bytes to stripping are passed as empty, so that they are skipped.
+    let reads = (0..dict.len())
+        .map(|id| match dict.n_count(id) {
+            0 => &seq[dict.start(id)..dict.end(id)],
+            _ => &seq[..0],
+        })
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    let dups = find_duplicates::<5>(&reads, k);
+    eprintln!("Dedup run time (s): {}", start.elapsed().as_secs_f64());
+    eprintln!(
+        "Duplicate reads: {}",
+        dups.iter().map(|d| d.multiplicity() - 1).sum::<usize>()
+    );
+
+    let mut w = BufWriter::new(std::io::stdout().lock());
+    for d in &dups {
+        let name = dict.name(d.reads[0]);
+        writeln!(w, "{name}\t{}\t{}", d.multiplicity(), d.extended_by.len()).unwrap();
+    }
+}
+
+/// Compute the LCP statistics if `--lcp-histogram` or `--stats` asks for them, and
+/// write the histogram to the file and stdout respectively.
+fn report_lcp_stats(args: &Args, stats: impl FnOnce() -> LcpStats) {
+    if args.lcp_histogram.is_none() && !args.stats {
+        return;
+    }
+    let start = Instant::now();
+    let stats = stats();
+    let write = |w: &mut dyn Write| {
+        for (lcp, count) in stats.histogram.iter().enumerate().filter(|(_, &c)| c > 0) {
+            writeln!(w, "{lcp}\t{count}").unwrap();
+        }
     };
-    let maxrss = rusage.ru_maxrss as f64;
-    if cfg!(target_os = "macos") {
-        maxrss / 1024.0 / 1024.0
-    } else {
-        maxrss / 1024.0
+    if let Some(path) = &args.lcp_histogram {
+        write(&mut BufWriter::new(File::create(path).unwrap()));
+    }
+    if args.stats {
+        write(&mut BufWriter::new(std::io::stdout().lock()));
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!(
+        "Mean / median / max LCP: {:.2} / {} / {}",
+        stats.mean(),
+        stats.median(),
+        stats.max()
+    );
+    eprintln!(
+        "Ties over the context: {} ({:.4}%)",
+        stats.ctx_ties,
+        100.0 * stats.ctx_tie_fraction()
+    );
+    eprintln!("LCP stats run time (s): {elapsed}");
+}
+
+/// Fill in the thread counts, chunking and memory budget left at 0 from the cgroup
+/// limits, and warn about explicit thread counts above the CPU quota.
+fn fit_to_cgroup(args: &mut Args, limits: &CgroupLimits) {
+    let threads = limits.threads();
+    if let Some(cpus) = limits.cpus {
+        eprintln!("Cgroup CPU quota: {cpus:.2} cores");
+    }
+    if let Some(memory) = limits.memory {
+        eprintln!("Cgroup memory limit: {} MiB", memory >> 20);
+    }
+    if args.threads > threads {
+        eprintln!(
+            "Warning: {} threads requested, but only {threads} CPUs are available",
+            args.threads
+        );
+    }
+    if args.threads == 0 {
+        args.threads = threads;
+    }
+    if args.bucket_threads == 0 {
+        args.bucket_threads = args.threads;
+    }
+    if args.chunks_per_thread == 0 {
+        let throttled = limits.cpus.is_some_and(|c| c < args.bucket_threads as f64);
+        args.chunks_per_thread = if throttled { 4 } else { 1 };
     }
+    if args.min_task_entries.is_none() {
+        args.min_task_entries = Some(if args.threads <= 4 { 1 << 16 } else { 0 });
+    }
+    if args.memory_budget == 0 {
+        let available = limits
+            .memory_available()
+            .map_or(usize::MAX, |m| (m / 2) >> 20);
+        args.memory_budget = available.clamp(1, 4096);
+    }
+}
+
+/// How the bucketing phases are split into tasks, from the flags resolved by
+/// `fit_to_cgroup`, with the watchdog and the build report on stderr.
+fn schedule(args: &Args) -> Schedule {
+    Schedule {
+        bucket_threads: args.bucket_threads,
+        chunks_per_thread: args.chunks_per_thread,
+        min_task_entries: args.min_task_entries.unwrap_or(0),
+        watchdog: watchdog(args),
+        progress: None,
+        verbose: true,
+    }
+}
+
+/// Warn on stderr about phases running longer than `--phase-timeout` and buckets
+/// larger than `--max-bucket`, and exit with status 3 on them with `--watchdog-abort`.
+fn watchdog(args: &Args) -> Option<Arc<Watchdog>> {
+    if args.phase_timeout.is_none() && args.max_bucket.is_none() {
+        return None;
+    }
+    let abort = args.watchdog_abort;
+    let mut watchdog = Watchdog::new(move |event| {
+        match event {
+            WatchdogEvent::SlowPhase { phase, elapsed } => eprintln!(
+                "Warning: {phase} still running after {:.0} s",
+                elapsed.as_secs_f64()
+            ),
+            WatchdogEvent::LargeBucket { kmer, size } => {
+                eprintln!("Warning: bucket {kmer} holds {size} suffixes")
+            }
+        }
+        if abort {
+            std::process::exit(3);
+        }
+    });
+    if let Some(minutes) = args.phase_timeout {
+        watchdog = watchdog.timeout(Duration::from_secs_f64(minutes * 60.0));
+    }
+    if let Some(max_bucket) = args.max_bucket {
+        watchdog = watchdog.max_bucket(max_bucket);
+    }
+    Some(Arc::new(watchdog))
+}
+
+fn max_mem_usage_mb() -> f64 {
+    peak_memory_usage() as f64 / 1024.0 / 1024.0
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the parameters, sequences, sections and build manifest of an index file
+    /// without loading it.
+    Info { index: PathBuf },
+    /// Rewrite a bundle written by an older simple-saca in the current format, filling
+    /// in the sections it lacks.
+    Migrate { input: PathBuf, output: PathBuf },
+    /// Build the positional BWT of a haplotype panel and write the set-maximal matches
+    /// of each query haplotype as `query, haplotype, start, end` to stdout. Both files
+    /// have one haplotype of `0`/`1` alleles per line.
+    Pbwt {
+        panel: PathBuf,
+        queries: PathBuf,
+        /// Only report matches spanning at least this many sites.
+        #[arg(long, default_value_t = 1)]
+        min_sites: usize,
+    },
+    /// Find reads of a FASTA or FASTQ file that are exact duplicates or a prefix of a
+    /// longer read, and write `name, multiplicity, extensions` per duplicated sequence
+    /// to stdout, named by its first read. Reads with non-ACGT bytes are skipped.
+    Dedup { reads: PathBuf },
 }
 
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Input fasta file.
-    fasta: PathBuf,
-    /// Number of threads to use.
-    #[arg(short, long, default_value_t = 32)]
+    #[arg(required = true)]
+    fasta: Option<PathBuf>,
+    /// Read the fasta (or stdin for `-`) as a stream, packing it while it arrives.
+    /// Only plain fasta is supported, not fastq or compressed input, and the text is
+    /// upper case.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "append_to", "divsufsort"])]
+    stream: bool,
+    /// Number of threads to use. Defaults to the cgroup CPU quota, or all CPUs.
+    #[arg(short, long, default_value_t = 0, hide_default_value = true)]
     threads: usize,
-    /// Number of threads to use for bucketing.
-    #[arg(short, long, default_value_t = 32)]
+    /// Number of threads to use for bucketing. Defaults to `--threads`.
+    #[arg(short, long, default_value_t = 0, hide_default_value = true)]
     bucket_threads: usize,
+    /// Chunks per bucket thread in the bucket count and scatter phases, stolen by idle
+    /// threads. Each chunk needs its own 4^k counters. Defaults to 1, or 4 when a cgroup
+    /// CPU quota throttles the threads.
+    #[arg(long, default_value_t = 0, hide_default_value = true)]
+    chunks_per_thread: usize,
+    /// Sort consecutive buckets in tasks of at least this many suffixes, to save
+    /// scheduling millions of tiny buckets on machines with few cores. Defaults to 65536
+    /// with at most 4 threads, and to a task per bucket otherwise.
+    #[arg(long)]
+    min_task_entries: Option<usize>,
+    /// Warn when a construction phase runs for longer than this many minutes.
+    #[arg(long)]
+    phase_timeout: Option<f64>,
+    /// Warn about every bucket of more than this many suffixes.
+    #[arg(long)]
+    max_bucket: Option<usize>,
+    /// Exit with status 3 instead of warning when `--phase-timeout` or `--max-bucket`
+    /// is exceeded.
+    #[arg(long)]
+    watchdog_abort: bool,
     /// Number of base pairs to use for bucketing.
     #[arg(short, long, default_value_t = 10)]
     k: usize,
@@ -97,6 +975,271 @@ struct Args {
     /// Supported multiples of 124: 124, 248, 496, 992
     #[arg(short, long, default_value_t = 248)]
     ctx: usize,
+    /// Coordinates of positions in BED, bedGraph, PAF and other outputs, and of
+    /// `--region`: `bed` (0-based, end-exclusive), `gff` (1-based, end-inclusive), or
+    /// `0-closed` / `1-half-open`.
+    #[arg(long, default_value = "bed")]
+    coords: Coords,
+    /// Before building, sweep `k` over `--sweep-k` and every supported context on a
+    /// sample of this many bases, print time, estimated memory and residual ties per
+    /// combination, and build with the recommended `k` and context.
+    #[arg(long)]
+    sweep: Option<usize>,
+    /// Choose `k` and the bucket threads (at most `--bucket-threads`) such that the
+    /// build is estimated to fit in this many MiB, with buckets kept small.
+    #[arg(long, conflicts_with = "sweep")]
+    tune_memory: Option<usize>,
+    /// Values of `k` to try with `--sweep`.
+    #[arg(long, value_delimiter = ',', default_values_t = [8, 10, 12])]
+    sweep_k: Vec<usize>,
+    /// Order of suffixes that are equal over the whole context: by ascending or
+    /// descending text position.
+    #[arg(long, default_value = "ascending", conflicts_with_all = ["sentinel", "canonical", "stream", "merge_partials", "worker_kmers"])]
+    tie_order: TieOrder,
+    /// Sort buckets on cached keys of the first 64 bases of each suffix, loading the
+    /// text only for suffixes that share those.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "stream", "merge_partials", "region"])]
+    key_cache: bool,
+    /// Order suffixes that are equal over the whole context by their full suffix, giving
+    /// an exact suffix array instead of a context-bounded one.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "stream", "merge_partials", "region", "key_cache"])]
+    exact: bool,
+    /// Only sort the suffixes starting in this region, `NAME[:START-END]` with
+    /// coordinates as set by `--coords` in the sequence with Ns removed.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "stream", "merge_partials", "bundle", "self_index", "split_bundles"])]
+    region: Option<String>,
+    /// Collapse runs of equal bases into one base before building, so the index is
+    /// over the homopolymer-compressed sequence.
+    #[arg(long, conflicts_with_all = ["stream", "append_to"])]
+    hpc: bool,
+    /// Write the map from compressed back to raw coordinates to this file.
+    #[arg(long, requires = "hpc")]
+    hpc_map: Option<PathBuf>,
+    /// Normalize the input before building: none, case (upper case) or dna (upper
+    /// case and U to T). The original alphabet and case are kept in bundles.
+    #[arg(long, default_value = "none")]
+    fold: Folding,
+    /// What to do with bytes other than ACGTU, such as N: strip them, error, or
+    /// treat each run of them (and each record end) as one symbol larger than T.
+    /// `symbol` builds like `--sentinel larger --shared-sentinel`.
+    #[arg(long, default_value = "strip", conflicts_with_all = ["stream", "hpc", "append_to", "sentinel", "canonical", "region", "merge_partials", "exact", "key_cache", "query", "overlaps", "bundle", "self_index"])]
+    non_acgt: NonAcgt,
+    /// End every record in a sentinel that sorts smaller or larger than the bases,
+    /// instead of letting suffixes run into the next record.
+    #[arg(long, conflicts_with_all = ["canonical", "query", "overlaps", "bundle", "self_index"])]
+    sentinel: Option<SentinelOrder>,
+    /// Use one sentinel shared by all records instead of a distinct one per record.
+    #[arg(long, requires = "sentinel")]
+    shared_sentinel: bool,
+    /// Bucket suffixes by canonical k-mer, so both strands of a k-mer share a bucket.
+    /// The result is not a lexicographic suffix array and cannot be queried.
+    #[arg(long, conflicts_with_all = ["query", "bundle"])]
+    canonical: bool,
+    /// Bucket suffixes by the bases at the 1s of this spaced seed, e.g. `1101101`,
+    /// instead of their first `k` bases. Like `--canonical`, the result is only sorted
+    /// within buckets and cannot be queried.
+    #[arg(long, conflicts_with_all = ["canonical", "query", "overlaps", "bundle", "self_index", "mappability", "sentinel", "region", "merge_partials", "stream", "exact", "key_cache"])]
+    spaced_seed: Option<SpacedSeed>,
+    /// Report the size of the suffix array with entries stored as offsets of this many
+    /// bytes (2, 3 or 4) relative to their bucket.
+    #[arg(long)]
+    relative_bytes: Option<usize>,
+    /// Write the k-mer frequency spectrum of the bucketing k-mers to this file.
+    #[arg(long)]
+    kmer_spectrum: Option<PathBuf>,
+    /// Write the count of every bucketing k-mer to this file.
+    #[arg(long)]
+    kmer_counts: Option<PathBuf>,
+    /// Write regions covered by repeats to this BED file.
+    #[arg(long)]
+    repeats_bed: Option<PathBuf>,
+    /// Minimum shared prefix length for a suffix to count as a repeat.
+    #[arg(long, default_value_t = 100)]
+    repeat_min_lcp: usize,
+    /// Write a bedGraph track of whether each position's `--mappability-k`-mer is unique
+    /// in the whole input to this file.
+    #[arg(long, conflicts_with = "canonical")]
+    mappability: Option<PathBuf>,
+    /// K-mer length for `--mappability`, at most the context length.
+    #[arg(long, default_value_t = 50)]
+    mappability_k: usize,
+    /// Query fasta file to find maximal exact matches for.
+    #[arg(long, requires = "paf")]
+    query: Option<PathBuf>,
+    /// Write query matches to this PAF file.
+    #[arg(long)]
+    paf: Option<PathBuf>,
+    /// Minimum length of reported query matches.
+    #[arg(long, default_value_t = 31)]
+    min_mem_len: usize,
+    /// Skip query matches occurring more often than this.
+    #[arg(long, default_value_t = 100)]
+    max_occ: usize,
+    /// Report at most this many matches per query strand.
+    #[arg(long, default_value_t = usize::MAX, hide_default_value = true)]
+    max_hits: usize,
+    /// BED file of masked intervals for query matches, in coordinates without Ns.
+    #[arg(long, requires = "query")]
+    mask_bed: Option<PathBuf>,
+    /// Drop query matches overlapping masked intervals, or flag them with `mk:i:1`.
+    #[arg(long, default_value = "drop")]
+    mask_mode: MaskMode,
+    /// Report query matches by text position instead of grouped by query position.
+    #[arg(long)]
+    text_order: bool,
+    /// Also report query matches that lie within a match on the other strand, such as
+    /// the reverse strand copy of a palindromic match.
+    #[arg(long)]
+    keep_strand_duplicates: bool,
+    /// Treat the input (fasta or fastq) as reads and write their suffix-prefix overlaps
+    /// to this PAF file.
+    #[arg(long, conflicts_with = "canonical")]
+    overlaps: Option<PathBuf>,
+    /// Minimum length of reported read overlaps.
+    #[arg(long, default_value_t = 50)]
+    min_overlap: usize,
+    /// Write the histogram of LCPs of adjacent suffixes to this file.
+    #[arg(long)]
+    lcp_histogram: Option<PathBuf>,
+    /// Print the histogram of LCPs of adjacent suffixes to stdout as `lcp, count`.
+    #[arg(long)]
+    stats: bool,
+    /// Cap LCPs in the histogram at this length instead of scanning to the end of the text.
+    #[arg(long)]
+    lcp_cap: Option<usize>,
+    /// Write the mean LCP, fraction of suffixes tied over the whole context, longest
+    /// run of such suffixes, fraction of distinct k-mers and BWT runs per base of every
+    /// window of each sequence to this TSV file, and report the most repetitive windows.
+    #[arg(long, conflicts_with = "canonical")]
+    repeat_windows: Option<PathBuf>,
+    /// Window length for `--repeat-windows`.
+    #[arg(long, default_value_t = 100_000)]
+    repeat_window_size: usize,
+    /// K-mer length for the distinct k-mer fraction of `--repeat-windows`, at most 32.
+    #[arg(long, default_value_t = 16)]
+    repeat_window_k: usize,
+    /// Report the empirical order-k entropies H_0 up to H_k of the text, for this k.
+    #[arg(long, conflicts_with = "canonical")]
+    entropy: Option<usize>,
+    /// Report per-sequence composition statistics, using k-mers of this length
+    /// to measure uniqueness against the rest of the collection.
+    #[arg(long)]
+    genome_stats: Option<usize>,
+    /// Write a samtools `.fai` and a `.dict` for the (uncompressed) fasta next to it,
+    /// and store them in the bundle.
+    #[arg(long, conflicts_with = "stream")]
+    fai: bool,
+    /// Save the suffix array, text and sequence dictionary to this bundle file.
+    #[arg(long)]
+    bundle: Option<PathBuf>,
+    /// Also write the suffix array with a per-bucket offset table and the text to this
+    /// file, for queries that map it and only touch the buckets they need.
+    #[arg(long, conflicts_with_all = ["canonical", "sentinel", "region"])]
+    bucketed: Option<PathBuf>,
+    /// Also write a table from every k-mer to its sorted text positions to this file, in
+    /// the flat format of `KmerIndex`, for seeding tools.
+    #[arg(long, conflicts_with_all = ["canonical", "spaced_seed", "sentinel", "region"])]
+    kmer_index: Option<PathBuf>,
+    /// K-mer length for `--kmer-index`, at most `-k`. Defaults to `-k`.
+    #[arg(long, requires = "kmer_index")]
+    kmer_index_k: Option<usize>,
+    /// Check that the built array is a permutation of the suffixes in order over the
+    /// context before using it.
+    #[arg(long, conflicts_with_all = ["sentinel", "region"])]
+    verify: bool,
+    /// Also write the suffix array on its own, without the text, to this file, to be
+    /// loaded or mapped with `SuffixArray::load` and `MappedSuffixArray::open`.
+    #[arg(long)]
+    save_sa: Option<PathBuf>,
+    /// Also write the bare entries to this file, as little-endian integers of
+    /// `--raw-width` bytes without a header, for other suffix array tools.
+    #[arg(long)]
+    save_raw: Option<PathBuf>,
+    /// Width in bytes of the entries written by `--save-raw`: 4 or 8 for `u32` or `u64`
+    /// arrays, or any width from 1 to 8 that fits the positions.
+    #[arg(long, default_value_t = 8)]
+    raw_width: usize,
+    /// Also write the LCP array to this file, in about one byte per entry, read back
+    /// with `CompressedLcp::read`.
+    #[arg(long, conflicts_with_all = ["canonical", "spaced_seed", "sentinel", "region"])]
+    save_lcp: Option<PathBuf>,
+    /// Build an independent suffix array per sequence, in parallel, and write them all
+    /// to this file, instead of one suffix array over all sequences.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "spaced_seed", "stream", "region", "exact", "key_cache", "bundle", "self_index", "query", "overlaps", "external", "merge_partials", "append_to", "index_bytes"])]
+    per_seq: Option<PathBuf>,
+    /// Store suffix array entries in this many bytes (4, 5, 6 or 8), or in the narrowest
+    /// width that fits the text for 0, instead of 5. Only the plain array is built,
+    /// for `--save-sa` and `--stats`.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "spaced_seed", "stream", "region", "exact", "key_cache", "bundle", "self_index", "query", "overlaps", "external", "merge_partials", "append_to"])]
+    index_bytes: Option<usize>,
+    /// Build out of core: spill partitions of whole buckets to temporary files in this
+    /// directory and sort them one at a time, writing the array to `--save-sa`. The
+    /// input is read like `--stream`, so only its packed text is kept in memory.
+    #[arg(long, requires = "save_sa", conflicts_with_all = ["sentinel", "canonical", "spaced_seed", "region", "exact", "key_cache", "bundle", "self_index", "query", "overlaps", "hpc", "non_acgt", "sweep", "tune_memory"])]
+    external: Option<PathBuf>,
+    /// Memory for suffix array entries in `--external` mode, in MiB, on top of the packed
+    /// text.
+    /// Defaults to 4096, or half the memory left under the cgroup limit if that is less.
+    #[arg(long, default_value_t = 0, hide_default_value = true)]
+    memory_budget: usize,
+    /// Also write the suffix array rank of every `--rank-sample-rate`-th position to
+    /// this file, a sparse inverse suffix array for later rank and suffix comparisons.
+    #[arg(long, conflicts_with_all = ["canonical", "sentinel", "region"])]
+    rank_sample: Option<PathBuf>,
+    /// Sample the rank of every this many positions.
+    #[arg(long, default_value_t = 32)]
+    rank_sample_rate: usize,
+    /// Also write one bundle per sequence, named after it, to this directory, so the
+    /// index can be spread over machines.
+    #[arg(long, conflicts_with_all = ["canonical", "sentinel"])]
+    split_bundles: Option<PathBuf>,
+    /// Write a self-index (FM-index with sampled suffix array) to this file, which
+    /// supports queries and text extraction without the fasta.
+    #[arg(long, conflicts_with_all = ["bundle", "canonical"])]
+    self_index: Option<PathBuf>,
+    /// Keep every this many suffix array entries in the self-index.
+    #[arg(long, default_value_t = 32)]
+    sa_sample_rate: usize,
+    /// Also store the 2-bit packed text in the self-index, for faster extraction.
+    #[arg(long)]
+    self_index_text: bool,
+    /// Print a split of the k-mer buckets into this many ranges of about equal size,
+    /// one per worker of a distributed construction, and stop.
+    #[arg(long, value_parser = parse_workers)]
+    plan_workers: Option<usize>,
+    /// Only sort the buckets of k-mers in this range (`START-END`, as printed by
+    /// `--plan-workers`) and write them to `--partial`.
+    #[arg(long, value_parser = parse_kmer_range, requires = "partial")]
+    worker_kmers: Option<Range<usize>>,
+    /// Output file for the partial suffix array of `--worker-kmers`.
+    #[arg(long)]
+    partial: Option<PathBuf>,
+    /// Build the suffix array by concatenating these partial suffix arrays of the
+    /// same input instead of sorting.
+    #[arg(long, num_args = 1.., conflicts_with_all = ["sentinel", "canonical"])]
+    merge_partials: Vec<PathBuf>,
+    /// Translate all six reading frames and build a suffix array of the protein text
+    /// instead, sorted on up to `--ctx` residues.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "stream", "hpc", "bundle", "self_index", "query"])]
+    translate: bool,
+    /// Look up the peptides in this fasta in the translated suffix array, and write
+    /// their hits as `peptide, sequence, start, end, strand` to stdout, in forward
+    /// coordinates of the codons.
+    #[arg(long, requires = "translate")]
+    peptides: Option<PathBuf>,
+    /// Build the complete suffix array through a prefix-free parse, which is much
+    /// cheaper on highly repetitive collections, and write the BWT to this file.
+    #[arg(long, conflicts_with_all = ["sentinel", "canonical", "stream", "bundle", "self_index"])]
+    pfp_bwt: Option<PathBuf>,
+    /// Length of the trigger strings of the prefix-free parse.
+    #[arg(long, default_value_t = 10)]
+    pfp_window: usize,
+    /// A window is a trigger string when its hash is 0 modulo this.
+    #[arg(long, default_value_t = 100)]
+    pfp_modulus: u64,
+    /// Append the sequences to this existing bundle instead of building a new index.
+    #[arg(long, conflicts_with = "bundle")]
+    append_to: Option<PathBuf>,
     /// Run 64-bit libdivsufsort instead.
     #[arg(long)]
     divsufsort: bool,
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::fingerprint::*;
+use crate::simd::*;
+
+/// Bytes of input hashed per leaf by `hash_file`.
+const LEAF: usize = 1 << 20;
+
+/// How a bundle was built: ordered key-value pairs, such as the crate version, CPU
+/// features, thread counts and build parameters, and a hash of the input file. Keys
+/// may repeat, e.g. one `append` entry per appended sequence.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Vec<(String, String)>,
+}
+
+impl Manifest {
+    /// A manifest recording the crate version and the CPU features of this machine.
+    pub fn new() -> Self {
+        Self::default()
+            .with("version", env!("CARGO_PKG_VERSION"))
+            .with("cpu_features", cpu_features().join(","))
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.push(key, value);
+        self
+    }
+
+    pub fn push(&mut self, key: impl Into<String>, value: impl ToString) {
+        let key = key.into();
+        let value = value.to_string();
+        assert!(!key.contains(['\t', '\n']) && !value.contains('\n'));
+        self.entries.push((key, value));
+    }
+
+    /// The first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// One `key\tvalue` line per entry.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|(k, v)| format!("{k}\t{v}\n").into_bytes())
+            .collect()
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> io::Result<Self> {
+        let data = std::str::from_utf8(data).map_err(|_| invalid_data("invalid manifest"))?;
+        let entries = data
+            .split_terminator('\n')
+            .map(|line| {
+                line.split_once('\t')
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .ok_or_else(|| invalid_data("invalid manifest entry"))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { entries })
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.entries
+            .iter()
+            .try_for_each(|(k, v)| writeln!(f, "{k}: {v}"))
+    }
+}
+
+/// SHA-256 of the SHA-256 digests of the 1 MiB blocks of the file at `path`.
+pub fn hash_file(path: impl AsRef<Path>) -> io::Result<Fingerprint> {
+    let mut f = File::open(path)?;
+    let mut digests = Vec::new();
+    let mut buf = vec![0u8; LEAF];
+    loop {
+        let mut len = 0;
+        while len < LEAF {
+            match f.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            break;
+        }
+        digests.extend_from_slice(&sha256(&buf[..len]));
+        if len < LEAF {
+            break;
+        }
+    }
+    Ok(Fingerprint(sha256(&digests)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let m = Manifest::new()
+            .with("k", 10)
+            .with("command", "simple-saca -k 10 genome.fa")
+            .with("append", "chrM")
+            .with("append", "chrY");
+        assert_eq!(m.get("version"), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(m.get("k"), Some("10"));
+        assert_eq!(m.get("append"), Some("chrM"));
+        assert_eq!(Manifest::decode(&m.encode()).unwrap(), m);
+        assert!(m
+            .to_string()
+            .contains("command: simple-saca -k 10 genome.fa\n"));
+        assert!(Manifest::decode(b"no tab\n").is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("simple-saca-{}.manifest", std::process::id()));
+        let data = b"ACGT".repeat(LEAF / 3);
+        std::fs::write(&path, &data).unwrap();
+        let hash = hash_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut digests = sha256(&data[..LEAF]).to_vec();
+        digests.extend_from_slice(&sha256(&data[LEAF..]));
+        assert_eq!(hash, Fingerprint(sha256(&digests)));
+    }
+}
@@ -0,0 +1,87 @@
+use rayon::prelude::*;
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::coords::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// For each text position, whether the `k` bases starting there occur nowhere else
+    /// in the text, i.e. share fewer than `k` bases with both neighbouring suffixes.
+    pub fn unique_kmers(&self, bytes: &[u8], k: usize) -> Vec<bool> {
+        assert!(k > 0 && k <= self.ctx());
+        assert!(self.is_lexicographic());
+        let packed = RevPacked::new(bytes);
+        let idxs = self.idxs();
+        let repeated = (0..bytes.len())
+            .map(|_| AtomicBool::new(false))
+            .collect::<Vec<_>>();
+
+        (1..idxs.len()).into_par_iter().for_each(|i| {
+            let a = idxs[i - 1].get_usize();
+            let b = idxs[i].get_usize();
+            if unsafe { packed.lcp(a, b, k) } >= k {
+                repeated[a].store(true, Ordering::Relaxed);
+                repeated[b].store(true, Ordering::Relaxed);
+            }
+        });
+
+        repeated.into_par_iter().map(|r| !r.into_inner()).collect()
+    }
+}
+
+/// Write the k-mer uniqueness of every position whose `k`-mer lies within its sequence
+/// as a bedGraph in the coordinates of `set_coords`: runs of equal value, 1 where the
+/// k-mer is unique and 0 elsewhere. Sort by name for `bedGraphToBigWig`.
+pub fn write_mappability(
+    mut w: impl Write,
+    dict: &SeqDict,
+    unique: &[bool],
+    k: usize,
+) -> io::Result<()> {
+    let coords = coords();
+    for id in 0..dict.len() {
+        let offset = dict.start(id);
+        let end = (dict.end(id) + 1).saturating_sub(k).max(offset);
+        let mut start = offset;
+        while start < end {
+            let value = unique[start];
+            let run = unique[start..end]
+                .iter()
+                .position(|&u| u != value)
+                .unwrap_or(end - start);
+            let (first, last) = coords.interval(start - offset..start + run - offset);
+            writeln!(w, "{}\t{first}\t{last}\t{}", dict.name(id), value as u8)?;
+            start += run;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mappability() {
+        const CTX: usize = 124;
+        let seq = b"CATTAGACCGTTAGACC";
+        let s = SuffixArray::<5>::new_packed::<CTX>(seq, 2, 1);
+        let unique = s.unique_kmers(&pad_text(seq, CTX), 4);
+        // TTAGACC occurs twice, so the 4-mers in both copies are repeated.
+        let repeated = (0..17).filter(|&i| !unique[i]).collect::<Vec<_>>();
+        assert_eq!(repeated, [2, 3, 4, 5, 10, 11, 12, 13]);
+
+        let mut dict = SeqDict::new();
+        dict.push("a", 12);
+        dict.push("b", 5);
+        let mut out = Vec::new();
+        write_mappability(&mut out, &dict, &unique, 4).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a\t0\t2\t1\na\t2\t6\t0\na\t6\t9\t1\nb\t0\t2\t0\n"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+use crate::alphabet::*;
+use crate::bed::*;
+use crate::query::*;
+use crate::suffix_array::*;
+
+/// An exact match between `query[query_pos..query_pos + len]` and
+/// `text[text_pos..text_pos + len]`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mem {
+    pub query_pos: usize,
+    pub text_pos: usize,
+    pub len: usize,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Narrow `range`, whose suffixes share their first `depth` bases, to the suffixes
+    /// whose next base is `b`.
+    fn extend_range(&self, seq: &[u8], range: Range<usize>, depth: usize, b: u8) -> Range<usize> {
+        let idxs = &self.idxs()[range.clone()];
+        let c = base_code(b);
+        // Past the end of the sequence, suffixes continue with the padding `A`s.
+        let code = |i: &crate::compact_vec::Int<BYTES>| {
+            seq.get(i.get_usize() + depth).map_or(0, |&b| base_code(b))
+        };
+        let start = idxs.partition_point(|i| code(i) < c);
+        let end = start + idxs[start..].partition_point(|i| code(i) == c);
+        range.start + start..range.start + end
+    }
+
+    /// Longest prefix of `query` (up to the sorted context) that occurs in the text,
+    /// with the range of suffixes it occurs at.
+    pub fn longest_match(&self, seq: &[u8], query: &[u8]) -> (usize, Range<usize>) {
+        let mut range = 0..self.idxs().len();
+        let mut len = 0;
+
+        for &b in query.iter().take(self.ctx()) {
+            if !is_acgt(b) {
+                break;
+            }
+            let next = self.extend_range(seq, range.clone(), len, b);
+            if next.is_empty() {
+                break;
+            }
+            range = next;
+            len += 1;
+        }
+
+        (len, range)
+    }
+
+    /// Left-maximal exact matches of at least `min_len` bases starting at every query
+    /// position, skipping matches that occur more than `max_occ` times. In suffix array
+    /// order the matches are grouped by query position.
+    pub fn mems(
+        &self,
+        seq: &[u8],
+        query: &[u8],
+        min_len: usize,
+        max_occ: usize,
+        order: HitOrder,
+    ) -> Vec<Mem> {
+        let filter = HitFilter {
+            min_len,
+            max_freq: max_occ,
+            ..HitFilter::default()
+        };
+        self.mems_filtered(seq, query, filter, order)
+    }
+
+    /// `mems`, limited by `filter`. Longest matches that are too short or too frequent
+    /// are skipped before any of their text positions are read, and at most
+    /// `filter.max_hits` matches are reported for the query.
+    pub fn mems_filtered(
+        &self,
+        seq: &[u8],
+        query: &[u8],
+        filter: HitFilter,
+        order: HitOrder,
+    ) -> Vec<Mem> {
+        let idxs = self.idxs();
+
+        let runs = (0..query.len())
+            .into_par_iter()
+            .map(|q| {
+                let (len, range) = self.longest_match(seq, &query[q..]);
+                let range = if filter.accepts(len, range.len()) {
+                    range
+                } else {
+                    0..0
+                };
+
+                range
+                    .filter_map(move |i| {
+                        let text_pos = idxs[i].get_usize();
+                        let left_maximal = q == 0
+                            || text_pos == 0
+                            || !is_acgt(query[q - 1])
+                            || base_code(query[q - 1]) != base_code(seq[text_pos - 1]);
+                        left_maximal.then_some(Mem {
+                            query_pos: q,
+                            text_pos,
+                            len,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut mems = match order {
+            HitOrder::SuffixArray => runs.concat(),
+            HitOrder::Text => {
+                let mut runs = runs;
+                runs.par_iter_mut()
+                    .for_each(|r| r.sort_unstable_by_key(|m| m.text_pos));
+                kway_merge(&runs, |m| (m.text_pos, m.query_pos))
+            }
+        };
+        mems.truncate(filter.max_hits);
+        mems
+    }
+
+    /// `mems_filtered`, dropping or flagging matches whose text side overlaps `mask`.
+    /// At most `filter.max_hits` matches remain after dropping masked ones.
+    pub fn mems_masked(
+        &self,
+        seq: &[u8],
+        query: &[u8],
+        filter: HitFilter,
+        order: HitOrder,
+        mask: &Mask,
+        mode: MaskMode,
+    ) -> Vec<(Mem, bool)> {
+        let unlimited = HitFilter {
+            max_hits: usize::MAX,
+            ..filter
+        };
+        let mems = self.mems_filtered(seq, query, unlimited, order);
+        let mut res = mask.apply(mems, mode, |m| m.text_pos..m.text_pos + m.len);
+        res.truncate(filter.max_hits);
+        res
+    }
+}
+
+/// Drop matches of one query strand that are redundant with a match of the other
+/// strand: their query interval, in forward coordinates, and their text interval both
+/// lie within those of the other match. Palindromic matches are found on both strands
+/// and keep only their forward copy. `rev` holds the matches of the reverse complement
+/// of a query of `query_len` bases, and `mem` gives the match of a hit.
+pub fn merge_strands<T>(
+    fwd: Vec<T>,
+    rev: Vec<T>,
+    query_len: usize,
+    mem: impl Fn(&T) -> Mem,
+) -> (Vec<T>, Vec<T>) {
+    let fwd_intervals = StrandIntervals::new(fwd.iter().map(|h| {
+        let m = mem(h);
+        (
+            m.query_pos..m.query_pos + m.len,
+            m.text_pos..m.text_pos + m.len,
+        )
+    }));
+    let rev_intervals = StrandIntervals::new(rev.iter().map(|h| {
+        let m = mem(h);
+        let query_end = query_len - m.query_pos;
+        (query_end - m.len..query_end, m.text_pos..m.text_pos + m.len)
+    }));
+
+    let fwd = fwd
+        .into_iter()
+        .zip(&fwd_intervals.unsorted)
+        .filter(|(_, (q, t))| !rev_intervals.covers(q, t, false))
+        .map(|(h, _)| h)
+        .collect();
+    let rev = rev
+        .into_iter()
+        .zip(&rev_intervals.unsorted)
+        .filter(|(_, (q, t))| !fwd_intervals.covers(q, t, true))
+        .map(|(h, _)| h)
+        .collect();
+    (fwd, rev)
+}
+
+/// Query and text intervals of the matches of one strand, also sorted by text start.
+struct StrandIntervals {
+    unsorted: Vec<(Range<usize>, Range<usize>)>,
+    sorted: Vec<(Range<usize>, Range<usize>)>,
+    max_len: usize,
+}
+
+impl StrandIntervals {
+    fn new(intervals: impl Iterator<Item = (Range<usize>, Range<usize>)>) -> Self {
+        let unsorted = intervals.collect::<Vec<_>>();
+        let mut sorted = unsorted.clone();
+        sorted.sort_unstable_by_key(|(q, t)| (t.start, q.start));
+        let max_len = sorted.iter().map(|(_, t)| t.len()).max().unwrap_or(0);
+        Self {
+            unsorted,
+            sorted,
+            max_len,
+        }
+    }
+
+    /// Whether some match contains both `query` and `text`, counting identical
+    /// intervals only if `or_equal`.
+    fn covers(&self, query: &Range<usize>, text: &Range<usize>, or_equal: bool) -> bool {
+        let end = self.sorted.partition_point(|(_, t)| t.start <= text.start);
+        self.sorted[..end]
+            .iter()
+            .rev()
+            .take_while(|(_, t)| t.start + self.max_len >= text.end)
+            .any(|(q, t)| {
+                let contains = q.start <= query.start
+                    && query.end <= q.end
+                    && t.start <= text.start
+                    && text.end <= t.end;
+                contains && (or_equal || (q, t) != (query, text))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_strands() {
+        const CTX: usize = 124;
+        // GAATTC is its own reverse complement.
+        let b = b"CCCCGAATTCGGGGTTTACG";
+        let s = SuffixArray::<5>::new_packed::<CTX>(b, 2, 1);
+
+        let query = b"TGAATTCT";
+        let rc = revcomp(query);
+        let fwd = s.mems(b, query, 6, 10, HitOrder::SuffixArray);
+        let rev = s.mems(b, &rc, 6, 10, HitOrder::SuffixArray);
+        let palindrome = Mem {
+            query_pos: 1,
+            text_pos: 4,
+            len: 6,
+        };
+        assert!(fwd.contains(&palindrome) && rev.contains(&palindrome));
+
+        let (fwd, rev) = merge_strands(fwd, rev, query.len(), |m| *m);
+        assert!(fwd.contains(&palindrome) && !rev.contains(&palindrome));
+
+        // A reverse match inside a forward one is dropped, one sticking out is kept.
+        let hit = |query_pos, text_pos, len| Mem {
+            query_pos,
+            text_pos,
+            len,
+        };
+        let (fwd, rev) = merge_strands(
+            vec![hit(10, 100, 30)],
+            vec![hit(60, 105, 20), hit(60, 95, 20)],
+            100,
+            |m| *m,
+        );
+        assert_eq!(fwd, [hit(10, 100, 30)]);
+        assert_eq!(rev, [hit(60, 95, 20)]);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cgroup::*;
+use crate::suffix_array::*;
+
+/// Memory available to new allocations according to the kernel, in bytes, and at most
+/// what is left under the cgroup memory limit.
+pub fn available_memory() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    let cgroup = CgroupLimits::detect().memory_available();
+    Some(cgroup.map_or(kb * 1024, |c| c.min(kb * 1024)))
+}
+
+/// Decides whether memory-hungry steps should fall back to leaner variants, by
+/// comparing their extra allocation against the memory currently available.
+pub struct MemoryMonitor {
+    reserve: usize,
+    min_checked: usize,
+    degraded: AtomicUsize,
+}
+
+impl Default for MemoryMonitor {
+    fn default() -> Self {
+        Self::new(256 << 20)
+    }
+}
+
+impl MemoryMonitor {
+    /// Keep at least `reserve` bytes free. Allocations below 1 MiB are never checked.
+    pub fn new(reserve: usize) -> Self {
+        Self {
+            reserve,
+            min_checked: 1 << 20,
+            degraded: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether an extra allocation of `bytes` can be afforded right now. Records a
+    /// degradation if not.
+    pub fn can_allocate(&self, bytes: usize) -> bool {
+        if bytes < self.min_checked {
+            return true;
+        }
+
+        let ok = available_memory().is_none_or(|a| a >= bytes + self.reserve);
+        if !ok {
+            self.degraded.fetch_add(1, Ordering::Relaxed);
+        }
+        ok
+    }
+
+    /// Number of times a leaner variant was chosen.
+    pub fn degraded(&self) -> usize {
+        self.degraded.load(Ordering::Relaxed)
+    }
+}
+
+/// Heap memory held by an index, per component, in bytes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryBreakdown {
+    /// Suffix array entries.
+    pub entries: usize,
+    /// The bucket end table.
+    pub buckets: usize,
+    /// Text kept with the index.
+    pub text: usize,
+    /// Everything else, such as the LCP or document array.
+    pub auxiliary: usize,
+}
+
+impl MemoryBreakdown {
+    pub fn total(&self) -> usize {
+        self.entries + self.buckets + self.text + self.auxiliary
+    }
+}
+
+impl fmt::Display for MemoryBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes: {} entries, {} buckets, {} text, {} auxiliary",
+            self.total(),
+            self.entries,
+            self.buckets,
+            self.text,
+            self.auxiliary
+        )
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Heap memory held by the array. It keeps no text.
+    pub fn memory_usage(&self) -> MemoryBreakdown {
+        MemoryBreakdown {
+            entries: self.idxs().heap_size(),
+            buckets: self.bucket_ends().heap_size(),
+            text: 0,
+            auxiliary: self.lcp_array().map_or(0, |lcp| lcp.heap_size()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_usage() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACA".repeat(10);
+        let mut sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 1);
+        let usage = sa.memory_usage();
+        assert_eq!(usage.entries, 150 * 5);
+        assert_eq!(usage.buckets, 64 * 5);
+        assert_eq!((usage.text, usage.auxiliary), (0, 0));
+
+        sa.set_lcp_array(Some(sa.lcp(&pad_text(&seq, CTX))));
+        assert_eq!(sa.memory_usage().auxiliary, 150 * 5);
+        assert_eq!(sa.memory_usage().total(), 150 * 10 + 64 * 5);
+    }
+}
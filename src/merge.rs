@@ -0,0 +1,460 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::compact_vec::*;
+use crate::report::*;
+use crate::suffix_array::*;
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Merge arrays built with `new_packed` over `text_a` and `text_b` into the suffix
+    /// array of `text_a ++ text_b`, with positions of `b` shifted by `text_a.len()`.
+    /// Suffixes are compared within their own text, so context never runs from `a`
+    /// into `b`, and ties are ordered `a` first (`b` first for descending tie order).
+    /// Both arrays must use the same `k`, context and tie order; buckets are merged in
+    /// parallel.
+    pub fn merge(&self, other: &Self, text_a: &[u8], text_b: &[u8]) -> Self {
+        let (a, b) = (self, other);
+        assert_eq!(a.k(), b.k());
+        assert_eq!(a.ctx(), b.ctx());
+        assert_eq!(a.is_canonical(), b.is_canonical());
+        assert_eq!(a.seed(), b.seed());
+        assert_eq!(a.tie_order(), b.tie_order());
+        let descending = a.tie_order() == TieOrder::Descending;
+        assert_eq!(a.bucket_ends().len(), b.bucket_ends().len());
+        assert!(!a.bucket_ends().is_empty());
+
+        let packed_a = RevPacked::new(text_a);
+        let packed_b = RevPacked::new(text_b);
+        let shift = text_a.len();
+        let ctx = a.ctx();
+        let num_buckets = a.bucket_ends().len();
+
+        let mut idxs = CompactVec::<BYTES>::new(a.idxs().len() + b.idxs().len());
+        let mut bucket_ends = CompactVec::<BYTES>::new(num_buckets);
+        bucket_ends
+            .par_iter_mut()
+            .zip(a.bucket_ends().par_iter().zip(b.bucket_ends().par_iter()))
+            .for_each(|(e, (ea, eb))| e.set_usize(ea.get_usize() + eb.get_usize()));
+
+        let idxs_ptr = MutPtr(idxs.as_mut_ptr());
+
+        (0..num_buckets).into_par_iter().for_each(|kmer| {
+            let range_a = a.bucket_range(kmer);
+            let range_b = b.bucket_range(kmer);
+            let ptr = idxs_ptr;
+            let out = unsafe {
+                std::slice::from_raw_parts_mut(
+                    ptr.0.add(range_a.start + range_b.start),
+                    range_a.len() + range_b.len(),
+                )
+            };
+
+            let mut ia = a.idxs()[range_a].iter().peekable();
+            let mut ib = b.idxs()[range_b].iter().peekable();
+
+            for o in out.iter_mut() {
+                let take_a = match (ia.peek(), ib.peek()) {
+                    (Some(x), Some(y)) => unsafe {
+                        match simd_cmp_packed_lce(
+                            &packed_a,
+                            x.get_usize(),
+                            &packed_b,
+                            y.get_usize(),
+                            ctx,
+                        ) {
+                            Ordering::Equal => !descending,
+                            ord => ord.is_lt(),
+                        }
+                    },
+                    (Some(_), None) => true,
+                    _ => false,
+                };
+
+                if take_a {
+                    *o = ia.next().unwrap().clone();
+                } else {
+                    o.set_usize(ib.next().unwrap().get_usize() + shift);
+                }
+            }
+        });
+
+        let mut res = Self::from_parts(idxs, bucket_ends, a.k(), ctx, a.is_canonical())
+            .with_tie_order(a.tie_order());
+        if let Some(seed) = a.seed() {
+            res = res.with_seed(seed);
+        }
+        res
+    }
+
+    /// `merge` of any number of arrays at once, each with its text: the suffix array of
+    /// the concatenation of all texts, with positions shifted by the lengths of the
+    /// texts before them. Ties are ordered by part, reversed for descending tie order.
+    /// Every bucket is merged with a heap over the next suffix of each part.
+    pub fn merge_many(parts: &[(&Self, &[u8])]) -> Self {
+        let (first, _) = *parts.first().expect("nothing to merge");
+        for (sa, _) in parts {
+            assert_eq!(sa.k(), first.k());
+            assert_eq!(sa.ctx(), first.ctx());
+            assert_eq!(sa.is_canonical(), first.is_canonical());
+            assert_eq!(sa.seed(), first.seed());
+            assert_eq!(sa.tie_order(), first.tie_order());
+            assert_eq!(sa.bucket_ends().len(), first.bucket_ends().len());
+        }
+        assert!(!first.bucket_ends().is_empty());
+        let descending = first.tie_order() == TieOrder::Descending;
+        let ctx = first.ctx();
+        let num_buckets = first.bucket_ends().len();
+
+        let packed = parts
+            .par_iter()
+            .map(|(_, text)| RevPacked::new(text))
+            .collect::<Vec<_>>();
+        let shifts = parts
+            .iter()
+            .scan(0, |shift, (_, text)| {
+                let s = *shift;
+                *shift += text.len();
+                Some(s)
+            })
+            .collect::<Vec<_>>();
+
+        let n = parts.iter().map(|(sa, _)| sa.idxs().len()).sum();
+        let mut idxs = CompactVec::<BYTES>::new(n);
+        let mut bucket_ends = CompactVec::<BYTES>::new(num_buckets);
+        bucket_ends.par_iter_mut().enumerate().for_each(|(i, e)| {
+            e.set_usize(
+                parts
+                    .iter()
+                    .map(|(sa, _)| sa.bucket_ends()[i].get_usize())
+                    .sum(),
+            )
+        });
+
+        let idxs_ptr = MutPtr(idxs.as_mut_ptr());
+        (0..num_buckets).into_par_iter().for_each(|kmer| {
+            let ranges = parts
+                .iter()
+                .map(|(sa, _)| sa.bucket_range(kmer))
+                .collect::<Vec<_>>();
+            let start = ranges.iter().map(|r| r.start).sum::<usize>();
+            let len = ranges.iter().map(|r| r.len()).sum::<usize>();
+            let ptr = idxs_ptr;
+            let out = unsafe { std::slice::from_raw_parts_mut(ptr.0.add(start), len) };
+
+            let mut iters = parts
+                .iter()
+                .zip(ranges)
+                .map(|((sa, _), range)| sa.idxs()[range].iter())
+                .collect::<Vec<_>>();
+            let head = |part: usize, pos: &Int<BYTES>| Head {
+                pos: pos.get_usize(),
+                part,
+                packed: &packed,
+                ctx,
+                descending,
+            };
+            let mut heap = iters
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(part, it)| Some(head(part, it.next()?)))
+                .collect::<BinaryHeap<_>>();
+            for o in out.iter_mut() {
+                let next = heap.pop().unwrap();
+                o.set_usize(next.pos + shifts[next.part]);
+                if let Some(pos) = iters[next.part].next() {
+                    heap.push(head(next.part, pos));
+                }
+            }
+        });
+
+        let mut res = Self::from_parts(idxs, bucket_ends, first.k(), ctx, first.is_canonical())
+            .with_tie_order(first.tie_order());
+        if let Some(seed) = first.seed() {
+            res = res.with_seed(seed);
+        }
+        res
+    }
+}
+
+/// The next suffix of one part in `merge_many`, ordered in reverse so that the
+/// `BinaryHeap` pops the smallest.
+struct Head<'a> {
+    pos: usize,
+    part: usize,
+    packed: &'a [RevPacked],
+    ctx: usize,
+    descending: bool,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ord = unsafe {
+            simd_cmp_packed_lce(
+                &self.packed[self.part],
+                self.pos,
+                &self.packed[other.part],
+                other.pos,
+                self.ctx,
+            )
+        };
+        let ties = if self.descending {
+            other.part.cmp(&self.part)
+        } else {
+            self.part.cmp(&other.part)
+        };
+        ord.then(ties).reverse()
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+/// Merged positions per parallel chunk of a Holt-McMillan round.
+const CHUNK: usize = 1 << 16;
+
+#[derive(Copy, Clone)]
+struct BoolPtr(*mut bool);
+unsafe impl Send for BoolPtr {}
+unsafe impl Sync for BoolPtr {}
+
+/// Merge the BWTs of two texts, as returned by `SuffixArray::bwt`, into the BWT of
+/// both texts, each ending in its own `$`, with the one of `a` sorting first.
+///
+/// Returns the merged BWT and the interleave: whether each merged row comes from `b`.
+/// The texts are not needed: the interleave is refined Holt-McMillan style, sorting
+/// on one more character per round, until it no longer changes. Every round is a
+/// parallel scan over chunks of the merged rows.
+pub fn merge_bwts(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<bool>) {
+    assert_eq!(a.iter().filter(|&&c| c == b'$').count(), 1);
+    assert_eq!(b.iter().filter(|&&c| c == b'$').count(), 1);
+    let n = a.len() + b.len();
+
+    let mut first = [0usize; 256];
+    for &c in a.iter().chain(b) {
+        first[c as usize] += 1;
+    }
+    let mut sum = 0;
+    for f in first.iter_mut() {
+        (*f, sum) = (sum, sum + *f);
+    }
+
+    let mut z = (0..n).map(|i| i >= a.len()).collect::<Vec<_>>();
+    let mut new_z = vec![false; n];
+    let mut rounds = 0;
+    loop {
+        rounds += 1;
+        // Rows of `b` before every chunk.
+        let mut from_b = z
+            .par_chunks(CHUNK)
+            .map(|zs| zs.iter().filter(|&&x| x).count())
+            .collect::<Vec<_>>();
+        let mut sum = 0;
+        for f in from_b.iter_mut() {
+            (*f, sum) = (sum, sum + *f);
+        }
+        // The characters in every chunk.
+        let chunk_counts = z
+            .par_chunks(CHUNK)
+            .zip(&from_b)
+            .enumerate()
+            .map(|(chunk, (zs, &from_b))| {
+                let (mut ia, mut ib) = (chunk * CHUNK - from_b, from_b);
+                let mut counts = [0usize; 256];
+                for &x in zs {
+                    let c = if x { b[ib] } else { a[ia] };
+                    (ia, ib) = if x { (ia, ib + 1) } else { (ia + 1, ib) };
+                    counts[c as usize] += 1;
+                }
+                (from_b, counts)
+            })
+            .collect::<Vec<_>>();
+
+        let mut ptrs = first;
+        let starts = chunk_counts
+            .iter()
+            .map(|(from_b, counts)| {
+                let start = ptrs;
+                ptrs.iter_mut().zip(counts).for_each(|(p, c)| *p += c);
+                (*from_b, start)
+            })
+            .collect::<Vec<_>>();
+
+        let out_ptr = BoolPtr(new_z.as_mut_ptr());
+        z.par_chunks(CHUNK).zip(starts).enumerate().for_each(
+            |(chunk, (zs, (from_b, mut ptrs)))| {
+                let ptr = out_ptr;
+                let (mut ia, mut ib) = (chunk * CHUNK - from_b, from_b);
+                for &x in zs {
+                    let c = if x { b[ib] } else { a[ia] };
+                    (ia, ib) = if x { (ia, ib + 1) } else { (ia + 1, ib) };
+                    // The `$` rows are ordered `a` first, not by the row before them.
+                    let v = if c == b'$' {
+                        ptrs[c as usize] > first[c as usize]
+                    } else {
+                        x
+                    };
+                    unsafe { *ptr.0.add(ptrs[c as usize]) = v };
+                    ptrs[c as usize] += 1;
+                }
+            },
+        );
+
+        std::mem::swap(&mut z, &mut new_z);
+        if z == new_z {
+            break;
+        }
+    }
+    build_log!("BWT merge rounds: {rounds}");
+
+    let (mut ia, mut ib) = (0, 0);
+    let bwt = z
+        .iter()
+        .map(|&x| {
+            let c = if x { b[ib] } else { a[ia] };
+            (ia, ib) = if x { (ia, ib + 1) } else { (ia + 1, ib) };
+            c
+        })
+        .collect();
+    (bwt, z)
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Merge complete suffix arrays (as built by `new_pfp`) of `text_a` and `text_b`
+    /// along the interleave of their merged BWT. Positions of `b` are shifted by
+    /// `text_a.len()`. The interleave has one more row per text for the `$` suffixes,
+    /// which are not in the suffix arrays.
+    pub fn merge_complete(a: &Self, b: &Self, text_a: &[u8], interleave: &[bool]) -> Self {
+        let n = a.idxs().len() + b.idxs().len();
+        assert_eq!(interleave.len(), n + 2);
+        let mut idxs = CompactVec::<BYTES>::new(n);
+        let (mut ia, mut ib) = (a.idxs().iter(), b.idxs().iter());
+        for (o, &x) in idxs.iter_mut().zip(&interleave[2..]) {
+            if x {
+                o.set_usize(ib.next().unwrap().get_usize() + text_a.len());
+            } else {
+                *o = ia.next().unwrap().clone();
+            }
+        }
+        Self::from_idxs(idxs, a.ctx().max(b.ctx()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        const CTX: usize = 124;
+        let sa = SuffixArray::<5>::new_packed::<CTX>(b"ACGTACGT", 2, 1);
+        let sb = SuffixArray::<5>::new_packed::<CTX>(b"GTTACG", 2, 1);
+        let a = pad_text(b"ACGTACGT", CTX);
+        let b = pad_text(b"GTTACG", CTX);
+        let merged = SuffixArray::merge(&sa, &sb, &a, &b);
+        let shift = a.len();
+        let correct = [
+            shift + 3,
+            4,
+            0,
+            shift + 4,
+            5,
+            1,
+            shift + 5,
+            6,
+            2,
+            shift,
+            7,
+            shift + 2,
+            3,
+            shift + 1,
+        ];
+        assert_eq!(merged.idxs().to_usize_vec(), correct);
+        assert_eq!(merged.bucket_range(0b10_11), 7..10);
+    }
+
+    #[test]
+    fn test_merge_many() {
+        const CTX: usize = 124;
+        let seqs = [
+            &b"ACGTTGCAGATTACAGGATCCA".repeat(7)[..],
+            b"GATTACAGATTACA",
+            &b"TTGCAGATTACAGG".repeat(9)[..],
+        ];
+        let texts = seqs.map(|s| pad_text(s, CTX));
+        let sas = seqs.map(|s| SuffixArray::<5>::new_packed::<CTX>(s, 3, 2));
+        let parts = sas
+            .iter()
+            .zip(&texts)
+            .map(|(sa, t)| (sa, &t[..]))
+            .collect::<Vec<_>>();
+        let merged = SuffixArray::merge_many(&parts);
+
+        let all = texts.concat();
+        let mut naive = Vec::new();
+        let mut shift = 0;
+        for (s, t) in seqs.iter().zip(&texts) {
+            naive.extend(shift..shift + s.len());
+            shift += t.len();
+        }
+        naive.sort_by_key(|&i| (&all[i..i + CTX], i));
+        assert_eq!(merged.idxs().to_usize_vec(), naive);
+        assert_eq!(merged.bucket_ends().len(), 64);
+
+        let ab = [&texts[0][..], &texts[1][..]].concat();
+        let pairwise = sas[0]
+            .merge(&sas[1], &texts[0], &texts[1])
+            .merge(&sas[2], &ab, &texts[2]);
+        assert_eq!(pairwise.idxs(), merged.idxs());
+    }
+
+    #[test]
+    fn test_merge_bwts() {
+        let a = b"ACGTACGTTTGACCA".repeat(7);
+        let b = b"GACCATTACGTAC".repeat(9);
+        let sa = SuffixArray::<5>::new_pfp(&a, 3, 4);
+        let sb = SuffixArray::<5>::new_pfp(&b, 3, 4);
+        let (bwt, interleave) = merge_bwts(&sa.bwt(&a), &sb.bwt(&b));
+
+        let merged = SuffixArray::merge_complete(&sa, &sb, &a, &interleave);
+        let mut correct = (0..a.len() + b.len()).collect::<Vec<_>>();
+        let suffix = |i: usize| {
+            let (t, j, end) = if i < a.len() {
+                (&a, i, 0)
+            } else {
+                (&b, i - a.len(), 1)
+            };
+            (&t[j..], end)
+        };
+        correct.sort_by(|&x, &y| {
+            let ((sx, ex), (sy, ey)) = (suffix(x), suffix(y));
+            let l = sx.len().min(sy.len());
+            sx[..l]
+                .cmp(&sy[..l])
+                .then((sx.len() != l).cmp(&(sy.len() != l)))
+                .then(ex.cmp(&ey))
+        });
+        assert_eq!(merged.idxs().to_usize_vec(), correct);
+
+        let mut correct_bwt = vec![*a.last().unwrap(), *b.last().unwrap()];
+        correct_bwt.extend(correct.iter().map(|&i| match suffix(i) {
+            (_, 0) if i == 0 => b'$',
+            (_, 0) => a[i - 1],
+            _ if i == a.len() => b'$',
+            _ => b[i - a.len() - 1],
+        }));
+        assert_eq!(bwt, correct_bwt);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::fingerprint::*;
+use crate::manifest::*;
+use crate::seq_dict::*;
+
+/// The kinds of files written by this crate, told apart by their magic.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IndexKind {
+    Bundle,
+    FmIndex,
+    Partial,
+    HpcMap,
+    Bucketed,
+    RankSample,
+    SuffixArray,
+    PerSeq,
+    Lcp,
+}
+
+impl IndexKind {
+    fn from_magic(magic: &[u8; 8]) -> Option<Self> {
+        [
+            (crate::bundle::MAGIC, Self::Bundle),
+            (crate::fm::MAGIC, Self::FmIndex),
+            (crate::distributed::MAGIC, Self::Partial),
+            (crate::hpc::MAGIC, Self::HpcMap),
+            (crate::bucketed::MAGIC, Self::Bucketed),
+            (crate::rank_sample::MAGIC, Self::RankSample),
+            (crate::sa_file::MAGIC, Self::SuffixArray),
+            (crate::per_seq::MAGIC, Self::PerSeq),
+            (crate::compressed_lcp::MAGIC, Self::Lcp),
+        ]
+        .into_iter()
+        .find(|(m, _)| *m == magic)
+        .map(|(_, kind)| kind)
+    }
+}
+
+impl fmt::Display for IndexKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bundle => "bundle",
+            Self::FmIndex => "FM-index",
+            Self::Partial => "partial suffix array",
+            Self::HpcMap => "homopolymer-compressed map",
+            Self::Bucketed => "bucketed suffix array",
+            Self::RankSample => "rank sample",
+            Self::SuffixArray => "suffix array",
+            Self::PerSeq => "per-sequence suffix arrays",
+            Self::Lcp => "compressed LCP array",
+        })
+    }
+}
+
+/// The parameters, sequences and section sizes of an index file, read without
+/// loading any of its large sections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: IndexKind,
+    /// Version of the file format.
+    pub version: u32,
+    pub entry_bytes: usize,
+    /// The k-mer length of the buckets, or the sampling rate for rank samples.
+    pub k: usize,
+    pub ctx: usize,
+    pub dict: Option<SeqDict>,
+    pub fingerprint: Option<Fingerprint>,
+    pub manifest: Option<Manifest>,
+    /// Tags and lengths of the sections, in file order.
+    pub sections: Vec<(String, u64)>,
+}
+
+impl Metadata {
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let kind = IndexKind::from_magic(&read_magic(&mut f)?)
+            .ok_or_else(|| invalid_data("not a simple-saca index file"))?;
+        let header = Header::read_after_magic(&mut f)?;
+
+        let dict = match header.section("dict") {
+            Some(_) => Some(decode_dict(&read_section(&mut f, &header, "dict")?)?),
+            None => None,
+        };
+        let fingerprint = match header.section("fprint") {
+            Some(_) => read_section(&mut f, &header, "fprint")?
+                .try_into()
+                .ok()
+                .map(Fingerprint),
+            None => None,
+        };
+        let manifest = match header.section("manifest") {
+            Some(_) => Some(Manifest::decode(&read_section(
+                &mut f, &header, "manifest",
+            )?)?),
+            None => None,
+        };
+
+        Ok(Self {
+            kind,
+            version: header.version,
+            entry_bytes: header.bytes,
+            k: header.k,
+            ctx: header.ctx,
+            dict,
+            fingerprint,
+            manifest,
+            sections: header.section_lens(),
+        })
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "kind: {}", self.kind)?;
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "entry bytes: {}", self.entry_bytes)?;
+        match self.kind {
+            IndexKind::RankSample => writeln!(f, "sample rate: {}", self.k)?,
+            _ => writeln!(f, "k: {}", self.k)?,
+        }
+        writeln!(f, "ctx: {}", self.ctx)?;
+        if let Some(fingerprint) = self.fingerprint {
+            writeln!(f, "fingerprint: {fingerprint}")?;
+        }
+        writeln!(f, "sections:")?;
+        for (tag, len) in &self.sections {
+            writeln!(f, "\t{tag}\t{len}")?;
+        }
+        if let Some(dict) = &self.dict {
+            writeln!(f, "sequences: {}", dict.len())?;
+            for id in 0..dict.len() {
+                writeln!(f, "\t{}\t{}", dict.name(id), dict.seq_len(id))?;
+            }
+        }
+        if let Some(manifest) = &self.manifest {
+            writeln!(f, "manifest:")?;
+            for (key, value) in manifest.entries() {
+                writeln!(f, "\t{key}\t{value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_metadata() {
+        const CTX: usize = 124;
+        let mut text = b"ACGTTGCAGATTACA".repeat(10);
+        let mut dict = SeqDict::new();
+        dict.push("a", 100);
+        dict.push("b", text.len() - 100);
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+        text.resize(text.len() + CTX, b'A');
+        dict.pad(CTX);
+
+        let bundle = Bundle::new(sa, text, &dict).with_manifest(Manifest::new());
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.meta", std::process::id()));
+        bundle.write(&path).unwrap();
+        let metadata = Metadata::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(metadata, bundle.metadata());
+        assert_eq!(metadata.kind, IndexKind::Bundle);
+        assert_eq!(
+            (metadata.entry_bytes, metadata.k, metadata.ctx),
+            (5, 3, CTX)
+        );
+        assert_eq!(metadata.dict.as_ref(), Some(&dict));
+        assert!(metadata.to_string().contains("\tb\t50\n"));
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.notmeta", std::process::id()));
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(Metadata::read(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
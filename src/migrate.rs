@@ -0,0 +1,116 @@
+use std::io;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::metadata::*;
+
+/// What `migrate_bundle` changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Migration {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Sections the input lacked and that were filled in, such as the alphabet or
+    /// fingerprint of bundles written before those were saved.
+    pub added: Vec<String>,
+}
+
+/// Read the bundle at `input`, written by any supported older version, and write it to
+/// `output` in the current format. Missing sections are recomputed the way
+/// `Bundle::read` fills them in, and the manifest, if any, records the migration.
+pub fn migrate_bundle(input: impl AsRef<Path>, output: impl AsRef<Path>) -> io::Result<Migration> {
+    let old = Metadata::read(&input)?;
+    if old.kind != IndexKind::Bundle {
+        return Err(invalid_data(format!(
+            "only bundles can be migrated, not a {}",
+            old.kind
+        )));
+    }
+    let new = match old.entry_bytes {
+        4 => rewrite::<4>(input.as_ref(), output.as_ref(), old.version)?,
+        5 => rewrite::<5>(input.as_ref(), output.as_ref(), old.version)?,
+        6 => rewrite::<6>(input.as_ref(), output.as_ref(), old.version)?,
+        b => return Err(invalid_data(format!("unsupported entry size {b}"))),
+    };
+    let added = new
+        .sections
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| !old.sections.iter().any(|(n, _)| n == name))
+        .collect();
+    Ok(Migration {
+        from_version: old.version,
+        to_version: new.version,
+        added,
+    })
+}
+
+fn rewrite<const BYTES: usize>(input: &Path, output: &Path, from: u32) -> io::Result<Metadata> {
+    let mut bundle = Bundle::<BYTES>::read(input)?;
+    if let Some(manifest) = bundle.manifest().cloned() {
+        let step = format!("{from} -> {VERSION} by {}", env!("CARGO_PKG_VERSION"));
+        bundle = bundle.with_manifest(manifest.with("migrated", step));
+    }
+    bundle.write(output)?;
+    Ok(bundle.metadata())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressed_lcp::CompressedLcp;
+    use crate::seq_dict::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_migrate() {
+        const CTX: usize = 124;
+        let mut text = b"ACGTTGCAGATTACA".repeat(4);
+        let mut dict = SeqDict::new();
+        dict.push("a", text.len());
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 2, 1);
+        text.resize(text.len() + CTX, b'A');
+        dict.pad(CTX);
+        let bundle = Bundle::new(sa, text.clone(), &dict);
+
+        // A bundle from before the alphabet, fingerprint and tie order were saved.
+        let docs = bundle
+            .docs()
+            .docs()
+            .iter()
+            .flat_map(|d| d.to_le_bytes())
+            .collect::<Vec<_>>();
+        let sa = bundle.suffix_array();
+        let old = std::env::temp_dir().join(format!("simple-saca-{}.old", std::process::id()));
+        let new = std::env::temp_dir().join(format!("simple-saca-{}.new", std::process::id()));
+        write_sections(
+            &old,
+            MAGIC,
+            5,
+            sa.k(),
+            sa.ctx(),
+            &[
+                ("sa", sa.idxs().as_bytes()),
+                ("buckets", sa.bucket_ends().as_bytes()),
+                ("text", &text),
+                ("dict", &encode_dict(&dict)),
+                ("docs", &docs),
+            ],
+        )
+        .unwrap();
+
+        let migration = migrate_bundle(&old, &new).unwrap();
+        assert_eq!((migration.from_version, migration.to_version), (1, VERSION));
+        assert_eq!(migration.added, ["alphabet", "fprint", "ties"]);
+        let read = Bundle::<5>::read(&new).unwrap();
+        assert_eq!(read.suffix_array().idxs(), bundle.suffix_array().idxs());
+        assert_eq!(Metadata::read(&new).unwrap(), bundle.metadata());
+
+        // Migrating again changes nothing, and other index kinds are refused.
+        assert!(migrate_bundle(&new, &old).unwrap().added.is_empty());
+        let lcp = CompressedLcp::new([0, 1, 2]);
+        lcp.write(&old).unwrap();
+        assert!(migrate_bundle(&old, &new).is_err());
+        std::fs::remove_file(&old).unwrap();
+        std::fs::remove_file(&new).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use rayon::prelude::*;
+
+use std::collections::HashSet;
+
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// A suffix of read `a` equal to a prefix of read `b`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Overlap {
+    pub a: SeqId,
+    pub b: SeqId,
+    pub len: usize,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Longest suffix-prefix overlap of at least `min_len` for every ordered pair of
+    /// reads in `bytes`, as laid out by `dict`. Overlaps covering a whole read
+    /// (containments) are not reported. Sorted by `a`, then by decreasing length.
+    pub fn overlaps(&self, bytes: &[u8], dict: &SeqDict, min_len: usize) -> Vec<Overlap> {
+        (0..dict.len())
+            .into_par_iter()
+            .flat_map_iter(|a| {
+                let end = dict.end(a);
+                let mut seen = HashSet::new();
+                let mut res = Vec::new();
+
+                for len in (min_len..dict.seq_len(a)).rev() {
+                    let suffix = &bytes[end - len..end];
+                    let range = self.range(bytes, &suffix[..len.min(self.ctx())]);
+
+                    for i in &self.idxs()[range] {
+                        let pos = i.get_usize();
+                        let Some((b, 0)) = dict.locate(pos) else {
+                            continue;
+                        };
+                        if b == a || dict.seq_len(b) <= len || seen.contains(&b) {
+                            continue;
+                        }
+                        let matches = len <= self.ctx()
+                            || bytes[pos..pos + len]
+                                .iter()
+                                .zip(suffix)
+                                .all(|(&x, &y)| base_code(x) == base_code(y));
+                        if matches {
+                            seen.insert(b);
+                            res.push(Overlap {
+                                a: a as SeqId,
+                                b: b as SeqId,
+                                len,
+                            });
+                        }
+                    }
+                }
+                res
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlaps() {
+        const CTX: usize = 124;
+        let reads: [&[u8]; 3] = [b"ACGTTGCAAGT", b"GCAAGTCCAT", b"CAAGTCC"];
+        let mut b = Vec::new();
+        let mut dict = SeqDict::new();
+        for (i, r) in reads.iter().enumerate() {
+            b.extend_from_slice(r);
+            dict.push(format!("r{i}"), r.len());
+        }
+        let s = SuffixArray::<5>::new_packed::<CTX>(&b, 2, 1);
+        b.resize(b.len() + CTX, b'A');
+        dict.pad(CTX);
+
+        let overlaps = s.overlaps(&b, &dict, 3);
+        assert_eq!(
+            overlaps,
+            [
+                Overlap { a: 0, b: 1, len: 6 },
+                Overlap { a: 0, b: 2, len: 5 },
+            ]
+        );
+    }
+}
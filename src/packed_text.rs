@@ -0,0 +1,98 @@
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+use crate::suffix_array::*;
+
+/// Bases per word.
+pub(crate) const WORD: usize = 32;
+
+/// Text packed to 2 bits per base, 32 bases per word with the first base in the least
+/// significant bits. Unlike the reversed packing used for sorting, this is meant for
+/// storage and random access.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackedText {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl PackedText {
+    /// Pack `bytes`, mapping `U` to `T`, ignoring case, and other characters to `A`.
+    pub fn new(bytes: &[u8]) -> Self {
+        Self::from_fn(bytes.len(), |i| base_code(bytes[i]))
+    }
+
+    /// Pack the 2-bit codes `code(0..len)`.
+    pub fn from_fn(len: usize, code: impl Fn(usize) -> u8 + Sync) -> Self {
+        let words = (0..len.div_ceil(WORD))
+            .into_par_iter()
+            .map(|w| {
+                (w * WORD..((w + 1) * WORD).min(len))
+                    .enumerate()
+                    .fold(0u64, |word, (j, i)| word | (code(i) as u64) << (2 * j))
+            })
+            .collect();
+        Self { words, len }
+    }
+
+    pub fn from_words(words: Vec<u64>, len: usize) -> Self {
+        assert_eq!(words.len(), len.div_ceil(WORD));
+        Self { words, len }
+    }
+
+    /// Append the 2-bit `code`.
+    #[inline(always)]
+    pub fn push(&mut self, code: u8) {
+        if self.len.is_multiple_of(WORD) {
+            self.words.push(0);
+        }
+        *self.words.last_mut().unwrap() |= (code as u64) << (2 * (self.len % WORD));
+        self.len += 1;
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 2-bit code of the base at `i`.
+    #[inline(always)]
+    pub fn get(&self, i: usize) -> u8 {
+        ((self.words[i / WORD] >> (2 * (i % WORD))) & 0b11) as u8
+    }
+
+    /// The bases of `range` as upper case `ACGT`.
+    pub fn decode(&self, range: Range<usize>) -> Vec<u8> {
+        assert!(range.end <= self.len);
+        range.map(|i| b"ACGT"[self.get(i) as usize]).collect()
+    }
+
+    /// The reverse complement of the bases of `range`.
+    pub fn decode_revcomp(&self, range: Range<usize>) -> Vec<u8> {
+        assert!(range.end <= self.len);
+        range.rev().map(|i| b"TGCA"[self.get(i) as usize]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let seq = b"ACGTTGCAacgtuuGGCCAATTACGTACGTACGTAACC";
+        let packed = PackedText::new(seq);
+        assert_eq!(packed.len(), seq.len());
+        assert_eq!(packed.decode(6..16), b"CAACGTTTGG");
+        assert_eq!(packed.decode(30..38), b"ACGTAACC");
+        assert_eq!(packed.decode_revcomp(30..38), b"GGTTACGT");
+        assert_eq!(packed.decode_revcomp(0..4), b"ACGT");
+    }
+}
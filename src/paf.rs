@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+use crate::coords::*;
+use crate::mem::*;
+use crate::overlap::*;
+use crate::seq_dict::*;
+
+/// Write matches of one query as PAF records, in the coordinates of `set_coords`.
+/// Matches on the reverse strand (`'-'`)
+/// have `query_pos` relative to the reverse complement of the query. Matches that
+/// cross a sequence boundary in the text are skipped. Matches flagged in `masked` get
+/// an `mk:i:1` tag.
+pub fn write_paf(
+    mut w: impl Write,
+    query_name: &str,
+    query_len: usize,
+    strand: char,
+    mems: &[Mem],
+    masked: Option<&[bool]>,
+    dict: &SeqDict,
+) -> io::Result<()> {
+    let coords = coords();
+    for (i, m) in mems.iter().enumerate() {
+        let Some((id, offset)) = dict.locate(m.text_pos) else {
+            continue;
+        };
+        if offset + m.len > dict.seq_len(id) {
+            continue;
+        }
+
+        let query_start = if strand == '-' {
+            query_len - m.query_pos - m.len
+        } else {
+            m.query_pos
+        };
+        let (query_start, query_end) = coords.interval(query_start..query_start + m.len);
+        let (target_start, target_end) = coords.interval(offset..offset + m.len);
+
+        write!(
+            w,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t255",
+            query_name,
+            query_len,
+            query_start,
+            query_end,
+            strand,
+            dict.name(id),
+            dict.seq_len(id),
+            target_start,
+            target_end,
+            m.len,
+            m.len,
+        )?;
+        if masked.is_some_and(|masked| masked[i]) {
+            write!(w, "\tmk:i:1")?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Write read overlaps as PAF records in the coordinates of `set_coords`, the suffix of
+/// `a` being the query and the prefix of `b` the target.
+pub fn write_overlaps_paf(
+    mut w: impl Write,
+    overlaps: &[Overlap],
+    dict: &SeqDict,
+) -> io::Result<()> {
+    let coords = coords();
+    for o in overlaps {
+        let (a, b) = (o.a as usize, o.b as usize);
+        let a_len = dict.seq_len(a);
+        let (query_start, query_end) = coords.interval(a_len - o.len..a_len);
+        let (target_start, target_end) = coords.interval(0..o.len);
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}\t+\t{}\t{}\t{}\t{}\t{}\t{}\t255",
+            dict.name(a),
+            a_len,
+            query_start,
+            query_end,
+            dict.name(b),
+            dict.seq_len(b),
+            target_start,
+            target_end,
+            o.len,
+            o.len,
+        )?;
+    }
+    Ok(())
+}
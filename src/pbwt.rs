@@ -0,0 +1,264 @@
+use std::io::{self, BufRead};
+
+use crate::bundle::*;
+
+/// Positional Burrows-Wheeler transform (Durbin 2014) of a panel of haplotypes over
+/// biallelic sites with alleles 0 and 1. Before site `k`, `orders[k]` sorts the
+/// haplotypes by their reversed prefixes of `k` sites, and `divergence[k][i]` is the
+/// site where the match of the `i`-th haplotype in that order with the one before it
+/// starts. All arrays are kept, taking about `12` bytes per haplotype and site.
+pub struct Pbwt {
+    haplotypes: Vec<Vec<u8>>,
+    orders: Vec<Vec<u32>>,
+    divergence: Vec<Vec<u32>>,
+    /// `zeros[k][i]` is the number of the first `i` haplotypes of `orders[k]` with
+    /// allele 0 at site `k`.
+    zeros: Vec<Vec<u32>>,
+}
+
+/// A match of a query with `haplotype` over the sites `start..end`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HaplotypeMatch {
+    pub haplotype: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Read haplotypes as lines of `0` and `1` characters, one line per haplotype.
+pub fn read_haplotypes(r: impl BufRead) -> io::Result<Vec<Vec<u8>>> {
+    r.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+        .map(|(line_no, line)| {
+            line?
+                .trim()
+                .bytes()
+                .map(|c| match c {
+                    b'0' | b'1' => Ok(c - b'0'),
+                    _ => Err(invalid_data(format!(
+                        "invalid allele on line {}",
+                        line_no + 1
+                    ))),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl Pbwt {
+    /// Build the transform of haplotypes of equal length, site by site.
+    pub fn new(haplotypes: Vec<Vec<u8>>) -> Self {
+        let m = haplotypes.len();
+        let n = haplotypes.first().map_or(0, |h| h.len());
+        assert!(m > 0 && m <= u32::MAX as usize);
+        assert!(haplotypes
+            .iter()
+            .all(|h| h.len() == n && h.iter().all(|&a| a <= 1)));
+
+        let mut orders = vec![(0..m as u32).collect::<Vec<_>>()];
+        let mut divergence = vec![vec![0u32; m]];
+        let mut zeros = Vec::with_capacity(n);
+        for k in 0..n {
+            let (mut a, mut b) = (Vec::with_capacity(m), Vec::new());
+            let (mut d, mut e) = (Vec::with_capacity(m), Vec::new());
+            let (mut p, mut q) = (k as u32 + 1, k as u32 + 1);
+            let mut z = Vec::with_capacity(m + 1);
+            z.push(0);
+            for (&h, &div) in orders[k].iter().zip(&divergence[k]) {
+                p = p.max(div);
+                q = q.max(div);
+                if haplotypes[h as usize][k] == 0 {
+                    a.push(h);
+                    d.push(p);
+                    p = 0;
+                } else {
+                    b.push(h);
+                    e.push(q);
+                    q = 0;
+                }
+                z.push(a.len() as u32);
+            }
+            a.extend(b);
+            d.extend(e);
+            orders.push(a);
+            divergence.push(d);
+            zeros.push(z);
+        }
+
+        Self {
+            haplotypes,
+            orders,
+            divergence,
+            zeros,
+        }
+    }
+
+    pub fn num_haplotypes(&self) -> usize {
+        self.haplotypes.len()
+    }
+
+    pub fn num_sites(&self) -> usize {
+        self.zeros.len()
+    }
+
+    /// The haplotypes sorted by their reversed prefixes of `k` sites.
+    pub fn order(&self, k: usize) -> &[u32] {
+        &self.orders[k]
+    }
+
+    pub fn divergence(&self, k: usize) -> &[u32] {
+        &self.divergence[k]
+    }
+
+    /// Position in `orders[k + 1]` of the first of the first `i` haplotypes of
+    /// `orders[k]` that have `allele` at site `k`.
+    fn next_pos(&self, k: usize, i: usize, allele: u8) -> usize {
+        let zeros = &self.zeros[k];
+        match allele {
+            0 => zeros[i] as usize,
+            _ => zeros[self.num_haplotypes()] as usize + i - zeros[i] as usize,
+        }
+    }
+
+    /// Set-maximal matches of `query` of at least `min_len` sites: matches with a
+    /// haplotype that are not contained in a longer match with any haplotype.
+    ///
+    /// This keeps the range of haplotypes matching the longest suffix of the query up
+    /// to the current site. When no haplotype extends it, its matches are reported, and
+    /// the longest match including the current site is with a neighbour of the query's
+    /// position in the next order, from which the divergence array gives the new range.
+    pub fn set_maximal_matches(&self, query: &[u8], min_len: usize) -> Vec<HaplotypeMatch> {
+        let m = self.num_haplotypes();
+        let n = self.num_sites();
+        assert_eq!(query.len(), n);
+        let min_len = min_len.max(1);
+        let mut res = Vec::new();
+        let report = |res: &mut Vec<_>, k: usize, range: std::ops::Range<usize>, start| {
+            if k - start >= min_len {
+                res.extend(self.orders[k][range].iter().map(|&h| HaplotypeMatch {
+                    haplotype: h as usize,
+                    start,
+                    end: k,
+                }));
+            }
+        };
+
+        let (mut start, mut f, mut g) = (0, 0, m);
+        for k in 0..n {
+            let next_f = self.next_pos(k, f, query[k]);
+            let next_g = self.next_pos(k, g, query[k]);
+            if next_f < next_g {
+                (f, g) = (next_f, next_g);
+                continue;
+            }
+            report(&mut res, k, f..g, start);
+
+            let order = &self.orders[k + 1];
+            let match_start = |i: usize| {
+                let h = &self.haplotypes[order[i] as usize];
+                (0..=k)
+                    .rev()
+                    .find(|&j| h[j] != query[j])
+                    .map_or(0, |j| j + 1)
+            };
+            let best = [next_f.checked_sub(1), (next_f < m).then_some(next_f)]
+                .into_iter()
+                .flatten()
+                .map(|i| (match_start(i), i))
+                .min();
+            match best {
+                Some((s, i)) if s <= k => {
+                    let d = &self.divergence[k + 1];
+                    (start, f, g) = (s, i, i + 1);
+                    while f > 0 && d[f] as usize <= start {
+                        f -= 1;
+                    }
+                    while g < m && d[g] as usize <= start {
+                        g += 1;
+                    }
+                }
+                _ => (start, f, g) = (k + 1, 0, m),
+            }
+        }
+        report(&mut res, n, f..g, start);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pbwt() {
+        let mut state = 12345u64;
+        let mut rand = |m: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % m
+        };
+        let n = 60;
+        let mut haplotypes = vec![(0..n).map(|_| rand(2) as u8).collect::<Vec<_>>()];
+        while haplotypes.len() < 20 {
+            // Mosaics of earlier haplotypes with some mutations.
+            let h = (0..n)
+                .map(|k| {
+                    let src = &haplotypes[(k / 10 + rand(3) as usize) % haplotypes.len()];
+                    src[k] ^ (rand(15) == 0) as u8
+                })
+                .collect();
+            haplotypes.push(h);
+        }
+        let pbwt = Pbwt::new(haplotypes.clone());
+
+        for k in 0..=n {
+            let mut order = pbwt.order(k).to_vec();
+            order.sort_unstable();
+            assert_eq!(order, (0..20).collect::<Vec<_>>());
+        }
+
+        for q in 0..10 {
+            let query = (0..n)
+                .map(|k| haplotypes[(q + k / 7) % 20][k] ^ (rand(10) == 0) as u8)
+                .collect::<Vec<_>>();
+
+            let mut matches = Vec::new();
+            for (h, hap) in haplotypes.iter().enumerate() {
+                let mut start = 0;
+                for end in 0..=n {
+                    if end == n || hap[end] != query[end] {
+                        if end > start {
+                            matches.push(HaplotypeMatch {
+                                haplotype: h,
+                                start,
+                                end,
+                            });
+                        }
+                        start = end + 1;
+                    }
+                }
+            }
+            let mut naive = matches
+                .iter()
+                .filter(|a| {
+                    !matches.iter().any(|b| {
+                        b.start <= a.start && a.end <= b.end && b.end - b.start > a.end - a.start
+                    })
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            naive.sort_unstable();
+
+            let mut res = pbwt.set_maximal_matches(&query, 1);
+            res.sort_unstable();
+            assert_eq!(res, naive);
+            let long = pbwt.set_maximal_matches(&query, 8);
+            assert!(long.iter().all(|m| m.end - m.start >= 8 && res.contains(m)));
+        }
+
+        let panel = read_haplotypes(&b"0101\n1100\n\n0111\n"[..]).unwrap();
+        assert_eq!(panel, [[0, 1, 0, 1], [1, 1, 0, 0], [0, 1, 1, 1]]);
+        assert!(read_haplotypes(&b"0121\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::report::*;
+use crate::seq_dict::*;
+use crate::shard::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACAPSQ";
+
+/// Independent suffix arrays of every sequence of a text, one per chromosome, each
+/// over its sequence padded with `ctx` `A`s. Saved together as one file.
+pub struct PerSeqIndex<const BYTES: usize> {
+    shards: Vec<Shard<BYTES>>,
+}
+
+/// How the build of one sequence of a `PerSeqIndex` went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeqBuildReport {
+    pub name: String,
+    pub len: usize,
+    pub stats: BuildStats,
+}
+
+impl<const BYTES: usize> PerSeqIndex<BYTES> {
+    /// Build the suffix arrays of the sequences of `dict` in the text `bytes` in
+    /// parallel on the current thread pool. Each build gets a share of the
+    /// `bucket_threads` chunks proportional to its length, so the `4^k` counters per
+    /// chunk stay bounded.
+    pub fn build(
+        bytes: &[u8],
+        dict: &SeqDict,
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> (Self, Vec<SeqBuildReport>) {
+        let schedule: Schedule = bucket_threads.into();
+        let total = dict.total_len().max(1);
+        let (shards, reports): (Vec<_>, Vec<_>) = (0..dict.len())
+            .into_par_iter()
+            .map(|id| {
+                let seq = &bytes[dict.start(id)..dict.end(id)];
+                let threads = Schedule {
+                    bucket_threads: (pool_bucket_threads(schedule.bucket_threads) * seq.len())
+                        .div_ceil(total)
+                        .max(1),
+                    ..schedule.clone()
+                };
+                let (sa, stats) =
+                    record_build(|| SuffixArray::new_packed_with_ctx(seq, ctx, k, threads));
+                let name = dict.name(id).to_owned();
+                let report = SeqBuildReport {
+                    name: name.clone(),
+                    len: seq.len(),
+                    stats,
+                };
+                let shard = Shard {
+                    name,
+                    offset: dict.start(id),
+                    sa,
+                    text: pad_text(seq, ctx),
+                };
+                (shard, report)
+            })
+            .unzip();
+        (Self { shards }, reports)
+    }
+
+    pub fn shards(&self) -> &[Shard<BYTES>] {
+        &self.shards
+    }
+
+    /// Routes global positions, counted over the sequences without padding, to shards.
+    pub fn router(&self) -> ShardRouter {
+        ShardRouter::new(&self.shards)
+    }
+
+    /// Write all shards to one file: their entries, bucket ends and padded texts one
+    /// after the other, with a dictionary of the concatenated texts.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let first = &self.shards.first().expect("no sequences to write").sa;
+        let (k, ctx) = (first.k(), first.ctx());
+        let mut dict = SeqDict::new();
+        let mut sa = Vec::new();
+        let mut buckets = Vec::new();
+        let mut text = Vec::new();
+        let mut offsets = Vec::new();
+        for shard in &self.shards {
+            assert_eq!((shard.sa.k(), shard.sa.ctx()), (k, ctx));
+            dict.push(&shard.name, shard.seq_len());
+            dict.pad(ctx);
+            offsets.extend_from_slice(&(shard.offset as u64).to_le_bytes());
+            sa.extend_from_slice(shard.sa.idxs().as_bytes());
+            buckets.extend_from_slice(shard.sa.bucket_ends().as_bytes());
+            text.extend_from_slice(&shard.text);
+        }
+        write_sections(
+            path.as_ref(),
+            MAGIC,
+            BYTES,
+            k,
+            ctx,
+            &[
+                ("sa", &sa),
+                ("buckets", &buckets),
+                ("text", &text),
+                ("dict", &encode_dict(&dict)),
+                ("offsets", &offsets),
+            ],
+        )
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let header = Header::read(&mut f, MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "per-sequence index has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+        let (k, ctx) = (header.k, header.ctx);
+        let sa = read_section(&mut f, &header, "sa")?;
+        let buckets = read_section(&mut f, &header, "buckets")?;
+        let text = read_section(&mut f, &header, "text")?;
+        let dict = decode_dict(&read_section(&mut f, &header, "dict")?)?;
+        let offsets = read_section(&mut f, &header, "offsets")?;
+
+        let bucket_bytes = (1usize << (2 * k)) * BYTES;
+        if buckets.len() != dict.len() * bucket_bytes || offsets.len() != dict.len() * 8 {
+            return Err(invalid_data("bucket table does not match the sequences"));
+        }
+        if text.len() != dict.total_len() {
+            return Err(invalid_data("text does not match the sequences"));
+        }
+        let mut entries = 0;
+        let mut shards = Vec::with_capacity(dict.len());
+        for id in 0..dict.len() {
+            let len = dict.seq_len(id);
+            let idxs = sa
+                .get(entries * BYTES..(entries + len) * BYTES)
+                .ok_or_else(|| invalid_data("suffix array is too short"))?;
+            entries += len;
+            let bucket_ends = &buckets[id * bucket_bytes..(id + 1) * bucket_bytes];
+            let offset = u64::from_le_bytes(offsets[id * 8..(id + 1) * 8].try_into().unwrap());
+            shards.push(Shard {
+                name: dict.name(id).to_owned(),
+                offset: offset as usize,
+                sa: SuffixArray::from_parts(
+                    CompactVec::from_bytes(idxs),
+                    CompactVec::from_bytes(bucket_ends),
+                    k,
+                    ctx,
+                    false,
+                ),
+                text: text[dict.start(id)..dict.end(id) + ctx].to_vec(),
+            });
+        }
+        if entries * BYTES != sa.len() {
+            return Err(invalid_data("suffix array does not match the sequences"));
+        }
+        Ok(Self { shards })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_seq() {
+        const CTX: usize = 124;
+        let mut dict = SeqDict::new();
+        let mut text = Vec::new();
+        for (name, seq) in [
+            ("chr1", &b"ACGTTGCAGATTACA"[..]),
+            ("chr2", b"GATTACATTT"),
+            ("chr3", b"CCGATTAG"),
+        ] {
+            let seq = seq.repeat(5);
+            dict.push(name, seq.len());
+            text.extend_from_slice(&seq);
+        }
+
+        let (index, reports) = PerSeqIndex::<5>::build(&text, &dict, CTX, 3, 4);
+        assert_eq!(index.shards().len(), 3);
+        assert_eq!(reports.len(), 3);
+        for (id, (shard, report)) in index.shards().iter().zip(&reports).enumerate() {
+            let seq = &text[dict.start(id)..dict.end(id)];
+            assert_eq!(
+                (shard.name.as_str(), report.len),
+                (dict.name(id), seq.len())
+            );
+            assert!(report.stats.max_bucket > 0);
+            let alone = SuffixArray::<5>::new_packed::<CTX>(seq, 3, 1);
+            assert_eq!(shard.sa.idxs().to_usize_vec(), alone.idxs().to_usize_vec());
+        }
+
+        let router = index.router();
+        assert_eq!(router.route(80), Some((1, 5)));
+        let hits = router.locate(index.shards(), b"GATTA");
+        assert_eq!(hits.len(), 15);
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.perseq", std::process::id()));
+        index.write(&path).unwrap();
+        let read = PerSeqIndex::<5>::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (a, b) in index.shards().iter().zip(read.shards()) {
+            assert_eq!((&a.name, a.offset, &a.text), (&b.name, b.offset, &b.text));
+            assert_eq!(a.sa.idxs().to_usize_vec(), b.sa.idxs().to_usize_vec());
+            assert_eq!(
+                a.sa.bucket_ends().to_usize_vec(),
+                b.sa.bucket_ends().to_usize_vec()
+            );
+        }
+        assert_eq!(read.router().locate(read.shards(), b"GATTA"), hits);
+    }
+}
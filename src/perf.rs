@@ -0,0 +1,184 @@
+use std::time::Instant;
+
+use crate::report::*;
+use crate::watchdog::*;
+
+/// Timing (and with the `perf` feature, hardware counters) of one construction phase.
+pub(crate) struct Phase {
+    name: &'static str,
+    start: Instant,
+    watch: Option<PhaseWatch>,
+    #[cfg(feature = "perf")]
+    counters: Option<counters::Counters>,
+}
+
+impl Phase {
+    /// Start timing the phase, and watching it if the watchdog has a timeout for it.
+    pub(crate) fn start(name: &'static str) -> Self {
+        report_progress(&Progress::PhaseStarted { name });
+        Self {
+            name,
+            watch: PhaseWatch::start(name),
+            #[cfg(feature = "perf")]
+            counters: counters::Counters::start(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the phase in the `BuildStats`, print it to the build report on stderr if
+    /// verbose, and return its run time in seconds.
+    pub(crate) fn finish(self) -> f64 {
+        let name = self.name;
+        drop(self.watch);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        record_phase(name, elapsed);
+        build_log!("\t{name} run time (s): {elapsed}");
+
+        #[cfg(feature = "perf")]
+        match self.counters.map(|c| c.stop()) {
+            Some(c) => build_log!(
+                "\t{name} instructions / cache misses / dTLB misses: {} / {} / {}",
+                c.instructions,
+                c.cache_misses,
+                c.dtlb_misses
+            ),
+            None => build_log!("\t{name} hardware counters unavailable"),
+        }
+
+        elapsed
+    }
+}
+
+#[cfg(feature = "perf")]
+pub use counters::CounterValues;
+
+#[cfg(feature = "perf")]
+mod counters {
+    const PERF_TYPE_HARDWARE: u32 = 0;
+    const PERF_TYPE_HW_CACHE: u32 = 3;
+    const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+    const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+    const PERF_COUNT_HW_CACHE_DTLB_READ_MISS: u64 = 3 | (1 << 16);
+
+    const FLAG_DISABLED: u64 = 1 << 0;
+    const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+    const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+    const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+
+    /// Prefix of the kernel's `perf_event_attr`; the remaining fields stay zero.
+    #[repr(C)]
+    struct PerfEventAttr {
+        type_: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        rest: [u64; 10],
+    }
+
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub struct CounterValues {
+        pub instructions: u64,
+        pub cache_misses: u64,
+        pub dtlb_misses: u64,
+    }
+
+    const EVENTS: [(u32, u64); 3] = [
+        (PERF_TYPE_HARDWARE, PERF_COUNT_HW_INSTRUCTIONS),
+        (PERF_TYPE_HARDWARE, PERF_COUNT_HW_CACHE_MISSES),
+        (PERF_TYPE_HW_CACHE, PERF_COUNT_HW_CACHE_DTLB_READ_MISS),
+    ];
+
+    /// Counters for the calling thread and every thread of the current rayon pool.
+    pub(super) struct Counters {
+        fds: Vec<[i32; 3]>,
+    }
+
+    fn open(type_: u32, config: u64) -> Option<i32> {
+        let attr = PerfEventAttr {
+            type_,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            sample_period: 0,
+            sample_type: 0,
+            read_format: 0,
+            flags: FLAG_DISABLED | FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+            rest: [0; 10],
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                0,
+                -1,
+                -1,
+                0,
+            )
+        };
+        (fd >= 0).then_some(fd as i32)
+    }
+
+    fn open_thread() -> Option<[i32; 3]> {
+        let mut fds = [-1; 3];
+        for (fd, &(type_, config)) in fds.iter_mut().zip(&EVENTS) {
+            match open(type_, config) {
+                Some(f) => *fd = f,
+                None => {
+                    close(&fds);
+                    return None;
+                }
+            }
+        }
+        Some(fds)
+    }
+
+    fn close(fds: &[i32]) {
+        for &fd in fds.iter().filter(|&&fd| fd >= 0) {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    impl Counters {
+        pub(super) fn start() -> Option<Self> {
+            let mut fds = rayon::broadcast(|_| open_thread());
+            fds.push(open_thread());
+
+            if fds.iter().any(|f| f.is_none()) {
+                fds.iter().flatten().for_each(|f| close(f));
+                return None;
+            }
+
+            let fds = fds.into_iter().flatten().collect::<Vec<_>>();
+            for &fd in fds.iter().flatten() {
+                unsafe { libc::ioctl(fd, PERF_EVENT_IOC_ENABLE, 0) };
+            }
+            Some(Self { fds })
+        }
+
+        pub(super) fn stop(self) -> CounterValues {
+            let mut sums = [0u64; 3];
+
+            for fds in &self.fds {
+                for (sum, &fd) in sums.iter_mut().zip(fds) {
+                    let mut val = 0u64;
+                    unsafe {
+                        libc::ioctl(fd, PERF_EVENT_IOC_DISABLE, 0);
+                        libc::read(fd, &mut val as *mut u64 as _, 8);
+                    }
+                    *sum += val;
+                }
+                close(fds);
+            }
+
+            CounterValues {
+                instructions: sums[0],
+                cache_misses: sums[1],
+                dtlb_misses: sums[2],
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use rayon::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::compact_vec::*;
+use crate::perf::*;
+use crate::report::*;
+use crate::suffix_array::*;
+
+const HASH_BASE: u64 = 0x100000001b3;
+
+/// Prefix-free parse of a text: the text split into phrases that start and end at
+/// trigger strings (windows of `w` bases whose hash is 0 modulo `p`), with consecutive
+/// phrases overlapping by `w` bases. Repetitive texts have few distinct phrases, so
+/// sorting the dictionary and the parse is much cheaper than sorting the text.
+pub struct Pfp {
+    w: usize,
+    /// Distinct phrases in lexicographic order. The last phrase of the text ends in a
+    /// 0 byte, which sorts before all bases.
+    dict: Vec<Vec<u8>>,
+    /// Dictionary rank of every phrase of the text.
+    parse: Vec<u32>,
+    /// Start of every phrase in the text.
+    starts: Vec<usize>,
+}
+
+/// Positions `i` where `bytes[i..i + w]` is a trigger string.
+fn triggers(bytes: &[u8], w: usize, p: u64) -> Vec<usize> {
+    if bytes.len() < w {
+        return Vec::new();
+    }
+    let top = HASH_BASE.wrapping_pow(w as u32 - 1);
+    let mut h = bytes[..w].iter().fold(0u64, |h, &b| {
+        h.wrapping_mul(HASH_BASE).wrapping_add(b as u64)
+    });
+    let mut res = Vec::new();
+    for i in 0..=bytes.len() - w {
+        if i > 0 {
+            h = h
+                .wrapping_sub((bytes[i - 1] as u64).wrapping_mul(top))
+                .wrapping_mul(HASH_BASE)
+                .wrapping_add(bytes[i + w - 1] as u64);
+        }
+        // Mix the bits, so that `p` need not be coprime to the base.
+        if ((h ^ (h >> 29)).wrapping_mul(0xbf58476d1ce4e5b9) >> 16) % p == 0 {
+            res.push(i);
+        }
+    }
+    res
+}
+
+impl Pfp {
+    /// Parse `bytes` with trigger strings of length `w` occurring about once every `p`
+    /// positions. `bytes` must not contain 0 bytes.
+    pub fn new(bytes: &[u8], w: usize, p: u64) -> Self {
+        assert!(w > 0 && p > 0);
+        assert!(!bytes.contains(&0));
+
+        let mut starts = vec![0];
+        starts.extend(triggers(bytes, w, p).into_iter().filter(|&i| i > 0));
+
+        let phrase = |j: usize| -> Vec<u8> {
+            match starts.get(j + 1) {
+                Some(&next) => bytes[starts[j]..next + w].to_vec(),
+                None => {
+                    let mut last = bytes[starts[j]..].to_vec();
+                    last.push(0);
+                    last
+                }
+            }
+        };
+
+        let mut ids = HashMap::<Vec<u8>, u32>::new();
+        let mut parse = Vec::with_capacity(starts.len());
+        for j in 0..starts.len() {
+            let next = ids.len() as u32;
+            parse.push(*ids.entry(phrase(j)).or_insert(next));
+        }
+
+        let mut dict = ids.into_iter().collect::<Vec<_>>();
+        dict.par_sort_unstable();
+        let mut rank = vec![0; dict.len()];
+        for (r, (_, id)) in dict.iter().enumerate() {
+            rank[*id as usize] = r as u32;
+        }
+        parse.iter_mut().for_each(|d| *d = rank[*d as usize]);
+
+        Self {
+            w,
+            dict: dict.into_iter().map(|(p, _)| p).collect(),
+            parse,
+            starts,
+        }
+    }
+
+    pub fn dictionary(&self) -> &[Vec<u8>] {
+        &self.dict
+    }
+
+    pub fn parse(&self) -> &[u32] {
+        &self.parse
+    }
+
+    /// Total length of the distinct phrases.
+    pub fn dictionary_len(&self) -> usize {
+        self.dict.iter().map(|p| p.len()).sum()
+    }
+
+    /// Number of text positions whose suffix starts in phrase `d`.
+    fn owned(&self, d: usize) -> usize {
+        match self.dict[d].last() {
+            Some(0) => self.dict[d].len() - 1,
+            _ => self.dict[d].len() - self.w,
+        }
+    }
+
+    /// The suffix array of the text, sorted on complete suffixes, with a shorter suffix
+    /// before the suffixes it is a prefix of.
+    ///
+    /// Distinct phrase suffixes are never prefixes of each other, so suffixes starting
+    /// in phrases are ordered by their phrase suffix first, and by the rank of the parse
+    /// suffix following the phrase when those are equal.
+    pub fn suffix_array<const BYTES: usize>(&self) -> SuffixArray<BYTES> {
+        let m = self.parse.len();
+        let parse_rank = parse_suffix_ranks(&self.parse);
+
+        // Text phrases of each dictionary phrase, in the order of the parse that follows.
+        let mut occ = vec![Vec::new(); self.dict.len()];
+        for (j, &d) in self.parse.iter().enumerate() {
+            occ[d as usize].push(j);
+        }
+        occ.par_iter_mut().for_each(|o| {
+            o.sort_unstable_by_key(|&j| if j + 1 < m { parse_rank[j + 1] } else { 0 })
+        });
+
+        let mut phrase_suffixes = (0..self.dict.len())
+            .flat_map(|d| (0..self.owned(d)).map(move |o| (d as u32, o as u32)))
+            .collect::<Vec<_>>();
+        phrase_suffixes.par_sort_unstable_by(|&(d1, o1), &(d2, o2)| {
+            self.dict[d1 as usize][o1 as usize..].cmp(&self.dict[d2 as usize][o2 as usize..])
+        });
+
+        let n = self.starts.last().unwrap() + self.owned(*self.parse.last().unwrap() as usize);
+        let mut idxs = CompactVec::<BYTES>::new(n);
+        let mut out = 0;
+        let mut group = Vec::new();
+        for g in phrase_suffixes.chunk_by(|&(d1, o1), &(d2, o2)| {
+            self.dict[d1 as usize][o1 as usize..] == self.dict[d2 as usize][o2 as usize..]
+        }) {
+            group.clear();
+            for &(d, o) in g {
+                group.extend(occ[d as usize].iter().map(|&j| (j, o as usize)));
+            }
+            if g.len() > 1 {
+                group.sort_unstable_by_key(|&(j, _)| parse_rank[j + 1]);
+            }
+            for &(j, o) in &group {
+                idxs[out].set_usize(self.starts[j] + o);
+                out += 1;
+            }
+        }
+        assert_eq!(out, n);
+
+        SuffixArray::from_idxs(idxs, n)
+    }
+}
+
+/// Rank of every suffix of `parse` among all its suffixes, by prefix doubling.
+fn parse_suffix_ranks(parse: &[u32]) -> Vec<u32> {
+    let m = parse.len();
+    let mut rank = parse.iter().map(|&d| d as usize + 1).collect::<Vec<_>>();
+    let mut order = (0..m).collect::<Vec<_>>();
+    let mut h = 1;
+    loop {
+        let key = |i: usize| (rank[i], rank.get(i + h).copied().unwrap_or(0));
+        order.par_sort_unstable_by_key(|&i| key(i));
+        let mut new_rank = vec![0; m];
+        for w in 1..m {
+            new_rank[order[w]] =
+                new_rank[order[w - 1]] + (key(order[w - 1]) != key(order[w])) as usize;
+        }
+        new_rank.iter_mut().for_each(|r| *r += 1);
+        let done = new_rank[order[m - 1]] == m;
+        rank = new_rank;
+        if done {
+            break;
+        }
+        h *= 2;
+    }
+    rank.into_iter().map(|r| r as u32 - 1).collect()
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// The complete suffix array of `bytes` (without padding), built through a
+    /// prefix-free parse with windows of `w` bases and modulus `p`.
+    pub fn new_pfp(bytes: &[u8], w: usize, p: u64) -> Self {
+        let phase = Phase::start("Prefix-free parse");
+        let pfp = Pfp::new(bytes, w, p);
+        phase.finish();
+        build_log!(
+            "\tPhrases / distinct / dictionary length: {} / {} / {}",
+            pfp.parse().len(),
+            pfp.dictionary().len(),
+            pfp.dictionary_len()
+        );
+
+        let phase = Phase::start("Suffix array from parse");
+        let sa = pfp.suffix_array();
+        phase.finish();
+        sa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_pfp() {
+        let base = pseudo_dna(500);
+        let mut b = Vec::new();
+        for copy in 0..20 {
+            b.extend_from_slice(&base);
+            b[copy * 500 + copy * 7] = b'T';
+        }
+
+        let pfp = Pfp::new(&b, 4, 8);
+        assert!(pfp.dictionary_len() < b.len() / 2);
+        let phrases = pfp
+            .parse()
+            .iter()
+            .map(|&d| pfp.dictionary()[d as usize].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(phrases[0][..4], b[..4]);
+
+        let s = SuffixArray::<5>::new_pfp(&b, 4, 8);
+        let mut correct = (0..b.len()).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| b[x..].cmp(&b[y..]));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+
+        let bwt = s.bwt(&b);
+        assert_eq!(bwt.len(), b.len() + 1);
+        assert_eq!(bwt.iter().filter(|&&c| c == b'$').count(), 1);
+    }
+}
@@ -0,0 +1,475 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+
+/// A file mapped read-only into memory, through `mmap` on Unix and a file mapping
+/// object on Windows.
+pub(crate) struct Mmap {
+    ptr: *const u8,
+    len: usize,
+    #[cfg(windows)]
+    mapping: windows::Handle,
+}
+
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+/// Expected access pattern of part of a mapping.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// Read the pages in ahead of use.
+    WillNeed,
+    /// Drop the pages from memory; they are read again on the next access.
+    DontNeed,
+    Sequential,
+    Random,
+}
+
+impl std::str::FromStr for Advice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "willneed" => Ok(Self::WillNeed),
+            "dontneed" => Ok(Self::DontNeed),
+            "sequential" => Ok(Self::Sequential),
+            "random" => Ok(Self::Random),
+            _ => Err(format!(
+                "unknown advice {s}, expected willneed, dontneed, sequential or random"
+            )),
+        }
+    }
+}
+
+const PAGE: usize = 4096;
+
+impl Mmap {
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// The mapping is page aligned, so widen `range` to whole pages.
+    fn pages(&self, range: Range<usize>) -> Range<usize> {
+        assert!(range.start <= range.end && range.end <= self.len);
+        range.start / PAGE * PAGE..range.end.div_ceil(PAGE) * PAGE
+    }
+
+    /// Fault in the pages of `range` by reading a byte of each, after asking the OS to
+    /// read them ahead.
+    pub(crate) fn warmup(&self, range: Range<usize>) -> io::Result<()> {
+        self.advise(range.clone(), Advice::WillNeed)?;
+        let bytes = &self.as_slice()[range];
+        let mut sum = 0u8;
+        for i in (0..bytes.len()).step_by(PAGE) {
+            sum = sum.wrapping_add(unsafe { std::ptr::read_volatile(&bytes[i]) });
+        }
+        std::hint::black_box(sum);
+        Ok(())
+    }
+}
+
+/// A zeroed, writable buffer backed by large pages where the OS provides them:
+/// transparent huge pages on Linux and `MEM_LARGE_PAGES` on Windows, which needs the
+/// "Lock pages in memory" privilege. Without them it falls back to normal pages.
+pub struct LargePages {
+    ptr: *mut u8,
+    len: usize,
+    large: bool,
+}
+
+unsafe impl Send for LargePages {}
+unsafe impl Sync for LargePages {}
+
+impl LargePages {
+    /// Whether the buffer got large pages. On Linux this only means they were asked for.
+    pub fn is_large(&self) -> bool {
+        self.large
+    }
+}
+
+impl std::ops::Deref for LargePages {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl std::ops::DerefMut for LargePages {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Mmap {
+    /// Map the first `len` bytes of `f`.
+    pub(crate) fn map(f: &File, len: usize) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                f.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Mmap {
+    pub(crate) fn advise(&self, range: Range<usize>, advice: Advice) -> io::Result<()> {
+        let pages = self.pages(range);
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let advice = match advice {
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+        };
+        // The last page may extend past the mapping, which madvise accepts.
+        let ret =
+            unsafe { libc::madvise(self.ptr.add(pages.start) as *mut _, pages.len(), advice) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut _, self.len) };
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn read_exact_at(f: &File, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    f.read_exact_at(buf, offset)
+}
+
+#[cfg(unix)]
+impl LargePages {
+    /// Allocate `len` zeroed bytes.
+    pub fn new(len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Ok(Self {
+                ptr: std::ptr::NonNull::dangling().as_ptr(),
+                len,
+                large: false,
+            });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // Huge pages are only a hint; the kernel may not have them enabled.
+        #[cfg(target_os = "linux")]
+        let large = unsafe { libc::madvise(ptr, len, libc::MADV_HUGEPAGE) } == 0;
+        #[cfg(not(target_os = "linux"))]
+        let large = false;
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+            large,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for LargePages {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr as *mut _, self.len) };
+        }
+    }
+}
+
+/// Peak resident memory of this process in bytes.
+#[cfg(unix)]
+pub fn peak_memory_usage() -> usize {
+    let rusage = unsafe {
+        let mut rusage = std::mem::MaybeUninit::uninit();
+        libc::getrusage(libc::RUSAGE_SELF, rusage.as_mut_ptr());
+        rusage.assume_init()
+    };
+    let maxrss = rusage.ru_maxrss as usize;
+    if cfg!(target_os = "macos") {
+        maxrss
+    } else {
+        maxrss * 1024
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const PAGE_READONLY: u32 = 0x02;
+    pub const PAGE_READWRITE: u32 = 0x04;
+    pub const FILE_MAP_READ: u32 = 0x04;
+    pub const MEM_COMMIT: u32 = 0x1000;
+    pub const MEM_RESERVE: u32 = 0x2000;
+    pub const MEM_RELEASE: u32 = 0x8000;
+    pub const MEM_LARGE_PAGES: u32 = 0x2000_0000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct ProcessMemoryCounters {
+        pub cb: u32,
+        pub page_fault_count: u32,
+        pub peak_working_set_size: usize,
+        pub working_set_size: usize,
+        pub quota_peak_paged_pool_usage: usize,
+        pub quota_paged_pool_usage: usize,
+        pub quota_peak_non_paged_pool_usage: usize,
+        pub quota_non_paged_pool_usage: usize,
+        pub pagefile_usage: usize,
+        pub peak_pagefile_usage: usize,
+    }
+
+    #[repr(C)]
+    pub struct MemoryRangeEntry {
+        pub addr: *mut c_void,
+        pub len: usize,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn PrefetchVirtualMemory(
+            process: Handle,
+            entries: usize,
+            ranges: *const MemoryRangeEntry,
+            flags: u32,
+        ) -> i32;
+        pub fn CreateFileMappingW(
+            file: Handle,
+            attributes: *mut c_void,
+            protect: u32,
+            max_size_high: u32,
+            max_size_low: u32,
+            name: *const u16,
+        ) -> Handle;
+        pub fn MapViewOfFile(
+            mapping: Handle,
+            access: u32,
+            offset_high: u32,
+            offset_low: u32,
+            len: usize,
+        ) -> *mut c_void;
+        pub fn UnmapViewOfFile(addr: *const c_void) -> i32;
+        pub fn CloseHandle(handle: Handle) -> i32;
+        pub fn GetCurrentProcess() -> Handle;
+        pub fn GetLargePageMinimum() -> usize;
+        pub fn VirtualAlloc(addr: *mut c_void, len: usize, kind: u32, protect: u32) -> *mut c_void;
+        pub fn VirtualFree(addr: *mut c_void, len: usize, kind: u32) -> i32;
+        pub fn K32GetProcessMemoryInfo(
+            process: Handle,
+            counters: *mut ProcessMemoryCounters,
+            cb: u32,
+        ) -> i32;
+    }
+}
+
+#[cfg(windows)]
+impl Mmap {
+    /// Map the first `len` bytes of `f`.
+    pub(crate) fn map(f: &File, len: usize) -> io::Result<Self> {
+        use std::os::windows::io::AsRawHandle;
+        unsafe {
+            let mapping = windows::CreateFileMappingW(
+                f.as_raw_handle() as windows::Handle,
+                std::ptr::null_mut(),
+                windows::PAGE_READONLY,
+                0,
+                0,
+                std::ptr::null(),
+            );
+            if mapping.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let ptr = windows::MapViewOfFile(mapping, windows::FILE_MAP_READ, 0, 0, len);
+            if ptr.is_null() {
+                let err = io::Error::last_os_error();
+                windows::CloseHandle(mapping);
+                return Err(err);
+            }
+            Ok(Self {
+                ptr: ptr as *const u8,
+                len,
+                mapping,
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Mmap {
+    /// Only `WillNeed` has an equivalent for file mappings; other advice is ignored.
+    pub(crate) fn advise(&self, range: Range<usize>, advice: Advice) -> io::Result<()> {
+        let pages = self.pages(range);
+        let pages = pages.start..pages.end.min(self.len);
+        if advice != Advice::WillNeed || pages.is_empty() {
+            return Ok(());
+        }
+        let entry = windows::MemoryRangeEntry {
+            addr: unsafe { self.ptr.add(pages.start) } as *mut _,
+            len: pages.len(),
+        };
+        if unsafe { windows::PrefetchVirtualMemory(windows::GetCurrentProcess(), 1, &entry, 0) }
+            == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            windows::UnmapViewOfFile(self.ptr as _);
+            windows::CloseHandle(self.mapping);
+        }
+    }
+}
+
+#[cfg(windows)]
+impl LargePages {
+    /// Allocate `len` zeroed bytes.
+    pub fn new(len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Ok(Self {
+                ptr: std::ptr::NonNull::dangling().as_ptr(),
+                len,
+                large: false,
+            });
+        }
+        use windows::*;
+        unsafe {
+            // Large pages must be allocated in whole large pages at once, and fail
+            // without the privilege to lock memory.
+            let page = GetLargePageMinimum();
+            if page > 0 {
+                let ptr = VirtualAlloc(
+                    std::ptr::null_mut(),
+                    len.next_multiple_of(page),
+                    MEM_COMMIT | MEM_RESERVE | MEM_LARGE_PAGES,
+                    PAGE_READWRITE,
+                );
+                if !ptr.is_null() {
+                    return Ok(Self {
+                        ptr: ptr as *mut u8,
+                        len,
+                        large: true,
+                    });
+                }
+            }
+            let ptr = VirtualAlloc(
+                std::ptr::null_mut(),
+                len,
+                MEM_COMMIT | MEM_RESERVE,
+                PAGE_READWRITE,
+            );
+            if ptr.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                ptr: ptr as *mut u8,
+                len,
+                large: false,
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for LargePages {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { windows::VirtualFree(self.ptr as _, 0, windows::MEM_RELEASE) };
+        }
+    }
+}
+
+/// `seek_read` may return fewer bytes than asked for, so keep reading.
+#[cfg(windows)]
+pub(crate) fn read_exact_at(f: &File, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match f.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Peak resident memory (working set) of this process in bytes.
+#[cfg(windows)]
+pub fn peak_memory_usage() -> usize {
+    let mut counters = windows::ProcessMemoryCounters {
+        cb: std::mem::size_of::<windows::ProcessMemoryCounters>() as u32,
+        ..Default::default()
+    };
+    unsafe {
+        windows::K32GetProcessMemoryInfo(windows::GetCurrentProcess(), &mut counters, counters.cb);
+    }
+    counters.peak_working_set_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_mmap() {
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.mmap", std::process::id()));
+        File::create(&path).unwrap().write_all(b"ACGTACGT").unwrap();
+        let map = Mmap::map(&File::open(&path).unwrap(), 8).unwrap();
+        map.warmup(2..6).unwrap();
+        map.advise(0..8, Advice::DontNeed).unwrap();
+        assert_eq!(map.as_slice(), b"ACGTACGT");
+        drop(map);
+        std::fs::remove_file(&path).unwrap();
+        assert!(peak_memory_usage() > 0);
+
+        let mut buf = LargePages::new(3 << 20).unwrap();
+        assert!(buf.iter().all(|&b| b == 0));
+        buf[(3 << 20) - 1] = 1;
+        assert_eq!(buf.iter().map(|&b| b as usize).sum::<usize>(), 1);
+        assert!(LargePages::new(0).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+//! One-call workflows for the common cases, without const generics or `CompactVec`:
+//! index a FASTA file, find a pattern or the MEMs of a query, and save and load the
+//! index. Import with `use simple_saca::prelude::*;`.
+
+use std::io;
+use std::path::Path;
+
+pub use crate::alphabet::NonAcgt;
+use crate::bundle::*;
+pub use crate::fasta::{parse_fastx, read_fastx, FastxInput};
+use crate::query::*;
+use crate::suffix_array::*;
+use crate::sweep::SUPPORTED_CTX;
+use crate::tune::*;
+
+/// Entry width of a `GenomeIndex`, enough for texts of up to 2^40 bases.
+const BYTES: usize = 5;
+
+/// Options of `index_fasta`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IndexOptions {
+    /// Bases to bucket suffixes on, or 0 to choose from the text length.
+    pub k: usize,
+    /// Bases suffixes are sorted on, one of `SUPPORTED_CTX`. Longer patterns are still
+    /// found, but take longer.
+    pub ctx: usize,
+    /// Threads to bucket on, or 0 for those of the current rayon pool.
+    pub threads: usize,
+    /// What to do with bytes other than `ACGTU`: `Strip` or `Error`.
+    pub non_acgt: NonAcgt,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            k: 0,
+            ctx: 248,
+            threads: 0,
+            non_acgt: NonAcgt::Strip,
+        }
+    }
+}
+
+/// An occurrence at offset `pos` of sequence `seq`, see `GenomeIndex::name`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hit {
+    pub seq: usize,
+    pub pos: usize,
+}
+
+/// An exact match of `query[query_pos..query_pos + len]` at offset `pos` of sequence
+/// `seq`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GenomeMem {
+    pub query_pos: usize,
+    pub seq: usize,
+    pub pos: usize,
+    pub len: usize,
+}
+
+/// The suffix array of a set of sequences, with the sequences and their names.
+pub struct GenomeIndex {
+    bundle: Bundle<BYTES>,
+}
+
+/// Read the FASTA or FASTQ file at `path` and index it.
+pub fn index_fasta(path: impl AsRef<Path>, opts: &IndexOptions) -> io::Result<GenomeIndex> {
+    GenomeIndex::build(read_fastx(path, opts.non_acgt)?, opts)
+}
+
+impl GenomeIndex {
+    /// Index `input`, as read by `read_fastx` or `parse_fastx` and not yet padded.
+    pub fn build(mut input: FastxInput, opts: &IndexOptions) -> io::Result<Self> {
+        if opts.non_acgt == NonAcgt::Symbol {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "non-ACGT symbols are not supported, strip them or fail on them",
+            ));
+        }
+        if !SUPPORTED_CTX.contains(&opts.ctx) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("context length {} is not supported", opts.ctx),
+            ));
+        }
+        let n = input.seq.len() + opts.ctx;
+        let threads = pool_bucket_threads(opts.threads);
+        let k = match opts.k {
+            0 => auto_tune(n, BYTES, usize::MAX, threads).unwrap().k,
+            k => k,
+        };
+        input.pad(opts.ctx);
+        let sa = input.suffix_array::<BYTES>(k, threads);
+        Ok(Self {
+            bundle: Bundle::new(sa, input.seq, &input.dict),
+        })
+    }
+
+    /// Total number of indexed bases.
+    pub fn len(&self) -> usize {
+        self.bundle.suffix_array().idxs().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn num_seqs(&self) -> usize {
+        self.bundle.dict().len()
+    }
+
+    /// Name of sequence `seq`, its whole header line.
+    pub fn name(&self, seq: usize) -> &str {
+        self.bundle.dict().name(seq)
+    }
+
+    /// Length of sequence `seq` in bases, without removed bytes.
+    pub fn seq_len(&self, seq: usize) -> usize {
+        self.bundle.dict().seq_len(seq)
+    }
+
+    /// The sequence and offset of `len` bases at text position `pos`, unless they run
+    /// past the end of the sequence.
+    fn hit(&self, pos: usize, len: usize) -> Option<Hit> {
+        let (seq, pos) = self.bundle.dict().locate(pos)?;
+        (pos + len <= self.seq_len(seq)).then_some(Hit { seq, pos })
+    }
+
+    /// All occurrences of `pattern` within one sequence, by sequence and position.
+    pub fn locate(&self, pattern: &[u8]) -> Vec<Hit> {
+        let (sa, text) = (self.bundle.suffix_array(), self.bundle.text());
+        let prefix = &pattern[..pattern.len().min(sa.ctx())];
+        let mut hits = sa
+            .locate(text, prefix, HitOrder::Text)
+            .into_iter()
+            .filter(|&pos| prefix.len() == pattern.len() || cmp_pattern(text, pos, pattern).is_eq())
+            .filter_map(|pos| self.hit(pos, pattern.len()))
+            .collect::<Vec<_>>();
+        hits.sort_unstable();
+        hits
+    }
+
+    /// Maximal exact matches of at least `min_len` bases between `query` and the
+    /// sequences, by query position. Matches running past the end of a sequence are cut
+    /// there.
+    pub fn mems(&self, query: &[u8], min_len: usize) -> Vec<GenomeMem> {
+        let (sa, text) = (self.bundle.suffix_array(), self.bundle.text());
+        sa.mems(text, query, min_len, usize::MAX, HitOrder::SuffixArray)
+            .into_iter()
+            .filter_map(|m| {
+                let (seq, pos) = self.bundle.dict().locate(m.text_pos)?;
+                let len = m.len.min(self.seq_len(seq) - pos);
+                (len >= min_len).then_some(GenomeMem {
+                    query_pos: m.query_pos,
+                    seq,
+                    pos,
+                    len,
+                })
+            })
+            .collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.bundle.write(path)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            bundle: Bundle::read(path)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genome_index() {
+        let mut fasta = b">a\n".to_vec();
+        fasta.extend_from_slice(&b"ACGTTGCAGATTACAGGATCCA".repeat(20));
+        fasta.extend_from_slice(b"\n>b second\nGATTACANNGATTACA\n");
+        let path =
+            std::env::temp_dir().join(format!("simple-saca-{}-prelude.fa", std::process::id()));
+        std::fs::write(&path, &fasta).unwrap();
+        let opts = IndexOptions {
+            ctx: 124,
+            ..IndexOptions::default()
+        };
+        let index = index_fasta(&path, &opts).unwrap();
+        assert_eq!(index.num_seqs(), 2);
+        assert_eq!(index.name(1), "b second");
+        assert_eq!(index.len(), 22 * 20 + 14);
+
+        let hits = index.locate(b"GATTACA");
+        assert_eq!(hits.len(), 22);
+        assert_eq!(hits[20..], [Hit { seq: 1, pos: 0 }, Hit { seq: 1, pos: 7 }]);
+        // Only found running from the end of `a` into `b`.
+        assert!(index.locate(b"GGATCCAGATTACA").is_empty());
+        let long = b"ACGTTGCAGATTACAGGATCCA".repeat(8);
+        assert_eq!(index.locate(&long).len(), 13);
+
+        let mems = index.mems(b"TTTTGATTACAGATTACATTTT", 10);
+        assert!(mems.contains(&GenomeMem {
+            query_pos: 4,
+            seq: 1,
+            pos: 0,
+            len: 14
+        }));
+
+        index.save(&path).unwrap();
+        let loaded = GenomeIndex::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.locate(b"GATTACA"), hits);
+
+        let symbol = IndexOptions {
+            non_acgt: NonAcgt::Symbol,
+            ..opts
+        };
+        assert!(GenomeIndex::build(FastxInput::new(NonAcgt::Strip), &symbol).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use crate::compact_vec::*;
+use crate::kmers::*;
+use crate::perf::*;
+use crate::sorter::*;
+use crate::suffix_array::*;
+
+/// A sequence packed once for building the suffix arrays of many overlapping regions
+/// of it, e.g. every chromosome and then the whole genome, or sliding windows.
+///
+/// The sequence is split into blocks of equal length. A build counts and scatters the
+/// blocks overlapping its range as its chunks, and keeps the bucket counts of the
+/// blocks it covers fully, `4^k` counters each, for later builds with the same `k`.
+pub struct PreparedText<const BYTES: usize> {
+    packed: RevPacked,
+    len: usize,
+    pad: usize,
+    block_len: usize,
+    counts: Mutex<HashMap<(usize, usize), CompactVec<BYTES>>>,
+}
+
+impl<const BYTES: usize> PreparedText<BYTES> {
+    /// Pack `seq`, without padding, into `blocks` blocks, for builds with a context of
+    /// at most `pad`.
+    pub fn new(seq: &[u8], pad: usize, blocks: usize) -> Self {
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, pad);
+        phase.finish();
+        Self {
+            packed,
+            len: seq.len(),
+            pad,
+            block_len: seq.len().div_ceil(blocks.max(1)).max(1),
+            counts: Mutex::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of block counts kept for reuse.
+    pub fn cached_blocks(&self) -> usize {
+        self.counts.lock().unwrap().len()
+    }
+
+    /// Drop the kept block counts.
+    pub fn clear_counts(&self) {
+        self.counts.lock().unwrap().clear();
+    }
+
+    fn block(&self, b: usize) -> Range<usize> {
+        b * self.block_len..((b + 1) * self.block_len).min(self.len)
+    }
+
+    /// The block that `chunk` is exactly, if any.
+    fn full_block(&self, chunk: &Range<usize>) -> Option<usize> {
+        let b = chunk.start / self.block_len;
+        (self.block(b) == *chunk).then_some(b)
+    }
+
+    /// `range` cut at the block boundaries.
+    fn chunks(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        if range.is_empty() {
+            return vec![range];
+        }
+        (range.start / self.block_len..range.end.div_ceil(self.block_len))
+            .map(|b| {
+                let block = self.block(b);
+                block.start.max(range.start)..block.end.min(range.end)
+            })
+            .collect()
+    }
+
+    /// The sorted suffixes starting in `range`, like `new_packed_range` on the
+    /// sequence: comparisons read the context beyond the end of `range`, and entries are
+    /// positions in the sequence.
+    pub fn build<const CTX: usize>(&self, range: Range<usize>, k: usize) -> SuffixArray<BYTES> {
+        assert!(
+            CTX <= self.pad,
+            "context of {CTX} exceeds the padding of {}",
+            self.pad
+        );
+        assert!(range.end <= self.len);
+        let seed = SpacedSeed::contiguous(k);
+        let chunks = self.chunks(range);
+
+        let mut counts = {
+            let cache = self.counts.lock().unwrap();
+            chunks
+                .iter()
+                .map(|c| cache.get(&(self.full_block(c)?, k)).cloned())
+                .collect::<Vec<_>>()
+        };
+        let missing = (0..chunks.len())
+            .filter(|&c| counts[c].is_none())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            let uncounted = missing
+                .iter()
+                .map(|&c| chunks[c].clone())
+                .collect::<Vec<_>>();
+            let fresh = unsafe {
+                SuffixArray::<BYTES>::count_buckets::<false>(
+                    &self.packed,
+                    &uncounted,
+                    seed,
+                    &Selection::all(),
+                )
+            };
+            let mut cache = self.counts.lock().unwrap();
+            for (c, chunk_counts) in missing.into_iter().zip(fresh) {
+                if let Some(b) = self.full_block(&chunks[c]) {
+                    cache.insert((b, k), chunk_counts.clone());
+                }
+                counts[c] = Some(chunk_counts);
+            }
+        }
+
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            SuffixArray::sort_rev_packed_counted::<CTX, false>(
+                &self.packed,
+                &chunks,
+                counts.into_iter().map(Option::unwrap).collect(),
+                SortOptions {
+                    seed,
+                    selection: Selection::all(),
+                    ties: TieOrder::Ascending,
+                    in_bucket: InBucket::Compare,
+                    min_task_entries: Schedule::default().min_task_entries,
+                },
+            )
+        };
+        SuffixArray::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
+    }
+
+    /// `build` with the context length chosen at runtime.
+    pub fn build_with_ctx(&self, range: Range<usize>, ctx: usize, k: usize) -> SuffixArray<BYTES> {
+        match ctx {
+            124 => self.build::<124>(range, k),
+            248 => self.build::<248>(range, k),
+            496 => self.build::<496>(range, k),
+            992 => self.build::<992>(range, k),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::*;
+
+    #[test]
+    fn test_prepared_text() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACAGGATCCA".repeat(40);
+        let prepared = PreparedText::<5>::new(&seq, CTX, 8);
+
+        for range in [0..seq.len(), 100..500, 333..334, 50..50, 0..seq.len()] {
+            let cached = prepared.cached_blocks();
+            let (sa, stats) = record_build(|| prepared.build::<CTX>(range.clone(), 3));
+            let fresh = SuffixArray::<5>::new_packed_range::<CTX>(&seq, range.clone(), 3, 2);
+            assert_eq!(sa.idxs().to_usize_vec(), fresh.idxs().to_usize_vec());
+            // `new_packed_range` lowers `k` for short ranges.
+            if fresh.k() == 3 {
+                assert_eq!(
+                    sa.bucket_ends().to_usize_vec(),
+                    fresh.bucket_ends().to_usize_vec()
+                );
+            }
+            // Once every block is counted, whole builds count nothing.
+            if cached == 8 && range == (0..seq.len()) {
+                assert!(stats.phase("Parallel bucket count").is_none());
+            }
+        }
+        assert_eq!(prepared.cached_blocks(), 8);
+        prepared.clear_counts();
+        assert_eq!(prepared.cached_blocks(), 0);
+    }
+}
@@ -0,0 +1,477 @@
+use rayon::prelude::*;
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::ops::Range;
+
+use crate::bed::*;
+use crate::compact_vec::*;
+use crate::seq_dict::*;
+use crate::simd::*;
+use crate::suffix_array::*;
+
+#[inline]
+pub(crate) fn cmp_pattern(seq: &[u8], pos: usize, pattern: &[u8]) -> Ordering {
+    // Identical bytes are identical bases; only compare codes from the first
+    // difference, which may just be one of case.
+    let skip = common_prefix(&seq[pos..], pattern);
+    for (&a, &b) in seq[pos + skip..].iter().zip(&pattern[skip..]) {
+        match base_code(a).cmp(&base_code(b)) {
+            Ordering::Equal => (),
+            ord => return ord,
+        }
+    }
+
+    // Past the end of the sequence, the suffix continues with the padding `A`s.
+    let rest = pattern.get(seq.len() - pos..).unwrap_or(&[]);
+    if rest.iter().any(|&b| base_code(b) > 0) {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// Range of the sorted `idxs` whose suffixes start with `pattern`.
+pub(crate) fn pattern_range<const BYTES: usize>(
+    idxs: &[Int<BYTES>],
+    seq: &[u8],
+    pattern: &[u8],
+) -> Range<usize> {
+    let start = idxs.partition_point(|i| cmp_pattern(seq, i.get_usize(), pattern).is_lt());
+    let end =
+        start + idxs[start..].partition_point(|i| cmp_pattern(seq, i.get_usize(), pattern).is_eq());
+    start..end
+}
+
+/// Number of binary searches interleaved by `pattern_ranges`.
+const INTERLEAVE: usize = 16;
+
+/// Partition points of `idxs` for all `patterns`, where an entry is before the point
+/// when `cmp_pattern` gives `Less`, or with `or_equal` also `Equal`.
+///
+/// The searches advance one step at a time in turn: each step first loads the suffix
+/// array entry prefetched by the previous step and prefetches its text, then compares
+/// and prefetches the next entry, so that the cache misses of all searches overlap.
+fn interleaved_partition_points<const BYTES: usize>(
+    idxs: &[Int<BYTES>],
+    seq: &[u8],
+    patterns: &[&[u8]],
+    or_equal: bool,
+) -> Vec<usize> {
+    let mut base = vec![0; patterns.len()];
+    let mut len = vec![idxs.len(); patterns.len()];
+    let mut pos = vec![0; patterns.len()];
+    for q in 0..patterns.len() {
+        if len[q] > 0 {
+            prefetch(&idxs[len[q] / 2]);
+        }
+    }
+
+    loop {
+        let mut active = false;
+        for q in 0..patterns.len() {
+            if len[q] > 0 {
+                active = true;
+                pos[q] = idxs[base[q] + len[q] / 2].get_usize();
+                prefetch(seq[pos[q]..].as_ptr());
+            }
+        }
+        if !active {
+            return base;
+        }
+        for q in 0..patterns.len() {
+            if len[q] == 0 {
+                continue;
+            }
+            let half = len[q] / 2;
+            let ord = cmp_pattern(seq, pos[q], patterns[q]);
+            if ord.is_lt() || (or_equal && ord.is_eq()) {
+                base[q] += half + 1;
+                len[q] -= half + 1;
+            } else {
+                len[q] = half;
+            }
+            if len[q] > 0 {
+                prefetch(&idxs[base[q] + len[q] / 2]);
+            }
+        }
+    }
+}
+
+/// `pattern_range` for many patterns, with the binary searches of `INTERLEAVE`
+/// patterns at a time interleaved to hide memory latency on large suffix arrays.
+pub(crate) fn pattern_ranges<const BYTES: usize>(
+    idxs: &[Int<BYTES>],
+    seq: &[u8],
+    patterns: &[&[u8]],
+) -> Vec<Range<usize>> {
+    patterns
+        .par_chunks(INTERLEAVE)
+        .flat_map_iter(|batch| {
+            let starts = interleaved_partition_points(idxs, seq, batch, false);
+            let ends = interleaved_partition_points(idxs, seq, batch, true);
+            starts.into_iter().zip(ends).map(|(s, e)| s..e)
+        })
+        .collect()
+}
+
+/// Order in which occurrences are reported.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HitOrder {
+    /// Suffix array order, i.e. lexicographic by the text following each hit.
+    #[default]
+    SuffixArray,
+    /// Increasing text position.
+    Text,
+}
+
+/// Limits on the occurrences a query reports. Patterns and matches that are too short
+/// or too frequent are skipped from their suffix array range alone, before any text
+/// position is read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HitFilter {
+    /// Skip patterns, and matches, shorter than this.
+    pub min_len: usize,
+    /// Skip patterns, and matches, occurring more often than this.
+    pub max_freq: usize,
+    /// Report at most this many occurrences per pattern or query, the first ones in
+    /// the requested order.
+    pub max_hits: usize,
+}
+
+impl Default for HitFilter {
+    fn default() -> Self {
+        Self {
+            min_len: 0,
+            max_freq: usize::MAX,
+            max_hits: usize::MAX,
+        }
+    }
+}
+
+impl HitFilter {
+    /// Whether a pattern of `len` bases with `freq` occurrences passes.
+    pub fn accepts(&self, len: usize, freq: usize) -> bool {
+        len >= self.min_len && freq <= self.max_freq
+    }
+}
+
+/// Merge runs that are each sorted by `key` into one sorted vector.
+pub(crate) fn kway_merge<T: Copy, K: Ord>(runs: &[Vec<T>], key: impl Fn(&T) -> K) -> Vec<T> {
+    let mut res = Vec::with_capacity(runs.iter().map(|r| r.len()).sum());
+    let mut heap = runs
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| !r.is_empty())
+        .map(|(i, r)| Reverse((key(&r[0]), i, 0)))
+        .collect::<BinaryHeap<_>>();
+
+    while let Some(Reverse((_, i, j))) = heap.pop() {
+        res.push(runs[i][j]);
+        if let Some(next) = runs[i].get(j + 1) {
+            heap.push(Reverse((key(next), i, j + 1)));
+        }
+    }
+    res
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Text positions of all occurrences of `pattern` in the sequence `seq`.
+    pub fn locate(&self, seq: &[u8], pattern: &[u8], order: HitOrder) -> Vec<usize> {
+        self.locate_filtered(seq, pattern, order, HitFilter::default())
+    }
+
+    /// `locate`, limited by `filter`.
+    pub fn locate_filtered(
+        &self,
+        seq: &[u8],
+        pattern: &[u8],
+        order: HitOrder,
+        filter: HitFilter,
+    ) -> Vec<usize> {
+        let range = self.range(seq, pattern);
+        if !filter.accepts(pattern.len(), range.len()) {
+            return Vec::new();
+        }
+        let idxs = &self.idxs()[range.clone()];
+        let mut hits = match order {
+            HitOrder::SuffixArray => idxs
+                .iter()
+                .take(filter.max_hits)
+                .map(|i| i.get_usize())
+                .collect(),
+            HitOrder::Text => {
+                // Short patterns span several buckets; sort the hits of each bucket
+                // separately and merge them.
+                let mut runs = self
+                    .bucket_splits(range.clone())
+                    .windows(2)
+                    .map(|w| {
+                        idxs[w[0] - range.start..w[1] - range.start]
+                            .iter()
+                            .map(|i| i.get_usize())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                runs.par_iter_mut().for_each(|r| r.sort_unstable());
+                kway_merge(&runs, |&p| p)
+            }
+        };
+        hits.truncate(filter.max_hits);
+        hits
+    }
+
+    /// `locate`, dropping or flagging occurrences that overlap `mask`.
+    pub fn locate_masked(
+        &self,
+        seq: &[u8],
+        pattern: &[u8],
+        order: HitOrder,
+        mask: &Mask,
+        mode: MaskMode,
+    ) -> Vec<(usize, bool)> {
+        let hits = self.locate(seq, pattern, order);
+        mask.apply(hits, mode, |&p| p..p + pattern.len())
+    }
+
+    /// Boundaries of `range` and of the buckets inside it.
+    fn bucket_splits(&self, range: Range<usize>) -> Vec<usize> {
+        let ends = self.bucket_ends();
+        let first = ends.partition_point(|e| e.get_usize() <= range.start);
+        let mut res = vec![range.start];
+        res.extend(
+            ends[first.min(ends.len())..]
+                .iter()
+                .map(|e| e.get_usize())
+                .take_while(|&e| e < range.end)
+                .filter(|&e| e > range.start),
+        );
+        res.push(range.end);
+        res.dedup();
+        res
+    }
+
+    /// Range of suffix array entries whose suffixes start with `pattern`.
+    /// Only patterns up to the sorted context length can be searched, and only in
+    /// arrays that are not sampled; use `locate_sparse` for those.
+    pub fn range(&self, seq: &[u8], pattern: &[u8]) -> Range<usize> {
+        assert!(pattern.len() <= self.ctx());
+        assert!(self.is_lexicographic());
+        assert_eq!(self.sample_rate(), 1, "sampled arrays need locate_sparse");
+        pattern_range(self.idxs(), seq, pattern)
+    }
+
+    /// `range` for every pattern, interleaving the binary searches.
+    pub fn ranges(&self, seq: &[u8], patterns: &[&[u8]]) -> Vec<Range<usize>> {
+        assert!(patterns.iter().all(|p| p.len() <= self.ctx()));
+        assert!(self.is_lexicographic());
+        assert_eq!(self.sample_rate(), 1, "sampled arrays need locate_sparse");
+        pattern_ranges(self.idxs(), seq, patterns)
+    }
+
+    /// Number of occurrences of `pattern`.
+    pub fn count(&self, seq: &[u8], pattern: &[u8]) -> usize {
+        self.range(seq, pattern).len()
+    }
+
+    /// Text positions of the occurrences of `pattern` in suffix array order, read from
+    /// the array as the iterator advances.
+    pub fn locate_iter(&self, seq: &[u8], pattern: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        self.idxs()[self.range(seq, pattern)]
+            .iter()
+            .map(|i| i.get_usize())
+    }
+
+    /// Number of occurrences of every pattern.
+    pub fn counts(&self, seq: &[u8], patterns: &[&[u8]]) -> Vec<usize> {
+        self.ranges(seq, patterns)
+            .into_iter()
+            .map(|r| r.len())
+            .collect()
+    }
+
+    /// `locate` for every pattern, interleaving the binary searches.
+    pub fn locate_batch(&self, seq: &[u8], patterns: &[&[u8]], order: HitOrder) -> Vec<Vec<usize>> {
+        self.locate_batch_filtered(seq, patterns, order, HitFilter::default())
+    }
+
+    /// `locate_batch`, limited by `filter`.
+    pub fn locate_batch_filtered(
+        &self,
+        seq: &[u8],
+        patterns: &[&[u8]],
+        order: HitOrder,
+        filter: HitFilter,
+    ) -> Vec<Vec<usize>> {
+        let ranges = self.ranges(seq, patterns);
+        ranges
+            .into_par_iter()
+            .zip(patterns)
+            .map(|(range, pattern)| {
+                if !filter.accepts(pattern.len(), range.len()) {
+                    return Vec::new();
+                }
+                let mut hits = self.idxs()[range]
+                    .iter()
+                    .map(|i| i.get_usize())
+                    .collect::<Vec<_>>();
+                if order == HitOrder::Text {
+                    hits.sort_unstable();
+                }
+                hits.truncate(filter.max_hits);
+                hits
+            })
+            .collect()
+    }
+
+    /// Sorted, deduplicated ids of the sequences containing `pattern`. Occurrences that
+    /// run across the end of a sequence are not counted.
+    pub fn documents_with(&self, seq: &[u8], docs: &DocArray, pattern: &[u8]) -> Vec<SeqId> {
+        let range = self.range(seq, pattern);
+        let dict = docs.dict();
+        let idxs = &self.idxs()[range.clone()];
+        let docs = &docs.docs()[range];
+        let contained = |(i, &d): (&Int<BYTES>, &SeqId)| {
+            d != DocArray::NONE && i.get_usize() + pattern.len() <= dict.end(d as usize)
+        };
+
+        if docs.len() < dict.len() {
+            let mut res = idxs
+                .iter()
+                .zip(docs)
+                .filter(|&e| contained(e))
+                .map(|(_, &d)| d)
+                .collect::<Vec<_>>();
+            res.sort_unstable();
+            res.dedup();
+            return res;
+        }
+
+        let seen = idxs
+            .par_iter()
+            .zip(docs.par_iter())
+            .filter(|&e| contained(e))
+            .fold(
+                || vec![false; dict.len()],
+                |mut seen, (_, &d)| {
+                    seen[d as usize] = true;
+                    seen
+                },
+            )
+            .reduce(
+                || vec![false; dict.len()],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a |= b);
+                    a
+                },
+            );
+
+        (0..dict.len() as SeqId)
+            .filter(|&d| seen[d as usize])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_documents_with() {
+        const CTX: usize = 124;
+        let b = b"ACGTACGTACGTTTTTCCAT";
+        let mut dict = SeqDict::new();
+        dict.push("a", 8);
+        dict.push("b", 8);
+        dict.push("c", 4);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(b, 2, 1);
+        let docs = DocArray::new(&s, &dict);
+        assert_eq!(s.range(b, b"ACGT").len(), 3);
+        assert_eq!(s.documents_with(b, &docs, b"ACGT"), [0, 1]);
+        assert_eq!(s.documents_with(b, &docs, b"TTT"), [1]);
+        assert_eq!(s.documents_with(b, &docs, b"TTCC"), []);
+        assert_eq!(s.documents_with(b, &docs, b"A"), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_locate_text_order() {
+        const CTX: usize = 124;
+        let b = b"TACGTACGTACGTTTTTCCAT";
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(b, 2, 1);
+        let mut hits = s.locate(b, b"T", HitOrder::SuffixArray);
+        assert_ne!(hits, [0, 4, 8, 12, 13, 14, 15, 16, 20]);
+        hits.sort();
+        assert_eq!(hits, [0, 4, 8, 12, 13, 14, 15, 16, 20]);
+        assert_eq!(s.locate(b, b"T", HitOrder::Text), hits);
+    }
+
+    #[test]
+    fn test_count_locate() {
+        const CTX: usize = 124;
+        let mut seq = b"ACGTTGCAGATTACA".repeat(10);
+        seq.extend_from_slice(&b"gattacagattacagattacagattacagattacagattaca".repeat(2));
+        let b = pad_text(&seq, CTX);
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 1);
+
+        let long = b"GATTACAGATTACAGATTACAGATTACAGATTACAGATTACA";
+        for pattern in [&b"GATTA"[..], b"gatta", &long[..], &long[..40], b"CCC", b""] {
+            let hits = s.locate(&seq, pattern, HitOrder::SuffixArray);
+            assert_eq!(s.count(&seq, pattern), hits.len());
+            assert_eq!(s.locate_iter(&seq, pattern).collect::<Vec<_>>(), hits);
+            // Occurrences may run into the padding.
+            let naive = (0..seq.len())
+                .filter(|&i| {
+                    b[i..].len() >= pattern.len()
+                        && b[i..]
+                            .iter()
+                            .zip(pattern)
+                            .all(|(&x, &y)| base_code(x) == base_code(y))
+                })
+                .count();
+            assert_eq!(hits.len(), naive, "{pattern:?}");
+        }
+        assert!(s.count(&seq, long) > 1);
+        assert_eq!(s.count(&seq, b"CAAA"), s.count(&b, b"CAAA"));
+        assert_eq!(s.locate(&seq, b"CAAA", HitOrder::Text), [seq.len() - 2]);
+    }
+
+    #[test]
+    fn test_batched_queries() {
+        const CTX: usize = 124;
+        let b = pseudo_dna(2000);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2);
+        let patterns = (0..100)
+            .map(|i| &b[i * 17..i * 17 + 1 + i % 9])
+            .chain([&b"TTTTTTTTTTTT"[..], b"", b"A"])
+            .collect::<Vec<_>>();
+        let ranges = s.ranges(&b, &patterns);
+        for (p, r) in patterns.iter().zip(&ranges) {
+            assert_eq!(*r, s.range(&b, p));
+        }
+        let hits = s.locate_batch(&b, &patterns, HitOrder::Text);
+        assert_eq!(hits[5], s.locate(&b, patterns[5], HitOrder::Text));
+        assert_eq!(s.counts(&b, &patterns)[100], 0);
+
+        let filter = HitFilter {
+            min_len: 2,
+            max_freq: 50,
+            max_hits: 3,
+        };
+        let filtered = s.locate_batch_filtered(&b, &patterns, HitOrder::Text, filter);
+        for (i, p) in patterns.iter().enumerate() {
+            let all = &hits[i];
+            let expected = if p.len() < 2 || all.len() > 50 {
+                &[][..]
+            } else {
+                &all[..all.len().min(3)]
+            };
+            assert_eq!(filtered[i], expected);
+            assert_eq!(s.locate_filtered(&b, p, HitOrder::Text, filter), expected);
+        }
+        assert!(filtered.iter().any(|h| h.len() == 3));
+    }
+}
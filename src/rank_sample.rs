@@ -0,0 +1,220 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::query::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACARNK";
+
+/// Suffix array ranks of every `rate`-th text position: a sparse inverse suffix array,
+/// `rate` times smaller than the full one.
+pub struct RankSample<const BYTES: usize> {
+    rate: usize,
+    ctx: usize,
+    ranks: CompactVec<BYTES>,
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// The inverse suffix array, in the same entry width: entry `pos` is the rank of the
+    /// suffix at `pos`. The array must hold every position of its text, as arrays of
+    /// the whole text do.
+    pub fn inverse(&self) -> CompactVec<BYTES> {
+        let n = self.idxs().len();
+        let mut isa = CompactVec::<BYTES>::new(n);
+        let isa_ptr = MutPtr(isa.as_mut_ptr());
+        self.idxs().par_iter().enumerate().for_each(|(rank, i)| {
+            let ptr = isa_ptr;
+            let pos = i.get_usize();
+            assert!(pos < n, "position {pos} out of range for an inverse of {n}");
+            unsafe { (*ptr.0.add(pos)).set_usize(rank) };
+        });
+        isa
+    }
+
+    /// Rank of the suffix at `pos` of the padded text `bytes`, by binary search over
+    /// the suffixes that share its context, without the inverse suffix array. `None`
+    /// if the array does not hold `pos`.
+    pub fn rank(&self, bytes: &[u8], pos: usize) -> Option<usize> {
+        assert!(self.is_lexicographic());
+        let range = pattern_range(self.idxs(), bytes, &bytes[pos..pos + self.ctx()]);
+        let tied = &self.idxs()[range.clone()];
+        // Suffixes tied over the context are ordered by position, unless the array is
+        // exact and orders them on their full suffix.
+        let offset = match (self.is_exact(), self.tie_order()) {
+            (true, _) => tied.iter().position(|i| i.get_usize() == pos)?,
+            (false, TieOrder::Ascending) => tied.partition_point(|i| i.get_usize() < pos),
+            (false, TieOrder::Descending) => tied.partition_point(|i| i.get_usize() > pos),
+        };
+        (tied.get(offset)?.get_usize() == pos).then_some(range.start + offset)
+    }
+
+    /// Sample the rank of every position that is a multiple of `rate`, for a text of
+    /// `text_len` positions (without padding).
+    pub fn sample_ranks(&self, text_len: usize, rate: usize) -> RankSample<BYTES> {
+        assert!(rate > 0);
+        assert!(self.is_lexicographic());
+        assert_eq!(self.idxs().len(), text_len);
+        let mut ranks = CompactVec::<BYTES>::new(text_len.div_ceil(rate));
+        let ranks_ptr = MutPtr(ranks.as_mut_ptr());
+        self.idxs().par_iter().enumerate().for_each(|(rank, i)| {
+            let ptr = ranks_ptr;
+            let pos = i.get_usize();
+            if pos.is_multiple_of(rate) {
+                unsafe { (*ptr.0.add(pos / rate)).set_usize(rank) };
+            }
+        });
+        RankSample {
+            rate,
+            ctx: self.ctx(),
+            ranks,
+        }
+    }
+}
+
+impl<const BYTES: usize> RankSample<BYTES> {
+    pub fn rate(&self) -> usize {
+        self.rate
+    }
+
+    /// Number of text positions covered.
+    fn text_len(&self) -> usize {
+        self.ranks.len() * self.rate
+    }
+
+    /// Rank of `pos` if it is sampled.
+    pub fn rank(&self, pos: usize) -> Option<usize> {
+        pos.is_multiple_of(self.rate)
+            .then(|| self.ranks.get(pos / self.rate).map(|r| r.get_usize()))
+            .flatten()
+    }
+
+    /// Rank of the last sampled position at or before `pos`, and the distance to it.
+    /// The suffix at `pos` is that suffix with the first `distance` bases removed.
+    pub fn approx_rank(&self, pos: usize) -> (usize, usize) {
+        let sampled = pos / self.rate;
+        (self.ranks[sampled].get_usize(), pos - sampled * self.rate)
+    }
+
+    /// Compare the suffixes at `i` and `j` of the padded text `bytes` without the full
+    /// inverse suffix array. When `i` and `j` are equal modulo the rate, fewer than
+    /// `rate` bases are compared before the ranks of the next sampled positions decide;
+    /// otherwise the whole context is compared, with ties by position. Suffixes that
+    /// differ within the context are ordered as in the suffix array.
+    pub fn cmp_suffixes(&self, bytes: &[u8], i: usize, j: usize) -> Ordering {
+        if i == j {
+            return Ordering::Equal;
+        }
+        let n = self.text_len();
+        let max = if i % self.rate == j % self.rate {
+            (self.rate - i % self.rate) % self.rate
+        } else {
+            self.ctx
+        };
+        for d in 0..max {
+            match base_code(bytes[i + d]).cmp(&base_code(bytes[j + d])) {
+                Ordering::Equal => (),
+                ord => return ord,
+            }
+        }
+        match (self.rank(i + max), self.rank(j + max)) {
+            (Some(a), Some(b)) if i + max < n && j + max < n => a.cmp(&b),
+            _ => i.cmp(&j),
+        }
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_sections(
+            path.as_ref(),
+            MAGIC,
+            BYTES,
+            self.rate,
+            self.ctx,
+            &[("ranks", self.ranks.as_bytes())],
+        )
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let header = Header::read(&mut f, MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "rank sample has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+        Ok(Self {
+            rate: header.k,
+            ctx: header.ctx,
+            ranks: CompactVec::from_bytes(&read_section(&mut f, &header, "ranks")?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_rank_sample() {
+        const CTX: usize = 124;
+        let mut seq = pseudo_dna(1000);
+        seq.extend_from_within(0..400);
+        let n = seq.len();
+        let b = pad_text(&seq, CTX);
+
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 1);
+        let sample = sa.sample_ranks(n, 8);
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.ranks", std::process::id()));
+        sample.write(&path).unwrap();
+        let sample = RankSample::<5>::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let idxs = sa.idxs().to_usize_vec();
+        let mut isa = vec![0; n];
+        for (r, &i) in idxs.iter().enumerate() {
+            isa[i] = r;
+        }
+        assert_eq!(sample.rank(800), Some(isa[800]));
+        assert_eq!(sample.rank(801), None);
+        assert_eq!(sample.approx_rank(805), (isa[800], 5));
+
+        for (i, j) in [(3, 11), (5, 1005), (4, 9), (1000, 0), (8, 1008), (17, 17)] {
+            let ord = sample.cmp_suffixes(&b, i, j);
+            if b[i..i + CTX] != b[j..j + CTX] {
+                assert_eq!(ord, isa[i].cmp(&isa[j]), "{i} {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        const CTX: usize = 124;
+        // The repeat of 400 bases ties suffixes over the context.
+        let mut seq = b"ACGTTGCAGATTACAGGATCCA".repeat(20);
+        seq.extend_from_within(0..400);
+        let b = pad_text(&seq, CTX);
+
+        for sa in [
+            SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2),
+            SuffixArray::<5>::new_packed_ordered::<CTX>(&seq, 3, 2, TieOrder::Descending),
+            SuffixArray::<5>::new_exact::<CTX>(&seq, 3, 2),
+        ] {
+            let isa = sa.inverse().to_usize_vec();
+            for (r, i) in sa.idxs().iter().enumerate() {
+                assert_eq!(isa[i.get_usize()], r);
+            }
+            for (pos, &r) in isa.iter().enumerate() {
+                assert_eq!(sa.rank(&b, pos), Some(r));
+            }
+        }
+        let region = SuffixArray::<5>::new_packed_range::<CTX>(&seq, 10..20, 3, 1);
+        assert_eq!(region.rank(&b, 30), None);
+    }
+}
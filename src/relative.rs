@@ -0,0 +1,162 @@
+use rayon::prelude::*;
+
+use crate::compact_vec::*;
+use crate::suffix_array::*;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct RelativeBucket {
+    base: usize,
+    offset: usize,
+    wide: bool,
+}
+
+/// Suffix array entries stored as `B`-byte offsets from the smallest position in their
+/// k-mer bucket. Buckets spanning too much of the text keep full `BYTES`-byte entries.
+/// Entries stay in suffix array order and are decoded on access.
+pub struct RelativeIdxs<const B: usize, const BYTES: usize> {
+    narrow: CompactVec<B>,
+    wide: CompactVec<BYTES>,
+    buckets: Vec<RelativeBucket>,
+    bucket_ends: CompactVec<BYTES>,
+}
+
+impl<const B: usize, const BYTES: usize> RelativeIdxs<B, BYTES> {
+    pub fn len(&self) -> usize {
+        self.narrow.len() + self.wide.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> usize {
+        let bucket = self.bucket_ends.partition_point(|e| e.get_usize() <= i);
+        let start = if bucket == 0 {
+            0
+        } else {
+            self.bucket_ends[bucket - 1].get_usize()
+        };
+        let b = &self.buckets[bucket];
+        let j = b.offset + i - start;
+        if b.wide {
+            self.wide[j].get_usize()
+        } else {
+            b.base + self.narrow[j].get_usize()
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .flat_map(move |(bucket, b)| {
+                let len = self.bucket_len(bucket);
+                (b.offset..b.offset + len).map(move |j| {
+                    if b.wide {
+                        self.wide[j].get_usize()
+                    } else {
+                        b.base + self.narrow[j].get_usize()
+                    }
+                })
+            })
+    }
+
+    fn bucket_len(&self, bucket: usize) -> usize {
+        let end = self.bucket_ends[bucket].get_usize();
+        if bucket == 0 {
+            end
+        } else {
+            end - self.bucket_ends[bucket - 1].get_usize()
+        }
+    }
+
+    /// Number of buckets stored with full-width entries.
+    pub fn wide_buckets(&self) -> usize {
+        self.buckets.iter().filter(|b| b.wide).count()
+    }
+
+    /// Bytes used by the entries and per-bucket bases.
+    pub fn size_in_bytes(&self) -> usize {
+        self.narrow.len() * B + self.wide.len() * BYTES + self.buckets.len() * (2 * 8 + 1)
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Store the entries relative to their bucket, see `RelativeIdxs`.
+    pub fn to_relative<const B: usize>(&self) -> RelativeIdxs<B, BYTES> {
+        assert!(!self.bucket_ends().is_empty());
+        assert!(B < BYTES);
+
+        let num_buckets = self.bucket_ends().len();
+        let mut buckets = (0..num_buckets)
+            .into_par_iter()
+            .map(|kmer| {
+                let idxs = &self.idxs()[self.bucket_range(kmer)];
+                let min = idxs.iter().map(|i| i.get_usize()).min().unwrap_or(0);
+                let max = idxs.iter().map(|i| i.get_usize()).max().unwrap_or(0);
+                RelativeBucket {
+                    base: min,
+                    offset: 0,
+                    wide: ((max - min) >> (8 * B)) > 0,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let (mut narrow_len, mut wide_len) = (0, 0);
+        for (kmer, b) in buckets.iter_mut().enumerate() {
+            let len = self.bucket_range(kmer).len();
+            if b.wide {
+                b.offset = wide_len;
+                wide_len += len;
+            } else {
+                b.offset = narrow_len;
+                narrow_len += len;
+            }
+        }
+
+        let mut narrow = CompactVec::<B>::new(narrow_len);
+        let mut wide = CompactVec::<BYTES>::new(wide_len);
+        let narrow_ptr = MutPtr(narrow.as_mut_ptr());
+        let wide_ptr = MutPtr(wide.as_mut_ptr());
+
+        buckets.par_iter().enumerate().for_each(|(kmer, b)| {
+            let (narrow_ptr, wide_ptr) = (narrow_ptr, wide_ptr);
+            for (j, i) in self.idxs()[self.bucket_range(kmer)].iter().enumerate() {
+                unsafe {
+                    if b.wide {
+                        *wide_ptr.0.add(b.offset + j) = i.clone();
+                    } else {
+                        (*narrow_ptr.0.add(b.offset + j)).set_usize(i.get_usize() - b.base);
+                    }
+                }
+            }
+        });
+
+        RelativeIdxs {
+            narrow,
+            wide,
+            buckets,
+            bucket_ends: self.bucket_ends().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_relative() {
+        const CTX: usize = 124;
+        let mut b = pseudo_dna(2000);
+        b[100..110].copy_from_slice(b"TTTTTTTTTT");
+        let s = SuffixArray::<5>::new_packed::<CTX>(&b, 8, 1);
+        let r = s.to_relative::<1>();
+        assert!(r.wide_buckets() > 0);
+        assert!(r.wide_buckets() < s.bucket_ends().len());
+        assert_eq!(r.len(), s.idxs().len());
+        assert_eq!(r.iter().collect::<Vec<_>>(), s.idxs().to_usize_vec());
+        assert!((0..r.len()).all(|i| r.get(i) == s.idxs()[i].get_usize()));
+    }
+}
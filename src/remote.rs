@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::platform::read_exact_at;
+use crate::query::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// Suffix array entries fetched per request.
+const SA_BLOCK: usize = 1 << 16;
+/// Suffix array blocks kept in memory by default, see `LazyBundle::with_cached_blocks`.
+const CACHED_BLOCKS: usize = 256;
+/// Size of the fixed part of the bundle header, up to the section count.
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8 + 8;
+
+/// The most recently used suffix array blocks, at most `capacity` of them.
+struct BlockCache<const BYTES: usize> {
+    capacity: usize,
+    /// Incremented on every access, to find the least recently used block.
+    clock: u64,
+    blocks: HashMap<usize, (u64, Arc<CompactVec<BYTES>>)>,
+}
+
+impl<const BYTES: usize> BlockCache<BYTES> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            clock: 0,
+            blocks: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, b: usize) -> Option<Arc<CompactVec<BYTES>>> {
+        self.clock += 1;
+        let (used, block) = self.blocks.get_mut(&b)?;
+        *used = self.clock;
+        Some(block.clone())
+    }
+
+    fn insert(&mut self, b: usize, block: Arc<CompactVec<BYTES>>) {
+        if self.blocks.len() >= self.capacity && !self.blocks.contains_key(&b) {
+            let oldest = self.blocks.iter().min_by_key(|(_, (used, _))| *used);
+            let oldest = *oldest.unwrap().0;
+            self.blocks.remove(&oldest);
+        }
+        self.clock += 1;
+        self.blocks.insert(b, (self.clock, block));
+    }
+
+    fn len(&self) -> usize {
+        self.blocks.len()
+    }
+}
+
+/// Random access to the bytes of a (possibly remote) file.
+pub trait RangeSource: Send + Sync {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+}
+
+impl RangeSource for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        read_exact_at(self, offset, buf)
+    }
+}
+
+/// A bundle opened for queries without reading it in full: only the header, bucket
+/// table and sequence dictionary are loaded up front, suffix array blocks and text
+/// are fetched from `source` on demand and the most recently used blocks are kept.
+/// Meant for stateless query workers reading bundles from object storage.
+pub struct LazyBundle<const BYTES: usize, S: RangeSource> {
+    source: S,
+    header: Header,
+    bucket_ends: CompactVec<BYTES>,
+    dict: SeqDict,
+    blocks: Mutex<BlockCache<BYTES>>,
+    fetched: AtomicUsize,
+}
+
+impl<const BYTES: usize, S: RangeSource> LazyBundle<BYTES, S> {
+    pub fn open(source: S) -> io::Result<Self> {
+        let mut buf = vec![0u8; HEADER_LEN];
+        source.read_at(0, &mut buf)?;
+        let num_sections = u64::from_le_bytes(buf[HEADER_LEN - 8..].try_into().unwrap());
+        buf.resize(HEADER_LEN + num_sections as usize * 24, 0);
+        source.read_at(HEADER_LEN as u64, &mut buf[HEADER_LEN..])?;
+
+        let header = Header::read(&mut &buf[..], MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "bundle has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+
+        let mut res = Self {
+            source,
+            header,
+            bucket_ends: CompactVec::new(0),
+            dict: SeqDict::new(),
+            blocks: Mutex::new(BlockCache::new(CACHED_BLOCKS)),
+            fetched: AtomicUsize::new(0),
+        };
+        res.bucket_ends = CompactVec::from_bytes(&res.fetch_section("buckets", None)?);
+        res.dict = decode_dict(&res.fetch_section("dict", None)?)?;
+        Ok(res)
+    }
+
+    /// Keep at most `blocks` suffix array blocks of 65536 entries in memory, instead of
+    /// 256.
+    pub fn with_cached_blocks(self, blocks: usize) -> Self {
+        *self.blocks.lock().unwrap() = BlockCache::new(blocks);
+        self
+    }
+
+    /// Bytes `range` of section `name`, or all of it.
+    fn fetch_section(&self, name: &str, range: Option<Range<usize>>) -> io::Result<Vec<u8>> {
+        let s = self
+            .header
+            .section(name)
+            .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+        let range = range.unwrap_or(0..s.len as usize);
+        assert!(range.end as u64 <= s.len);
+        let mut res = vec![0u8; range.len()];
+        self.source
+            .read_at(s.offset + range.start as u64, &mut res)?;
+        Ok(res)
+    }
+
+    pub fn k(&self) -> usize {
+        self.header.k
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.header.ctx
+    }
+
+    pub fn dict(&self) -> &SeqDict {
+        &self.dict
+    }
+
+    pub fn len(&self) -> usize {
+        self.header
+            .section("sa")
+            .map_or(0, |s| s.len as usize / BYTES)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.header.section("text").map_or(0, |s| s.len as usize)
+    }
+
+    /// Number of suffix array blocks fetched so far, including ones fetched again after
+    /// they were evicted.
+    pub fn fetched_blocks(&self) -> usize {
+        self.fetched.load(Ordering::Relaxed)
+    }
+
+    /// Number of suffix array blocks currently kept in memory.
+    pub fn cached_blocks(&self) -> usize {
+        self.blocks.lock().unwrap().len()
+    }
+
+    fn block(&self, b: usize) -> io::Result<Arc<CompactVec<BYTES>>> {
+        if let Some(block) = self.blocks.lock().unwrap().get(b) {
+            return Ok(block);
+        }
+        let range = b * SA_BLOCK..((b + 1) * SA_BLOCK).min(self.len());
+        let bytes = self.fetch_section("sa", Some(range.start * BYTES..range.end * BYTES))?;
+        let block = Arc::new(CompactVec::from_bytes(&bytes));
+        self.fetched.fetch_add(1, Ordering::Relaxed);
+        self.blocks.lock().unwrap().insert(b, block.clone());
+        Ok(block)
+    }
+
+    /// Suffix array entry `i`.
+    pub fn sa(&self, i: usize) -> io::Result<usize> {
+        Ok(self.block(i / SA_BLOCK)?[i % SA_BLOCK].get_usize())
+    }
+
+    pub fn text(&self, range: Range<usize>) -> io::Result<Vec<u8>> {
+        self.fetch_section("text", Some(range))
+    }
+
+    /// Entries of the buckets of all k-mers starting with the first bases of `pattern`.
+    fn bucket_span(&self, pattern: &[u8]) -> Range<usize> {
+        let k = self.k();
+        let m = pattern.len().min(k);
+        let prefix = pattern[..m]
+            .iter()
+            .fold(0usize, |kmer, &b| kmer << 2 | base_code(b) as usize);
+        let first = prefix << (2 * (k - m));
+        let last = ((prefix + 1) << (2 * (k - m))) - 1;
+        let start = match first {
+            0 => 0,
+            _ => self.bucket_ends[first - 1].get_usize(),
+        };
+        start..self.bucket_ends[last].get_usize()
+    }
+
+    fn cmp_at(&self, i: usize, pattern: &[u8]) -> io::Result<std::cmp::Ordering> {
+        let pos = self.sa(i)?;
+        let suffix = self.text(pos..(pos + pattern.len()).min(self.text_len()))?;
+        Ok(cmp_pattern(&suffix, 0, pattern))
+    }
+
+    fn partition_point(
+        &self,
+        mut range: Range<usize>,
+        pattern: &[u8],
+        pred: impl Fn(std::cmp::Ordering) -> bool,
+    ) -> io::Result<usize> {
+        while !range.is_empty() {
+            let mid = range.start + range.len() / 2;
+            if pred(self.cmp_at(mid, pattern)?) {
+                range.start = mid + 1;
+            } else {
+                range.end = mid;
+            }
+        }
+        Ok(range.start)
+    }
+
+    /// Range of suffix array entries whose suffixes start with `pattern`, like
+    /// `SuffixArray::range`, fetching only the entries and text the search visits.
+    pub fn range(&self, pattern: &[u8]) -> io::Result<Range<usize>> {
+        assert!(pattern.len() <= self.ctx());
+        let span = self.bucket_span(pattern);
+        let start = self.partition_point(span.clone(), pattern, |o| o.is_lt())?;
+        let end = self.partition_point(start..span.end, pattern, |o| o.is_eq())?;
+        Ok(start..end)
+    }
+
+    /// Text positions of the entries in `range`, in suffix array order.
+    pub fn locate(&self, range: Range<usize>) -> io::Result<Vec<usize>> {
+        range.map(|i| self.sa(i)).collect()
+    }
+}
+
+#[cfg(feature = "object-store")]
+pub use http::HttpRangeSource;
+
+#[cfg(feature = "object-store")]
+mod http {
+    use std::io::{self, Read};
+
+    use super::RangeSource;
+
+    /// Ranged reads from an object behind an `http://` or `https://` URL, such as an S3
+    /// or GCS bucket endpoint or a presigned URL. Credentials go in extra headers, see
+    /// `with_header`.
+    pub struct HttpRangeSource {
+        agent: ureq::Agent,
+        url: String,
+        headers: Vec<(String, String)>,
+    }
+
+    impl HttpRangeSource {
+        pub fn new(url: &str) -> io::Result<Self> {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "only http:// and https:// URLs are supported",
+                ));
+            }
+            Ok(Self {
+                agent: ureq::AgentBuilder::new().build(),
+                url: url.to_owned(),
+                headers: Vec::new(),
+            })
+        }
+
+        /// Send this header with every request, e.g. `Authorization: Bearer <token>`.
+        pub fn with_header(mut self, name: &str, value: &str) -> Self {
+            self.headers.push((name.to_owned(), value.to_owned()));
+            self
+        }
+    }
+
+    impl RangeSource for HttpRangeSource {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            if buf.is_empty() {
+                return Ok(());
+            }
+            let range = format!("bytes={}-{}", offset, offset + buf.len() as u64 - 1);
+            let mut request = self.agent.get(&self.url).set("Range", &range);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            let response = request
+                .call()
+                .map_err(|e| io::Error::other(format!("ranged GET of {} failed: {e}", self.url)))?;
+            // A server that ignores the range sends the whole object.
+            if response.status() != 206 {
+                return Err(io::Error::other(format!(
+                    "ranged GET of {} failed: {} {}",
+                    self.url,
+                    response.status(),
+                    response.status_text()
+                )));
+            }
+            response.into_reader().read_exact(buf)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lazy_bundle() {
+        const CTX: usize = 124;
+        let mut text = b"ACGTTGCAGATTACA".repeat(20);
+        let mut dict = SeqDict::new();
+        dict.push("a", text.len());
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+        text.resize(text.len() + CTX, b'A');
+        dict.pad(CTX);
+
+        let bundle = Bundle::new(sa, text.clone(), &dict);
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.lazy", std::process::id()));
+        bundle.write(&path).unwrap();
+
+        let lazy = LazyBundle::<5, _>::open(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lazy.dict(), &dict);
+        assert_eq!(lazy.len(), bundle.suffix_array().idxs().len());
+        assert_eq!(lazy.fetched_blocks(), 0);
+
+        let sa = bundle.suffix_array();
+        for pattern in [&b"GATT"[..], b"A", b"CAGATTACAA", b"TTT", b"AAAA"] {
+            let range = lazy.range(pattern).unwrap();
+            assert_eq!(range, sa.range(&text, pattern));
+            assert_eq!(
+                lazy.locate(range.clone()).unwrap(),
+                sa.idxs()[range]
+                    .iter()
+                    .map(|i| i.get_usize())
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(lazy.fetched_blocks(), 1);
+        assert_eq!(lazy.cached_blocks(), 1);
+
+        let block = Arc::new(CompactVec::<5>::new(1));
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, block.clone());
+        cache.insert(1, block.clone());
+        assert!(cache.get(0).is_some());
+        cache.insert(2, block);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(0).is_some() && cache.get(2).is_some());
+    }
+
+    #[cfg(feature = "object-store")]
+    #[test]
+    fn test_http_range_source() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let data = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/index.bundle", listener.local_addr().unwrap());
+        let served = data.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut r = BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                let mut authorized = false;
+                let mut line = String::new();
+                while r.read_line(&mut line).unwrap() > 2 {
+                    if let Some(v) = line.trim_end().strip_prefix("Range: bytes=") {
+                        let (a, b) = v.split_once('-').unwrap();
+                        range = Some(a.parse::<usize>().unwrap()..b.parse::<usize>().unwrap() + 1);
+                    }
+                    authorized |= line.trim_end() == "Authorization: Bearer token";
+                    line.clear();
+                }
+                let range = range.unwrap();
+                if !authorized {
+                    write!(stream, "HTTP/1.0 403 Forbidden\r\n\r\n").unwrap();
+                    continue;
+                }
+                write!(stream, "HTTP/1.0 206 Partial Content\r\n\r\n").unwrap();
+                stream.write_all(&served[range]).unwrap();
+            }
+        });
+
+        let mut buf = [0u8; 10];
+        let source = HttpRangeSource::new(&url).unwrap();
+        assert!(source.read_at(500, &mut buf).is_err());
+        let source = source.with_header("Authorization", "Bearer token");
+        source.read_at(500, &mut buf).unwrap();
+        assert_eq!(buf, data[500..510]);
+        assert!(HttpRangeSource::new("ftp://example.com/a").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use rayon::prelude::*;
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use crate::suffix_array::*;
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Sorted, merged intervals of the text covered by a repeat of at least `min_lcp`
+    /// bases, i.e. prefixes of suffixes that share at least `min_lcp` bases with a
+    /// neighbouring suffix. Repeat lengths are capped at the sorted context.
+    pub fn repeat_regions(&self, bytes: &[u8], min_lcp: usize) -> Vec<Range<usize>> {
+        assert!(min_lcp > 0 && min_lcp <= self.ctx() && self.ctx() <= u16::MAX as usize);
+        let packed = RevPacked::new(bytes);
+        let idxs = self.idxs();
+        let rep_len = (0..bytes.len())
+            .map(|_| AtomicU16::new(0))
+            .collect::<Vec<_>>();
+
+        (1..idxs.len()).into_par_iter().for_each(|i| {
+            let a = idxs[i - 1].get_usize();
+            let b = idxs[i].get_usize();
+            let lcp = unsafe { packed.lcp(a, b, self.ctx()) };
+
+            if lcp >= min_lcp {
+                rep_len[a].fetch_max(lcp as u16, Ordering::Relaxed);
+                rep_len[b].fetch_max(lcp as u16, Ordering::Relaxed);
+            }
+        });
+
+        let mut res: Vec<Range<usize>> = Vec::new();
+        for (pos, len) in rep_len.into_iter().enumerate() {
+            let len = len.into_inner() as usize;
+            if len == 0 {
+                continue;
+            }
+            match res.last_mut() {
+                Some(r) if r.end >= pos => r.end = r.end.max(pos + len),
+                _ => res.push(pos..pos + len),
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bed::*;
+    use crate::seq_dict::*;
+
+    #[test]
+    fn test_repeat_regions() {
+        const CTX: usize = 124;
+        let seq = b"CATTAGACCGTTAGACC";
+        let s = SuffixArray::<5>::new_packed::<CTX>(seq, 2, 1);
+        let regions = s.repeat_regions(&pad_text(seq, CTX), 6);
+        assert_eq!(regions, [2..9, 10..17]);
+
+        let mut dict = SeqDict::new();
+        dict.push("a", 12);
+        dict.push("b", 5);
+        let mut out = Vec::new();
+        write_bed(&mut out, &dict, &regions).unwrap();
+        assert_eq!(out, b"a\t2\t9\na\t10\t12\nb\t0\t5\n");
+    }
+}
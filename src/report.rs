@@ -0,0 +1,226 @@
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::platform::*;
+
+/// Run time of one construction phase.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseStats {
+    /// Name of the phase as in the build report, e.g. `Parallel bucket count`.
+    pub name: &'static str,
+    pub seconds: f64,
+}
+
+/// What happened during a construction, see `record_build`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuildStats {
+    /// Finished phases in order.
+    pub phases: Vec<PhaseStats>,
+    /// Number of suffixes in the largest bucket.
+    pub max_bucket: usize,
+    /// Peak memory usage of the whole process at the end of the build, in bytes.
+    pub peak_memory: usize,
+}
+
+impl BuildStats {
+    /// Total run time of the phases named `name`, in seconds.
+    pub fn phase(&self, name: &str) -> Option<f64> {
+        let mut phases = self.phases.iter().filter(|p| p.name == name).peekable();
+        phases.peek()?;
+        Some(phases.map(|p| p.seconds).sum())
+    }
+
+    /// Total run time of all phases, in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.phases.iter().map(|p| p.seconds).sum()
+    }
+}
+
+thread_local! {
+    static STATS: RefCell<Option<BuildStats>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, which builds one or more suffix arrays, and collect the `BuildStats` of the
+/// phases it runs on the calling thread. Builds nested in parallel iterators run on
+/// other threads and are not included.
+pub fn record_build<T>(f: impl FnOnce() -> T) -> (T, BuildStats) {
+    let outer = STATS.with(|s| s.replace(Some(BuildStats::default())));
+    let result = f();
+    let mut stats = STATS.with(|s| s.replace(outer)).unwrap_or_default();
+    stats.peak_memory = peak_memory_usage();
+    (result, stats)
+}
+
+fn with_stats(f: impl FnOnce(&mut BuildStats)) {
+    STATS.with(|s| {
+        if let Some(stats) = s.borrow_mut().as_mut() {
+            f(stats);
+        }
+    });
+}
+
+pub(crate) fn record_phase(name: &'static str, seconds: f64) {
+    with_stats(|s| s.phases.push(PhaseStats { name, seconds }));
+    report_progress(&Progress::PhaseFinished { name, seconds });
+}
+
+pub(crate) fn record_max_bucket(size: usize) {
+    with_stats(|s| s.max_bucket = s.max_bucket.max(size));
+}
+
+/// A step of a construction, passed to the `progress` callback of its `Schedule`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Progress {
+    PhaseStarted {
+        name: &'static str,
+    },
+    PhaseFinished {
+        name: &'static str,
+        seconds: f64,
+    },
+    /// `sorted` of the `total` non-empty buckets are sorted. Reported about every
+    /// percent.
+    Buckets {
+        sorted: usize,
+        total: usize,
+    },
+}
+
+/// Callback on the progress of a build, see `Schedule::progress`.
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+thread_local! {
+    static PROGRESS: RefCell<Option<ProgressCallback>> = const { RefCell::new(None) };
+    static VERBOSE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Report the construction running on the calling thread to `progress`, and return
+/// the callback it reported to before.
+pub(crate) fn replace_progress(progress: Option<ProgressCallback>) -> Option<ProgressCallback> {
+    PROGRESS.with(|p| p.replace(progress))
+}
+
+/// The progress callback of the construction running on the calling thread.
+pub(crate) fn progress() -> Option<ProgressCallback> {
+    PROGRESS.with(|p| p.borrow().clone())
+}
+
+pub(crate) fn report_progress(progress: &Progress) {
+    if let Some(callback) = self::progress() {
+        callback(progress);
+    }
+}
+
+/// Print the build report of the construction running on the calling thread, and
+/// return whether it was printed before.
+pub(crate) fn replace_verbose(verbose: bool) -> bool {
+    VERBOSE.with(|v| v.replace(verbose))
+}
+
+pub(crate) fn verbose() -> bool {
+    VERBOSE.with(|v| v.get())
+}
+
+/// `eprintln!` if the build report is enabled with `Schedule::verbose`.
+macro_rules! build_log {
+    ($($arg:tt)*) => {
+        if $crate::report::verbose() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use build_log;
+
+/// Counts sorted buckets and reports them about every percent, to the progress
+/// callback of the build that created it, from any thread.
+pub(crate) struct BucketProgress {
+    sorted: AtomicUsize,
+    total: usize,
+    step: usize,
+    callback: Option<ProgressCallback>,
+}
+
+impl BucketProgress {
+    pub(crate) fn new(total: usize) -> Self {
+        Self {
+            sorted: AtomicUsize::new(0),
+            total,
+            step: total.div_ceil(100).max(1),
+            callback: progress(),
+        }
+    }
+
+    pub(crate) fn tick(&self) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let sorted = self.sorted.fetch_add(1, Ordering::Relaxed) + 1;
+        if sorted.is_multiple_of(self.step) || sorted == self.total {
+            callback(&Progress::Buckets {
+                sorted,
+                total: self.total,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_build_stats() {
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACA".repeat(20);
+        let (sa, stats) = record_build(|| SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2));
+        let names = stats.phases.iter().map(|p| p.name).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "2 bit packing",
+                "Parallel bucket count",
+                "Bucket prefix sum",
+                "Parallel move into and sort buckets"
+            ]
+        );
+        let largest = (0..64).map(|kmer| sa.bucket_range(kmer).len()).max();
+        assert_eq!(Some(stats.max_bucket), largest);
+        assert!(stats.phase("Bucket prefix sum").is_some());
+        assert!(stats.phase("LCP array").is_none());
+        assert!(stats.peak_memory > 0);
+
+        let ((), outside) = record_build(|| ());
+        assert!(outside.phases.is_empty());
+    }
+
+    #[test]
+    fn test_progress() {
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACA".repeat(20);
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let schedule = Schedule {
+            bucket_threads: 2,
+            progress: Some(Arc::new(move |p: &Progress| sink.lock().unwrap().push(*p))),
+            ..Schedule::default()
+        };
+        SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2);
+        assert!(events.lock().unwrap().is_empty());
+
+        SuffixArray::<5>::new_packed::<CTX>(&b, 3, schedule);
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events.first(),
+            Some(&Progress::PhaseStarted {
+                name: "2 bit packing"
+            })
+        );
+        assert!(matches!(
+            events.iter().rev().find(|p| matches!(p, Progress::Buckets { .. })),
+            Some(Progress::Buckets { sorted, total }) if sorted == total
+        ));
+        assert!(!verbose() && progress().is_none());
+    }
+}
@@ -0,0 +1,446 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::fingerprint::*;
+use crate::kmers::*;
+use crate::platform::{read_exact_at, Advice, Mmap};
+use crate::query::*;
+use crate::suffix_array::*;
+
+pub(crate) const MAGIC: &[u8; 8] = b"SSACASAF";
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Write the array on its own, without the text: `k`, `ctx` and the entry width in
+    /// the header, followed by the raw entries and bucket ends.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_into(&mut w)?;
+        w.flush()
+    }
+
+    /// Write the array to `w`, in the same format as `write_to`.
+    pub fn write_into(&self, w: &mut impl Write) -> io::Result<()> {
+        let flags = [self.is_canonical() as u8];
+        let ties = [self.tie_order() as u8];
+        let fingerprint = self.fingerprint();
+        let seed = self.seed().map(|seed| seed.to_string());
+        let mut sections = vec![
+            ("sa", self.idxs().as_bytes()),
+            ("buckets", self.bucket_ends().as_bytes()),
+            ("flags", &flags[..]),
+            ("ties", &ties[..]),
+        ];
+        if let Some(fingerprint) = &fingerprint {
+            sections.push(("fprint", &fingerprint.0));
+        }
+        if let Some(seed) = &seed {
+            sections.push(("seed", seed.as_bytes()));
+        }
+        if self.is_exact() {
+            sections.push(("exact", &[1]));
+        }
+        if let Some(lcp) = self.lcp_array() {
+            sections.push(("lcp", lcp.as_bytes()));
+        }
+        let residual = self.residual_ties().map(|ties| (ties as u64).to_le_bytes());
+        if let Some(residual) = &residual {
+            sections.push(("residual", residual));
+        }
+        write_sections_to(w, MAGIC, BYTES, self.k(), self.ctx(), &sections)
+    }
+
+    /// Read a file written by `write_to` into memory. Use `MappedSuffixArray` to map
+    /// it instead.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(&mut File::open(path)?)
+    }
+
+    /// Read an array written by `write_into` or `write_to` from `f`.
+    pub fn read_from(f: &mut (impl Read + Seek)) -> io::Result<Self> {
+        let header = read_header::<BYTES>(f)?;
+        let idxs = read_entries(f, &header, "sa")?;
+        let bucket_ends = read_entries(f, &header, "buckets")?;
+        let canonical = read_section(f, &header, "flags")? == [1];
+        let ties = decode_ties(&read_section(f, &header, "ties")?)?;
+        let mut sa = SuffixArray::from_parts(idxs, bucket_ends, header.k, header.ctx, canonical)
+            .with_tie_order(ties);
+        if header.section("fprint").is_some() {
+            sa.set_fingerprint(Some(Fingerprint(
+                read_section(f, &header, "fprint")?
+                    .try_into()
+                    .map_err(|_| invalid_data("invalid fingerprint"))?,
+            )));
+        }
+        if header.section("seed").is_some() {
+            sa = sa.with_seed(decode_seed(&read_section(f, &header, "seed")?)?);
+        }
+        sa.set_exact(header.section("exact").is_some());
+        if header.section("lcp").is_some() {
+            let lcp = read_entries(f, &header, "lcp")?;
+            sa.set_lcp_array(Some(lcp));
+        }
+        if header.section("residual").is_some() {
+            let ties = read_section(f, &header, "residual")?
+                .try_into()
+                .map_err(|_| invalid_data("invalid residual tie count"))?;
+            sa = sa.with_residual_ties(u64::from_le_bytes(ties) as usize);
+        }
+        Ok(sa)
+    }
+}
+
+fn decode_seed(bytes: &[u8]) -> io::Result<SpacedSeed> {
+    std::str::from_utf8(bytes)
+        .map_err(|_| invalid_data("invalid spaced seed"))?
+        .parse()
+        .map_err(invalid_data)
+}
+
+fn read_header<const BYTES: usize>(f: &mut impl Read) -> io::Result<Header> {
+    let header = Header::read(f, MAGIC)?;
+    if header.bytes != BYTES {
+        return Err(invalid_data(format!(
+            "suffix array has {}-byte entries, expected {BYTES}",
+            header.bytes
+        )));
+    }
+    Ok(header)
+}
+
+/// A suffix array file written by `SuffixArray::write_to`, mapped read-only, so that it
+/// opens instantly and pages are only read as they are used.
+pub struct MappedSuffixArray<const BYTES: usize> {
+    map: Mmap,
+    header: Header,
+}
+
+impl<const BYTES: usize> MappedSuffixArray<BYTES> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let len = f.metadata()?.len() as usize;
+        let header = read_header::<BYTES>(&mut f)?;
+        for name in ["sa", "buckets", "flags", "ties"] {
+            let s = header
+                .section(name)
+                .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+            if s.offset + s.len > len as u64 {
+                return Err(invalid_data("section extends past the end of the file"));
+            }
+        }
+
+        Ok(Self {
+            map: Mmap::map(&f, len)?,
+            header,
+        })
+    }
+
+    fn section_range(&self, name: &str) -> io::Result<Range<usize>> {
+        let s = self
+            .header
+            .section(name)
+            .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+        Ok(s.offset as usize..(s.offset + s.len) as usize)
+    }
+
+    fn section(&self, name: &str) -> Option<&[u8]> {
+        Some(&self.map.as_slice()[self.section_range(name).ok()?])
+    }
+
+    /// Advise the OS how the named sections (`sa`, `buckets`) will be accessed.
+    pub fn advise(&self, sections: &[&str], advice: Advice) -> io::Result<()> {
+        for name in sections {
+            self.map.advise(self.section_range(name)?, advice)?;
+        }
+        Ok(())
+    }
+
+    /// Make the named sections resident before the first queries arrive.
+    pub fn warmup(&self, sections: &[&str]) -> io::Result<()> {
+        for name in sections {
+            self.map.warmup(self.section_range(name)?)?;
+        }
+        Ok(())
+    }
+
+    fn ints(&self, name: &str) -> &[Int<BYTES>] {
+        let bytes = self.section(name).unwrap_or_default();
+        // `Int` is a byte array, so any offset is suitably aligned.
+        unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr() as *const Int<BYTES>, bytes.len() / BYTES)
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.header.k
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.header.ctx
+    }
+
+    pub fn idxs(&self) -> &[Int<BYTES>] {
+        self.ints("sa")
+    }
+
+    pub fn bucket_ends(&self) -> &[Int<BYTES>] {
+        self.ints("buckets")
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        self.section("flags") == Some(&[1])
+    }
+
+    pub fn seed(&self) -> Option<SpacedSeed> {
+        self.section("seed").and_then(|s| decode_seed(s).ok())
+    }
+
+    pub fn tie_order(&self) -> TieOrder {
+        self.section("ties")
+            .and_then(|t| decode_ties(t).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.section("fprint")
+            .and_then(|f| f.try_into().ok())
+            .map(Fingerprint)
+    }
+
+    /// Range of suffix array entries whose suffixes of the sequence `seq` start with
+    /// `pattern`.
+    pub fn range(&self, seq: &[u8], pattern: &[u8]) -> Range<usize> {
+        assert!(!self.is_canonical() && self.seed().is_none());
+        assert!(pattern.len() <= self.ctx());
+        pattern_range(self.idxs(), seq, pattern)
+    }
+}
+
+/// Entries read at once by `StreamedSuffixArray` to finish a search in memory.
+const STREAM_BLOCK: usize = 1 << 12;
+
+/// A suffix array file written by `SuffixArray::write_to`, searched with positioned
+/// reads instead of loaded or mapped, for occasional queries against arrays larger
+/// than memory. Only every entry at a multiple of a stride, the top levels of the
+/// binary search, is kept in memory.
+pub struct StreamedSuffixArray<const BYTES: usize> {
+    file: File,
+    header: Header,
+    len: usize,
+    stride: usize,
+    /// Entries `0, stride, 2 * stride, ...`.
+    samples: Vec<usize>,
+    reads: AtomicUsize,
+}
+
+impl<const BYTES: usize> StreamedSuffixArray<BYTES> {
+    /// Open `path`, caching the top `levels` levels of the search, `2^levels` entries.
+    pub fn open(path: impl AsRef<Path>, levels: u32) -> io::Result<Self> {
+        let mut f = File::open(path)?;
+        let file_len = f.metadata()?.len();
+        let header = read_header::<BYTES>(&mut f)?;
+        for name in ["sa", "buckets", "flags", "ties"] {
+            let s = header
+                .section(name)
+                .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+            if s.offset + s.len > file_len {
+                return Err(invalid_data("section extends past the end of the file"));
+            }
+        }
+        let flags = read_section(&mut f, &header, "flags")?;
+        if flags == [1] || header.section("seed").is_some() {
+            return Err(invalid_data(
+                "canonical and spaced seed suffix arrays cannot be searched",
+            ));
+        }
+
+        let len = header.section("sa").unwrap().len as usize / BYTES;
+        let stride = (len >> levels.min(usize::BITS - 1)).max(1);
+        let mut res = Self {
+            file: f,
+            header,
+            len,
+            stride,
+            samples: Vec::new(),
+            reads: AtomicUsize::new(0),
+        };
+        let mut samples = Vec::with_capacity(len.div_ceil(stride));
+        for i in (0..len).step_by(stride) {
+            samples.push(res.entry(i)?);
+        }
+        res.samples = samples;
+        res.reads = AtomicUsize::new(0);
+        Ok(res)
+    }
+
+    pub fn k(&self) -> usize {
+        self.header.k
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.header.ctx
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of reads from the file by queries so far.
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// Entries `range` of section `name`.
+    fn read_ints(&self, name: &str, range: Range<usize>) -> io::Result<CompactVec<BYTES>> {
+        let s = self.header.section(name).unwrap();
+        let mut buf = vec![0u8; range.len() * BYTES];
+        read_exact_at(
+            &self.file,
+            s.offset + (range.start * BYTES) as u64,
+            &mut buf,
+        )?;
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        Ok(CompactVec::from_bytes(&buf))
+    }
+
+    /// Suffix array entry `i`.
+    pub fn entry(&self, i: usize) -> io::Result<usize> {
+        Ok(self.read_ints("sa", i..i + 1)?[0].get_usize())
+    }
+
+    /// Entries of the buckets of all k-mers starting with the first bases of `pattern`,
+    /// read from the two bucket ends that bound them.
+    fn bucket_span(&self, pattern: &[u8]) -> io::Result<Range<usize>> {
+        let k = self.k();
+        let m = pattern.len().min(k);
+        let prefix = pattern[..m]
+            .iter()
+            .fold(0usize, |kmer, &b| kmer << 2 | base_code(b) as usize);
+        let first = prefix << (2 * (k - m));
+        let last = ((prefix + 1) << (2 * (k - m))) - 1;
+        let start = match first {
+            0 => 0,
+            _ => self.read_ints("buckets", first - 1..first)?[0].get_usize(),
+        };
+        Ok(start..self.read_ints("buckets", last..last + 1)?[0].get_usize())
+    }
+
+    /// First entry of `range` for which `pred` fails, given that it holds for a prefix.
+    fn partition_point(
+        &self,
+        mut range: Range<usize>,
+        pred: impl Fn(usize) -> bool,
+    ) -> io::Result<usize> {
+        // Narrow down on the cached samples inside the range first.
+        let first = range.start.div_ceil(self.stride);
+        let last = range.end.div_ceil(self.stride);
+        let s = first + self.samples[first..last].partition_point(|&pos| pred(pos));
+        if s > first {
+            range.start = (s - 1) * self.stride + 1;
+        }
+        if s < last {
+            range.end = s * self.stride;
+        }
+
+        while range.len() > STREAM_BLOCK {
+            let mid = range.start + range.len() / 2;
+            if pred(self.entry(mid)?) {
+                range.start = mid + 1;
+            } else {
+                range.end = mid;
+            }
+        }
+        let block = self.read_ints("sa", range.clone())?;
+        Ok(range.start + block.partition_point(|i| pred(i.get_usize())))
+    }
+
+    /// Range of suffix array entries whose suffixes of the sequence `seq` start with
+    /// `pattern`, like `MappedSuffixArray::range`.
+    pub fn range(&self, seq: &[u8], pattern: &[u8]) -> io::Result<Range<usize>> {
+        assert!(pattern.len() <= self.ctx());
+        let span = self.bucket_span(pattern)?;
+        let start =
+            self.partition_point(span.clone(), |pos| cmp_pattern(seq, pos, pattern).is_lt())?;
+        let end = self.partition_point(start..span.end, |pos| {
+            cmp_pattern(seq, pos, pattern).is_le()
+        })?;
+        Ok(start..end)
+    }
+
+    /// Text positions of the entries in `range`, in suffix array order.
+    pub fn locate(&self, range: Range<usize>) -> io::Result<Vec<usize>> {
+        Ok(self.read_ints("sa", range)?.to_usize_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sa_file() {
+        const CTX: usize = 124;
+        let mut text = b"ACGTTGCAGATTACAGATTACA".repeat(5);
+        let mut sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+        text.resize(text.len() + CTX, b'A');
+        sa.record_fingerprint(&text);
+
+        let path = std::env::temp_dir().join(format!("simple-saca-{}.sa", std::process::id()));
+        sa.write_to(&path).unwrap();
+        let loaded = SuffixArray::<5>::load(&path).unwrap();
+        let mapped = MappedSuffixArray::<5>::open(&path).unwrap();
+        assert!(SuffixArray::<4>::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.idxs(), sa.idxs());
+        assert_eq!(loaded.bucket_ends(), sa.bucket_ends());
+        assert_eq!((loaded.k(), loaded.ctx()), (3, CTX));
+        assert_eq!(loaded.tie_order(), sa.tie_order());
+        assert_eq!(loaded.fingerprint(), sa.fingerprint());
+        assert_eq!(loaded.residual_ties(), sa.residual_ties());
+        assert!(loaded.matches_text(&text));
+
+        assert_eq!(mapped.idxs(), &sa.idxs()[..]);
+        assert_eq!(mapped.bucket_ends(), &sa.bucket_ends()[..]);
+        assert_eq!((mapped.k(), mapped.ctx()), (3, CTX));
+        assert!(!mapped.is_canonical());
+        assert_eq!(mapped.fingerprint(), sa.fingerprint());
+        assert_eq!(mapped.range(&text, b"GATTACA").len(), 10);
+    }
+    #[test]
+    fn test_streamed() {
+        const CTX: usize = 124;
+        let mut text = b"ACGTTGCAGATTACAGGATCCA".repeat(400);
+        text.extend_from_slice(b"TTTTGGGG");
+        let n = text.len();
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 2, 2);
+        text.resize(n + CTX, b'A');
+
+        let path =
+            std::env::temp_dir().join(format!("simple-saca-{}-streamed.sa", std::process::id()));
+        sa.write_to(&path).unwrap();
+        for levels in [0, 4, 30] {
+            let streamed = StreamedSuffixArray::<5>::open(&path, levels).unwrap();
+            assert_eq!(streamed.len(), n);
+            assert_eq!(streamed.reads(), 0);
+            for pattern in [&b"GATTACA"[..], b"A", b"TTTTGGGGA", b"CCCC", b""] {
+                let range = streamed.range(&text, pattern).unwrap();
+                assert_eq!(range, sa.range(&text, pattern), "{levels} levels");
+                assert_eq!(
+                    streamed.locate(range).unwrap(),
+                    sa.locate(&text, pattern, HitOrder::SuffixArray)
+                );
+            }
+            assert!(streamed.reads() > 0);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
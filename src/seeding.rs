@@ -0,0 +1,200 @@
+use rayon::prelude::*;
+
+use std::str::FromStr;
+
+use crate::mem::*;
+use crate::query::*;
+use crate::suffix_array::*;
+
+/// Sequencing technologies with typical per-base error rates, to configure a
+/// `SeedExtractor` without tuning seed parameters by hand.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadProfile {
+    Illumina,
+    /// PacBio HiFi reads.
+    PacBio,
+    /// Oxford Nanopore reads.
+    Ont,
+}
+
+impl FromStr for ReadProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "illumina" => Ok(Self::Illumina),
+            "pacbio" => Ok(Self::PacBio),
+            "ont" => Ok(Self::Ont),
+            _ => Err(format!(
+                "unknown read profile {s}, expected illumina, pacbio or ont"
+            )),
+        }
+    }
+}
+
+impl ReadProfile {
+    pub fn error_rate(self) -> f64 {
+        match self {
+            Self::Illumina => 0.002,
+            Self::PacBio => 0.01,
+            Self::Ont => 0.05,
+        }
+    }
+}
+
+/// Longest seed length a profile asks for; longer exact matches are still reported.
+const MAX_MIN_LEN: usize = 31;
+/// Seeds occurring more often than this are skipped as repeats.
+const MAX_OCC: usize = 500;
+
+/// Seeding parameters derived from an error rate, see `SeedParams::for_error_rate`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SeedParams {
+    /// Minimum length of a seed match.
+    pub min_len: usize,
+    /// Skip seeds occurring more often than this.
+    pub max_occ: usize,
+    /// Seed at every this many query positions.
+    pub stride: usize,
+}
+
+impl SeedParams {
+    /// Parameters for reads with per-base `error_rate` against a text of `text_len`
+    /// bases.
+    ///
+    /// A seed must be long enough that random matches in the text are rare, about
+    /// `log4(text_len)` bases, but short enough that a read window of that length is
+    /// error free at least half of the time. Within such an error free stretch,
+    /// seeding every `stride` positions still leaves room for a full length seed, so
+    /// accurate reads are sampled sparsely and noisy ones at every position.
+    pub fn for_error_rate(error_rate: f64, text_len: usize) -> Self {
+        assert!((0.0..1.0).contains(&error_rate));
+        let specific = (usize::BITS - text_len.max(1).leading_zeros()) as usize / 2 + 2;
+        let error_free = if error_rate == 0.0 {
+            usize::MAX
+        } else {
+            (0.5f64.ln() / (1.0 - error_rate).ln()) as usize
+        };
+        let min_len = error_free.clamp(specific, MAX_MIN_LEN.max(specific));
+        let stride = (error_free.saturating_sub(min_len) / 2).clamp(1, min_len);
+        Self {
+            min_len,
+            max_occ: MAX_OCC,
+            stride,
+        }
+    }
+}
+
+/// Finds seed matches of reads in an indexed text, with parameters picked for the
+/// expected sequencing error rate.
+pub struct SeedExtractor<'a, const BYTES: usize> {
+    sa: &'a SuffixArray<BYTES>,
+    seq: &'a [u8],
+    params: SeedParams,
+}
+
+impl<'a, const BYTES: usize> SeedExtractor<'a, BYTES> {
+    pub fn new(sa: &'a SuffixArray<BYTES>, seq: &'a [u8], params: SeedParams) -> Self {
+        assert!(sa.is_lexicographic());
+        assert!(params.stride > 0);
+        Self { sa, seq, params }
+    }
+
+    /// An extractor for reads of `profile` against the sequence `seq` of `sa`.
+    pub fn for_profile(sa: &'a SuffixArray<BYTES>, seq: &'a [u8], profile: ReadProfile) -> Self {
+        let text_len = sa.idxs().len();
+        Self::new(
+            sa,
+            seq,
+            SeedParams::for_error_rate(profile.error_rate(), text_len),
+        )
+    }
+
+    pub fn params(&self) -> SeedParams {
+        self.params
+    }
+
+    /// The longest matches of `query` starting at every `stride`-th query position,
+    /// sorted by diagonal and query position. A match contained in the match of an
+    /// earlier seed on the same diagonal is dropped.
+    pub fn seeds(&self, query: &[u8]) -> Vec<Mem> {
+        let filter = HitFilter {
+            min_len: self.params.min_len,
+            max_freq: self.params.max_occ,
+            ..HitFilter::default()
+        };
+        let idxs = self.sa.idxs();
+        let mut seeds = (0..query.len())
+            .into_par_iter()
+            .step_by(self.params.stride)
+            .flat_map_iter(|q| {
+                let (len, range) = self.sa.longest_match(self.seq, &query[q..]);
+                let range = if filter.accepts(len, range.len()) {
+                    range
+                } else {
+                    0..0
+                };
+                range.map(move |i| Mem {
+                    query_pos: q,
+                    text_pos: idxs[i].get_usize(),
+                    len,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let diagonal = |m: &Mem| m.text_pos as isize - m.query_pos as isize;
+        seeds.par_sort_unstable_by_key(|m| (diagonal(m), m.query_pos));
+        let mut end = None;
+        seeds.retain(|m| {
+            let keep = end.is_none_or(|(d, e)| d != diagonal(m) || m.query_pos + m.len > e);
+            if keep {
+                end = Some((diagonal(m), m.query_pos + m.len));
+            }
+            keep
+        });
+        seeds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_seeding() {
+        let ont = SeedParams::for_error_rate(ReadProfile::Ont.error_rate(), 1 << 30);
+        assert_eq!((ont.min_len, ont.stride), (17, 1));
+        let illumina = SeedParams::for_error_rate(ReadProfile::Illumina.error_rate(), 1 << 30);
+        assert_eq!((illumina.min_len, illumina.stride), (31, 31));
+        assert_eq!("pacbio".parse(), Ok(ReadProfile::PacBio));
+
+        const CTX: usize = 124;
+        let b = pseudo_dna(5000);
+        let s = SuffixArray::<5>::new_packed::<CTX>(&b, 4, 2);
+
+        // A read from position 1000 with a substitution every 25 bases.
+        let mut read = b[1000..1400].to_vec();
+        for i in (12..read.len()).step_by(25) {
+            read[i] = if read[i] == b'A' { b'C' } else { b'A' };
+        }
+        let seeder = SeedExtractor::for_profile(&s, &b, ReadProfile::Ont);
+        let params = seeder.params();
+        let seeds = seeder.seeds(&read);
+        assert!(seeds.len() >= 10);
+        for m in &seeds {
+            assert!(m.len >= params.min_len);
+            assert_eq!(m.query_pos % params.stride, 0);
+            assert_eq!(
+                read[m.query_pos..m.query_pos + m.len],
+                b[m.text_pos..m.text_pos + m.len]
+            );
+        }
+        assert!(seeds.iter().any(|m| m.text_pos == 1000 + m.query_pos));
+        let on_diagonal = seeds.iter().filter(|m| m.text_pos == 1000 + m.query_pos);
+        assert!(on_diagonal
+            .clone()
+            .zip(on_diagonal.skip(1))
+            .all(|(a, b)| b.query_pos + b.len > a.query_pos + a.len));
+    }
+}
@@ -0,0 +1,277 @@
+use rayon::prelude::*;
+
+use crate::compact_vec::*;
+use crate::suffix_array::*;
+
+/// Names and extents of the sequences (genomes, chromosomes, contigs) that were
+/// concatenated into the indexed text, possibly separated by gaps such as padding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeqDict {
+    names: Vec<String>,
+    starts: Vec<usize>,
+    lens: Vec<usize>,
+    ns: Vec<usize>,
+    total_len: usize,
+}
+
+impl Default for SeqDict {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeqDict {
+    pub fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            starts: Vec::new(),
+            lens: Vec::new(),
+            ns: Vec::new(),
+            total_len: 0,
+        }
+    }
+
+    pub fn push(&mut self, name: impl Into<String>, len: usize) {
+        self.push_with_ns(name, len, 0);
+    }
+
+    /// Add a sequence of `len` indexed bases from which `ns` N bases were removed.
+    pub fn push_with_ns(&mut self, name: impl Into<String>, len: usize, ns: usize) {
+        self.names.push(name.into());
+        self.starts.push(self.total_len);
+        self.lens.push(len);
+        self.ns.push(ns);
+        self.total_len += len;
+    }
+
+    /// Add `len` positions to the text that belong to no sequence.
+    pub fn pad(&mut self, len: usize) {
+        self.total_len += len;
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn start(&self, id: usize) -> usize {
+        self.starts[id]
+    }
+
+    pub fn seq_len(&self, id: usize) -> usize {
+        self.lens[id]
+    }
+
+    pub fn n_count(&self, id: usize) -> usize {
+        self.ns[id]
+    }
+
+    pub fn end(&self, id: usize) -> usize {
+        self.starts[id] + self.lens[id]
+    }
+
+    /// Length of the concatenated text, including gaps.
+    pub fn total_len(&self) -> usize {
+        self.total_len
+    }
+
+    pub fn max_seq_len(&self) -> usize {
+        self.lens.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Sequence id and offset within that sequence of a position in the concatenated
+    /// text, or `None` if the position is in a gap.
+    pub fn locate(&self, pos: usize) -> Option<(usize, usize)> {
+        let id = self.starts.partition_point(|&s| s <= pos).checked_sub(1)?;
+        let offset = pos - self.starts[id];
+        (offset < self.lens[id]).then_some((id, offset))
+    }
+
+    /// Split the sequences at the sorted text positions `breaks`, keeping the gaps.
+    pub fn split_at(&self, breaks: &[usize]) -> SeqDict {
+        let mut res = SeqDict::new();
+        for id in 0..self.len() {
+            let (start, end) = (self.start(id), self.end(id));
+            let i = breaks.partition_point(|&b| b <= start);
+            let j = breaks.partition_point(|&b| b < end);
+            let bounds = std::iter::once(start)
+                .chain(breaks[i..j].iter().copied())
+                .chain(std::iter::once(end))
+                .collect::<Vec<_>>();
+            res.pad(start - res.total_len);
+            for (part, w) in bounds.windows(2).enumerate() {
+                res.push(format!("{}:{part}", self.name(id)), w[1] - w[0]);
+            }
+        }
+        res.pad(self.total_len - res.total_len);
+        res
+    }
+
+    pub fn codec(&self) -> EntryCodec {
+        EntryCodec::new(self.len(), self.max_seq_len())
+    }
+}
+
+pub type SeqId = u32;
+
+/// Sequence id of every suffix array entry, in suffix array order.
+pub struct DocArray {
+    docs: Vec<SeqId>,
+    dict: SeqDict,
+}
+
+impl DocArray {
+    /// Id used for suffixes starting in a gap between sequences (e.g. padding).
+    pub const NONE: SeqId = SeqId::MAX;
+
+    pub fn new<const BYTES: usize>(sa: &SuffixArray<BYTES>, dict: &SeqDict) -> Self {
+        assert!(dict.len() < Self::NONE as usize);
+        let docs = sa
+            .idxs()
+            .par_iter()
+            .map(|i| {
+                dict.locate(i.get_usize())
+                    .map_or(Self::NONE, |(id, _)| id as SeqId)
+            })
+            .collect();
+
+        Self {
+            docs,
+            dict: dict.clone(),
+        }
+    }
+
+    pub(crate) fn from_parts(docs: Vec<SeqId>, dict: SeqDict) -> Self {
+        Self { docs, dict }
+    }
+
+    pub fn docs(&self) -> &[SeqId] {
+        &self.docs
+    }
+
+    pub fn dict(&self) -> &SeqDict {
+        &self.dict
+    }
+}
+
+/// Packs a (sequence id, offset) pair into one integer: the id in the high bits and
+/// the offset in the low bits, with both widths just large enough for a dictionary.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EntryCodec {
+    id_bits: u32,
+    offset_bits: u32,
+}
+
+impl EntryCodec {
+    pub fn new(num_seqs: usize, max_seq_len: usize) -> Self {
+        let id_bits = usize::BITS - num_seqs.saturating_sub(1).leading_zeros();
+        let offset_bits = usize::BITS - max_seq_len.saturating_sub(1).leading_zeros();
+        assert!(id_bits + offset_bits <= 64);
+
+        Self {
+            id_bits,
+            offset_bits,
+        }
+    }
+
+    pub fn id_bits(&self) -> u32 {
+        self.id_bits
+    }
+
+    pub fn offset_bits(&self) -> u32 {
+        self.offset_bits
+    }
+
+    /// Smallest `BYTES` that can hold an encoded entry.
+    pub fn bytes(&self) -> usize {
+        ((self.id_bits + self.offset_bits) as usize)
+            .div_ceil(8)
+            .max(1)
+    }
+
+    /// The entry of `offset` in sequence `id`. Offsets take all 64 bits for a single
+    /// sequence of more than 2^63 bases, so the shifts here and in `decode` are checked.
+    #[inline(always)]
+    pub fn encode(&self, id: usize, offset: usize) -> usize {
+        id.checked_shl(self.offset_bits).unwrap_or(0) | offset
+    }
+
+    #[inline(always)]
+    pub fn decode(&self, entry: usize) -> (usize, usize) {
+        let mask = 1usize
+            .checked_shl(self.offset_bits)
+            .map_or(usize::MAX, |b| b - 1);
+        (
+            entry.checked_shr(self.offset_bits).unwrap_or(0),
+            entry & mask,
+        )
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Re-encode every suffix index as a (sequence id, offset) entry of width `B`.
+    pub fn to_seq_entries<const B: usize>(
+        &self,
+        dict: &SeqDict,
+        codec: EntryCodec,
+    ) -> CompactVec<B> {
+        assert!(codec.bytes() <= B);
+        let mut res = CompactVec::<B>::new(self.idxs().len());
+
+        res.par_iter_mut()
+            .zip(self.idxs().par_iter())
+            .for_each(|(e, i)| {
+                let (id, offset) = dict.locate(i.get_usize()).unwrap();
+                e.set_usize(codec.encode(id, offset));
+            });
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_entries() {
+        let mut dict = SeqDict::new();
+        dict.push("a", 5);
+        dict.push("b", 3);
+        dict.push("c", 9);
+        dict.pad(4);
+        assert_eq!(dict.locate(0), Some((0, 0)));
+        assert_eq!(dict.locate(5), Some((1, 0)));
+        assert_eq!(dict.locate(16), Some((2, 8)));
+        assert_eq!(dict.locate(17), None);
+
+        let codec = dict.codec();
+        assert_eq!((codec.id_bits(), codec.offset_bits()), (2, 4));
+        assert_eq!(codec.bytes(), 1);
+        for pos in 0..17 {
+            let (id, offset) = dict.locate(pos).unwrap();
+            assert_eq!(codec.decode(codec.encode(id, offset)), (id, offset));
+        }
+        let wide = EntryCodec::new(1, usize::MAX);
+        assert_eq!((wide.id_bits(), wide.offset_bits()), (0, 64));
+        assert_eq!(
+            wide.decode(wide.encode(0, usize::MAX - 1)),
+            (0, usize::MAX - 1)
+        );
+
+        let split = dict.split_at(&[2, 5, 7, 8]);
+        assert_eq!(split.len(), 5);
+        assert_eq!(split.total_len(), dict.total_len());
+        assert_eq!(split.locate(4), Some((1, 2)));
+        assert_eq!(split.locate(7), Some((3, 0)));
+        assert_eq!((split.start(4), split.end(4)), (8, 17));
+        assert_eq!(split.name(3), "b:1");
+    }
+}
@@ -0,0 +1,212 @@
+use std::ops::Range;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// The part of a whole-genome index covering one sequence, with positions rebased to
+/// the start of the sequence.
+///
+/// The shard text is the sequence followed by the `ctx` bases that came after it in
+/// the original text, so its suffixes keep the order they had in the full index. Shards
+/// of a `PerSeqIndex` are built on their own and padded with `A`s instead.
+///
+/// Shards split off a whole-genome index keep no bucket ends of their own, which would
+/// take `4^k` entries per sequence; `buckets` reads the k-mers from the text instead.
+pub struct Shard<const BYTES: usize> {
+    pub name: String,
+    /// Start of the sequence in the original text.
+    pub offset: usize,
+    pub sa: SuffixArray<BYTES>,
+    pub text: Vec<u8>,
+}
+
+impl<const BYTES: usize> Shard<BYTES> {
+    pub fn seq_len(&self) -> usize {
+        self.text.len() - self.sa.ctx()
+    }
+
+    /// The non-empty k-mer buckets in increasing k-mer order, each with its suffixes in
+    /// sorted order, like `SuffixArray::buckets`.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, &[Int<BYTES>])> + '_ {
+        let k = self.sa.k();
+        let kmer = move |i: &Int<BYTES>| {
+            self.text[i.get_usize()..][..k]
+                .iter()
+                .fold(0u64, |x, &b| x << 2 | base_code(b) as u64)
+        };
+        self.sa
+            .idxs()
+            .chunk_by(move |a, b| kmer(a) == kmer(b))
+            .map(move |bucket| (kmer(&bucket[0]), bucket))
+    }
+
+    /// The shard as a bundle of its own, with the trailing context as padding.
+    pub fn into_bundle(self) -> Bundle<BYTES> {
+        let mut dict = SeqDict::new();
+        dict.push(&self.name, self.seq_len());
+        dict.pad(self.sa.ctx());
+        Bundle::new(self.sa, self.text, &dict)
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Split the index of `bytes` into one shard per sequence of `dict`.
+    pub fn split_by_seq(&self, bytes: &[u8], dict: &SeqDict) -> Vec<Shard<BYTES>> {
+        assert!(self.is_lexicographic());
+        let ctx = self.ctx();
+        let docs = DocArray::new(self, dict);
+        let docs = docs.docs();
+
+        let mut sizes = vec![0; dict.len()];
+        for &d in docs.iter().filter(|&&d| d != DocArray::NONE) {
+            sizes[d as usize] += 1;
+        }
+
+        let mut idxs = sizes
+            .iter()
+            .map(|&s| CompactVec::<BYTES>::new(s))
+            .collect::<Vec<_>>();
+        let mut fill = vec![0; dict.len()];
+        for (i, &d) in self.idxs().iter().zip(docs) {
+            if d == DocArray::NONE {
+                continue;
+            }
+            let d = d as usize;
+            idxs[d][fill[d]].set_usize(i.get_usize() - dict.start(d));
+            fill[d] += 1;
+        }
+
+        idxs.into_iter()
+            .enumerate()
+            .map(|(id, idxs)| Shard {
+                name: dict.name(id).to_owned(),
+                offset: dict.start(id),
+                sa: SuffixArray::from_parts(idxs, CompactVec::new(0), self.k(), ctx, false)
+                    .with_tie_order(self.tie_order()),
+                text: bytes[dict.start(id)..dict.end(id) + ctx].to_vec(),
+            })
+            .collect()
+    }
+}
+
+/// Routes coordinates and queries over the shards of a split index.
+pub struct ShardRouter {
+    starts: Vec<usize>,
+    lens: Vec<usize>,
+}
+
+impl ShardRouter {
+    pub fn new<const BYTES: usize>(shards: &[Shard<BYTES>]) -> Self {
+        assert!(shards.windows(2).all(|w| w[0].offset < w[1].offset));
+        Self {
+            starts: shards.iter().map(|s| s.offset).collect(),
+            lens: shards.iter().map(|s| s.seq_len()).collect(),
+        }
+    }
+
+    /// Shard holding global position `pos`, and the position within it.
+    pub fn route(&self, pos: usize) -> Option<(usize, usize)> {
+        let shard = self.starts.partition_point(|&s| s <= pos).checked_sub(1)?;
+        let local = pos - self.starts[shard];
+        (local < self.lens[shard]).then_some((shard, local))
+    }
+
+    /// Shards overlapping the global `range`.
+    pub fn route_range(&self, range: Range<usize>) -> Range<usize> {
+        let mut first = self
+            .starts
+            .partition_point(|&s| s <= range.start)
+            .saturating_sub(1);
+        if first < self.starts.len() && self.starts[first] + self.lens[first] <= range.start {
+            first += 1;
+        }
+        let last = self.starts.partition_point(|&s| s < range.end);
+        first..last.max(first)
+    }
+
+    /// Global position of `local` in `shard`.
+    pub fn global(&self, shard: usize, local: usize) -> usize {
+        self.starts[shard] + local
+    }
+
+    /// Global positions of all occurrences of `pattern` that start in a shard, in
+    /// increasing order. Every shard is searched since a pattern can occur anywhere.
+    pub fn locate<const BYTES: usize>(
+        &self,
+        shards: &[Shard<BYTES>],
+        pattern: &[u8],
+    ) -> Vec<usize> {
+        let mut res = shards
+            .iter()
+            .enumerate()
+            .flat_map(|(id, s)| {
+                s.sa.idxs()[s.sa.range(&s.text, pattern)]
+                    .iter()
+                    .map(move |i| self.global(id, i.get_usize()))
+            })
+            .collect::<Vec<_>>();
+        res.sort_unstable();
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmers::*;
+
+    #[test]
+    fn test_split_by_seq() {
+        const CTX: usize = 124;
+        let mut dict = SeqDict::new();
+        let mut text = Vec::new();
+        for (name, seq) in [
+            ("a", &b"ACGTTGCAGATTACA"[..]),
+            ("b", b"GATTACATTT"),
+            ("c", b"CCGATTAG"),
+        ] {
+            let seq = seq.repeat(5);
+            dict.push(name, seq.len());
+            text.extend_from_slice(&seq);
+        }
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+        text.resize(text.len() + CTX, b'A');
+        dict.pad(CTX);
+
+        let shards = sa.split_by_seq(&text, &dict);
+        assert_eq!(shards.len(), 3);
+
+        for (id, shard) in shards.iter().enumerate() {
+            let n = shard.seq_len();
+            assert_eq!(n, dict.seq_len(id));
+            let mut correct = (0..n).collect::<Vec<_>>();
+            correct.sort_by(|&x, &y| {
+                shard.text[x..x + CTX]
+                    .cmp(&shard.text[y..y + CTX])
+                    .then(x.cmp(&y))
+            });
+            assert_eq!(shard.sa.idxs().to_usize_vec(), correct);
+            for (kmer, bucket) in shard.buckets() {
+                for i in bucket.iter().map(|i| i.get_usize()) {
+                    assert_eq!(shard.text[i..i + 3], decode_kmer(kmer as usize, 3));
+                }
+            }
+        }
+
+        let router = ShardRouter::new(&shards);
+        assert_eq!(router.route(0), Some((0, 0)));
+        assert_eq!(router.route(80), Some((1, 5)));
+        assert_eq!(router.route(dict.end(2)), None);
+        assert_eq!(router.route_range(70..80), 0..2);
+        assert_eq!(router.route_range(75..76), 1..2);
+
+        let mut correct = sa.idxs()[sa.range(&text, b"GATTA")]
+            .iter()
+            .map(|i| i.get_usize())
+            .collect::<Vec<_>>();
+        correct.sort_unstable();
+        assert_eq!(router.locate(&shards, b"GATTA"), correct);
+    }
+}
@@ -0,0 +1,279 @@
+#[cfg(unix)]
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+#[cfg(unix)]
+use std::os::fd::FromRawFd;
+use std::path::Path;
+
+use crate::bundle::*;
+use crate::compact_vec::*;
+use crate::fingerprint::*;
+use crate::manifest::*;
+use crate::platform::{Advice, Mmap};
+use crate::query::*;
+use crate::seq_dict::*;
+
+/// A bundle mapped read-only into memory. All processes attached to the same file or
+/// shared-memory segment share one physical copy of the index through the page cache.
+pub struct MappedBundle<const BYTES: usize> {
+    map: Mmap,
+    header: Header,
+    dict: SeqDict,
+    sa: Range<usize>,
+    buckets: Range<usize>,
+    text: Range<usize>,
+}
+
+#[cfg(unix)]
+fn shm_name(name: &str) -> io::Result<CString> {
+    CString::new(format!("/{}", name.trim_start_matches('/')))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid segment name"))
+}
+
+/// Copy the bundle at `path` into the named shared-memory segment `name`, replacing
+/// any segment of that name. The segment lives until `unpublish_shm` or reboot.
+#[cfg(unix)]
+pub fn publish_shm(path: impl AsRef<Path>, name: &str) -> io::Result<()> {
+    let c_name = shm_name(name)?;
+    let fd = unsafe {
+        libc::shm_open(
+            c_name.as_ptr(),
+            libc::O_CREAT | libc::O_TRUNC | libc::O_RDWR,
+            0o644,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut segment = unsafe { File::from_raw_fd(fd) };
+    io::copy(&mut File::open(path)?, &mut segment)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn unpublish_shm(name: &str) -> io::Result<()> {
+    let c_name = shm_name(name)?;
+    if unsafe { libc::shm_unlink(c_name.as_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The byte range of the section `name`, or an error if the bundle has none.
+fn section_range(header: &Header, name: &str) -> io::Result<Range<usize>> {
+    let s = header
+        .section(name)
+        .ok_or_else(|| invalid_data(format!("missing section {name}")))?;
+    Ok(s.offset as usize..(s.offset + s.len) as usize)
+}
+
+impl<const BYTES: usize> MappedBundle<BYTES> {
+    /// Map the bundle file at `path`.
+    pub fn attach(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::map(File::open(path)?)
+    }
+
+    /// Map the shared-memory segment `name` created by `publish_shm`.
+    #[cfg(unix)]
+    pub fn attach_shm(name: &str) -> io::Result<Self> {
+        let c_name = shm_name(name)?;
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDONLY, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Self::map(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn map(mut f: File) -> io::Result<Self> {
+        let len = f.metadata()?.len() as usize;
+        let header = Header::read(&mut f.by_ref(), MAGIC)?;
+        if header.bytes != BYTES {
+            return Err(invalid_data(format!(
+                "bundle has {}-byte entries, expected {BYTES}",
+                header.bytes
+            )));
+        }
+        if header.sections.iter().any(|s| {
+            s.offset
+                .checked_add(s.len)
+                .is_none_or(|end| end > len as u64)
+        }) {
+            return Err(invalid_data("section extends past the end of the bundle"));
+        }
+        // The sections that queries read are checked here, so that they cannot fail.
+        let sa = section_range(&header, "sa")?;
+        let buckets = section_range(&header, "buckets")?;
+        let text = section_range(&header, "text")?;
+        if sa.len() % BYTES != 0 || buckets.len() % BYTES != 0 {
+            return Err(invalid_data("section is not a whole number of entries"));
+        }
+
+        let mut res = Self {
+            map: Mmap::map(&f, len)?,
+            header,
+            dict: SeqDict::new(),
+            sa,
+            buckets,
+            text,
+        };
+        res.dict = decode_dict(res.section("dict")?)?;
+        Ok(res)
+    }
+
+    fn section_range(&self, name: &str) -> io::Result<Range<usize>> {
+        section_range(&self.header, name)
+    }
+
+    fn section(&self, name: &str) -> io::Result<&[u8]> {
+        Ok(&self.map.as_slice()[self.section_range(name)?])
+    }
+
+    /// Advise the OS how the named sections (`sa`, `buckets`, `text`, `dict`) will be
+    /// accessed, e.g. to drop cold sections from memory.
+    pub fn advise(&self, sections: &[&str], advice: Advice) -> io::Result<()> {
+        for name in sections {
+            self.map.advise(self.section_range(name)?, advice)?;
+        }
+        Ok(())
+    }
+
+    /// Make the named sections resident before the first queries arrive.
+    pub fn warmup(&self, sections: &[&str]) -> io::Result<()> {
+        for name in sections {
+            self.map.warmup(self.section_range(name)?)?;
+        }
+        Ok(())
+    }
+
+    fn ints(&self, range: &Range<usize>) -> &[Int<BYTES>] {
+        let bytes = &self.map.as_slice()[range.clone()];
+        // `Int` is a byte array, so any offset is suitably aligned.
+        unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr() as *const Int<BYTES>, bytes.len() / BYTES)
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.header.k
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.header.ctx
+    }
+
+    pub fn idxs(&self) -> &[Int<BYTES>] {
+        self.ints(&self.sa)
+    }
+
+    pub fn bucket_ends(&self) -> &[Int<BYTES>] {
+        self.ints(&self.buckets)
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.map.as_slice()[self.text.clone()]
+    }
+
+    pub fn dict(&self) -> &SeqDict {
+        &self.dict
+    }
+
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.section("fprint")
+            .ok()
+            .and_then(|f| f.try_into().ok())
+            .map(Fingerprint)
+    }
+
+    /// How the bundle was built, if it records a manifest.
+    pub fn manifest(&self) -> io::Result<Option<Manifest>> {
+        match self.header.section("manifest") {
+            Some(_) => Manifest::decode(self.section("manifest")?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Tags and lengths of the sections, in file order.
+    pub fn sections(&self) -> Vec<(String, u64)> {
+        self.header.section_lens()
+    }
+
+    /// Range of suffix array entries whose suffixes start with `pattern`.
+    pub fn range(&self, pattern: &[u8]) -> Range<usize> {
+        assert!(pattern.len() <= self.ctx());
+        pattern_range(self.idxs(), self.text(), pattern)
+    }
+
+    /// `range` for every pattern, interleaving the binary searches.
+    pub fn ranges(&self, patterns: &[&[u8]]) -> Vec<Range<usize>> {
+        assert!(patterns.iter().all(|p| p.len() <= self.ctx()));
+        pattern_ranges(self.idxs(), self.text(), patterns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_mapped_bundle() {
+        const CTX: usize = 124;
+        let mut text = b"ACGTTGCAGATTACA".repeat(10);
+        let mut dict = SeqDict::new();
+        dict.push("a", text.len());
+        let sa = SuffixArray::<5>::new_packed::<CTX>(&text, 3, 1);
+        text.resize(text.len() + CTX, b'A');
+        dict.pad(CTX);
+
+        let bundle = Bundle::new(sa, text.clone(), &dict);
+        let id = format!("simple-saca-{}", std::process::id());
+        let path = std::env::temp_dir().join(format!("{id}.mapped"));
+        bundle.write(&path).unwrap();
+
+        let mut mapped = vec![MappedBundle::<5>::attach(&path).unwrap()];
+        #[cfg(unix)]
+        {
+            publish_shm(&path, &id).unwrap();
+            mapped.push(MappedBundle::<5>::attach_shm(&id).unwrap());
+            unpublish_shm(&id).unwrap();
+        }
+
+        // A section whose end overflows, and a missing section, fail to attach.
+        let data = std::fs::read(&path).unwrap();
+        let bad = std::env::temp_dir().join(format!("{id}.bad"));
+        let mut overflow = data.clone();
+        overflow[56..64].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&bad, &overflow).unwrap();
+        assert!(MappedBundle::<5>::attach(&bad).is_err());
+        let mut missing = data;
+        missing[40..48].copy_from_slice(b"nosa\0\0\0\0");
+        std::fs::write(&bad, &missing).unwrap();
+        assert!(MappedBundle::<5>::attach(&bad).is_err());
+        std::fs::remove_file(&bad).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let sa = bundle.suffix_array();
+        for m in &mapped {
+            m.warmup(&["sa", "text"]).unwrap();
+            m.advise(&["buckets"], Advice::DontNeed).unwrap();
+            assert!(m.warmup(&["fm"]).is_err());
+            assert_eq!(m.idxs(), &sa.idxs()[..]);
+            assert_eq!(m.bucket_ends(), &sa.bucket_ends()[..]);
+            assert_eq!(m.text(), text);
+            assert_eq!(m.dict(), &dict);
+            assert_eq!(m.fingerprint(), sa.fingerprint());
+            assert_eq!(m.manifest().unwrap(), None);
+            assert_eq!(
+                m.sections()[0],
+                ("sa".to_string(), sa.idxs().as_bytes().len() as u64)
+            );
+            assert_eq!(m.range(b"GATTA"), sa.range(&text, b"GATTA"));
+            assert_eq!(
+                m.ranges(&[b"GATTA", b"CAG"]),
+                sa.ranges(&text, &[b"GATTA", b"CAG"])
+            );
+        }
+    }
+}
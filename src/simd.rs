@@ -0,0 +1,451 @@
+//! The 256-bit vector operations of the comparison kernels: AVX2 on x86, two NEON
+//! registers on aarch64, and plain arrays elsewhere or on x86 CPUs without AVX2. All
+//! backends give the same results; the masks have one bit per byte, as
+//! `_mm256_movemask_epi8`. On x86 CPUs with AVX-512BW, `V512` additionally compares
+//! 64 bytes at a time for the packed comparisons.
+
+pub(crate) use imp::V;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) use imp::V512;
+
+pub(crate) trait Vector: Copy {
+    unsafe fn load(ptr: *const u8) -> Self;
+    /// The 32 bytes at `ptr` as one 256-bit integer shifted left by `shift < 8` bits,
+    /// with the lowest byte cleared.
+    unsafe fn load_shifted(ptr: *const u8, shift: usize) -> Self;
+    unsafe fn eq_mask8(self, other: Self) -> u32;
+    /// Bytes where `self >= other`, unsigned.
+    unsafe fn ge_mask8(self, other: Self) -> u32;
+    unsafe fn eq_mask16(self, other: Self) -> u32;
+    unsafe fn ge_mask16(self, other: Self) -> u32;
+    unsafe fn xor_bytes(self, other: Self) -> [u8; 32];
+}
+
+/// Whether the native vector kernels can run on this CPU.
+pub(crate) fn simd_supported() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        true
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        false
+    }
+}
+
+/// Whether the 512-bit packed comparisons can run on this CPU.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) fn avx512_supported() -> bool {
+    is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw")
+}
+
+/// CPU features relevant to the kernels that this CPU supports.
+pub(crate) fn cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut res = Vec::new();
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            res.push("avx2");
+        }
+        if is_x86_feature_detected!("bmi2") {
+            res.push("bmi2");
+        }
+        if is_x86_feature_detected!("popcnt") {
+            res.push("popcnt");
+        }
+        if is_x86_feature_detected!("avx512f") {
+            res.push("avx512f");
+        }
+        if is_x86_feature_detected!("avx512bw") {
+            res.push("avx512bw");
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    res.push("neon");
+    res
+}
+
+/// Number of leading bytes in which `a` and `b` agree.
+#[inline]
+pub(crate) fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    if simd_supported() {
+        unsafe { common_prefix_native(a, b) }
+    } else {
+        a.iter().zip(b).take_while(|(x, y)| x == y).count()
+    }
+}
+
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn common_prefix_native(a: &[u8], b: &[u8]) -> usize {
+    let n = a.len().min(b.len());
+    let mut i = 0;
+    while i + 32 <= n {
+        let eq = V::load(a.as_ptr().add(i)).eq_mask8(V::load(b.as_ptr().add(i)));
+        if eq != u32::MAX {
+            return i + (!eq).trailing_zeros() as usize;
+        }
+        i += 32;
+    }
+    i + a[i..n]
+        .iter()
+        .zip(&b[i..n])
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+#[inline(always)]
+pub(crate) fn prefetch<T>(ptr: *const T) {
+    #[cfg(target_arch = "x86")]
+    unsafe {
+        std::arch::x86::_mm_prefetch::<{ std::arch::x86::_MM_HINT_T0 }>(ptr as *const i8)
+    };
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::x86_64::_mm_prefetch::<{ std::arch::x86_64::_MM_HINT_T0 }>(ptr as *const i8)
+    };
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        std::arch::asm!("prfm pldl1keep, [{0}]", in(reg) ptr, options(nostack, readonly, preserves_flags))
+    };
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    let _ = ptr;
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod imp {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::Vector;
+
+    #[derive(Copy, Clone)]
+    pub(crate) struct V(__m256i);
+
+    impl Vector for V {
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn load(ptr: *const u8) -> Self {
+            Self(_mm256_loadu_si256(ptr as _))
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn load_shifted(ptr: *const u8, shift: usize) -> Self {
+            let val = _mm256_loadu_si256(ptr as _);
+
+            let hi = _mm256_sllv_epi64(val, _mm256_set1_epi64x(shift as _));
+            let right_shift = _mm256_set1_epi64x((64 - shift) as _);
+            let lo = _mm256_srlv_epi64(_mm256_permute4x64_epi64(val, 0b10_01_00_11), right_shift);
+
+            let mask = _mm256_set_epi8(
+                -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+                -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, 0,
+            );
+
+            Self(_mm256_and_si256(_mm256_or_si256(hi, lo), mask))
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn eq_mask8(self, other: Self) -> u32 {
+            _mm256_movemask_epi8(_mm256_cmpeq_epi8(self.0, other.0)) as u32
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn ge_mask8(self, other: Self) -> u32 {
+            let max = _mm256_max_epu8(self.0, other.0);
+            _mm256_movemask_epi8(_mm256_cmpeq_epi8(max, self.0)) as u32
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn eq_mask16(self, other: Self) -> u32 {
+            _mm256_movemask_epi8(_mm256_cmpeq_epi16(self.0, other.0)) as u32
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn ge_mask16(self, other: Self) -> u32 {
+            let max = _mm256_max_epu16(self.0, other.0);
+            _mm256_movemask_epi8(_mm256_cmpeq_epi16(max, self.0)) as u32
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn xor_bytes(self, other: Self) -> [u8; 32] {
+            let mut x = [0u8; 32];
+            _mm256_storeu_si256(x.as_mut_ptr() as _, _mm256_xor_si256(self.0, other.0));
+            x
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub(crate) struct V512(__m512i);
+
+    impl V512 {
+        /// The 64 bytes at `ptr` as one 512-bit integer shifted left by `shift < 8` bits,
+        /// with the lowest byte cleared.
+        #[inline]
+        #[target_feature(enable = "avx512f,avx512bw")]
+        pub(crate) unsafe fn load_shifted(ptr: *const u8, shift: usize) -> Self {
+            let val = _mm512_loadu_si512(ptr as _);
+
+            let hi = _mm512_sllv_epi64(val, _mm512_set1_epi64(shift as _));
+            let right_shift = _mm512_set1_epi64((64 - shift) as _);
+            let below = _mm512_permutexvar_epi64(_mm512_set_epi64(6, 5, 4, 3, 2, 1, 0, 7), val);
+            let lo = _mm512_srlv_epi64(below, right_shift);
+
+            Self(_mm512_maskz_mov_epi8(!0b1, _mm512_or_si512(hi, lo)))
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx512f,avx512bw")]
+        pub(crate) unsafe fn eq_mask8(self, other: Self) -> u64 {
+            _mm512_cmpeq_epi8_mask(self.0, other.0)
+        }
+
+        /// Bytes where `self >= other`, unsigned.
+        #[inline]
+        #[target_feature(enable = "avx512f,avx512bw")]
+        pub(crate) unsafe fn ge_mask8(self, other: Self) -> u64 {
+            _mm512_cmpge_epu8_mask(self.0, other.0)
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod imp {
+    use std::arch::aarch64::*;
+
+    use super::Vector;
+
+    #[derive(Copy, Clone)]
+    pub(crate) struct V(uint8x16_t, uint8x16_t);
+
+    /// `_mm256_movemask_epi8` of two halves with all-ones or all-zeros bytes.
+    #[inline(always)]
+    unsafe fn movemask(lo: uint8x16_t, hi: uint8x16_t) -> u32 {
+        const BITS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+        let bits = vld1q_u8(BITS.as_ptr());
+        let sum = vpaddq_u8(vandq_u8(lo, bits), vandq_u8(hi, bits));
+        let sum = vpaddq_u8(sum, sum);
+        let sum = vpaddq_u8(sum, sum);
+        vgetq_lane_u32::<0>(vreinterpretq_u32_u8(sum))
+    }
+
+    impl Vector for V {
+        #[inline(always)]
+        unsafe fn load(ptr: *const u8) -> Self {
+            Self(vld1q_u8(ptr), vld1q_u8(ptr.add(16)))
+        }
+
+        #[inline(always)]
+        unsafe fn load_shifted(ptr: *const u8, shift: usize) -> Self {
+            let v0 = vreinterpretq_u64_u8(vld1q_u8(ptr));
+            let v1 = vreinterpretq_u64_u8(vld1q_u8(ptr.add(16)));
+            // Negative shifts shift right; shifting by 64 gives 0.
+            let left = vdupq_n_s64(shift as i64);
+            let right = vdupq_n_s64(shift as i64 - 64);
+            // The lanes below each lane: (l3, l0) and (l1, l2).
+            let p0 = vextq_u64::<1>(v1, v0);
+            let p1 = vextq_u64::<1>(v0, v1);
+            let r0 = vorrq_u64(vshlq_u64(v0, left), vshlq_u64(p0, right));
+            let r1 = vorrq_u64(vshlq_u64(v1, left), vshlq_u64(p1, right));
+            Self(
+                vsetq_lane_u8::<0>(0, vreinterpretq_u8_u64(r0)),
+                vreinterpretq_u8_u64(r1),
+            )
+        }
+
+        #[inline(always)]
+        unsafe fn eq_mask8(self, other: Self) -> u32 {
+            movemask(vceqq_u8(self.0, other.0), vceqq_u8(self.1, other.1))
+        }
+
+        #[inline(always)]
+        unsafe fn ge_mask8(self, other: Self) -> u32 {
+            movemask(vcgeq_u8(self.0, other.0), vcgeq_u8(self.1, other.1))
+        }
+
+        #[inline(always)]
+        unsafe fn eq_mask16(self, other: Self) -> u32 {
+            let eq = |a, b| {
+                vreinterpretq_u8_u16(vceqq_u16(vreinterpretq_u16_u8(a), vreinterpretq_u16_u8(b)))
+            };
+            movemask(eq(self.0, other.0), eq(self.1, other.1))
+        }
+
+        #[inline(always)]
+        unsafe fn ge_mask16(self, other: Self) -> u32 {
+            let ge = |a, b| {
+                vreinterpretq_u8_u16(vcgeq_u16(vreinterpretq_u16_u8(a), vreinterpretq_u16_u8(b)))
+            };
+            movemask(ge(self.0, other.0), ge(self.1, other.1))
+        }
+
+        #[inline(always)]
+        unsafe fn xor_bytes(self, other: Self) -> [u8; 32] {
+            let mut x = [0u8; 32];
+            vst1q_u8(x.as_mut_ptr(), veorq_u8(self.0, other.0));
+            vst1q_u8(x.as_mut_ptr().add(16), veorq_u8(self.1, other.1));
+            x
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+mod imp {
+    pub(crate) type V = super::Scalar;
+}
+
+/// The vector operations on plain arrays, for CPUs without the native instructions.
+#[derive(Copy, Clone)]
+pub(crate) struct Scalar([u8; 32]);
+
+fn mask(f: impl Fn(usize) -> bool) -> u32 {
+    (0..32).fold(0, |m, i| m | (f(i) as u32) << i)
+}
+
+fn u16_at(x: &[u8; 32], i: usize) -> u16 {
+    u16::from_le_bytes([x[i & !1], x[i | 1]])
+}
+
+impl Vector for Scalar {
+    #[inline]
+    unsafe fn load(ptr: *const u8) -> Self {
+        Self(std::ptr::read_unaligned(ptr as *const [u8; 32]))
+    }
+
+    #[inline]
+    unsafe fn load_shifted(ptr: *const u8, shift: usize) -> Self {
+        let val = Self::load(ptr).0;
+        let lanes = std::array::from_fn::<u64, 4, _>(|l| {
+            u64::from_le_bytes(val[8 * l..8 * l + 8].try_into().unwrap())
+        });
+        let mut res = [0u8; 32];
+        for l in 0..4 {
+            let below = lanes[(l + 3) % 4]
+                .checked_shr(64 - shift as u32)
+                .unwrap_or(0);
+            let lane = lanes[l] << shift | below;
+            res[8 * l..8 * l + 8].copy_from_slice(&lane.to_le_bytes());
+        }
+        res[0] = 0;
+        Self(res)
+    }
+
+    unsafe fn eq_mask8(self, other: Self) -> u32 {
+        mask(|i| self.0[i] == other.0[i])
+    }
+
+    unsafe fn ge_mask8(self, other: Self) -> u32 {
+        mask(|i| self.0[i] >= other.0[i])
+    }
+
+    unsafe fn eq_mask16(self, other: Self) -> u32 {
+        mask(|i| u16_at(&self.0, i) == u16_at(&other.0, i))
+    }
+
+    unsafe fn ge_mask16(self, other: Self) -> u32 {
+        mask(|i| u16_at(&self.0, i) >= u16_at(&other.0, i))
+    }
+
+    unsafe fn xor_bytes(self, other: Self) -> [u8; 32] {
+        std::array::from_fn(|i| self.0[i] ^ other.0[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn check<W: Vector>() {
+        let a = (0..40u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b[5] = 0;
+        b[20] = 255;
+        let (x, y) = (W::load(a.as_ptr()), W::load(b.as_ptr()));
+        assert_eq!(!x.eq_mask8(y), 1 << 5 | 1 << 20);
+        assert_eq!(x.ge_mask8(y) & (1 << 5 | 1 << 20), 1 << 5);
+        assert_eq!(!x.eq_mask16(y), 0b11 << 4 | 0b11 << 20);
+        assert_eq!(x.ge_mask16(y) & (0b11 << 4 | 0b11 << 20), 0b11 << 4);
+        assert_eq!(x.xor_bytes(y)[5], a[5]);
+
+        // The 32 bytes as a little-endian 256-bit integer, shifted left.
+        for shift in [0, 2, 4, 6] {
+            let shifted = W::load_shifted(a.as_ptr(), shift).xor_bytes(W::load([0; 32].as_ptr()));
+            let expected = (0..32)
+                .map(|r| match r {
+                    0 => 0,
+                    r => ((a[r] as u16) << shift | (a[r - 1] as u16) >> (8 - shift)) as u8,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(shifted[..], expected);
+        }
+    }
+
+    #[test]
+    fn test_simd() {
+        unsafe {
+            check::<Scalar>();
+            if simd_supported() {
+                check::<V>();
+            }
+        }
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let a = (0..100u8).collect::<Vec<_>>();
+        for diff in [0, 5, 31, 32, 40, 64, 99] {
+            let mut b = a.clone();
+            b[diff] = 255;
+            assert_eq!(common_prefix(&a, &b), diff);
+            assert_eq!(common_prefix(&a[..diff], &b), diff);
+        }
+        assert_eq!(common_prefix(&a, &a), 100);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_avx512() {
+        if !avx512_supported() {
+            return;
+        }
+        let a = (0..64u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b[5] = 0;
+        b[40] = 255;
+        unsafe {
+            let (x, y) = (
+                V512::load_shifted(a.as_ptr(), 0),
+                V512::load_shifted(b.as_ptr(), 0),
+            );
+            assert_eq!(!x.eq_mask8(y), 1 << 5 | 1 << 40);
+            assert_eq!(x.ge_mask8(y) & (1 << 5 | 1 << 40), 1 << 5);
+
+            for shift in [0, 2, 4, 6] {
+                let expected = (0..64)
+                    .map(|r| match r {
+                        0 => 0,
+                        r => ((a[r] as u16) << shift | (a[r - 1] as u16) >> (8 - shift)) as u8,
+                    })
+                    .collect::<Vec<_>>();
+                let shifted = V512::load_shifted(a.as_ptr(), shift);
+                let expected = V512::load_shifted(expected.as_ptr(), 0);
+                assert_eq!(shifted.eq_mask8(expected), !0);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::cmp::Ordering;
+
+use crate::compact_vec::*;
+use crate::kmers::*;
+use crate::perf::*;
+use crate::suffix_array::*;
+
+/// An in-bucket sorting strategy, e.g. a GPU or learned sorter, that replaces the
+/// comparison sort of `new_packed` while reusing its packing, counting and scatter.
+/// Buckets are sorted in parallel, so implementations must be `Sync`.
+pub trait BucketSorter<const BYTES: usize>: Sync {
+    /// Sort the suffix positions of `bucket`, which all start with the same bucket
+    /// k-mer, on their first `text.ctx()` bases, ordering ties by increasing position.
+    fn sort(&self, text: &BucketText, bucket: &mut [Int<BYTES>]);
+}
+
+impl<const BYTES: usize, F: Fn(&BucketText, &mut [Int<BYTES>]) + Sync> BucketSorter<BYTES> for F {
+    fn sort(&self, text: &BucketText, bucket: &mut [Int<BYTES>]) {
+        self(text, bucket)
+    }
+}
+
+/// Read access to the packed text for a `BucketSorter`. Positions are suffix starts
+/// as stored in the bucket.
+pub struct BucketText<'a> {
+    packed: &'a RevPacked,
+    ctx: usize,
+}
+
+impl<'a> BucketText<'a> {
+    pub(crate) fn new(packed: &'a RevPacked, ctx: usize) -> Self {
+        Self { packed, ctx }
+    }
+
+    /// Number of bases suffixes are sorted on.
+    pub fn ctx(&self) -> usize {
+        self.ctx
+    }
+
+    /// 2-bit code of the base at `i`, in the order `ACGT`.
+    pub fn get(&self, i: usize) -> u8 {
+        self.packed.get(i)
+    }
+
+    /// The first 64 bases of the suffix at `i`, first base most significant, as a
+    /// radix key.
+    pub fn prefix(&self, i: usize) -> u128 {
+        unsafe { prefix_key(self.packed, i) }
+    }
+
+    /// Number of equal bases of the suffixes at `a` and `b`, up to the context.
+    pub fn lcp(&self, a: usize, b: usize) -> usize {
+        unsafe { self.packed.lcp(a, b, self.ctx) }
+    }
+
+    /// The order `new_packed` sorts suffixes in: on the context, then by position.
+    pub fn cmp(&self, a: usize, b: usize) -> Ordering {
+        unsafe { simd_cmp_packed_lce(self.packed, a, self.packed, b, self.ctx) }.then(a.cmp(&b))
+    }
+}
+
+/// How `sort_rev_packed` sorts each bucket.
+#[derive(Copy, Clone)]
+pub(crate) enum InBucket<'a, const BYTES: usize> {
+    /// Comparison sort on the packed text.
+    Compare,
+    /// Comparison sort on cached 64-base keys, see `new_packed_key_cached`.
+    KeyCached,
+    Custom(&'a dyn BucketSorter<BYTES>),
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Like `new_packed`, but every bucket is sorted by `sorter`.
+    pub fn new_packed_with_sorter<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        sorter: &dyn BucketSorter<BYTES>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &packed,
+                0..seq.len(),
+                SpacedSeed::contiguous(k),
+                schedule,
+                Selection::all(),
+                TieOrder::Ascending,
+                InBucket::Custom(sorter),
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_sorter() {
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACAGATTACA".repeat(20);
+
+        let radix = |text: &BucketText, bucket: &mut [Int<5>]| {
+            assert_eq!(text.ctx(), CTX);
+            bucket.sort_by(|x, y| {
+                let (x, y) = (x.get_usize(), y.get_usize());
+                text.prefix(x)
+                    .cmp(&text.prefix(y))
+                    .then_with(|| text.cmp(x, y))
+            });
+        };
+        let s = SuffixArray::<5>::new_packed_with_sorter::<CTX>(&b, 3, 2, &radix);
+        let t = SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2);
+        assert_eq!(s.idxs(), t.idxs());
+        assert_eq!(s.bucket_ends(), t.bucket_ends());
+    }
+}
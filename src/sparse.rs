@@ -0,0 +1,323 @@
+use rayon::prelude::*;
+
+use std::collections::VecDeque;
+
+use crate::compact_vec::*;
+use crate::kmers::*;
+use crate::perf::*;
+use crate::query::*;
+use crate::sorter::*;
+use crate::stages::*;
+use crate::suffix_array::*;
+
+/// Which suffixes a sparse suffix array keeps.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Sampling {
+    /// The suffixes starting at multiples of the sample rate. Unsampled suffixes are
+    /// never bucketed, so construction also needs that much less memory.
+    #[default]
+    Text,
+    /// Every sample-rate-th entry of the sorted array, e.g. for locating through an
+    /// FM-index. The array is sampled after sorting, so peak memory is unchanged.
+    Rank,
+    /// The suffixes at positions chosen by the caller, e.g. minimizers, see
+    /// `new_packed_at`. The sample rate is 1, but most suffixes are missing.
+    Positions,
+}
+
+impl std::str::FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "rank" => Ok(Self::Rank),
+            _ => Err(format!("unknown sampling {s}, expected text or rank")),
+        }
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Like `new_packed`, but keeping only one in `rate` suffixes, chosen by `sampling`.
+    /// Rank-sampled arrays have no bucket ends, as buckets no longer align with entries.
+    pub fn new_packed_sparse<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        sampling: Sampling,
+        rate: usize,
+    ) -> Self {
+        assert!(rate > 0);
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+
+        let selection = Selection {
+            kmers: None,
+            sample_rate: if sampling == Sampling::Text { rate } else { 1 },
+        };
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &packed,
+                0..seq.len(),
+                SpacedSeed::contiguous(k),
+                schedule,
+                selection,
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+
+        let sa = if sampling == Sampling::Rank && rate > 1 {
+            let mut sampled = CompactVec::<BYTES>::new(idxs.len().div_ceil(rate));
+            sampled
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(j, e)| *e = idxs[j * rate].clone());
+            // Sampled entries are no longer adjacent in sorted order, so their ties are
+            // not known.
+            Self::from_parts(sampled, CompactVec::new(0), k, CTX, false)
+        } else {
+            Self::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
+        };
+        sa.with_sampling(sampling, rate)
+    }
+
+    /// Like `new_packed`, but with only the suffixes at the strictly increasing
+    /// `positions`, e.g. `minimizer_positions`. They go through the same count, scatter
+    /// and sort stages, so memory scales with their number instead of the text length.
+    pub fn new_packed_at<const CTX: usize>(
+        seq: &[u8],
+        positions: &[usize],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        assert!(positions.par_windows(2).all(|w| w[0] < w[1]));
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let input = pack_text(seq, CTX);
+        if let Some(&last) = positions.last() {
+            assert!(last < input.len(), "position {last} is past the text");
+        }
+
+        let chunks = bucket_chunks(positions.len(), &schedule);
+        let key = |j: usize| input.kmer(positions[j], k);
+        let mut counts = count_keys::<BYTES>(&chunks, 1 << (2 * k), key);
+        let bucket_ends = prefix_sum(&mut counts);
+        let mut sorted = scatter_keys(&chunks, &counts, &bucket_ends, key);
+        drop(counts);
+        sorted
+            .par_iter_mut()
+            .for_each(|e| e.set_usize(positions[e.get_usize()]));
+        let text = input.text();
+        sort_buckets_by(&mut sorted, &bucket_ends, schedule, |a, b| text.cmp(a, b));
+        into_suffix_array(sorted, bucket_ends, k, CTX).with_sampling(Sampling::Positions, 1)
+    }
+
+    /// `new_packed_at` on the minimizers of every `w` consecutive `minimizer_k`-mers of
+    /// `seq`, keeping about `2 / (w + 1)` of the suffixes.
+    pub fn new_packed_minimizers<const CTX: usize>(
+        seq: &[u8],
+        minimizer_k: usize,
+        w: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("Minimizer positions");
+        let positions = minimizer_positions(seq, minimizer_k, w);
+        phase.finish();
+        Self::new_packed_at::<CTX>(seq, &positions, k, schedule)
+    }
+
+    pub fn new_packed_sparse_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        sampling: Sampling,
+        rate: usize,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_sparse::<124>(seq, k, bucket_threads, sampling, rate),
+            248 => Self::new_packed_sparse::<248>(seq, k, bucket_threads, sampling, rate),
+            496 => Self::new_packed_sparse::<496>(seq, k, bucket_threads, sampling, rate),
+            992 => Self::new_packed_sparse::<992>(seq, k, bucket_threads, sampling, rate),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    /// Sorted text positions of all occurrences of `pattern` in the sequence `seq`, for
+    /// a sampled array.
+    ///
+    /// In text order, every occurrence covers the first sampled position at or after its
+    /// start, at an offset `d` below the sample rate, so the suffixes starting with
+    /// `pattern[d..]` are searched for every `d` and extended back over the `d` bases
+    /// before them. Patterns shorter than the sample rate may cover no sampled position,
+    /// and rank-sampled arrays miss the entries between samples, so both scan the text
+    /// instead; `into_fm_index` locates through rank samples without a scan. The pattern
+    /// must be at most the context long.
+    pub fn locate_sparse(&self, seq: &[u8], pattern: &[u8]) -> Vec<usize> {
+        assert!(pattern.len() <= self.ctx());
+        assert!(self.is_lexicographic());
+        let rate = self.sample_rate();
+        if self.sampling() != Sampling::Text || pattern.len() < rate {
+            return scan_text(seq, pattern);
+        }
+
+        let mut res = (0..rate)
+            .into_par_iter()
+            .flat_map_iter(|d| {
+                let range = pattern_range(self.idxs(), seq, &pattern[d..]);
+                self.idxs()[range].iter().filter_map(move |q| {
+                    let q = q.get_usize();
+                    let before = seq[q.checked_sub(d)?..q].iter();
+                    before
+                        .zip(&pattern[..d])
+                        .all(|(&a, &b)| base_code(a) == base_code(b))
+                        .then(|| q - d)
+                })
+            })
+            .collect::<Vec<_>>();
+        res.par_sort_unstable();
+        res
+    }
+}
+
+/// The positions at which `seq`, followed by padding `A`s, starts with `pattern`, in
+/// order.
+fn scan_text(seq: &[u8], pattern: &[u8]) -> Vec<usize> {
+    (0..seq.len())
+        .into_par_iter()
+        .filter(|&p| {
+            let text = seq[p..].iter().chain(std::iter::repeat(&b'A'));
+            text.zip(pattern)
+                .all(|(&a, &b)| base_code(a) == base_code(b))
+        })
+        .collect()
+}
+
+/// Order of `k`-mers for minimizers: a bijective mix of their 2-bit codes, so that
+/// low-complexity k-mers such as poly-A are not always chosen.
+fn kmer_order(kmer: u64) -> u64 {
+    let x = (kmer ^ (kmer >> 31)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Start positions of the minimizers of `seq`: of every `w` consecutive `k`-mers, the
+/// one smallest in a hashed order, the leftmost on ties. Sorted and without duplicates.
+/// Chunks of the text are scanned in parallel, each with a monotone queue.
+pub fn minimizer_positions(seq: &[u8], k: usize, w: usize) -> Vec<usize> {
+    assert!((1..=32).contains(&k) && w > 0);
+    let Some(windows) = (seq.len() + 2).checked_sub(k + w).filter(|&n| n > 0) else {
+        return vec![];
+    };
+    let mask = u64::MAX >> (64 - 2 * k);
+    let chunks = split_chunks(0..windows, (4 * rayon::current_num_threads()).min(windows));
+    let mut res = chunks
+        .into_par_iter()
+        .flat_map_iter(|chunk| {
+            let mut out = Vec::new();
+            let mut queue = VecDeque::<(u64, usize)>::new();
+            let mut kmer = seq[chunk.start..chunk.start + k - 1]
+                .iter()
+                .fold(0, |x, &b| (x << 2) | base_code(b) as u64);
+            // The k-mers of the windows starting in this chunk.
+            for i in chunk.start..chunk.end + w - 1 {
+                kmer = ((kmer << 2) | base_code(seq[i + k - 1]) as u64) & mask;
+                let order = kmer_order(kmer);
+                while queue.back().is_some_and(|&(o, _)| o > order) {
+                    queue.pop_back();
+                }
+                queue.push_back((order, i));
+                let Some(start) = (i + 1).checked_sub(w).filter(|&s| s >= chunk.start) else {
+                    continue;
+                };
+                while queue[0].1 < start {
+                    queue.pop_front();
+                }
+                if out.last() != Some(&queue[0].1) {
+                    out.push(queue[0].1);
+                }
+            }
+            out
+        })
+        .collect::<Vec<_>>();
+    res.dedup();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    fn kmer_code(kmer: &[u8]) -> u64 {
+        kmer.iter().fold(0, |x, &b| (x << 2) | base_code(b) as u64)
+    }
+
+    #[test]
+    fn test_sparse() {
+        const CTX: usize = 124;
+        let mut b = pseudo_dna(800);
+        b.extend_from_within(100..400);
+        let n = b.len();
+        let full = SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2)
+            .idxs()
+            .to_usize_vec();
+
+        let text = SuffixArray::<5>::new_packed_sparse::<CTX>(&b, 3, 2, Sampling::Text, 4);
+        assert_eq!(text.sample_rate(), 4);
+        let expected = full.iter().filter(|&&i| i % 4 == 0);
+        assert!(text
+            .idxs()
+            .iter()
+            .map(|i| i.get_usize())
+            .eq(expected.copied()));
+        assert_eq!(
+            text.bucket_ends().last().unwrap().get_usize(),
+            n.div_ceil(4)
+        );
+
+        let rank = SuffixArray::<5>::new_packed_sparse::<CTX>(&b, 3, 2, Sampling::Rank, 4);
+        let expected = full.iter().step_by(4);
+        assert!(rank
+            .idxs()
+            .iter()
+            .map(|i| i.get_usize())
+            .eq(expected.copied()));
+
+        for pattern in [&b[150..160], &b[398..404], &b[3..7], &b[20..22]] {
+            let naive = (0..=n - pattern.len())
+                .filter(|&i| &b[i..i + pattern.len()] == pattern)
+                .collect::<Vec<_>>();
+            assert_eq!(text.locate_sparse(&b, pattern), naive);
+            assert_eq!(rank.locate_sparse(&b, pattern), naive);
+        }
+
+        let (m, w) = (5, 8);
+        let minimizers = minimizer_positions(&b, m, w);
+        let naive = (0..=n - m - w + 1)
+            .map(|s| {
+                (s..s + w)
+                    .min_by_key(|&i| kmer_order(kmer_code(&b[i..i + m])))
+                    .unwrap()
+            })
+            .collect::<std::collections::BTreeSet<_>>();
+        assert!(minimizers.iter().eq(&naive));
+        assert!(minimizers.len() < n / 3);
+        let sa = SuffixArray::<5>::new_packed_minimizers::<CTX>(&b, m, w, 3, 2);
+        assert_eq!(sa.sampling(), Sampling::Positions);
+        let expected = full.iter().filter(|&i| naive.contains(i));
+        assert!(sa
+            .idxs()
+            .iter()
+            .map(|i| i.get_usize())
+            .eq(expected.copied()));
+        assert_eq!(sa.bucket_ends().last().unwrap().get_usize(), naive.len());
+    }
+}
@@ -0,0 +1,252 @@
+//! The stages of bucketed construction as standalone functions, to compose with other
+//! bucket keys or in-bucket sorters: `pack_text`, `bucket_chunks`, `count_keys`,
+//! `prefix_sum`, `scatter_keys`, `sort_buckets` and `into_suffix_array`.
+
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::compact_vec::*;
+use crate::perf::*;
+use crate::sorter::*;
+use crate::suffix_array::*;
+
+/// A text packed at 2 bits per base, with the `ctx` bases of padding it ends in.
+pub struct PackedInput {
+    packed: RevPacked,
+    len: usize,
+    ctx: usize,
+}
+
+impl PackedInput {
+    /// Number of suffixes, not counting the padding.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.ctx
+    }
+
+    /// The first `k <= 16` bases of the suffix at `i`, first base most significant, as
+    /// `new_packed` buckets it.
+    pub fn kmer(&self, i: usize, k: usize) -> usize {
+        assert!(i < self.len && k <= 16);
+        unsafe { self.packed.load_k(i, k) as usize }
+    }
+
+    /// Read access for sorting buckets, see `BucketText::cmp` for the order of
+    /// `new_packed`.
+    pub fn text(&self) -> BucketText<'_> {
+        BucketText::new(&self.packed, self.ctx)
+    }
+}
+
+/// Pack `seq`, followed by `ctx` bases of padding.
+pub fn pack_text(seq: &[u8], ctx: usize) -> PackedInput {
+    assert!(
+        ctx >= 16,
+        "contexts shorter than 16 bases are not supported"
+    );
+    let phase = Phase::start("2 bit packing");
+    let packed = RevPacked::with_padding(seq, ctx);
+    phase.finish();
+    PackedInput {
+        packed,
+        len: seq.len(),
+        ctx,
+    }
+}
+
+/// Split the positions `0..n` into the chunks that `count_keys` and `scatter_keys`
+/// work on, `chunks_per_thread` for each of the bucket threads of the `schedule`.
+pub fn bucket_chunks(n: usize, schedule: impl Into<Schedule>) -> Vec<Range<usize>> {
+    let schedule: Schedule = schedule.into();
+    split_chunks(0..n, schedule.chunk_count())
+}
+
+/// Per chunk, the number of positions with each of the `buckets` keys. Every `key` must
+/// be below `buckets`.
+pub fn count_keys<const BYTES: usize>(
+    chunks: &[Range<usize>],
+    buckets: usize,
+    key: impl Fn(usize) -> usize + Sync,
+) -> Vec<CompactVec<BYTES>> {
+    let phase = Phase::start("Parallel bucket count");
+    let mut counts = vec![CompactVec::<BYTES>::new(buckets); chunks.len()];
+    counts
+        .par_iter_mut()
+        .with_max_len(1)
+        .zip(chunks)
+        .for_each(|(counts, chunk)| {
+            for i in chunk.clone() {
+                let count = &mut counts[key(i)];
+                count.set_usize(count.get_usize() + 1);
+            }
+        });
+    phase.finish();
+    counts
+}
+
+/// Turn the `counts` of `count_keys` into the position in the output where each chunk
+/// starts writing each bucket, and return the end of every bucket.
+pub fn prefix_sum<const BYTES: usize>(counts: &mut [CompactVec<BYTES>]) -> CompactVec<BYTES> {
+    let phase = Phase::start("Bucket prefix sum");
+    let buckets = counts.first().map_or(0, |c| c.len());
+    let mut bucket_ends = CompactVec::<BYTES>::new(buckets);
+    let mut sum = 0;
+    for (i, end) in bucket_ends.iter_mut().enumerate() {
+        for counts in counts.iter_mut() {
+            let curr = counts[i].get_usize();
+            counts[i].set_usize(sum);
+            sum += curr;
+        }
+        end.set_usize(sum);
+    }
+    phase.finish();
+    bucket_ends
+}
+
+/// Write every position of `chunks` into its bucket, in text order within each bucket,
+/// given the `starts` and `bucket_ends` from `prefix_sum`. `key` must be the one that
+/// was counted: a chunk writing past its share of a bucket panics.
+pub fn scatter_keys<const BYTES: usize>(
+    chunks: &[Range<usize>],
+    starts: &[CompactVec<BYTES>],
+    bucket_ends: &CompactVec<BYTES>,
+    key: impl Fn(usize) -> usize + Sync,
+) -> CompactVec<BYTES> {
+    assert_eq!(starts.len(), chunks.len());
+    let phase = Phase::start("Parallel move into buckets");
+    let n = bucket_ends.last().map_or(0, |e| e.get_usize());
+    let mut sorted = CompactVec::<BYTES>::new(n);
+    let sorted_ptr = MutPtr(sorted.as_mut_ptr());
+    (0..chunks.len())
+        .into_par_iter()
+        .with_max_len(1)
+        .for_each(|c| {
+            let ptr = sorted_ptr;
+            // Each chunk owns its share of every bucket, up to where the next chunk starts.
+            let limits = starts.get(c + 1).unwrap_or(bucket_ends);
+            let mut next = starts[c].clone();
+            for i in chunks[c].clone() {
+                let b = key(i);
+                let idx = next[b].get_usize();
+                assert!(
+                    idx < limits[b].get_usize(),
+                    "position {i} has a key that was not counted"
+                );
+                unsafe { (*ptr.0.add(idx)).set_usize(i) };
+                next[b].set_usize(idx + 1);
+            }
+        });
+    phase.finish();
+    sorted
+}
+
+/// Sort every bucket of `sorted` by `sort`, buckets in parallel in runs of at least
+/// the `min_task_entries` of the `schedule`.
+pub fn sort_buckets<const BYTES: usize>(
+    sorted: &mut [Int<BYTES>],
+    bucket_ends: &[Int<BYTES>],
+    schedule: impl Into<Schedule>,
+    sort: impl Fn(&mut [Int<BYTES>]) + Sync,
+) {
+    let phase = Phase::start("Parallel sort buckets");
+    let start = |b: usize| {
+        if b == 0 {
+            0
+        } else {
+            bucket_ends[b - 1].get_usize()
+        }
+    };
+    let sizes = (0..bucket_ends.len()).map(|b| start(b + 1) - start(b));
+    let schedule: Schedule = schedule.into();
+    let runs = task_runs(sizes, schedule.min_task_entries);
+    let mut tasks = Vec::with_capacity(runs.len());
+    let mut rest = sorted;
+    for run in runs {
+        let len = start(run.end) - start(run.start);
+        let (slice, tail) = std::mem::take(&mut rest).split_at_mut(len);
+        tasks.push((run, slice));
+        rest = tail;
+    }
+    tasks.into_par_iter().for_each(|(run, mut slice)| {
+        for b in run {
+            let (bucket, tail) = std::mem::take(&mut slice).split_at_mut(start(b + 1) - start(b));
+            sort(bucket);
+            slice = tail;
+        }
+    });
+    phase.finish();
+}
+
+/// `sort_buckets` with a stable sort on `cmp` of positions. Buckets holding a large
+/// share of the entries are themselves sorted in parallel.
+pub fn sort_buckets_by<const BYTES: usize>(
+    sorted: &mut [Int<BYTES>],
+    bucket_ends: &[Int<BYTES>],
+    schedule: impl Into<Schedule>,
+    cmp: impl Fn(usize, usize) -> Ordering + Sync,
+) {
+    let par_bucket = (sorted.len() / (4 * rayon::current_num_threads())).max(PAR_MIN_BUCKET);
+    let cmp = |a: &Int<BYTES>, b: &Int<BYTES>| cmp(a.get_usize(), b.get_usize());
+    sort_buckets(sorted, bucket_ends, schedule, |slice| {
+        if slice.len() >= par_bucket {
+            slice.par_sort_by(cmp);
+        } else {
+            slice.sort_by(cmp);
+        }
+    });
+}
+
+/// The suffix array of the `sorted` entries, with a context of `ctx` bases. The
+/// `bucket_ends` must be those of buckets of `k`-mers as `PackedInput::kmer` reads
+/// them, for `bucket_range` and `buckets`; otherwise use `SuffixArray::from_idxs`.
+pub fn into_suffix_array<const BYTES: usize>(
+    sorted: CompactVec<BYTES>,
+    bucket_ends: CompactVec<BYTES>,
+    k: usize,
+    ctx: usize,
+) -> SuffixArray<BYTES> {
+    assert_eq!(bucket_ends.len(), 1 << (2 * k));
+    SuffixArray::from_parts(sorted, bucket_ends, k, ctx, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACAGGATCCA".repeat(30);
+        let k = 3;
+
+        let input = pack_text(&seq, CTX);
+        let chunks = bucket_chunks(input.len(), 2);
+        let key = |i| input.kmer(i, k);
+        let mut counts = count_keys::<5>(&chunks, 1 << (2 * k), key);
+        let bucket_ends = prefix_sum(&mut counts);
+        let mut sorted = scatter_keys(&chunks, &counts, &bucket_ends, key);
+        let text = input.text();
+        sort_buckets_by(&mut sorted, &bucket_ends, 2, |a, b| text.cmp(a, b));
+        let sa = into_suffix_array(sorted, bucket_ends, k, CTX);
+
+        let expected = SuffixArray::<5>::new_packed::<CTX>(&seq, k, 2);
+        assert_eq!(sa.idxs(), expected.idxs());
+        assert_eq!(sa.bucket_ends(), expected.bucket_ends());
+
+        // A key that was not counted is caught.
+        let mut counts = count_keys::<5>(&chunks, 4, |i| input.kmer(i, 1));
+        let bucket_ends = prefix_sum(&mut counts);
+        let wrong = std::panic::catch_unwind(|| {
+            scatter_keys(&chunks, &counts, &bucket_ends, |i| 3 - input.kmer(i, 1))
+        });
+        assert!(wrong.is_err());
+    }
+}
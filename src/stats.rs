@@ -0,0 +1,519 @@
+use rayon::prelude::*;
+
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// Contribution of a single sequence to a multi-sequence build.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenomeStats {
+    /// Number of bases indexed.
+    pub bases: usize,
+    /// Number of N bases removed before indexing.
+    pub n_bases: usize,
+    /// Number of distinct k-mers in the sequence.
+    pub kmers: usize,
+    /// Number of distinct k-mers that occur in no other sequence.
+    pub unique_kmers: usize,
+}
+
+impl GenomeStats {
+    pub fn n_fraction(&self) -> f64 {
+        self.n_bases as f64 / (self.bases + self.n_bases).max(1) as f64
+    }
+
+    pub fn unique_fraction(&self) -> f64 {
+        self.unique_kmers as f64 / self.kmers.max(1) as f64
+    }
+}
+
+/// Distribution of the longest common prefixes of adjacent suffixes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LcpStats {
+    /// `histogram[l]` is the number of adjacent pairs with LCP `l`.
+    pub histogram: Vec<usize>,
+    pub pairs: usize,
+    pub sum: usize,
+    /// Number of adjacent pairs equal over the whole context, which the construction
+    /// left in tie order. Many of them mean a longer context would sort differently.
+    pub ctx_ties: usize,
+}
+
+impl LcpStats {
+    pub fn max(&self) -> usize {
+        self.histogram.len().saturating_sub(1)
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.pairs.max(1) as f64
+    }
+
+    /// The lower median LCP.
+    pub fn median(&self) -> usize {
+        let half = self.pairs.div_ceil(2);
+        let mut seen = 0;
+        self.histogram
+            .iter()
+            .position(|&c| {
+                seen += c;
+                seen >= half.max(1)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Fraction of adjacent pairs that tie over the whole context.
+    pub fn ctx_tie_fraction(&self) -> f64 {
+        self.ctx_ties as f64 / self.pairs.max(1) as f64
+    }
+
+    fn add(&mut self, lcp: usize) {
+        if self.histogram.len() <= lcp {
+            self.histogram.resize(lcp + 1, 0);
+        }
+        self.histogram[lcp] += 1;
+        self.pairs += 1;
+        self.sum += lcp;
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        if self.histogram.len() < other.histogram.len() {
+            self.histogram.resize(other.histogram.len(), 0);
+        }
+        self.histogram
+            .iter_mut()
+            .zip(other.histogram)
+            .for_each(|(a, b)| *a += b);
+        self.pairs += other.pairs;
+        self.sum += other.sum;
+        self.ctx_ties += other.ctx_ties;
+        self
+    }
+}
+
+/// Repetitiveness of a window of a sequence, from the longest prefix each of its
+/// suffixes shares with any other suffix, capped at the context.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepeatWindow {
+    pub seq: usize,
+    /// Offsets of the window within the sequence.
+    pub start: usize,
+    pub end: usize,
+    pub mean_lcp: f64,
+    /// Fraction of suffixes that are equal to another one over the whole context.
+    pub saturated_fraction: f64,
+    /// Longest run of consecutive such suffixes, e.g. a tandem repeat array.
+    pub longest_saturated_run: usize,
+    /// Distinct k-mers of the window over all k-mers that lie within it.
+    pub distinct_kmer_fraction: f64,
+    /// BWT runs starting at the suffixes of the window, per base: close to 1 for
+    /// unique sequence and low in repeats, which compress well in run-length indexes.
+    pub bwt_run_density: f64,
+}
+
+/// Total entropy in bits of a run of bases with these counts.
+fn run_entropy(counts: &[usize; 4]) -> f64 {
+    let total = counts.iter().sum::<usize>() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| c as f64 * (total / c as f64).log2())
+        .sum()
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Histogram of the LCPs of adjacent suffixes, and their ties over the context,
+    /// computed in parallel over ranges of the array on the packed text. LCPs are
+    /// capped at `lcp_cap`, or extend to the end of the text if `None`; with a cap
+    /// below the context, comparisons still run up to the context to count ties.
+    pub fn stats(&self, bytes: &[u8], lcp_cap: Option<usize>) -> LcpStats {
+        let packed = RevPacked::new(bytes);
+        let idxs = self.idxs();
+        let ctx = self.ctx();
+        let max = lcp_cap.map_or(usize::MAX, |cap| cap.max(ctx));
+
+        (1..idxs.len())
+            .into_par_iter()
+            .with_min_len(1 << 12)
+            .fold(LcpStats::default, |mut stats, i| {
+                let (a, b) = (idxs[i - 1].get_usize(), idxs[i].get_usize());
+                let lcp = lcp_up_to(&packed, bytes.len(), a, b, max);
+                stats.ctx_ties += (lcp >= ctx) as usize;
+                stats.add(lcp_cap.map_or(lcp, |cap| lcp.min(cap)));
+                stats
+            })
+            .reduce(LcpStats::default, LcpStats::merge)
+    }
+
+    /// Empirical order-k entropies `H_0..=H_max_k` of the padded text `bytes`, in bits
+    /// per base: the entropy of the base preceding a suffix given its first `k` bases,
+    /// which is what BWT-based indexes compress to. Contexts up to the bucket length are
+    /// counted per bucket, longer ones over runs of adjacent suffixes sharing them.
+    pub fn order_k_entropies(&self, bytes: &[u8], max_k: usize) -> Vec<f64> {
+        assert!(self.is_lexicographic());
+        assert!(max_k <= self.ctx());
+        let packed = RevPacked::new(bytes);
+        let idxs = self.idxs();
+        let k = self.k();
+        let ranges: Vec<_> = if self.bucket_ends().is_empty() {
+            std::iter::once(0..idxs.len()).collect()
+        } else {
+            (0..self.bucket_ends().len())
+                .map(|kmer| self.bucket_range(kmer))
+                .collect()
+        };
+        let long = max_k.saturating_sub(k);
+
+        let per_bucket = ranges
+            .into_par_iter()
+            .map(|range| {
+                let mut counts = [0usize; 4];
+                let mut runs = vec![[0usize; 4]; long];
+                let mut sums = vec![0f64; long];
+                for i in range.clone() {
+                    let pos = idxs[i].get_usize();
+                    if i > range.start {
+                        let l = unsafe { packed.lcp(idxs[i - 1].get_usize(), pos, max_k) };
+                        for (j, (run, sum)) in runs.iter_mut().zip(&mut sums).enumerate() {
+                            if l < k + 1 + j {
+                                *sum += run_entropy(run);
+                                *run = [0; 4];
+                            }
+                        }
+                    }
+                    if pos > 0 {
+                        let c = base_code(bytes[pos - 1]) as usize;
+                        counts[c] += 1;
+                        runs.iter_mut().for_each(|run| run[c] += 1);
+                    }
+                }
+                for (run, sum) in runs.iter().zip(&mut sums) {
+                    *sum += run_entropy(run);
+                }
+                (counts, sums)
+            })
+            .collect::<Vec<_>>();
+
+        let n = per_bucket
+            .iter()
+            .map(|(c, _)| c.iter().sum::<usize>())
+            .sum::<usize>()
+            .max(1) as f64;
+        let mut res = (0..=max_k.min(k))
+            .map(|kk| {
+                let mut contexts = vec![[0usize; 4]; 1 << (2 * kk)];
+                for (kmer, (counts, _)) in per_bucket.iter().enumerate() {
+                    let context = &mut contexts[kmer >> (2 * (k - kk))];
+                    context.iter_mut().zip(counts).for_each(|(a, b)| *a += b);
+                }
+                contexts.iter().map(run_entropy).sum::<f64>() / n
+            })
+            .collect::<Vec<_>>();
+        res.extend((0..long).map(|j| per_bucket.iter().map(|(_, sums)| sums[j]).sum::<f64>() / n));
+        res
+    }
+
+    /// Repetitiveness of consecutive windows of `window` bases of every sequence, to find
+    /// e.g. centromeric and telomeric repeat arrays and the regions causing bucket skew.
+    /// Distinct k-mers are counted for `k`-mers of at most 32 bases.
+    pub fn repeat_windows(
+        &self,
+        bytes: &[u8],
+        dict: &SeqDict,
+        window: usize,
+        k: usize,
+    ) -> Vec<RepeatWindow> {
+        assert!(window > 0 && self.is_lexicographic());
+        assert!((1..=32).contains(&k));
+        let ctx = self.ctx();
+        assert!(ctx <= u16::MAX as usize);
+        let packed = RevPacked::new(bytes);
+        let idxs = self.idxs();
+        let lcp = (0..bytes.len())
+            .map(|_| AtomicU16::new(0))
+            .collect::<Vec<_>>();
+        let run_heads = (0..bytes.len())
+            .map(|_| AtomicBool::new(false))
+            .collect::<Vec<_>>();
+        // The BWT character of an entry, `None` for the suffix at 0.
+        let bwt = |i: usize| idxs[i].get_usize().checked_sub(1).map(|p| packed.get(p));
+
+        run_heads[idxs[0].get_usize()].store(true, Ordering::Relaxed);
+        (1..idxs.len()).into_par_iter().for_each(|i| {
+            let a = idxs[i - 1].get_usize();
+            let b = idxs[i].get_usize();
+            let l = unsafe { packed.lcp(a, b, ctx) } as u16;
+            lcp[a].fetch_max(l, Ordering::Relaxed);
+            lcp[b].fetch_max(l, Ordering::Relaxed);
+            if bwt(i) != bwt(i - 1) {
+                run_heads[b].store(true, Ordering::Relaxed);
+            }
+        });
+        let lcp = lcp
+            .into_iter()
+            .map(AtomicU16::into_inner)
+            .collect::<Vec<_>>();
+        let run_heads = run_heads
+            .into_iter()
+            .map(AtomicBool::into_inner)
+            .collect::<Vec<_>>();
+
+        let windows = (0..dict.len())
+            .flat_map(|id| {
+                (dict.start(id)..dict.end(id))
+                    .step_by(window)
+                    .map(move |start| (id, start))
+            })
+            .collect::<Vec<_>>();
+        windows
+            .into_par_iter()
+            .map(|(id, start)| {
+                let end = (start + window).min(dict.end(id));
+                let lcps = &lcp[start..end];
+                let mut run = 0;
+                let mut longest = 0;
+                for &l in lcps {
+                    run = if l as usize >= ctx { run + 1 } else { 0 };
+                    longest = longest.max(run);
+                }
+                let len = lcps.len() as f64;
+                let sum = lcps.iter().map(|&l| l as usize).sum::<usize>();
+                let saturated = lcps.iter().filter(|&&l| l as usize >= ctx).count();
+                let mask = u64::MAX >> (64 - 2 * k);
+                let mut kmers = bytes[start..end]
+                    .iter()
+                    .scan(0u64, |x, &b| {
+                        *x = ((*x << 2) | base_code(b) as u64) & mask;
+                        Some(*x)
+                    })
+                    .skip(k - 1)
+                    .collect::<Vec<_>>();
+                let total = kmers.len();
+                kmers.sort_unstable();
+                kmers.dedup();
+                let heads = run_heads[start..end].iter().filter(|&&h| h).count();
+                RepeatWindow {
+                    seq: id,
+                    start: start - dict.start(id),
+                    end: end - dict.start(id),
+                    mean_lcp: sum as f64 / len,
+                    saturated_fraction: saturated as f64 / len,
+                    longest_saturated_run: longest,
+                    distinct_kmer_fraction: kmers.len() as f64 / total.max(1) as f64,
+                    bwt_run_density: heads as f64 / len,
+                }
+            })
+            .collect()
+    }
+
+    /// Per-sequence statistics, with k-mer uniqueness derived from runs of suffixes
+    /// sharing their first `k` bases. `bytes` must be the text the array was built on.
+    pub fn genome_stats(&self, bytes: &[u8], dict: &SeqDict, k: usize) -> Vec<GenomeStats> {
+        assert!(k > 0 && k <= self.ctx());
+        let packed = RevPacked::new(bytes);
+        let idxs = self.idxs();
+        let n = idxs.len();
+        let num_chunks = (rayon::current_num_threads() * 4).min(n).max(1);
+        let chunk_size = n.div_ceil(num_chunks);
+
+        let kmer_at = |i: usize| {
+            let (id, offset) = dict.locate(idxs[i].get_usize())?;
+            (offset + k <= dict.seq_len(id)).then_some(id)
+        };
+        let same_kmer =
+            |i: usize| unsafe { packed.lcp(idxs[i - 1].get_usize(), idxs[i].get_usize(), k) >= k };
+
+        let counts = (0..num_chunks)
+            .into_par_iter()
+            .map(|chunk| {
+                let mut kmers = vec![0usize; dict.len()];
+                let mut unique = vec![0usize; dict.len()];
+                let mut ids = Vec::new();
+                let mut i = chunk * chunk_size;
+                let end = ((chunk + 1) * chunk_size).min(n);
+
+                while i > 0 && i < end && same_kmer(i) {
+                    i += 1;
+                }
+
+                while i < end {
+                    ids.clear();
+                    ids.extend(kmer_at(i));
+                    i += 1;
+                    while i < n && same_kmer(i) {
+                        ids.extend(kmer_at(i));
+                        i += 1;
+                    }
+
+                    ids.sort_unstable();
+                    ids.dedup();
+                    for &id in &ids {
+                        kmers[id] += 1;
+                    }
+                    if ids.len() == 1 {
+                        unique[ids[0]] += 1;
+                    }
+                }
+
+                (kmers, unique)
+            })
+            .reduce(
+                || (vec![0; dict.len()], vec![0; dict.len()]),
+                |(mut a_kmers, mut a_unique), (b_kmers, b_unique)| {
+                    a_kmers.iter_mut().zip(b_kmers).for_each(|(a, b)| *a += b);
+                    a_unique.iter_mut().zip(b_unique).for_each(|(a, b)| *a += b);
+                    (a_kmers, a_unique)
+                },
+            );
+
+        (0..dict.len())
+            .map(|id| GenomeStats {
+                bases: dict.seq_len(id),
+                n_bases: dict.n_count(id),
+                kmers: counts.0[id],
+                unique_kmers: counts.1[id],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_genome_stats() {
+        const CTX: usize = 124;
+        let seq = b"ACGTACGTACGTTTTT";
+        let b = pad_text(seq, CTX);
+        let mut dict = SeqDict::new();
+        dict.push_with_ns("a", 8, 2);
+        dict.push("b", 8);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(seq, 2, 1);
+        let stats = s.genome_stats(&b, &dict, 4);
+        assert_eq!((stats[0].bases, stats[0].n_bases), (8, 2));
+        assert_eq!((stats[0].kmers, stats[0].unique_kmers), (4, 3));
+        assert_eq!((stats[1].kmers, stats[1].unique_kmers), (4, 3));
+    }
+
+    #[test]
+    fn test_lcp_stats() {
+        const CTX: usize = 124;
+        let mut seq = pseudo_dna(1000);
+        seq.extend_from_within(100..600);
+        let b = pad_text(&seq, CTX);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 4, 1);
+        let stats = s.stats(&b, None);
+        let idxs = s.idxs().to_usize_vec();
+        let mut naive = LcpStats::default();
+        let mut lcps = Vec::new();
+        for w in idxs.windows(2) {
+            let lcp = b[w[0]..]
+                .iter()
+                .zip(&b[w[1]..])
+                .take_while(|(x, y)| x == y)
+                .count();
+            naive.add(lcp);
+            naive.ctx_ties += (lcp >= CTX) as usize;
+            lcps.push(lcp);
+        }
+        assert_eq!(stats, naive);
+        assert!(stats.max() > 500);
+        assert!(stats.ctx_ties > 300);
+        lcps.sort_unstable();
+        assert_eq!(stats.median(), lcps[(lcps.len() - 1) / 2]);
+
+        let capped = s.stats(&b, Some(100));
+        assert_eq!(capped.max(), 100);
+        assert_eq!(capped.pairs, stats.pairs);
+        assert_eq!(capped.ctx_ties, stats.ctx_ties);
+        assert_eq!(capped.histogram[..100], stats.histogram[..100]);
+    }
+
+    #[test]
+    fn test_repeat_windows() {
+        const CTX: usize = 124;
+        let mut seq = (0..1000u64)
+            .map(|i| {
+                let x = i.wrapping_mul(0x9e3779b97f4a7c15);
+                b"ACGT"[((x ^ x >> 29).wrapping_mul(0xbf58476d1ce4e5b9) >> 62) as usize]
+            })
+            .collect::<Vec<_>>();
+        seq.extend_from_within(100..400);
+        let n = seq.len();
+        let b = pad_text(&seq, CTX);
+        let mut dict = SeqDict::new();
+        dict.push("a", 1000);
+        dict.push("b", 300);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 1);
+        let windows = s.repeat_windows(&b, &dict, 250, 8);
+        assert_eq!(windows.len(), 6);
+        assert_eq!(
+            (windows[4].seq, windows[4].start, windows[4].end),
+            (1, 0, 250)
+        );
+
+        let idxs = s.idxs().to_usize_vec();
+        let bwt = idxs
+            .iter()
+            .map(|&p| p.checked_sub(1).map(|p| b[p]))
+            .collect::<Vec<_>>();
+        let naive_lcp = |p: usize| {
+            (0..n)
+                .filter(|&q| q != p)
+                .map(|q| (0..CTX).take_while(|&l| b[p + l] == b[q + l]).count())
+                .max()
+                .unwrap()
+        };
+        for w in &windows {
+            let offset = dict.start(w.seq);
+            let lcps = (w.start..w.end)
+                .map(|p| naive_lcp(p + offset))
+                .collect::<Vec<_>>();
+            let mean = lcps.iter().sum::<usize>() as f64 / lcps.len() as f64;
+            assert!((w.mean_lcp - mean).abs() < 1e-9);
+            let saturated = lcps.iter().filter(|&&l| l == CTX).count() as f64;
+            assert_eq!(w.saturated_fraction, saturated / lcps.len() as f64);
+
+            let range = w.start + offset..w.end + offset;
+            let kmers = b[range.clone()].windows(8).collect::<Vec<_>>();
+            let distinct = kmers.iter().collect::<std::collections::HashSet<_>>();
+            let fraction = distinct.len() as f64 / kmers.len() as f64;
+            assert_eq!(w.distinct_kmer_fraction, fraction);
+            let heads = (0..idxs.len())
+                .filter(|&i| range.contains(&idxs[i]))
+                .filter(|&i| i == 0 || bwt[i] != bwt[i - 1])
+                .count();
+            assert_eq!(w.bwt_run_density, heads as f64 / lcps.len() as f64);
+        }
+        // The copy of 100..400 ties with the original over the whole context.
+        assert_eq!(windows[0].longest_saturated_run, 150);
+        assert!(windows[4].saturated_fraction > 0.6);
+        assert_eq!(windows[2].longest_saturated_run, 0);
+        assert!(windows[4].bwt_run_density < windows[2].bwt_run_density);
+    }
+
+    #[test]
+    fn test_order_k_entropies() {
+        const CTX: usize = 124;
+        let seq = pseudo_dna(2000);
+        let n = seq.len();
+        let b = pad_text(&seq, CTX);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 2, 1);
+        let h = s.order_k_entropies(&b, 5);
+        for (k, &h) in h.iter().enumerate() {
+            let mut contexts = std::collections::HashMap::<&[u8], [usize; 4]>::new();
+            for i in 1..n {
+                contexts.entry(&b[i..i + k]).or_default()[base_code(b[i - 1]) as usize] += 1;
+            }
+            let naive = contexts.values().map(run_entropy).sum::<f64>() / (n - 1) as f64;
+            assert!((h - naive).abs() < 1e-9, "H_{k}: {h} != {naive}");
+        }
+        assert!(h[0] > 1.9 && h.windows(2).all(|w| w[1] <= w[0] + 1e-12));
+    }
+}
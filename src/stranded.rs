@@ -0,0 +1,145 @@
+use crate::alphabet::*;
+use crate::generalized::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// The strand of the text a suffix array entry was taken from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+/// A suffix array over the records of a text and their reverse complements, built in
+/// one pass, so that a pattern is found on both strands with a single search.
+///
+/// The indexed text holds the forward records followed by the reverse complement of
+/// each record, in the same order. Every record ends in its own sentinel, so suffixes
+/// never run from one strand into the other.
+pub struct StrandedSuffixArray<const BYTES: usize> {
+    sa: SuffixArray<BYTES>,
+    text: Vec<u8>,
+    /// The records of both strands in the indexed text.
+    both: SeqDict,
+    /// Number of forward records.
+    records: usize,
+}
+
+impl<const BYTES: usize> StrandedSuffixArray<BYTES> {
+    /// Index the records of `dict` in `bytes` and their reverse complements.
+    pub fn new(bytes: &[u8], dict: &SeqDict, ctx: usize, k: usize) -> Self {
+        let records = dict.len();
+        let total = (0..records).map(|id| dict.seq_len(id)).sum::<usize>();
+        let mut text = Vec::with_capacity(2 * total + ctx);
+        let mut both = SeqDict::new();
+        for id in 0..records {
+            text.extend_from_slice(&bytes[dict.start(id)..dict.end(id)]);
+            both.push(dict.name(id), dict.seq_len(id));
+        }
+        for id in 0..records {
+            text.extend(revcomp(&bytes[dict.start(id)..dict.end(id)]));
+            both.push(dict.name(id), dict.seq_len(id));
+        }
+        let sa = SuffixArray::new_generalized_with_ctx(&text, &both, ctx, k, Sentinels::default());
+        text.resize(text.len() + ctx, b'A');
+        both.pad(ctx);
+        Self {
+            sa,
+            text,
+            both,
+            records,
+        }
+    }
+
+    pub fn suffix_array(&self) -> &SuffixArray<BYTES> {
+        &self.sa
+    }
+
+    /// The indexed text: forward records, then their reverse complements, then padding.
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    pub fn len(&self) -> usize {
+        self.sa.idxs().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The strand suffix array entry `i` was taken from.
+    pub fn strand(&self, i: usize) -> Strand {
+        let (id, _) = self.both.locate(self.sa.idxs()[i].get_usize()).unwrap();
+        if id < self.records {
+            Strand::Forward
+        } else {
+            Strand::Reverse
+        }
+    }
+
+    /// Record index, strand, and offset on that strand of suffix array entry `i`.
+    /// Offsets on the reverse strand count from the start of the reverse complement.
+    pub fn entry(&self, i: usize) -> (usize, Strand, usize) {
+        let (id, offset) = self.both.locate(self.sa.idxs()[i].get_usize()).unwrap();
+        if id < self.records {
+            (id, Strand::Forward, offset)
+        } else {
+            (id - self.records, Strand::Reverse, offset)
+        }
+    }
+
+    /// Forward coordinates of the `len` bases starting at suffix array entry `i`:
+    /// record index, strand, and the start of the interval on the forward strand.
+    pub fn forward_interval(&self, i: usize, len: usize) -> (usize, Strand, usize) {
+        match self.entry(i) {
+            (id, Strand::Reverse, offset) => {
+                (id, Strand::Reverse, self.both.seq_len(id) - offset - len)
+            }
+            entry => entry,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stranded() {
+        const CTX: usize = 124;
+        let records: [&[u8]; 3] = [b"ACGTACCA", b"GGATTC", b"TTTAC"];
+        let mut dict = SeqDict::new();
+        for (i, r) in records.iter().enumerate() {
+            dict.push(format!("r{i}"), r.len());
+        }
+        let b = records.concat();
+
+        let s = StrandedSuffixArray::<5>::new(&b, &dict, CTX, 3);
+        let rc = records.map(revcomp);
+        let all = records
+            .iter()
+            .copied()
+            .chain(rc.iter().map(|r| r.as_slice()))
+            .collect::<Vec<_>>();
+        let g = GeneralizedSuffixArray::<5>::new(&all, CTX, 3);
+        assert_eq!(s.suffix_array().idxs(), g.suffix_array().idxs());
+        assert_eq!(s.len(), 2 * b.len());
+
+        for i in 0..s.len() {
+            let (id, strand, offset) = s.entry(i);
+            assert_eq!(s.strand(i), strand);
+            let pos = s.suffix_array().idxs()[i].get_usize();
+            let (_, _, start) = s.forward_interval(i, 1);
+            let base = records[id][start];
+            let expected = match strand {
+                Strand::Forward => {
+                    assert_eq!(start, offset);
+                    base
+                }
+                Strand::Reverse => complement(base),
+            };
+            assert_eq!(s.text()[pos], expected);
+        }
+    }
+}
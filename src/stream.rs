@@ -0,0 +1,187 @@
+use std::io::{self, Read};
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+use crate::alphabet::*;
+use crate::bundle::invalid_data;
+use crate::packed_text::*;
+use crate::seq_dict::*;
+use crate::suffix_array::*;
+
+/// Chunks that may be in flight between the reader and the packer.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// A FASTA text read from a stream, with Ns and other non-ACGT bytes removed. Only the
+/// 2-bit packed bases are kept, so case is lost; `PackedText::decode` gives them back
+/// as upper case `ACGT`.
+pub struct StreamedInput {
+    pub dict: SeqDict,
+    pub packed: PackedText,
+}
+
+/// Incremental FASTA parser that can be fed arbitrary chunks. Records are named by
+/// their whole header line, like `parse_fastx` does.
+#[derive(Default)]
+struct FastaParser {
+    dict: SeqDict,
+    packed: PackedText,
+    name: Option<Vec<u8>>,
+    in_header: bool,
+    record_start: usize,
+    ns: usize,
+}
+
+impl FastaParser {
+    fn feed(&mut self, chunk: &[u8]) -> io::Result<()> {
+        for &b in chunk {
+            if self.in_header {
+                if b == b'\n' {
+                    self.in_header = false;
+                } else {
+                    self.name.as_mut().unwrap().push(b);
+                }
+                continue;
+            }
+
+            match b {
+                b'>' => {
+                    self.finish_record();
+                    self.name = Some(Vec::new());
+                    self.in_header = true;
+                }
+                b'\n' | b'\r' | b' ' | b'\t' => {}
+                _ if self.name.is_none() => return Err(invalid_data("expected FASTA")),
+                _ if !is_acgt(b) => self.ns += 1,
+                _ => self.packed.push(base_code(b)),
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_record(&mut self) {
+        if let Some(name) = self.name.take() {
+            let name = name.strip_suffix(b"\r").unwrap_or(&name[..]);
+            let len = self.packed.len() - self.record_start;
+            self.dict
+                .push_with_ns(String::from_utf8_lossy(name), len, self.ns);
+        }
+        self.record_start = self.packed.len();
+        self.ns = 0;
+    }
+
+    fn finish(mut self) -> StreamedInput {
+        self.finish_record();
+        StreamedInput {
+            dict: self.dict,
+            packed: self.packed,
+        }
+    }
+}
+
+/// Read FASTA from `reader` in chunks of `chunk_size` bytes on a separate thread, so
+/// that a slow blocking source (e.g. a download) overlaps with parsing and packing.
+pub fn read_fasta_stream(
+    mut reader: impl Read + Send + 'static,
+    chunk_size: usize,
+) -> io::Result<StreamedInput> {
+    let (tx, rx) = sync_channel::<io::Result<Vec<u8>>>(CHUNKS_IN_FLIGHT);
+
+    let handle = thread::spawn(move || loop {
+        let mut chunk = vec![0u8; chunk_size];
+        let res = match reader.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(n) => {
+                chunk.truncate(n);
+                Ok(chunk)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+        let failed = res.is_err();
+        if tx.send(res).is_err() || failed {
+            return;
+        }
+    });
+
+    let mut parser = FastaParser::default();
+    // Dropping the receiver on an error stops the reader thread at its next send.
+    let res = rx.into_iter().try_for_each(|chunk| parser.feed(&chunk?));
+    handle.join().unwrap();
+    res?;
+
+    Ok(parser.finish())
+}
+
+/// Like `read_fasta_stream`, for an async `reader`: every chunk is parsed and packed as
+/// soon as it arrives, while the runtime keeps receiving the next one.
+#[cfg(feature = "async")]
+pub async fn read_fasta_async(
+    mut reader: impl futures_io::AsyncRead + Unpin,
+    chunk_size: usize,
+) -> io::Result<StreamedInput> {
+    let mut parser = FastaParser::default();
+    let mut chunk = vec![0u8; chunk_size];
+    loop {
+        let read =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut reader).poll_read(cx, &mut chunk));
+        match read.await {
+            Ok(0) => break,
+            Ok(n) => parser.feed(&chunk[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(parser.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::*;
+
+    #[test]
+    fn test_read_fasta_stream() {
+        let fasta = b"\n>chr1 first\nACGTN\nacgu\n>chr2\r\nNNGGCC\r\n>empty\n".to_vec();
+        let input = read_fasta_stream(io::Cursor::new(fasta.clone()), 3).unwrap();
+
+        assert_eq!(input.packed, PackedText::new(b"ACGTacguGGCC"));
+        assert_eq!(input.dict.len(), 3);
+        assert_eq!(input.dict.name(0), "chr1 first");
+        assert_eq!(input.dict.seq_len(0), 8);
+        assert_eq!(input.dict.n_count(0), 1);
+        assert_eq!(input.dict.name(1), "chr2");
+        assert_eq!(input.dict.n_count(1), 2);
+        assert_eq!(input.dict.seq_len(2), 0);
+        let parsed = parse_fastx(&fasta, NonAcgt::Strip).unwrap();
+        assert_eq!(
+            (0..3).map(|id| input.dict.name(id)).collect::<Vec<_>>(),
+            (0..3).map(|id| parsed.dict.name(id)).collect::<Vec<_>>()
+        );
+        assert!(read_fasta_stream(io::Cursor::new(b"ACGT\n>chr1\nACGT\n"), 3).is_err());
+
+        #[cfg(feature = "async")]
+        {
+            use std::future::Future;
+            use std::task::{Context, RawWaker, RawWakerVTable, Waker};
+            const VTABLE: RawWakerVTable = RawWakerVTable::new(
+                |_| RawWaker::new(std::ptr::null(), &VTABLE),
+                |_| {},
+                |_| {},
+                |_| {},
+            );
+            let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+            // Reading from a slice never waits, so the first poll finishes.
+            let mut read = std::pin::pin!(read_fasta_async(&fasta[..], 3));
+            let std::task::Poll::Ready(res) = read.as_mut().poll(&mut Context::from_waker(&waker))
+            else {
+                panic!("reading from a slice was pending");
+            };
+            assert_eq!(res.unwrap().packed, input.packed);
+        }
+
+        let b = input.packed.decode(0..input.packed.len()).repeat(20);
+        let s = SuffixArray::<5>::new_packed_text::<124>(&PackedText::new(&b), 3, 2);
+        let t = SuffixArray::<5>::new_packed::<124>(&b, 3, 2);
+        assert_eq!(s.idxs().to_usize_vec(), t.idxs().to_usize_vec());
+    }
+}
@@ -1,336 +1,1616 @@
 use rayon::prelude::*;
 
 use std::cmp::Ordering;
-use std::time::Instant;
-
-#[cfg(target_arch = "x86")]
-use std::arch::x86::*;
-#[cfg(target_arch = "x86_64")]
-use std::arch::x86_64::*;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
 use crate::compact_vec::*;
+use crate::fingerprint::*;
+use crate::kmers::*;
+use crate::memory::*;
+use crate::packed_text::*;
+use crate::perf::*;
+use crate::report::*;
+use crate::simd::*;
+use crate::sorter::*;
+use crate::sparse::*;
+use crate::stages::*;
+use crate::watchdog::*;
 
 pub struct SuffixArray<const BYTES: usize> {
     idxs: CompactVec<BYTES>,
+    bucket_ends: CompactVec<BYTES>,
     k: usize,
     ctx: usize,
+    canonical: bool,
+    seed: Option<SpacedSeed>,
+    exact: bool,
+    lcp: Option<CompactVec<BYTES>>,
+    sampling: Sampling,
+    sample_rate: usize,
+    fingerprint: Option<Fingerprint>,
+    ties: TieOrder,
+    residual_ties: Option<usize>,
+}
+
+/// Order of suffixes that are equal over the whole context.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TieOrder {
+    /// By increasing text position.
+    #[default]
+    Ascending,
+    /// By decreasing text position.
+    Descending,
+}
+
+impl std::str::FromStr for TieOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascending" => Ok(Self::Ascending),
+            "descending" => Ok(Self::Descending),
+            _ => Err(format!(
+                "unknown tie order {s}, expected ascending or descending"
+            )),
+        }
+    }
+}
+
+/// The suffixes `sort_rev_packed` buckets: those at multiples of `sample_rate` whose
+/// k-mer is in `kmers` (all k-mers if `None`).
+#[derive(Clone, Debug)]
+pub(crate) struct Selection {
+    pub(crate) kmers: Option<Range<usize>>,
+    pub(crate) sample_rate: usize,
+}
+
+/// How `sort_rev_packed_counted` buckets and sorts the suffixes.
+pub(crate) struct SortOptions<'a, const BYTES: usize> {
+    pub(crate) seed: SpacedSeed,
+    pub(crate) selection: Selection,
+    pub(crate) ties: TieOrder,
+    pub(crate) in_bucket: InBucket<'a, BYTES>,
+    /// See `Schedule::min_task_entries`.
+    pub(crate) min_task_entries: usize,
+}
+
+impl Selection {
+    pub(crate) fn all() -> Self {
+        Self {
+            kmers: None,
+            sample_rate: 1,
+        }
+    }
+
+    pub(crate) fn kmers(kmers: Range<usize>) -> Self {
+        Self {
+            kmers: Some(kmers),
+            sample_rate: 1,
+        }
+    }
+}
+
+/// How the bucketing phases are split into parallel tasks, and what watches them.
+/// Every construction taking `bucket_threads` takes a `Schedule` as well; a plain
+/// thread count converts to one with the default chunking and grouping.
+#[derive(Clone)]
+pub struct Schedule {
+    /// Threads to count and scatter buckets on, see `pool_bucket_threads`.
+    pub bucket_threads: usize,
+    /// Chunks per bucket thread in the count and scatter phases, which idle threads
+    /// steal from slower ones. Every chunk has its own array of `4^k` counters.
+    pub chunks_per_thread: usize,
+    /// Sort consecutive buckets in tasks of at least this many entries, which saves
+    /// scheduling millions of tiny buckets on machines with few cores. For 0 every
+    /// bucket is a task of its own.
+    pub min_task_entries: usize,
+    /// Watches the phases and buckets of this build, and of the builds it runs on the
+    /// same thread that have none of their own.
+    pub watchdog: Option<Arc<Watchdog>>,
+    /// Called, from any thread, on the phases and sorted buckets of this build, and of
+    /// the builds it runs on the same thread that have none of their own.
+    pub progress: Option<ProgressCallback>,
+    /// Print the build report of phase run times and bucket statistics to stderr.
+    pub verbose: bool,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            bucket_threads: 0,
+            chunks_per_thread: 1,
+            min_task_entries: 0,
+            watchdog: None,
+            progress: None,
+            verbose: false,
+        }
+    }
+}
+
+impl fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Schedule")
+            .field("bucket_threads", &self.bucket_threads)
+            .field("chunks_per_thread", &self.chunks_per_thread)
+            .field("min_task_entries", &self.min_task_entries)
+            .field("watchdog", &self.watchdog)
+            .field("progress", &self.progress.is_some())
+            .field("verbose", &self.verbose)
+            .finish()
+    }
+}
+
+/// Watchdogs and progress callbacks compare by identity.
+impl PartialEq for Schedule {
+    fn eq(&self, other: &Self) -> bool {
+        fn same<T: ?Sized>(a: &Option<Arc<T>>, b: &Option<Arc<T>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+        }
+        self.bucket_threads == other.bucket_threads
+            && self.chunks_per_thread == other.chunks_per_thread
+            && self.min_task_entries == other.min_task_entries
+            && same(&self.watchdog, &other.watchdog)
+            && same(&self.progress, &other.progress)
+            && self.verbose == other.verbose
+    }
+}
+
+impl Eq for Schedule {}
+
+impl From<&Schedule> for Schedule {
+    fn from(schedule: &Schedule) -> Self {
+        schedule.clone()
+    }
+}
+
+impl From<usize> for Schedule {
+    fn from(bucket_threads: usize) -> Self {
+        Self {
+            bucket_threads,
+            ..Self::default()
+        }
+    }
+}
+
+impl Schedule {
+    /// Number of chunks the count and scatter phases are split into.
+    pub(crate) fn chunk_count(&self) -> usize {
+        pool_bucket_threads(self.bucket_threads) * self.chunks_per_thread.max(1)
+    }
+
+    /// Report the builds on the calling thread to the watchdog and progress callback,
+    /// and print their build report if verbose, until the returned guard is dropped.
+    /// Constructions taking a `Schedule` do this themselves; this also covers those
+    /// that take only a thread count.
+    pub fn observe(&self) -> Observed {
+        let watchdog = self.watchdog.clone().or_else(watchdog);
+        let progress = self.progress.clone().or_else(progress);
+        let verbose = self.verbose || verbose();
+        Observed {
+            watchdog: replace_watchdog(watchdog),
+            progress: replace_progress(progress),
+            verbose: replace_verbose(verbose),
+        }
+    }
+}
+
+/// Restores what the enclosing build reports to when dropped, see `Schedule::observe`.
+pub struct Observed {
+    watchdog: Option<Arc<Watchdog>>,
+    progress: Option<ProgressCallback>,
+    verbose: bool,
+}
+
+impl Drop for Observed {
+    fn drop(&mut self) {
+        replace_watchdog(self.watchdog.take());
+        replace_progress(self.progress.take());
+        replace_verbose(self.verbose);
+    }
+}
+
+/// Consecutive runs of the buckets with the given `sizes`, each of at least
+/// `min_entries` entries but the last, to sort as one task each.
+pub(crate) fn task_runs(
+    sizes: impl Iterator<Item = usize>,
+    min_entries: usize,
+) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let (mut start, mut sum, mut end) = (0, 0, 0);
+    for size in sizes {
+        sum += size;
+        end += 1;
+        if sum >= min_entries {
+            runs.push(start..end);
+            (start, sum) = (end, 0);
+        }
+    }
+    if start < end {
+        runs.push(start..end);
+    }
+    runs
+}
+
+/// `bucket_threads`, or for 0 the number of threads of the rayon pool the call runs in,
+/// e.g. inside `ThreadPool::install`. Every construction taking `bucket_threads`
+/// accepts 0, so that the bucket count and scatter phases are split over the same
+/// threads that sort the buckets.
+pub fn pool_bucket_threads(bucket_threads: usize) -> usize {
+    match bucket_threads {
+        0 => rayon::current_num_threads(),
+        n => n,
+    }
+}
+
+/// `positions` split into `chunks` contiguous ranges, the last taking the remainder.
+pub(crate) fn split_chunks(positions: Range<usize>, chunks: usize) -> Vec<Range<usize>> {
+    let chunk_size = positions.len() / chunks;
+    (0..chunks)
+        .map(|c| {
+            let start = positions.start + c * chunk_size;
+            let end = if c == chunks - 1 {
+                positions.end
+            } else {
+                start + chunk_size
+            };
+            start..end
+        })
+        .collect()
+}
+
+/// The positions of `chunk` at a multiple of `rate`.
+fn sampled(chunk: &Range<usize>, rate: usize) -> std::iter::StepBy<Range<usize>> {
+    (chunk.start.next_multiple_of(rate)..chunk.end).step_by(rate)
+}
+
+/// `seq` followed by `ctx` `A`s, the padded text that analyses over the whole text,
+/// such as `lcp` and `bwt`, take. Constructors and queries take `seq` itself.
+pub fn pad_text(seq: &[u8], ctx: usize) -> Vec<u8> {
+    let mut text = Vec::with_capacity(seq.len() + ctx);
+    text.extend_from_slice(seq);
+    text.resize(seq.len() + ctx, b'A');
+    text
 }
 
 impl<const BYTES: usize> SuffixArray<BYTES> {
-    pub fn new_packed<const CTX: usize>(bytes: &[u8], k: usize, bucket_threads: usize) -> Self {
-        let idxs = unsafe { Self::sort_packed::<CTX>(bytes, k, bucket_threads) };
+    /// The suffix array of the sequence `seq`. The packed text is padded internally, so
+    /// `seq` is not copied; queries take `seq` as well.
+    pub fn new_packed<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &packed,
+                0..seq.len(),
+                SpacedSeed::contiguous(k),
+                schedule,
+                Selection::all(),
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
+    }
+
+    /// Like `new_packed`, for `len` bases already packed to 2 bits: 4 bases per
+    /// byte with the first in the most significant bits, coded `A=0, C=1, G=2, T=3`.
+    /// The bytes are copied in reverse instead of being packed. Queries still take the
+    /// ASCII sequence.
+    pub fn new_from_packed<const CTX: usize>(
+        packed: &[u8],
+        len: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("2 bit packing");
+        let rev = RevPacked::from_msb_first(packed, len, CTX);
+        phase.finish();
+
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &rev,
+                0..len,
+                SpacedSeed::contiguous(k),
+                schedule,
+                Selection::all(),
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
+    }
+
+    /// Like `new_packed`, with suffixes that are equal over the context ordered by
+    /// `ties`.
+    pub fn new_packed_ordered<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        ties: TieOrder,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_packed::<CTX, false>(
+                seq,
+                SpacedSeed::contiguous(k),
+                schedule,
+                ties,
+                InBucket::Compare,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, false)
+            .with_tie_order(ties)
+            .with_residual_ties(residual_ties)
+    }
 
-        Self { idxs, k, ctx: CTX }
+    /// Like `new_packed_ordered`, but every bucket is sorted on cached keys of the first
+    /// 64 bases of its suffixes, loading the text only to compare suffixes that share
+    /// those. The keys take a `(u128, Int<BYTES>)`, 32 bytes, per suffix of the bucket
+    /// being sorted.
+    pub fn new_packed_key_cached<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        ties: TieOrder,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_packed::<CTX, false>(
+                seq,
+                SpacedSeed::contiguous(k),
+                schedule,
+                ties,
+                InBucket::KeyCached,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, false)
+            .with_tie_order(ties)
+            .with_residual_ties(residual_ties)
+    }
+
+    /// Like `new_packed`, but suffixes are bucketed by their canonical k-mer (the
+    /// smaller of the k-mer and its reverse complement), so each bucket holds both
+    /// strands of a k-mer. Within a bucket suffixes are sorted as usual, but the array
+    /// as a whole is not in lexicographic order.
+    pub fn new_packed_canonical<const CTX: usize>(
+        seq: &[u8],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_packed::<CTX, true>(
+                seq,
+                SpacedSeed::contiguous(k),
+                schedule,
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, true).with_residual_ties(residual_ties)
+    }
+
+    /// Like `new_packed`, but suffixes are bucketed by the care bases of a spaced `seed`
+    /// instead of their first `k` bases, which spreads low-complexity regions over more
+    /// buckets. Within a bucket suffixes are sorted as usual, but the array as a whole
+    /// is not in lexicographic order unless the seed is contiguous.
+    pub fn new_packed_spaced<const CTX: usize>(
+        seq: &[u8],
+        seed: SpacedSeed,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_packed::<CTX, false>(
+                seq,
+                seed,
+                schedule,
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, seed.weight(), CTX, false)
+            .with_seed(seed)
+            .with_residual_ties(residual_ties)
+    }
+
+    /// Like `new_packed`, but for a sequence that is already packed, e.g. while it was
+    /// being streamed in.
+    pub fn new_packed_text<const CTX: usize>(
+        text: &PackedText,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::from_text(text, CTX);
+        phase.finish();
+
+        let (idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &packed,
+                0..text.len(),
+                SpacedSeed::contiguous(k),
+                schedule,
+                Selection::all(),
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+        Self::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
+    }
+
+    /// The sorted suffixes of `seq` starting in `range` only, for analyses of a single
+    /// region. Comparisons still read the context beyond the end of `range`, and entries
+    /// are positions in `seq`.
+    ///
+    /// Only the region and its trailing context are packed. Since more buckets than
+    /// suffixes only cost memory, `k` is lowered to `log4` of the region length, and
+    /// the bucket threads to one per `4^k` suffixes.
+    pub fn new_packed_range<const CTX: usize>(
+        seq: &[u8],
+        range: Range<usize>,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        assert!(
+            range.start <= range.end && range.end <= seq.len(),
+            "range {range:?} is not within the {} bases of the sequence",
+            seq.len()
+        );
+        let len = range.len();
+        let k = k.min(len.max(4).ilog2() as usize / 2);
+        let mut schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        schedule.bucket_threads = pool_bucket_threads(schedule.bucket_threads)
+            .min(len >> (2 * k))
+            .max(1);
+        let phase = Phase::start("2 bit packing");
+        let end = seq.len().min(range.end + CTX);
+        let packed = RevPacked::with_padding(&seq[range.start..end], range.end + CTX - end);
+        phase.finish();
+
+        let (mut idxs, bucket_ends, residual_ties) = unsafe {
+            Self::sort_rev_packed::<CTX, false>(
+                &packed,
+                0..len,
+                SpacedSeed::contiguous(k),
+                schedule,
+                Selection::all(),
+                TieOrder::Ascending,
+                InBucket::Compare,
+            )
+        };
+        idxs.par_iter_mut()
+            .for_each(|i| i.set_usize(i.get_usize() + range.start));
+        Self::from_parts(idxs, bucket_ends, k, CTX, false).with_residual_ties(residual_ties)
     }
 
-    #[target_feature(enable = "avx2")]
-    unsafe fn sort_packed<const CTX: usize>(
+    pub fn new_packed_range_with_ctx(
         bytes: &[u8],
+        range: Range<usize>,
+        ctx: usize,
         k: usize,
-        bucket_threads: usize,
-    ) -> CompactVec<BYTES> {
-        let k_bits = k * 2;
-        let len_no_ctx = bytes.len() - CTX;
-        let chunk_size = len_no_ctx / bucket_threads;
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_range::<124>(bytes, range, k, bucket_threads),
+            248 => Self::new_packed_range::<248>(bytes, range, k, bucket_threads),
+            496 => Self::new_packed_range::<496>(bytes, range, k, bucket_threads),
+            992 => Self::new_packed_range::<992>(bytes, range, k, bucket_threads),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
 
-        let start = Instant::now();
-        let packed = RevPacked::new(bytes);
-        let elapsed = start.elapsed().as_secs_f64();
-        eprintln!("\t2 bit packing run time (s): {elapsed}");
+    pub fn new_from_packed_with_ctx(
+        packed: &[u8],
+        len: usize,
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_from_packed::<124>(packed, len, k, bucket_threads),
+            248 => Self::new_from_packed::<248>(packed, len, k, bucket_threads),
+            496 => Self::new_from_packed::<496>(packed, len, k, bucket_threads),
+            992 => Self::new_from_packed::<992>(packed, len, k, bucket_threads),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
 
-        let start = Instant::now();
-        let mut thread_counts = vec![CompactVec::<BYTES>::new(1 << k_bits); bucket_threads];
+    /// `new_packed` with the context length chosen at runtime for `seq`.
+    /// Supported multiples of 124: 124, 248, 496, 992.
+    pub fn new_packed_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        Self::new_packed_ordered_with_ctx(seq, ctx, k, bucket_threads, TieOrder::Ascending)
+    }
 
-        rayon::scope(|scope| {
-            for (thread_idx, counts) in thread_counts.iter_mut().enumerate() {
-                let packed = &packed;
-                scope.spawn(move |_| {
-                    let start = thread_idx * chunk_size;
-                    let end = if thread_idx >= bucket_threads - 1 {
-                        len_no_ctx
-                    } else {
-                        (thread_idx + 1) * chunk_size
-                    };
-
-                    for i in start..end {
-                        let kmer = packed.load_k(i, k);
-                        let count = (*counts.as_ptr().add(kmer as usize)).get_usize();
-                        (*counts.as_mut_ptr().add(kmer as usize)).set_usize(count + 1);
+    pub fn new_packed_ordered_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        ties: TieOrder,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_ordered::<124>(seq, k, bucket_threads, ties),
+            248 => Self::new_packed_ordered::<248>(seq, k, bucket_threads, ties),
+            496 => Self::new_packed_ordered::<496>(seq, k, bucket_threads, ties),
+            992 => Self::new_packed_ordered::<992>(seq, k, bucket_threads, ties),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    pub fn new_packed_key_cached_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        ties: TieOrder,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_key_cached::<124>(seq, k, bucket_threads, ties),
+            248 => Self::new_packed_key_cached::<248>(seq, k, bucket_threads, ties),
+            496 => Self::new_packed_key_cached::<496>(seq, k, bucket_threads, ties),
+            992 => Self::new_packed_key_cached::<992>(seq, k, bucket_threads, ties),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    pub fn new_packed_text_with_ctx(
+        text: &PackedText,
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_text::<124>(text, k, bucket_threads),
+            248 => Self::new_packed_text::<248>(text, k, bucket_threads),
+            496 => Self::new_packed_text::<496>(text, k, bucket_threads),
+            992 => Self::new_packed_text::<992>(text, k, bucket_threads),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    pub fn new_packed_canonical_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_canonical::<124>(seq, k, bucket_threads),
+            248 => Self::new_packed_canonical::<248>(seq, k, bucket_threads),
+            496 => Self::new_packed_canonical::<496>(seq, k, bucket_threads),
+            992 => Self::new_packed_canonical::<992>(seq, k, bucket_threads),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    pub fn new_packed_spaced_with_ctx(
+        seq: &[u8],
+        ctx: usize,
+        seed: SpacedSeed,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        match ctx {
+            124 => Self::new_packed_spaced::<124>(seq, seed, bucket_threads),
+            248 => Self::new_packed_spaced::<248>(seq, seed, bucket_threads),
+            496 => Self::new_packed_spaced::<496>(seq, seed, bucket_threads),
+            992 => Self::new_packed_spaced::<992>(seq, seed, bucket_threads),
+            _ => panic!("Context length of {ctx} is not supported!"),
+        }
+    }
+
+    unsafe fn sort_packed<const CTX: usize, const CANONICAL: bool>(
+        seq: &[u8],
+        seed: SpacedSeed,
+        schedule: Schedule,
+        ties: TieOrder,
+        in_bucket: InBucket<BYTES>,
+    ) -> (CompactVec<BYTES>, CompactVec<BYTES>, usize) {
+        let phase = Phase::start("2 bit packing");
+        let packed = RevPacked::with_padding(seq, CTX);
+        phase.finish();
+
+        Self::sort_rev_packed::<CTX, CANONICAL>(
+            &packed,
+            0..seq.len(),
+            seed,
+            schedule,
+            Selection::all(),
+            ties,
+            in_bucket,
+        )
+    }
+
+    /// Bucket the suffixes by the care bases of `seed` and sort every bucket. Suffixes
+    /// in a bucket need not share any prefix when the seed is spaced, so buckets are
+    /// sorted from the first base rather than past the key.
+    pub(crate) unsafe fn sort_rev_packed<const CTX: usize, const CANONICAL: bool>(
+        packed: &RevPacked,
+        positions: Range<usize>,
+        seed: SpacedSeed,
+        schedule: Schedule,
+        selection: Selection,
+        ties: TieOrder,
+        in_bucket: InBucket<BYTES>,
+    ) -> (CompactVec<BYTES>, CompactVec<BYTES>, usize) {
+        // Every chunk has its own counters, so that chunks can be counted and scattered
+        // by whichever thread is free, while each bucket stays in text order.
+        let chunks = split_chunks(positions, schedule.chunk_count());
+        let counts = Self::count_buckets::<CANONICAL>(packed, &chunks, seed, &selection);
+        Self::sort_rev_packed_counted::<CTX, CANONICAL>(
+            packed,
+            &chunks,
+            counts,
+            SortOptions {
+                seed,
+                selection,
+                ties,
+                in_bucket,
+                min_task_entries: schedule.min_task_entries,
+            },
+        )
+    }
+
+    /// Per chunk of positions, the number of selected suffixes in every bucket.
+    pub(crate) unsafe fn count_buckets<const CANONICAL: bool>(
+        packed: &RevPacked,
+        chunks: &[Range<usize>],
+        seed: SpacedSeed,
+        selection: &Selection,
+    ) -> Vec<CompactVec<BYTES>> {
+        let k = seed.weight();
+        let kmers = selection.kmers.clone().unwrap_or(0..1 << (2 * k));
+        let kmers = &kmers;
+        let phase = Phase::start("Parallel bucket count");
+        let mut thread_counts = vec![CompactVec::<BYTES>::new(1 << (2 * k)); chunks.len()];
+
+        thread_counts
+            .par_iter_mut()
+            .with_max_len(1)
+            .zip(chunks)
+            .for_each(|(counts, chunk)| {
+                for i in sampled(chunk, selection.sample_rate) {
+                    let mut kmer = packed.load_k_masked(i, seed);
+                    if CANONICAL {
+                        kmer = canonical_kmer(kmer, k);
                     }
-                });
-            }
-        });
+                    if !kmers.contains(&(kmer as usize)) {
+                        continue;
+                    }
+                    let count = (*counts.as_ptr().add(kmer as usize)).get_usize();
+                    (*counts.as_mut_ptr().add(kmer as usize)).set_usize(count + 1);
+                }
+            });
 
-        let elapsed = start.elapsed().as_secs_f64();
-        eprintln!("\tParallel bucket count run time (s): {elapsed}");
+        phase.finish();
+        thread_counts
+    }
+
+    /// `sort_rev_packed` over the given `chunks` of positions, with `thread_counts`
+    /// from `count_buckets` on the same chunks, seed and selection.
+    pub(crate) unsafe fn sort_rev_packed_counted<const CTX: usize, const CANONICAL: bool>(
+        packed: &RevPacked,
+        chunks: &[Range<usize>],
+        mut thread_counts: Vec<CompactVec<BYTES>>,
+        options: SortOptions<BYTES>,
+    ) -> (CompactVec<BYTES>, CompactVec<BYTES>, usize) {
+        let SortOptions {
+            seed,
+            selection,
+            ties,
+            in_bucket,
+            min_task_entries,
+        } = options;
+        assert!(!CANONICAL || seed.is_contiguous());
+        assert_eq!(thread_counts.len(), chunks.len());
+        let k = seed.weight();
+        let k_bits = k * 2;
+        // Only suffixes in the chunks at a multiple of the sample rate with a k-mer in
+        // `kmers` are bucketed; the other buckets stay empty.
+        let kmers = selection.kmers.unwrap_or(0..1 << k_bits);
+        let kmers = &kmers;
+        let rate = selection.sample_rate;
 
         let mut sum = 0;
-        let mut max_bucket = 0;
 
-        let start = Instant::now();
+        let phase = Phase::start("Bucket prefix sum");
+        // Per chunk, the buckets it writes to, and per bucket, the number of chunks still
+        // writing to it. A bucket is sorted as soon as its last chunk is scattered.
+        let words = (1usize << k_bits).div_ceil(64);
+        let mut touched = vec![vec![0u64; words]; chunks.len()];
+        let mut pending = (0..1usize << k_bits)
+            .map(|_| AtomicUsize::new(0))
+            .collect::<Vec<_>>();
+        let mut bucket_starts = CompactVec::<BYTES>::new((1 << k_bits) + 1);
+        let mut nonempty = 0;
 
         for i in 0..(1 << k_bits) {
-            let mut curr_bucket = 0;
-
-            for thread_idx in 0..bucket_threads {
-                let curr = thread_counts[thread_idx][i].get_usize();
-                thread_counts[thread_idx][i].set_usize(sum);
+            bucket_starts[i].set_usize(sum);
+            for (counts, touched) in thread_counts.iter_mut().zip(&mut touched) {
+                let curr = counts[i].get_usize();
+                counts[i].set_usize(sum);
                 sum += curr;
-                curr_bucket += curr;
+                if curr > 0 {
+                    touched[i / 64] |= 1 << (i % 64);
+                    *pending[i].get_mut() += 1;
+                }
+            }
+            if *pending[i].get_mut() > 0 {
+                nonempty += 1;
             }
-
-            max_bucket = max_bucket.max(curr_bucket);
         }
+        bucket_starts[1 << k_bits].set_usize(sum);
+        check_buckets(
+            bucket_starts
+                .windows(2)
+                .map(|w| w[1].get_usize() - w[0].get_usize()),
+            |kmer| seed.decode(kmer),
+        );
 
-        let elapsed = start.elapsed().as_secs_f64();
-        eprintln!("\tBucket prefix sum run time (s): {elapsed}");
+        phase.finish();
 
-        let start = Instant::now();
-        let mut sorted = CompactVec::<BYTES>::new(len_no_ctx);
+        let phase = Phase::start("Parallel move into and sort buckets");
+        let mut sorted = CompactVec::<BYTES>::new(sum);
         let sorted_ptr = MutPtr(sorted.as_mut_ptr());
+        let monitor = MemoryMonitor::default();
+        let shallow = AtomicUsize::new(0);
+        let deepened = AtomicUsize::new(0);
+        let radixed = AtomicUsize::new(0);
+        let parallel = AtomicUsize::new(0);
+        // Buckets holding a large share of the suffixes, e.g. of poly-A or telomeric
+        // repeats, are themselves sorted in parallel, so they do not leave the other
+        // threads idle at the end.
+        let par_bucket = (sum / (4 * rayon::current_num_threads())).max(PAR_MIN_BUCKET);
+        let kernel = Kernel::detect(CTX);
+        let residual = AtomicUsize::new(0);
+        let record_ties = |slice: &[Int<BYTES>]| {
+            residual.fetch_add(
+                count_ties::<CTX, BYTES>(packed, slice),
+                AtomicOrdering::Relaxed,
+            );
+        };
+
+        let sort = |i: usize| {
+            let start = bucket_starts[i].get_usize();
+            let end = bucket_starts[i + 1].get_usize();
+            let ptr = sorted_ptr;
+            let slice = unsafe { std::slice::from_raw_parts_mut(ptr.0.add(start), end - start) };
+            let par = slice.len() >= par_bucket;
+            if par {
+                parallel.fetch_add(1, AtomicOrdering::Relaxed);
+            }
+            let keyed = keyed_bytes::<BYTES>(slice.len());
+            match in_bucket {
+                InBucket::Custom(sorter) => {
+                    sorter.sort(&BucketText::new(packed, CTX), slice);
+                    record_ties(slice);
+                    return;
+                }
+                InBucket::KeyCached if monitor.can_allocate(keyed) => {
+                    sort_bucket_keyed::<CTX, BYTES>(packed, slice, ties, par);
+                    record_ties(slice);
+                    return;
+                }
+                _ => (),
+            }
+            // Large buckets of short contexts are radix sorted on cached keys, with the
+            // comparison sort as fallback when memory is short.
+            if CTX <= RADIX_MAX_CTX
+                && !par
+                && slice.len() >= RADIX_MIN_BUCKET
+                && monitor.can_allocate(2 * keyed)
+            {
+                radixed.fetch_add(1, AtomicOrdering::Relaxed);
+                sort_bucket_radix::<CTX, BYTES>(packed, slice, ties);
+                record_ties(slice);
+                return;
+            }
+            let in_place = !monitor.can_allocate(slice.len() / 2 * BYTES);
+
+            // Buckets whose sampled suffixes diverge within one block are first sorted on
+            // a single block, and only sorted on the full context if that left ties.
+            if CTX > SHALLOW_CTX && is_shallow(packed, slice) {
+                shallow.fetch_add(1, AtomicOrdering::Relaxed);
+                sort_bucket::<SHALLOW_CTX, BYTES>(kernel, packed, slice, in_place, par, ties);
+                let tied = slice.windows(2).any(|w| unsafe {
+                    packed.lcp(w[0].get_usize(), w[1].get_usize(), SHALLOW_CTX) >= SHALLOW_CTX
+                });
+                if !tied {
+                    return;
+                }
+                deepened.fetch_add(1, AtomicOrdering::Relaxed);
+            }
+            sort_bucket::<CTX, BYTES>(kernel, packed, slice, in_place, par, ties);
+            record_ties(slice);
+        };
+        let progress = BucketProgress::new(nonempty);
+        let (sort, pending, progress, starts) = (&sort, &pending, &progress, &bucket_starts);
 
         rayon::scope(|scope| {
-            for (thread_idx, counts) in thread_counts.iter_mut().enumerate() {
-                let packed = &packed;
-                scope.spawn(move |_| {
-                    let start = thread_idx * chunk_size;
-                    let end = if thread_idx >= bucket_threads - 1 {
-                        len_no_ctx
-                    } else {
-                        (thread_idx + 1) * chunk_size
-                    };
+            thread_counts
+                .par_iter_mut()
+                .zip(&touched)
+                .zip(chunks)
+                .with_max_len(1)
+                .for_each(|((counts, touched), chunk)| {
                     let ptr = sorted_ptr;
-
-                    for i in start..end {
-                        let kmer = packed.load_k(i, k);
+                    for i in sampled(chunk, rate) {
+                        let mut kmer = packed.load_k_masked(i, seed);
+                        if CANONICAL {
+                            kmer = canonical_kmer(kmer, k);
+                        }
+                        if !kmers.contains(&(kmer as usize)) {
+                            continue;
+                        }
                         let idx = (*counts.as_ptr().add(kmer as usize)).get_usize();
 
                         (*ptr.0.add(idx)).set_usize(i);
                         (*counts.as_mut_ptr().add(kmer as usize)).set_usize(idx + 1);
                     }
+
+                    let mut ready = Vec::new();
+                    for (w, &word) in touched.iter().enumerate() {
+                        let mut word = word;
+                        while word != 0 {
+                            let i = w * 64 + word.trailing_zeros() as usize;
+                            word &= word - 1;
+                            if pending[i].fetch_sub(1, AtomicOrdering::AcqRel) == 1 {
+                                ready.push(i);
+                            }
+                        }
+                    }
+                    if !ready.is_empty() {
+                        scope.spawn(move |_| {
+                            let size = |i: usize| starts[i + 1].get_usize() - starts[i].get_usize();
+                            let runs = task_runs(ready.iter().map(|&i| size(i)), min_task_entries);
+                            runs.into_par_iter().for_each(|run| {
+                                for &i in &ready[run] {
+                                    sort(i);
+                                    progress.tick();
+                                }
+                            })
+                        });
+                    }
                 });
-            }
         });
 
-        let elapsed = start.elapsed().as_secs_f64();
-        eprintln!("\tParallel move into buckets run time (s): {elapsed}");
-
-        let start = Instant::now();
+        phase.finish();
         let counts = thread_counts.into_iter().last().unwrap();
+        if CTX > SHALLOW_CTX {
+            build_log!(
+                "\tBuckets sorted shallow / re-sorted deep: {} / {}",
+                shallow.into_inner(),
+                deepened.into_inner()
+            );
+        }
+        build_log!("\tBuckets sorted in parallel: {}", parallel.into_inner());
+        if CTX <= RADIX_MAX_CTX {
+            build_log!("\tBuckets radix sorted: {}", radixed.into_inner());
+        }
+        let skew = BucketSkew::new(&counts, k, SKEW_TOP_N);
+        record_max_bucket(skew.largest());
+        build_log!("\tLargest bucket / total: {} / {sum}", skew.largest());
+        for &(kmer, size) in &skew.top {
+            build_log!(
+                "\t\t{}: {size} ({:.2}%)",
+                String::from_utf8_lossy(&seed.decode(kmer)),
+                100.0 * size as f64 / sum.max(1) as f64
+            );
+        }
+        if let Some(advice) = skew.recommendation(CANONICAL) {
+            build_log!("\t{advice}");
+        }
+        if monitor.degraded() > 0 {
+            build_log!(
+                "\tBuckets sorted in place due to memory pressure: {}",
+                monitor.degraded()
+            );
+        }
+        let residual = residual.into_inner();
+        build_log!("\tAdjacent suffixes tied over the context: {residual}");
 
-        (0..(1 << k_bits)).into_par_iter().for_each(|i| {
-            let start = if i == 0 {
-                0
-            } else {
-                (*counts.as_ptr().add(i - 1)).get_usize()
-            };
-            let end = (*counts.as_ptr().add(i)).get_usize();
-            let ptr = sorted_ptr;
-            let slice = unsafe { std::slice::from_raw_parts_mut(ptr.0.add(start), end - start) };
+        (sorted, counts, residual)
+    }
 
-            slice.sort_by(|a_idx, b_idx| unsafe {
-                simd_cmp_packed::<CTX>(&packed, a_idx.get_usize(), b_idx.get_usize())
-            });
+    /// The suffix array of `seq` over arbitrary byte values, sorted by plain byte
+    /// comparisons. `seq` is copied into a buffer padded with `pad_text`.
+    ///
+    /// Suffixes are first bucketed by their first `p` bytes, `1 <= p <= 3`, into
+    /// `256^p` buckets, counted and scattered in parallel like `new_packed` with
+    /// `bucket_threads * chunks_per_thread` chunks of `256^p` counters each.
+    pub fn new_bytes<const CTX: usize>(
+        seq: &[u8],
+        p: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        let idxs = Self::sort_bytes::<CTX>(&pad_text(seq, CTX), p, schedule);
+        Self::from_parts(idxs, CompactVec::new(0), 0, CTX, false)
+    }
+
+    fn sort_bytes<const CTX: usize>(
+        bytes: &[u8],
+        p: usize,
+        schedule: Schedule,
+    ) -> CompactVec<BYTES> {
+        assert_padded(bytes, CTX);
+        // Buckets must agree with the comparison, which reads whole 32-byte blocks.
+        assert!((1..=3).contains(&p) && p <= CTX / 32 * 32);
+        let n = bytes.len() - CTX;
+
+        let key = |i: usize| {
+            bytes[i..i + p]
+                .iter()
+                .fold(0, |key, &b| key << 8 | b as usize)
+        };
+        let chunks = bucket_chunks(n, &schedule);
+        let mut counts = count_keys(&chunks, 1 << (8 * p), key);
+        let bucket_ends = prefix_sum(&mut counts);
+        let mut sorted = scatter_keys(&chunks, &counts, &bucket_ends, key);
+        sort_buckets_by(&mut sorted, &bucket_ends, schedule, |a, b| unsafe {
+            simd_cmp_bytes::<CTX>(bytes, a, b)
         });
+        sorted
+    }
 
-        let elapsed = start.elapsed().as_secs_f64();
-        eprintln!("\tParallel sort buckets run time (s): {elapsed}");
-        eprintln!("\tLargest bucket / total: {max_bucket} / {sum}");
+    /// Suffix array over `seeds`, values below `2^k`, followed by `CTX` seeds of
+    /// padding. Suffixes are bucketed by their first seed and sorted on the `CTX` seeds
+    /// after it. Like `new_packed`, the counting and scattering are split over
+    /// `bucket_threads * chunks_per_thread` chunks with `2^k` counters each.
+    pub fn new<const CTX: usize>(
+        seeds: &[u16],
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let _observed = schedule.observe();
+        assert!(k <= 16);
 
-        sorted
+        let (idxs, bucket_ends) = Self::sort::<CTX>(seeds, k, schedule);
+        Self::from_parts(idxs, bucket_ends, k, CTX, false)
     }
 
-    pub fn new_bytes<const CTX: usize>(bytes: &[u8]) -> Self {
-        let idxs = unsafe { Self::sort_bytes::<CTX>(bytes) };
+    fn sort<const CTX: usize>(
+        seeds: &[u16],
+        k: usize,
+        schedule: Schedule,
+    ) -> (CompactVec<BYTES>, CompactVec<BYTES>) {
+        let n = seeds.len() - CTX;
+        assert!(
+            seeds[..n].par_iter().all(|&s| (s as usize) < 1 << k),
+            "seeds must be below 2^{k}"
+        );
+
+        let key = |i: usize| seeds[i] as usize;
+        let chunks = bucket_chunks(n, &schedule);
+        let mut counts = count_keys(&chunks, 1 << k, key);
+        let bucket_ends = prefix_sum(&mut counts);
+        let mut sorted = scatter_keys(&chunks, &counts, &bucket_ends, key);
+        // Suffixes in a bucket share their first seed.
+        sort_buckets_by(&mut sorted, &bucket_ends, schedule, |a, b| unsafe {
+            simd_cmp::<CTX>(seeds, a + 1, b + 1)
+        });
+        (sorted, bucket_ends)
+    }
 
+    pub(crate) fn from_parts(
+        idxs: CompactVec<BYTES>,
+        bucket_ends: CompactVec<BYTES>,
+        k: usize,
+        ctx: usize,
+        canonical: bool,
+    ) -> Self {
         Self {
             idxs,
-            k: 0,
-            ctx: CTX,
+            bucket_ends,
+            k,
+            ctx,
+            canonical,
+            seed: None,
+            exact: false,
+            lcp: None,
+            sampling: Sampling::Text,
+            sample_rate: 1,
+            fingerprint: None,
+            ties: TieOrder::Ascending,
+            residual_ties: None,
         }
     }
 
-    #[target_feature(enable = "avx2")]
-    unsafe fn sort_bytes<const CTX: usize>(bytes: &[u8]) -> CompactVec<BYTES> {
-        let bytes_no_ctx = &bytes[..bytes.len() - CTX];
+    /// Wrap suffix indices produced elsewhere (e.g. another construction algorithm),
+    /// sorted on at least `ctx` bases.
+    pub fn from_idxs(idxs: CompactVec<BYTES>, ctx: usize) -> Self {
+        Self::from_parts(idxs, CompactVec::new(0), 0, ctx, false)
+    }
+
+    pub fn idxs(&self) -> &CompactVec<BYTES> {
+        &self.idxs
+    }
 
-        let mut sorted = CompactVec::<BYTES>::new(bytes_no_ctx.len());
+    pub(crate) fn idxs_mut(&mut self) -> &mut CompactVec<BYTES> {
+        &mut self.idxs
+    }
 
-        for i in 0..bytes_no_ctx.len() {
-            sorted[i].set_usize(i);
-        }
+    pub fn k(&self) -> usize {
+        self.k
+    }
 
-        sorted.par_sort_by(|a_idx, b_idx| unsafe {
-            simd_cmp_bytes::<CTX>(bytes, a_idx.get_usize(), b_idx.get_usize())
-        });
+    pub fn ctx(&self) -> usize {
+        self.ctx
+    }
 
-        sorted
+    /// Whether buckets are keyed by canonical k-mers, see `new_packed_canonical`.
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
     }
 
-    pub fn new<const CTX: usize>(seeds: &[u16], k: usize) -> Self {
-        assert!(k <= 16);
+    /// The spaced seed buckets are keyed by, see `new_packed_spaced`. `None` for
+    /// contiguous k-mers.
+    pub fn seed(&self) -> Option<SpacedSeed> {
+        self.seed
+    }
+
+    pub(crate) fn with_seed(mut self, seed: SpacedSeed) -> Self {
+        self.seed = (!seed.is_contiguous()).then_some(seed);
+        self
+    }
+
+    /// Whether the suffixes are in lexicographic order, as needed for queries. Canonical
+    /// and spaced-seed buckets only sort the suffixes within each bucket.
+    pub fn is_lexicographic(&self) -> bool {
+        !self.canonical && self.seed.is_none()
+    }
+
+    pub fn tie_order(&self) -> TieOrder {
+        self.ties
+    }
+
+    pub(crate) fn with_tie_order(mut self, ties: TieOrder) -> Self {
+        self.ties = ties;
+        self
+    }
+
+    /// Whether suffixes tied over the context are ordered by their full suffix, see
+    /// `resolve_ties`.
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+
+    pub(crate) fn set_exact(&mut self, exact: bool) {
+        self.exact = exact;
+    }
+
+    /// The LCP array, if it was computed during construction, see `new_with_effort`.
+    pub fn lcp_array(&self) -> Option<&CompactVec<BYTES>> {
+        self.lcp.as_ref()
+    }
+
+    pub(crate) fn set_lcp_array(&mut self, lcp: Option<CompactVec<BYTES>>) {
+        self.lcp = lcp;
+    }
+
+    /// Which suffixes a sparse array keeps, see `new_packed_sparse`.
+    pub fn sampling(&self) -> Sampling {
+        self.sampling
+    }
+
+    /// Keep one in this many suffixes; 1 for a full array.
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    pub(crate) fn with_sampling(mut self, sampling: Sampling, sample_rate: usize) -> Self {
+        self.sampling = sampling;
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Number of adjacent entries whose suffixes are equal over the whole context, as
+    /// counted while sorting, so their relative order is decided by the tie order
+    /// rather than the text. `None` if not known, e.g. for merged arrays; see
+    /// `count_residual_ties`.
+    pub fn residual_ties(&self) -> Option<usize> {
+        self.residual_ties
+    }
+
+    pub(crate) fn with_residual_ties(mut self, residual_ties: usize) -> Self {
+        self.residual_ties = Some(residual_ties);
+        self
+    }
+
+    /// Count the adjacent entries that are equal over the context of the padded
+    /// `bytes`, for arrays that did not record `residual_ties`.
+    pub fn count_residual_ties(&self, bytes: &[u8]) -> usize {
+        let ctx = self.ctx;
+        let idxs = &self.idxs;
+        let packed = RevPacked::new(bytes);
+        (1..idxs.len())
+            .into_par_iter()
+            .filter(|&i| unsafe {
+                packed.lcp(idxs[i - 1].get_usize(), idxs[i].get_usize(), ctx) >= ctx
+            })
+            .count()
+    }
+
+    /// Fingerprint of the text the index was built on, if recorded.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
+    }
 
-        let idxs = unsafe { Self::sort::<CTX>(seeds, k) };
+    pub(crate) fn set_fingerprint(&mut self, fingerprint: Option<Fingerprint>) {
+        self.fingerprint = fingerprint;
+    }
 
-        Self { idxs, k, ctx: CTX }
+    /// End offsets of the k-mer buckets, indexed by k-mer. Empty if the array was
+    /// built without bucketing.
+    pub fn bucket_ends(&self) -> &CompactVec<BYTES> {
+        &self.bucket_ends
     }
 
-    #[target_feature(enable = "avx2")]
-    unsafe fn sort<const CTX: usize>(seeds: &[u16], k: usize) -> CompactVec<BYTES> {
-        let seeds_no_ctx = &seeds[..seeds.len() - CTX];
+    pub fn bucket_range(&self, kmer: usize) -> std::ops::Range<usize> {
+        let start = if kmer == 0 {
+            0
+        } else {
+            self.bucket_ends[kmer - 1].get_usize()
+        };
+        start..self.bucket_ends[kmer].get_usize()
+    }
+
+    /// The non-empty k-mer buckets in increasing k-mer order, each with its suffixes
+    /// in sorted order.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, &[Int<BYTES>])> + '_ {
+        (0..self.bucket_ends.len()).filter_map(move |kmer| {
+            let range = self.bucket_range(kmer);
+            (!range.is_empty()).then(|| (kmer as u64, &self.idxs[range]))
+        })
+    }
+}
 
-        let mut counts = CompactVec::<BYTES>::new(1 << k);
+pub(crate) struct RevPacked {
+    data: Vec<u8>,
+    len: usize,
+}
 
-        for &s in seeds_no_ctx {
-            let count = counts[s as usize].get_usize();
-            counts[s as usize].set_usize(count + 1);
+static LUT: [u8; 128] = {
+    let mut l = [0u8; 128];
+    l[b'A' as usize] = 0b00;
+    l[b'C' as usize] = 0b01;
+    l[b'G' as usize] = 0b10;
+    l[b'T' as usize] = 0b11;
+    l[b'a' as usize] = 0b00;
+    l[b'c' as usize] = 0b01;
+    l[b'g' as usize] = 0b10;
+    l[b't' as usize] = 0b11;
+    l[b'U' as usize] = 0b11;
+    l[b'u' as usize] = 0b11;
+    l
+};
+
+/// 2-bit code of a base, matching the order used by the packed comparisons.
+#[inline(always)]
+pub(crate) fn base_code(b: u8) -> u8 {
+    LUT[(b & 0x7f) as usize]
+}
+
+/// Panic with an explanation, instead of an underflow later on, if `bytes` is too short
+/// to end in `ctx` bases of padding.
+pub(crate) fn assert_padded(bytes: &[u8], ctx: usize) {
+    assert!(
+        bytes.len() >= ctx,
+        "text of {} bytes cannot end in the {ctx} bytes of padding of the context, see `pad_text`",
+        bytes.len()
+    );
+}
+
+impl RevPacked {
+    /// Zero bases past the end of the text, so that the 512-bit loads of the last
+    /// context window of a suffix stay in bounds.
+    const PAD: usize = 8;
+
+    pub fn new(bytes: &[u8]) -> Self {
+        Self::with_padding(bytes, 0)
+    }
+
+    /// Pack `bytes` followed by `pad` `A`s, which are zero like the padding past the end.
+    pub fn with_padding(bytes: &[u8], pad: usize) -> Self {
+        let padded_len = bytes.len() + pad + Self::PAD;
+        let len = padded_len.div_ceil(4);
+        let mut data = vec![0u8; len];
+
+        for (i, &b) in bytes.iter().enumerate() {
+            let i = padded_len - i - 1;
+            unsafe {
+                *data.as_mut_ptr().add(i / 4) |= *LUT.as_ptr().add(b as usize) << ((i % 4) * 2);
+            }
         }
 
-        let mut seed_to_idx = CompactVec::<BYTES>::new((1 << k) + 1);
-        let mut idx = 0;
-        let mut sum = 0;
+        Self {
+            data,
+            len: padded_len,
+        }
+    }
 
-        seed_to_idx[idx].set_usize(sum);
-        idx += 1;
-        for c in counts.iter() {
-            sum += c.get_usize();
-            seed_to_idx[idx].set_usize(sum);
-            idx += 1;
+    /// Unpack nothing: `packed` holds `len` bases at 4 per byte, first base in the most
+    /// significant bits, which is the reversed packing read backwards, so the bytes are
+    /// only copied in reverse order behind `pad` `A`s.
+    pub fn from_msb_first(packed: &[u8], len: usize, pad: usize) -> Self {
+        let bytes = len.div_ceil(4);
+        assert!(
+            packed.len() >= bytes,
+            "{len} bases need {bytes} packed bytes"
+        );
+        // Base `i` lands at reversed index `padded_len - i - 1`, so a multiple of 4
+        // keeps every byte whole.
+        let padded_len = (len + pad + Self::PAD).next_multiple_of(4);
+        let mut data = vec![0u8; padded_len / 4];
+        let n = data.len();
+        data[n - bytes..]
+            .par_iter_mut()
+            .rev()
+            .zip(&packed[..bytes])
+            .for_each(|(d, &p)| *d = p);
+        // Bases past `len` in the last byte are padding.
+        if !len.is_multiple_of(4) {
+            data[n - bytes] &= !0u8 << (2 * (4 - len % 4));
         }
 
-        let mut sorted = CompactVec::<BYTES>::new(seeds_no_ctx.len());
+        Self {
+            data,
+            len: padded_len,
+        }
+    }
 
-        for (i, &s) in seeds_no_ctx.iter().enumerate() {
-            let end = seed_to_idx[(s as usize) + 1].get_usize();
-            let count = counts[s as usize].get_usize();
+    /// Reverse the forward packing of `text`, followed by `pad` `A`s.
+    pub fn from_text(text: &PackedText, pad: usize) -> Self {
+        let padded_len = text.len() + pad + Self::PAD;
+        let mut data = vec![0u8; padded_len.div_ceil(4)];
+
+        data.par_iter_mut().enumerate().for_each(|(d, byte)| {
+            for j in 0..4 {
+                // Reversed positions below `pad + PAD` and past the end are padding.
+                let r = 4 * d + j;
+                if (pad + Self::PAD..padded_len).contains(&r) {
+                    *byte |= text.get(padded_len - r - 1) << (j * 2);
+                }
+            }
+        });
 
-            sorted[end - count].set_usize(i);
-            counts[s as usize].set_usize(count - 1);
+        Self {
+            data,
+            len: padded_len,
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn load_124<W: Vector>(&self, idx: usize) -> W {
+        let idx = self.len - idx - 128;
+        let i = idx.div_ceil(4);
+        let j = (idx + 3) % 4;
+        W::load_shifted(self.data.as_ptr().add(i), (3 - j) * 2)
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[inline(always)]
+    unsafe fn load_252(&self, idx: usize) -> V512 {
+        let idx = self.len - idx - 256;
+        let i = idx.div_ceil(4);
+        let j = (idx + 3) % 4;
+        V512::load_shifted(self.data.as_ptr().add(i), (3 - j) * 2)
+    }
+
+    /// The `k <= 16` bases at `idx`, the first in the highest bits. They are read from
+    /// the 32 bases ending at the byte of base `idx`, which hold them at any offset.
+    #[inline]
+    pub(crate) unsafe fn load_k(&self, idx: usize, k: usize) -> u32 {
+        debug_assert!((1..=16).contains(&k));
+        let end = self.len - idx;
+        let i = (end - 1) / 4 - 7;
+        let val = std::ptr::read_unaligned(self.data.as_ptr().add(i) as *const u64);
+        ((val << ((32 - (end - 4 * i)) * 2)) >> ((32 - k) * 2)) as u32
+    }
+
+    /// The `seed.weight()` care bases of the `seed.span()` bases at `idx`.
+    #[inline]
+    pub(crate) unsafe fn load_k_masked(&self, idx: usize, seed: SpacedSeed) -> u32 {
+        seed.extract(self.load_k(idx, seed.span()))
+    }
+
+    #[inline]
+    pub(crate) unsafe fn lcp(&self, a_idx: usize, b_idx: usize, max_len: usize) -> usize {
+        simd_lcp_packed(self, a_idx, self, b_idx, max_len)
+    }
+
+    /// 2-bit code of the base at `idx`.
+    #[inline(always)]
+    pub(crate) fn get(&self, idx: usize) -> u8 {
+        let i = self.len - idx - 1;
+        (self.data[i / 4] >> ((i % 4) * 2)) & 0b11
+    }
+}
+
+/// Number of equal bases, up to `max_len`, of suffix `a_idx` of `a` and suffix `b_idx` of `b`.
+#[inline]
+pub(crate) unsafe fn simd_lcp_packed(
+    a_packed: &RevPacked,
+    a_idx: usize,
+    b_packed: &RevPacked,
+    b_idx: usize,
+    max_len: usize,
+) -> usize {
+    if simd_supported() {
+        lcp_packed_native(a_packed, a_idx, b_packed, b_idx, max_len)
+    } else {
+        lcp_packed::<Scalar>(a_packed, a_idx, b_packed, b_idx, max_len)
+    }
+}
+
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn lcp_packed_native(
+    a_packed: &RevPacked,
+    a_idx: usize,
+    b_packed: &RevPacked,
+    b_idx: usize,
+    max_len: usize,
+) -> usize {
+    lcp_packed::<V>(a_packed, a_idx, b_packed, b_idx, max_len)
+}
+
+#[inline(always)]
+unsafe fn lcp_packed<W: Vector>(
+    a_packed: &RevPacked,
+    a_idx: usize,
+    b_packed: &RevPacked,
+    b_idx: usize,
+    max_len: usize,
+) -> usize {
+    const L: usize = 128 - 4;
+    let mut res = 0;
+
+    while res < max_len {
+        let a = a_packed.load_124::<W>(a_idx + res);
+        let b = b_packed.load_124::<W>(b_idx + res);
+
+        let neq_mask = !a.eq_mask8(b);
+
+        if neq_mask != 0 {
+            let msb = 31 - neq_mask.leading_zeros() as usize;
+            let x = a.xor_bytes(b);
+            res += (31 - msb) * 4 + (x[msb].leading_zeros() / 2) as usize;
+            return res.min(max_len);
         }
 
-        drop(counts);
+        res += L;
+    }
 
-        let sorted_ptr = MutPtr(sorted.as_mut_ptr());
+    max_len
+}
 
-        (0..(1 << k)).into_par_iter().for_each(|i| {
-            let start = seed_to_idx[i].get_usize();
-            let end = seed_to_idx[i + 1].get_usize();
-            let ptr = sorted_ptr;
-            let slice = unsafe { std::slice::from_raw_parts_mut(ptr.0.add(start), end - start) };
-            slice.sort_by(|a_idx, b_idx| unsafe {
-                simd_cmp::<CTX>(seeds, a_idx.get_usize() + 1, b_idx.get_usize() + 1)
-            });
-        });
+/// LCP of the suffixes at `a` and `b`, up to the end of the text.
+pub(crate) fn lcp_to_end(packed: &RevPacked, len: usize, a: usize, b: usize) -> usize {
+    lcp_up_to(packed, len, a, b, usize::MAX)
+}
 
-        sorted
+/// LCP of the suffixes at `a` and `b`, up to `max` and the end of the text.
+pub(crate) fn lcp_up_to(packed: &RevPacked, len: usize, a: usize, b: usize, max: usize) -> usize {
+    let end = (len - a.max(b)).min(max);
+    // Block loads must stay within the packed text, so the last block is compared
+    // base by base.
+    let simd_max = (len - a.max(b)).saturating_sub(124).min(end);
+    let mut l = unsafe { packed.lcp(a, b, simd_max) };
+    if l == simd_max {
+        while l < end && packed.get(a + l) == packed.get(b + l) {
+            l += 1;
+        }
     }
+    l
+}
 
-    pub fn idxs(&self) -> &CompactVec<BYTES> {
-        &self.idxs
+/// Compare suffixes of two different packed texts on their first `ctx` bases.
+#[inline]
+pub(crate) unsafe fn simd_cmp_packed_lce(
+    a_packed: &RevPacked,
+    a_idx: usize,
+    b_packed: &RevPacked,
+    b_idx: usize,
+    ctx: usize,
+) -> Ordering {
+    let lcp = simd_lcp_packed(a_packed, a_idx, b_packed, b_idx, ctx);
+
+    if lcp >= ctx {
+        Ordering::Equal
+    } else {
+        a_packed.get(a_idx + lcp).cmp(&b_packed.get(b_idx + lcp))
     }
+}
 
-    pub fn k(&self) -> usize {
-        self.k
+/// Kernel of the packed suffix comparisons. It is detected once per build and passed
+/// down to the in-bucket sorts, which are compiled per kernel so that the comparisons
+/// inline into their comparators.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Kernel {
+    Scalar,
+    /// AVX2 on x86, NEON on aarch64.
+    Native,
+    /// AVX-512BW, 252 bases per step.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Avx512,
+}
+
+impl Kernel {
+    /// The fastest kernel this CPU supports for a text ending in `pad` bases of
+    /// padding. The 512-bit loads read 256 bases, so a text padded for a context of
+    /// 124 bases only gets the native kernel.
+    pub(crate) fn detect(pad: usize) -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if pad >= 248 && avx512_supported() {
+            return Kernel::Avx512;
+        }
+        if simd_supported() {
+            Kernel::Native
+        } else {
+            Kernel::Scalar
+        }
     }
 
-    pub fn ctx(&self) -> usize {
-        self.ctx
+    /// Compare the suffixes at `a_idx` and `b_idx` on their first `CTX` bases, a
+    /// multiple of 124, and break ties by position. The sorts inline the kernel's
+    /// comparison instead, see `sort_bucket`.
+    #[cfg(any(test, feature = "fuzz"))]
+    #[inline]
+    pub(crate) unsafe fn cmp<const CTX: usize>(
+        self,
+        packed: &RevPacked,
+        a_idx: usize,
+        b_idx: usize,
+        ties: TieOrder,
+    ) -> Ordering {
+        match self {
+            Kernel::Scalar => cmp_packed_scalar::<CTX>(packed, a_idx, b_idx, ties),
+            Kernel::Native => cmp_packed_native::<CTX>(packed, a_idx, b_idx, ties),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Avx512 => cmp_packed_512::<CTX>(packed, a_idx, b_idx, ties),
+        }
     }
 }
 
-struct RevPacked {
-    data: Vec<u8>,
-    len: usize,
+/// `Kernel::cmp` with the kernel detected for this call, to check the kernels against
+/// each other in tests and fuzzing.
+#[cfg(any(test, feature = "fuzz"))]
+#[inline]
+pub(crate) unsafe fn simd_cmp_packed<const CTX: usize>(
+    packed: &RevPacked,
+    a_idx: usize,
+    b_idx: usize,
+    ties: TieOrder,
+) -> Ordering {
+    Kernel::detect(CTX).cmp::<CTX>(packed, a_idx, b_idx, ties)
 }
 
-static LUT: [u8; 128] = {
-    let mut l = [0u8; 128];
-    l[b'A' as usize] = 0b00;
-    l[b'C' as usize] = 0b01;
-    l[b'G' as usize] = 0b10;
-    l[b'T' as usize] = 0b11;
-    l[b'a' as usize] = 0b00;
-    l[b'c' as usize] = 0b01;
-    l[b'g' as usize] = 0b10;
-    l[b't' as usize] = 0b11;
-    l
-};
+#[cfg(any(test, feature = "fuzz"))]
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn cmp_packed_native<const CTX: usize>(
+    packed: &RevPacked,
+    a_idx: usize,
+    b_idx: usize,
+    ties: TieOrder,
+) -> Ordering {
+    cmp_packed::<CTX, V>(packed, a_idx, b_idx, ties)
+}
 
-impl RevPacked {
-    pub fn new(bytes: &[u8]) -> Self {
-        let padded_len = bytes.len() + 4;
-        let len = (padded_len + 3) / 4;
-        let mut data = vec![0u8; len];
+#[cfg(all(
+    any(test, feature = "fuzz"),
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn cmp_packed_512<const CTX: usize>(
+    packed: &RevPacked,
+    a_idx: usize,
+    b_idx: usize,
+    ties: TieOrder,
+) -> Ordering {
+    cmp_packed_wide::<CTX>(packed, a_idx, b_idx, ties)
+}
 
-        for (i, &b) in bytes.iter().enumerate() {
-            let i = padded_len - i - 1;
-            unsafe {
-                *data.as_mut_ptr().add(i / 4) |= *LUT.as_ptr().add(b as usize) << ((i % 4) * 2);
-            }
-        }
+/// `cmp_packed` comparing 252 bases per step. A last step shorter than that compares
+/// the 252 bases ending at the context, which overlap bases already found equal, so
+/// that the loads end at most 4 bases past the context; a context shorter than one
+/// step compares only its own bytes of the load.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline(always)]
+unsafe fn cmp_packed_wide<const CTX: usize>(
+    packed: &RevPacked,
+    a_idx: usize,
+    b_idx: usize,
+    ties: TieOrder,
+) -> Ordering {
+    const L: usize = 256 - 4;
+    let mut done = 0;
 
-        Self {
-            data,
-            len: padded_len,
-        }
-    }
+    while done < CTX {
+        let (offset, bytes) = match CTX - done {
+            rest if rest >= L || CTX < L => (done, rest.min(L) / 4),
+            _ => (CTX - L, L / 4),
+        };
+        let a = packed.load_252(a_idx + offset);
+        let b = packed.load_252(b_idx + offset);
 
-    #[inline]
-    #[target_feature(enable = "avx2")]
-    unsafe fn load_124(&self, idx: usize) -> __m256i {
-        let idx = self.len - idx - 128;
-        let i = (idx + 3) / 4;
-        let j = (idx + 3) % 4;
-        let val = _mm256_loadu_si256(self.data.as_ptr().add(i) as _);
+        let neq_mask = !a.eq_mask8(b) & (!0u64 << (64 - bytes));
 
-        // shift left by bits
-        let left_shift = _mm256_set1_epi64x(((3 - j) * 2) as _);
-        let hi = _mm256_sllv_epi64(val, left_shift);
-        let right_shift = _mm256_set1_epi64x(((32 - (3 - j)) * 2) as _);
-        let lo = _mm256_srlv_epi64(_mm256_permute4x64_epi64(val, 0b10_01_00_11), right_shift);
+        if neq_mask != 0 {
+            let msb_mask = 1u64 << (63 - neq_mask.leading_zeros());
+            let gt_mask = a.ge_mask8(b);
 
-        let mask = _mm256_set_epi8(
-            -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
-            -1, -1, -1, -1, -1, -1, -1, -1, -1, 0,
-        );
+            if (msb_mask & gt_mask) > 0 {
+                return Ordering::Greater;
+            } else {
+                return Ordering::Less;
+            }
+        }
 
-        _mm256_and_si256(_mm256_or_si256(hi, lo), mask)
+        done = offset + 4 * bytes;
     }
 
-    #[inline]
-    #[target_feature(enable = "avx2")]
-    unsafe fn load_k(&self, idx: usize, k: usize) -> u32 {
-        let idx = self.len - idx - 16;
-        let i = (idx + 3) / 4;
-        let j = (idx + 3) % 4;
-        let val = std::ptr::read_unaligned(self.data.as_ptr().add(i) as *const u32);
-        (val << ((3 - j) * 2)) >> ((16 - k) * 2)
+    match ties {
+        TieOrder::Ascending => a_idx.cmp(&b_idx),
+        TieOrder::Descending => b_idx.cmp(&a_idx),
     }
 }
 
-#[inline]
-#[target_feature(enable = "avx2")]
-unsafe fn simd_cmp_packed<const CTX: usize>(
+#[inline(always)]
+unsafe fn cmp_packed<const CTX: usize, W: Vector>(
     packed: &RevPacked,
     a_idx: usize,
     b_idx: usize,
+    ties: TieOrder,
 ) -> Ordering {
     const L: usize = 128 - 4;
     let mut a_i = a_idx;
     let mut b_i = b_idx;
 
     for _ in 0..(CTX / L) {
-        let a = packed.load_124(a_i);
-        let b = packed.load_124(b_i);
+        let a = packed.load_124::<W>(a_i);
+        let b = packed.load_124::<W>(b_i);
 
-        let eq = _mm256_cmpeq_epi8(a, b);
-        let neq_mask = !(_mm256_movemask_epi8(eq) as u32);
+        let neq_mask = !a.eq_mask8(b);
 
         if neq_mask != 0 {
             let msb_mask = 1u32 << (31 - neq_mask.leading_zeros());
-            let gt = _mm256_max_epu8(a, b);
-            let gt = _mm256_cmpeq_epi8(gt, a);
-            let gt_mask = _mm256_movemask_epi8(gt) as u32;
+            let gt_mask = a.ge_mask8(b);
 
             if (msb_mask & gt_mask) > 0 {
                 return Ordering::Greater;
@@ -343,29 +1623,49 @@ unsafe fn simd_cmp_packed<const CTX: usize>(
         b_i += L;
     }
 
-    a_i.cmp(&b_i)
+    match ties {
+        TieOrder::Ascending => a_i.cmp(&b_i),
+        TieOrder::Descending => b_i.cmp(&a_i),
+    }
 }
 
 #[inline]
-#[target_feature(enable = "avx2")]
 unsafe fn simd_cmp_bytes<const CTX: usize>(bytes: &[u8], a_idx: usize, b_idx: usize) -> Ordering {
+    if simd_supported() {
+        cmp_bytes_native::<CTX>(bytes, a_idx, b_idx)
+    } else {
+        cmp_bytes_scalar::<CTX>(bytes, a_idx, b_idx)
+    }
+}
+
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn cmp_bytes_native<const CTX: usize>(bytes: &[u8], a_idx: usize, b_idx: usize) -> Ordering {
+    cmp_bytes::<CTX, V>(bytes, a_idx, b_idx)
+}
+
+#[inline(always)]
+unsafe fn cmp_bytes<const CTX: usize, W: Vector>(
+    bytes: &[u8],
+    a_idx: usize,
+    b_idx: usize,
+) -> Ordering {
     const L: usize = 32;
     let ptr = bytes.as_ptr();
     let mut a_i = a_idx;
     let mut b_i = b_idx;
 
     for _ in 0..(CTX / L) {
-        let a = _mm256_loadu_si256(ptr.add(a_i) as _);
-        let b = _mm256_loadu_si256(ptr.add(b_i) as _);
+        let a = W::load(ptr.add(a_i));
+        let b = W::load(ptr.add(b_i));
 
-        let eq = _mm256_cmpeq_epi8(a, b);
-        let neq_mask = !(_mm256_movemask_epi8(eq) as u32);
+        let neq_mask = !a.eq_mask8(b);
 
         if neq_mask != 0 {
             let lsb_mask = neq_mask & neq_mask.wrapping_neg();
-            let gt = _mm256_max_epu8(a, b);
-            let gt = _mm256_cmpeq_epi8(gt, a);
-            let gt_mask = _mm256_movemask_epi8(gt) as u32;
+            let gt_mask = a.ge_mask8(b);
 
             if (lsb_mask & gt_mask) > 0 {
                 return Ordering::Greater;
@@ -382,25 +1682,46 @@ unsafe fn simd_cmp_bytes<const CTX: usize>(bytes: &[u8], a_idx: usize, b_idx: us
 }
 
 #[inline]
-#[target_feature(enable = "avx2")]
 unsafe fn simd_cmp<const CTX: usize>(seeds: &[u16], a_idx: usize, b_idx: usize) -> Ordering {
+    if simd_supported() {
+        cmp_seeds_native::<CTX>(seeds, a_idx, b_idx)
+    } else {
+        cmp_seeds_scalar::<CTX>(seeds, a_idx, b_idx)
+    }
+}
+
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn cmp_seeds_native<const CTX: usize>(
+    seeds: &[u16],
+    a_idx: usize,
+    b_idx: usize,
+) -> Ordering {
+    cmp_seeds::<CTX, V>(seeds, a_idx, b_idx)
+}
+
+#[inline(always)]
+unsafe fn cmp_seeds<const CTX: usize, W: Vector>(
+    seeds: &[u16],
+    a_idx: usize,
+    b_idx: usize,
+) -> Ordering {
     const L: usize = 16;
     let ptr = seeds.as_ptr();
     let mut a_i = a_idx;
     let mut b_i = b_idx;
 
     for _ in 0..(CTX / L) {
-        let a = _mm256_loadu_si256(ptr.add(a_i) as _);
-        let b = _mm256_loadu_si256(ptr.add(b_i) as _);
+        let a = W::load(ptr.add(a_i) as *const u8);
+        let b = W::load(ptr.add(b_i) as *const u8);
 
-        let eq = _mm256_cmpeq_epi16(a, b);
-        let neq_mask = !(_mm256_movemask_epi8(eq) as u32);
+        let neq_mask = !a.eq_mask16(b);
 
         if neq_mask != 0 {
             let lsb_mask = neq_mask & neq_mask.wrapping_neg();
-            let gt = _mm256_max_epu16(a, b);
-            let gt = _mm256_cmpeq_epi16(gt, a);
-            let gt_mask = _mm256_movemask_epi8(gt) as u32;
+            let gt_mask = a.ge_mask16(b);
 
             if (lsb_mask & gt_mask) > 0 {
                 return Ordering::Greater;
@@ -416,51 +1737,763 @@ unsafe fn simd_cmp<const CTX: usize>(seeds: &[u16], a_idx: usize, b_idx: usize)
     Ordering::Equal
 }
 
+// Scalar references of the comparison kernels, one base, byte or seed at a time. They
+// are the fallback on CPUs without vector instructions, and what the kernels are
+// tested against; each compares exactly what its kernel does, e.g. whole blocks only.
+
+/// `cmp_packed` on the first `CTX` bases.
+fn cmp_packed_scalar<const CTX: usize>(
+    packed: &RevPacked,
+    a_idx: usize,
+    b_idx: usize,
+    ties: TieOrder,
+) -> Ordering {
+    (0..CTX / 124 * 124)
+        .map(|i| packed.get(a_idx + i).cmp(&packed.get(b_idx + i)))
+        .find(|o| o.is_ne())
+        .unwrap_or(match ties {
+            TieOrder::Ascending => a_idx.cmp(&b_idx),
+            TieOrder::Descending => b_idx.cmp(&a_idx),
+        })
+}
+
+/// `cmp_bytes` on the first `CTX` bytes, rounded down to whole 32-byte blocks.
+fn cmp_bytes_scalar<const CTX: usize>(bytes: &[u8], a_idx: usize, b_idx: usize) -> Ordering {
+    let l = CTX / 32 * 32;
+    bytes[a_idx..a_idx + l].cmp(&bytes[b_idx..b_idx + l])
+}
+
+/// `cmp_seeds` on the first `CTX` seeds, rounded down to whole 16-seed blocks.
+fn cmp_seeds_scalar<const CTX: usize>(seeds: &[u16], a_idx: usize, b_idx: usize) -> Ordering {
+    let l = CTX / 16 * 16;
+    seeds[a_idx..a_idx + l].cmp(&seeds[b_idx..b_idx + l])
+}
+
+#[cfg(test)]
+impl RevPacked {
+    /// `load_124` as bytes: 2-bit field `p` of the 256-bit little-endian integer holds
+    /// base `idx + 127 - p`, and the lowest 4 fields are zero.
+    fn load_124_scalar(&self, idx: usize) -> [u8; 32] {
+        let mut res = [0u8; 32];
+        for p in 4..128 {
+            res[p / 4] |= self.get(idx + 127 - p) << (p % 4 * 2);
+        }
+        res
+    }
+
+    /// `load_k`: the `k` bases at `idx`, the first in the highest bits.
+    fn load_k_scalar(&self, idx: usize, k: usize) -> u32 {
+        (0..k).fold(0, |x, i| x << 2 | self.get(idx + i) as u32)
+    }
+}
+
+/// Comparison depth tried first for buckets whose suffixes diverge quickly.
+const SHALLOW_CTX: usize = 124;
+const SHALLOW_SAMPLES: usize = 16;
+
+/// Sample pairs of suffixes half a bucket apart, and check that they all differ
+/// within `SHALLOW_CTX` bases.
+fn is_shallow<const BYTES: usize>(packed: &RevPacked, bucket: &[Int<BYTES>]) -> bool {
+    let half = bucket.len() / 2;
+    (0..half.min(SHALLOW_SAMPLES)).all(|s| {
+        let i = s * half / SHALLOW_SAMPLES.min(half);
+        unsafe {
+            packed.lcp(
+                bucket[i].get_usize(),
+                bucket[i + half].get_usize(),
+                SHALLOW_CTX,
+            ) < SHALLOW_CTX
+        }
+    })
+}
+
+/// Number of adjacent suffixes of a sorted bucket that are equal over the context.
+fn count_ties<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    bucket: &[Int<BYTES>],
+) -> usize {
+    bucket
+        .windows(2)
+        .filter(|w| unsafe { packed.lcp(w[0].get_usize(), w[1].get_usize(), CTX) >= CTX })
+        .count()
+}
+
+/// Smallest bucket that is sorted in parallel, see `sort_rev_packed`.
+pub(crate) const PAR_MIN_BUCKET: usize = 1 << 16;
+
+/// Sort `bucket` on the first `CTX` bases of its suffixes with `kernel`. Every kernel
+/// has its own copy of the sort, compiled with its target features.
+pub(crate) fn sort_bucket<const CTX: usize, const BYTES: usize>(
+    kernel: Kernel,
+    packed: &RevPacked,
+    bucket: &mut [Int<BYTES>],
+    in_place: bool,
+    parallel: bool,
+    ties: TieOrder,
+) {
+    unsafe {
+        match kernel {
+            Kernel::Scalar => {
+                sort_bucket_scalar::<CTX, BYTES>(packed, bucket, in_place, parallel, ties)
+            }
+            Kernel::Native => {
+                sort_bucket_native::<CTX, BYTES>(packed, bucket, in_place, parallel, ties)
+            }
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Avx512 => {
+                sort_bucket_512::<CTX, BYTES>(packed, bucket, in_place, parallel, ties)
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn sort_by_cmp<T: Send>(
+    bucket: &mut [T],
+    in_place: bool,
+    parallel: bool,
+    cmp: impl Fn(&T, &T) -> Ordering + Sync,
+) {
+    // Ties are broken by index, so the in-place unstable sort gives the same order
+    // without the merge sort's scratch buffer of half the bucket.
+    match (in_place, parallel) {
+        (true, false) => bucket.sort_unstable_by(cmp),
+        (false, false) => bucket.sort_by(cmp),
+        (true, true) => bucket.par_sort_unstable_by(cmp),
+        (false, true) => bucket.par_sort_by(cmp),
+    }
+}
+
+fn sort_bucket_scalar<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    bucket: &mut [Int<BYTES>],
+    in_place: bool,
+    parallel: bool,
+    ties: TieOrder,
+) {
+    sort_by_cmp(bucket, in_place, parallel, |a_idx, b_idx| {
+        cmp_packed_scalar::<CTX>(packed, a_idx.get_usize(), b_idx.get_usize(), ties)
+    });
+}
+
+#[cfg_attr(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature(enable = "avx2")
+)]
+unsafe fn sort_bucket_native<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    bucket: &mut [Int<BYTES>],
+    in_place: bool,
+    parallel: bool,
+    ties: TieOrder,
+) {
+    sort_by_cmp(bucket, in_place, parallel, |a_idx, b_idx| unsafe {
+        cmp_packed::<CTX, V>(packed, a_idx.get_usize(), b_idx.get_usize(), ties)
+    });
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn sort_bucket_512<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    bucket: &mut [Int<BYTES>],
+    in_place: bool,
+    parallel: bool,
+    ties: TieOrder,
+) {
+    sort_by_cmp(bucket, in_place, parallel, |a_idx, b_idx| unsafe {
+        cmp_packed_wide::<CTX>(packed, a_idx.get_usize(), b_idx.get_usize(), ties)
+    });
+}
+
+/// Bases of every suffix kept in the key cache of `sort_bucket_keyed`.
+const KEY_BASES: usize = 64;
+
+/// The first `KEY_BASES` bases of the suffix at `idx`, first base most significant.
+#[inline]
+pub(crate) unsafe fn prefix_key(packed: &RevPacked, idx: usize) -> u128 {
+    // `load_k` reads a 4-byte word, so unaligned loads hold only a few bases more than 12.
+    (0..8).fold(0, |key, j| {
+        key << 16 | packed.load_k(idx + 8 * j, 8) as u128
+    })
+}
+
+/// Compare suffixes with equal keys on the rest of the context.
+#[inline]
+unsafe fn cmp_after_key<const CTX: usize>(
+    packed: &RevPacked,
+    a_idx: usize,
+    b_idx: usize,
+    ties: TieOrder,
+) -> Ordering {
+    let (a, b) = (a_idx + KEY_BASES, b_idx + KEY_BASES);
+    let rest = CTX - KEY_BASES;
+    // Block loads must stay within the packed text, so suffixes near its end are
+    // compared base by base.
+    let lcp = if a.max(b) + rest + 128 <= packed.len {
+        packed.lcp(a, b, rest)
+    } else {
+        (0..rest)
+            .find(|&l| packed.get(a + l) != packed.get(b + l))
+            .unwrap_or(rest)
+    };
+    if lcp < rest {
+        return packed.get(a + lcp).cmp(&packed.get(b + lcp));
+    }
+    match ties {
+        TieOrder::Ascending => a_idx.cmp(&b_idx),
+        TieOrder::Descending => b_idx.cmp(&a_idx),
+    }
+}
+
+/// Bytes of the cached keys of a bucket of `len` suffixes. The radix sort needs twice
+/// this, for its scratch copy.
+fn keyed_bytes<const BYTES: usize>(len: usize) -> usize {
+    len * std::mem::size_of::<(u128, Int<BYTES>)>()
+}
+
+/// Sort a bucket on a cache of 64-base keys, loading the text only for suffixes whose
+/// keys are equal.
+fn sort_bucket_keyed<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    bucket: &mut [Int<BYTES>],
+    ties: TieOrder,
+    parallel: bool,
+) {
+    let mut keyed = bucket
+        .iter()
+        .map(|i| (unsafe { prefix_key(packed, i.get_usize()) }, i.clone()))
+        .collect::<Vec<_>>();
+    let cmp = |(ka, a): &(u128, Int<BYTES>), (kb, b): &(u128, Int<BYTES>)| {
+        ka.cmp(kb).then_with(|| unsafe {
+            cmp_after_key::<CTX>(packed, a.get_usize(), b.get_usize(), ties)
+        })
+    };
+    if parallel {
+        keyed.par_sort_unstable_by(cmp);
+    } else {
+        keyed.sort_unstable_by(cmp);
+    }
+    bucket.iter_mut().zip(keyed).for_each(|(o, (_, i))| *o = i);
+}
+
+/// Largest context for which buckets are radix sorted. Beyond two blocks the 64-base
+/// keys decide too little of the order.
+const RADIX_MAX_CTX: usize = 248;
+/// Smallest bucket that is radix sorted.
+const RADIX_MIN_BUCKET: usize = 1 << 10;
+/// Ranges of keys at most this long are sorted by comparison.
+const RADIX_CUTOFF: usize = 64;
+
+/// Sort a bucket by an MSD radix sort on 64-base keys, one byte of 4 bases at a time,
+/// and sort suffixes with equal keys on the rest of the context.
+fn sort_bucket_radix<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    bucket: &mut [Int<BYTES>],
+    ties: TieOrder,
+) {
+    let mut keyed = bucket
+        .iter()
+        .map(|i| (unsafe { prefix_key(packed, i.get_usize()) }, i.clone()))
+        .collect::<Vec<_>>();
+    let mut scratch = keyed.clone();
+    radix_sort_keyed::<CTX, BYTES>(packed, &mut keyed, &mut scratch, 0, ties);
+    bucket.iter_mut().zip(keyed).for_each(|(o, (_, i))| *o = i);
+}
+
+fn radix_sort_keyed<const CTX: usize, const BYTES: usize>(
+    packed: &RevPacked,
+    keyed: &mut [(u128, Int<BYTES>)],
+    scratch: &mut [(u128, Int<BYTES>)],
+    depth: usize,
+    ties: TieOrder,
+) {
+    if keyed.len() <= RADIX_CUTOFF || depth == 16 {
+        keyed.sort_unstable_by(|(ka, a), (kb, b)| {
+            ka.cmp(kb).then_with(|| unsafe {
+                cmp_after_key::<CTX>(packed, a.get_usize(), b.get_usize(), ties)
+            })
+        });
+        return;
+    }
+    let digit = |key: u128| (key >> (120 - 8 * depth)) as u8 as usize;
+    let mut counts = [0usize; 256];
+    for (key, _) in keyed.iter() {
+        counts[digit(*key)] += 1;
+    }
+    // All keys share this byte, so there is nothing to move.
+    if counts.contains(&keyed.len()) {
+        radix_sort_keyed::<CTX, BYTES>(packed, keyed, scratch, depth + 1, ties);
+        return;
+    }
+    let mut starts = [0usize; 257];
+    for (d, &count) in counts.iter().enumerate() {
+        starts[d + 1] = starts[d] + count;
+    }
+    let mut next = starts;
+    for entry in keyed.iter() {
+        let d = digit(entry.0);
+        scratch[next[d]] = entry.clone();
+        next[d] += 1;
+    }
+    keyed.clone_from_slice(scratch);
+    for w in starts.windows(2) {
+        let range = w[0]..w[1];
+        if range.len() > 1 {
+            radix_sort_keyed::<CTX, BYTES>(
+                packed,
+                &mut keyed[range.clone()],
+                &mut scratch[range],
+                depth + 1,
+                ties,
+            );
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
-struct MutPtr<const BYTES: usize>(*mut Int<BYTES>);
+pub(crate) struct MutPtr<const BYTES: usize>(pub(crate) *mut Int<BYTES>);
 unsafe impl<const BYTES: usize> std::marker::Send for MutPtr<BYTES> {}
 unsafe impl<const BYTES: usize> std::marker::Sync for MutPtr<BYTES> {}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::pseudo_dna;
 
     #[test]
     fn test_packed() {
         {
             const CTX: usize = 124;
-            let mut b = b"ACGTACGT".to_vec();
-            b.resize(b.len() + CTX, b'A');
-            let s = SuffixArray::<5>::new_packed::<CTX>(&b, 1, 1);
+            let s = SuffixArray::<5>::new_packed::<CTX>(b"ACGTACGT", 1, 1);
             let correct = [4, 0, 5, 1, 6, 2, 7, 3];
             assert_eq!(s.idxs().to_usize_vec(), correct);
         }
 
         {
             const CTX: usize = 124;
-            let mut b = b"ACGTACGT".to_vec();
-            b.resize(b.len() + CTX, b'A');
-            let s = SuffixArray::<5>::new_packed::<CTX>(&b, 2, 1);
+            let s = SuffixArray::<5>::new_packed::<CTX>(b"ACGTACGT", 2, 1);
             let correct = [4, 0, 5, 1, 6, 2, 7, 3];
             assert_eq!(s.idxs().to_usize_vec(), correct);
         }
 
         {
             const CTX: usize = 124 * 2;
-            let mut b = b"ACGTACGT".to_vec();
-            b.resize(b.len() + CTX, b'A');
-            let s = SuffixArray::<5>::new_packed::<CTX>(&b, 2, 1);
+            let s = SuffixArray::<5>::new_packed::<CTX>(b"ACGTACGT", 2, 1);
             let correct = [4, 0, 5, 1, 6, 2, 7, 3];
             assert_eq!(s.idxs().to_usize_vec(), correct);
         }
 
         {
             const CTX: usize = 124;
-            let mut b = b"TTTT".to_vec();
-            b.resize(b.len() + CTX, b'A');
-            let s = SuffixArray::<5>::new_packed::<CTX>(&b, 2, 1);
+            let s = SuffixArray::<5>::new_packed::<CTX>(b"TTTT", 2, 1);
             let correct = [3, 2, 1, 0];
             assert_eq!(s.idxs().to_usize_vec(), correct);
         }
     }
+
+    #[test]
+    fn test_radix_buckets() {
+        const CTX: usize = 124;
+        // Pseudo-random bases with repeats, so buckets hold more than `RADIX_MIN_BUCKET`
+        // suffixes, some of which tie over the context.
+        let mut seq = pseudo_dna(6000);
+        seq.extend_from_within(1000..3000);
+        seq.extend_from_within(..4000);
+        let b = pad_text(&seq, CTX);
+        for ties in [TieOrder::Ascending, TieOrder::Descending] {
+            let s = SuffixArray::<5>::new_packed_ordered::<CTX>(&seq, 1, 2, ties);
+            let mut naive = (0..seq.len()).collect::<Vec<_>>();
+            naive.sort_by(|&i, &j| {
+                b[i..i + CTX].cmp(&b[j..j + CTX]).then(match ties {
+                    TieOrder::Ascending => i.cmp(&j),
+                    TieOrder::Descending => j.cmp(&i),
+                })
+            });
+            assert_eq!(s.idxs().to_usize_vec(), naive);
+        }
+    }
+
+    #[test]
+    fn test_skewed_bucket() {
+        const CTX: usize = 124;
+        // A poly-A run much larger than `PAR_MIN_BUCKET`, which is sorted in parallel.
+        let mut seq = vec![b'A'; 100_000];
+        seq.extend_from_slice(&b"ACGTTGCAGATTACAGGATCCA".repeat(100));
+        seq.extend_from_slice(&[b'A'; 1000]);
+        seq.extend_from_slice(b"CCGT");
+        let b = pad_text(&seq, CTX);
+        let mut naive = (0..seq.len()).collect::<Vec<_>>();
+        naive.sort_by(|&i, &j| b[i..i + CTX].cmp(&b[j..j + CTX]).then(i.cmp(&j)));
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 2, 4);
+        assert_eq!(s.idxs().to_usize_vec(), naive);
+        let s = SuffixArray::<5>::new_packed_key_cached::<CTX>(&seq, 2, 4, TieOrder::Ascending);
+        assert_eq!(s.idxs().to_usize_vec(), naive);
+    }
+
+    #[test]
+    fn test_from_packed() {
+        const CTX: usize = 124;
+        for len in [0, 1, 70, 101, 102, 103] {
+            let seq = b"ACGTTGCAGATTACAGGATCCA".repeat(5)[..len].to_vec();
+            let packed = seq
+                .chunks(4)
+                .map(|c| {
+                    c.iter()
+                        .enumerate()
+                        .fold(0, |byte, (j, &b)| byte | base_code(b) << (6 - 2 * j))
+                })
+                .collect::<Vec<u8>>();
+            let s = SuffixArray::<5>::new_from_packed::<CTX>(&packed, len, 3, 2);
+            let t = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+            assert_eq!(s.idxs().to_usize_vec(), t.idxs().to_usize_vec());
+            assert_eq!(
+                s.bucket_ends().to_usize_vec(),
+                t.bucket_ends().to_usize_vec()
+            );
+        }
+        // Bits past the last base are ignored.
+        let s = SuffixArray::<5>::new_from_packed::<CTX>(&[0b1100_1111], 2, 2, 1);
+        assert_eq!(s.idxs().to_usize_vec(), [1, 0]);
+        assert_eq!(s.bucket_range(0), 0..1);
+    }
+
+    #[test]
+    fn test_seeds() {
+        const CTX: usize = 32;
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let mut seeds = (0..3000)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                (x % 64) as u16
+            })
+            .collect::<Vec<_>>();
+        // Long repeats leave suffixes tied over the context.
+        seeds.extend_from_within(100..300);
+        seeds.extend_from_within(100..300);
+        let n = seeds.len();
+        seeds.resize(n + CTX, 0);
+
+        let mut naive = (0..n).collect::<Vec<_>>();
+        naive.sort_by_key(|&i| (&seeds[i..i + 1 + CTX], i));
+        for threads in [1, 3] {
+            let s = SuffixArray::<5>::new::<CTX>(&seeds, 6, threads);
+            assert_eq!(s.idxs().to_usize_vec(), naive);
+            assert_eq!(
+                s.bucket_range(5).len(),
+                seeds[..n].iter().filter(|&&x| x == 5).count()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bytes() {
+        const CTX: usize = 64;
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let mut seq = (0..3000)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                b"\0ACGT~\xff"[x as usize % 7]
+            })
+            .collect::<Vec<_>>();
+        // Long repeats leave suffixes tied over the context.
+        seq.extend_from_within(100..300);
+        seq.extend_from_within(100..300);
+        let b = pad_text(&seq, CTX);
+
+        let mut naive = (0..seq.len()).collect::<Vec<_>>();
+        naive.sort_by_key(|&i| (&b[i..i + CTX], i));
+        for (p, threads) in [(1, 1), (2, 3), (3, 2)] {
+            let s = SuffixArray::<5>::new_bytes::<CTX>(&seq, p, threads);
+            assert_eq!(s.idxs().to_usize_vec(), naive, "p = {p}");
+        }
+    }
+
+    #[test]
+    fn test_unpadded() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCAGATTACAGATTACA".repeat(5);
+        let b = pad_text(&seq, CTX);
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 2);
+        let mut naive = (0..seq.len()).collect::<Vec<_>>();
+        naive.sort_by(|&i, &j| b[i..i + CTX].cmp(&b[j..j + CTX]).then(i.cmp(&j)));
+        assert_eq!(s.idxs().to_usize_vec(), naive);
+        assert_eq!(s.residual_ties(), Some(s.count_residual_ties(&b)));
+
+        // `new_bytes` pads internally like sorting the padded text does.
+        let t = SuffixArray::<5>::new_bytes::<CTX>(&seq, 2, 2);
+        let padded = SuffixArray::<5>::sort_bytes::<CTX>(&b, 2, Schedule::from(2));
+        assert_eq!(t.idxs().to_usize_vec(), padded.to_usize_vec());
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(b"", 2, 1);
+        assert!(s.idxs().is_empty());
+        let s = SuffixArray::<5>::new_packed_ordered::<CTX>(b"TGCA", 2, 1, TieOrder::Ascending);
+        assert_eq!(s.idxs().to_usize_vec(), [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_buckets() {
+        const CTX: usize = 124;
+        let s = SuffixArray::<5>::new_packed::<CTX>(b"ACGTACGT", 2, 1);
+        let buckets = s
+            .buckets()
+            .map(|(kmer, idxs)| (kmer, idxs.iter().map(|i| i.get_usize()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            buckets,
+            [
+                (0b0001, vec![4, 0]),
+                (0b0110, vec![5, 1]),
+                (0b1011, vec![6, 2]),
+                (0b1100, vec![7, 3])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variable_depth() {
+        const CTX: usize = 124 * 4;
+        let mut seq = pseudo_dna(3000);
+        seq.extend_from_within(500..1000);
+        let b = pad_text(&seq, CTX);
+
+        let s = SuffixArray::<5>::new_packed::<CTX>(&seq, 3, 1);
+        let mut correct = (0..seq.len()).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| b[x..x + CTX].cmp(&b[y..y + CTX]).then(x.cmp(&y)));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+    }
+
+    #[test]
+    fn test_tie_order() {
+        const CTX: usize = 124;
+        let seq = b"ACGTTGCA".repeat(80);
+        let b = pad_text(&seq, CTX);
+
+        let s = SuffixArray::<5>::new_packed_ordered::<CTX>(&seq, 3, 2, TieOrder::Descending);
+        let mut correct = (0..seq.len()).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| b[x..x + CTX].cmp(&b[y..y + CTX]).then(y.cmp(&x)));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+        assert_eq!(s.tie_order(), TieOrder::Descending);
+    }
+
+    #[test]
+    fn test_range() {
+        const CTX: usize = 124;
+        let mut seq = pseudo_dna(2000);
+        seq.extend_from_within(300..700);
+        let b = pad_text(&seq, CTX);
+
+        let s = SuffixArray::<5>::new_packed_range::<CTX>(&seq, 500..1200, 3, 3);
+        let mut correct = (500..1200).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| b[x..x + CTX].cmp(&b[y..y + CTX]).then(x.cmp(&y)));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+        assert_eq!(s.bucket_ends()[63].get_usize(), 700);
+
+        let s = SuffixArray::<5>::new_packed_range::<CTX>(&seq, 500..520, 8, 3);
+        let mut correct = (500..520).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| b[x..x + CTX].cmp(&b[y..y + CTX]).then(x.cmp(&y)));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+        assert_eq!((s.k(), s.bucket_ends().len()), (2, 16));
+
+        // The context of a range at the end of the sequence runs into the padding.
+        let s = SuffixArray::<5>::new_packed_range::<CTX>(&seq, 2300..2400, 3, 3);
+        let mut correct = (2300..2400).collect::<Vec<_>>();
+        correct.sort_by(|&x, &y| b[x..x + CTX].cmp(&b[y..y + CTX]).then(x.cmp(&y)));
+        assert_eq!(s.idxs().to_usize_vec(), correct);
+        let outside = std::panic::catch_unwind(|| {
+            SuffixArray::<5>::new_packed_range::<CTX>(&seq, 2300..2401, 3, 3)
+        });
+        assert!(outside.is_err());
+    }
+
+    #[test]
+    fn test_key_cached() {
+        const CTX: usize = 248;
+        let mut seq = pseudo_dna(3000);
+        seq.extend_from_within(100..400);
+        seq.extend_from_within(0..100);
+
+        for ties in [TieOrder::Ascending, TieOrder::Descending] {
+            let s = SuffixArray::<5>::new_packed_key_cached::<CTX>(&seq, 3, 2, ties);
+            let t = SuffixArray::<5>::new_packed_ordered::<CTX>(&seq, 3, 2, ties);
+            assert_eq!(s.idxs(), t.idxs());
+            assert_eq!(s.bucket_ends(), t.bucket_ends());
+            assert_eq!(s.tie_order(), ties);
+        }
+    }
+
+    #[test]
+    fn test_residual_ties() {
+        let mut seq = pseudo_dna(3000);
+        seq.extend_from_within(1000..1200);
+        let deep = SuffixArray::<5>::new_packed::<248>(&seq, 4, 3);
+        assert_eq!(
+            deep.residual_ties(),
+            Some(deep.count_residual_ties(&pad_text(&seq, 248)))
+        );
+        let shallow = SuffixArray::<5>::new_packed::<124>(&seq, 4, 3);
+        assert!(shallow.residual_ties().unwrap() > deep.residual_ties().unwrap());
+        assert_eq!(
+            shallow.residual_ties(),
+            Some(shallow.count_residual_ties(&pad_text(&seq, 124)))
+        );
+        assert_eq!(
+            SuffixArray::from_idxs(deep.idxs().clone(), 248).residual_ties(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_schedule() {
+        let b = pseudo_dna(3000);
+        let one = SuffixArray::<5>::new_packed::<124>(&b, 4, 3);
+        let schedule = Schedule {
+            bucket_threads: 3,
+            chunks_per_thread: 7,
+            ..Schedule::default()
+        };
+        let many = SuffixArray::<5>::new_packed::<124>(&b, 4, schedule);
+        assert_eq!(one.idxs(), many.idxs());
+        assert_eq!(one.bucket_ends(), many.bucket_ends());
+
+        let schedule = Schedule {
+            bucket_threads: 3,
+            min_task_entries: 100,
+            ..Schedule::default()
+        };
+        let grouped = SuffixArray::<5>::new_packed::<124>(&b, 4, schedule);
+        assert_eq!(one.idxs(), grouped.idxs());
+        assert_eq!(task_runs([3, 0, 5, 1, 2].into_iter(), 4), [0..3, 3..5]);
+        assert_eq!(task_runs([3, 0].into_iter(), 0), [0..1, 1..2]);
+    }
+
+    #[test]
+    fn test_scalar_references() {
+        // Random text and seeds from a fixed xorshift sequence, with some repeats so
+        // that comparisons also run past the first block.
+        let mut x = 0x2545f4914f6cdd1du64;
+        let mut next = || {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+        let mut b = (0..4000)
+            .map(|_| b"ACGT"[next() as usize % 4])
+            .collect::<Vec<_>>();
+        b.extend_from_within(500..1500);
+        b.extend(std::iter::repeat_n(b'A', 496));
+        let packed = RevPacked::new(&b);
+        let mut seeds = (0..4000).map(|_| next() as u16).collect::<Vec<_>>();
+        seeds.extend_from_within(500..1500);
+        seeds.resize(seeds.len() + 496, 0);
+        let raw = (0..b.len()).map(|_| next() as u8).collect::<Vec<_>>();
+        let zero = [0u8; 32];
+
+        for _ in 0..2000 {
+            let a = next() as usize % 5000;
+            let c = match next() % 3 {
+                // The same suffix one repeat earlier or later.
+                0 if (500..1500).contains(&a) => a + 3500,
+                0 if (4000..5000).contains(&a) => a - 3500,
+                _ => next() as usize % 5000,
+            };
+            let ties = [TieOrder::Ascending, TieOrder::Descending][a % 2];
+            unsafe {
+                let cmp = cmp_packed_scalar::<248>(&packed, a, c, ties);
+                assert_eq!(cmp, cmp_packed::<248, Scalar>(&packed, a, c, ties));
+                assert_eq!(cmp, simd_cmp_packed::<248>(&packed, a, c, ties));
+                let cmp = cmp_packed_scalar::<496>(&packed, a, c, ties);
+                assert_eq!(cmp, simd_cmp_packed::<496>(&packed, a, c, ties));
+                // The text is padded for 496 bases, so the 512-bit kernel also runs
+                // the shorter contexts.
+                for kernel in [Kernel::Scalar, Kernel::detect(496)] {
+                    assert_eq!(cmp, kernel.cmp::<496>(&packed, a, c, ties));
+                    let cmp = cmp_packed_scalar::<124>(&packed, a, c, ties);
+                    assert_eq!(cmp, kernel.cmp::<124>(&packed, a, c, ties));
+                    let cmp = cmp_packed_scalar::<248>(&packed, a, c, ties);
+                    assert_eq!(cmp, kernel.cmp::<248>(&packed, a, c, ties));
+                }
+
+                let cmp = cmp_bytes_scalar::<124>(&raw, a, c);
+                assert_eq!(cmp, cmp_bytes::<124, Scalar>(&raw, a, c));
+                assert_eq!(cmp, simd_cmp_bytes::<124>(&raw, a, c));
+                let cmp = cmp_bytes_scalar::<248>(&b, a, c);
+                assert_eq!(cmp, simd_cmp_bytes::<248>(&b, a, c));
+
+                let cmp = cmp_seeds_scalar::<112>(&seeds, a, c);
+                assert_eq!(cmp, cmp_seeds::<112, Scalar>(&seeds, a, c));
+                assert_eq!(cmp, simd_cmp::<112>(&seeds, a, c));
+
+                let load = packed.load_124_scalar(a);
+                let z = Scalar::load(zero.as_ptr());
+                assert_eq!(packed.load_124::<Scalar>(a).xor_bytes(z), load);
+                if simd_supported() {
+                    let z = V::load(zero.as_ptr());
+                    assert_eq!(packed.load_124::<V>(a).xor_bytes(z), load);
+                }
+                let k = 1 + a % 16;
+                assert_eq!(packed.load_k(a, k), packed.load_k_scalar(a, k));
+            }
+        }
+
+        let sorted = [Kernel::Scalar, Kernel::detect(496)].map(|kernel| {
+            let mut bucket = CompactVec::<5>::new(5000);
+            for (i, e) in bucket.iter_mut().enumerate() {
+                e.set_usize(4999 - i);
+            }
+            sort_bucket::<496, 5>(
+                kernel,
+                &packed,
+                &mut bucket,
+                false,
+                false,
+                TieOrder::Ascending,
+            );
+            bucket.to_usize_vec()
+        });
+        assert_eq!(sorted[0], sorted[1]);
+    }
+
+    #[test]
+    fn test_scalar_fallback() {
+        let b = pseudo_dna(3000)
+            .into_iter()
+            .chain((0..500).map(|i| b"ACGT"[i % 3]))
+            .chain(std::iter::repeat_n(b'A', 496))
+            .collect::<Vec<_>>();
+        let packed = RevPacked::new(&b);
+        let seeds = b.iter().map(|&c| c as u16 * 7).collect::<Vec<_>>();
+        for a in (0..3500).step_by(37) {
+            for c in (1..3500).step_by(53) {
+                unsafe {
+                    assert_eq!(
+                        lcp_packed::<Scalar>(&packed, a, &packed, c, 248),
+                        simd_lcp_packed(&packed, a, &packed, c, 248)
+                    );
+                    assert_eq!(
+                        cmp_packed::<248, Scalar>(&packed, a, c, TieOrder::Ascending),
+                        simd_cmp_packed::<248>(&packed, a, c, TieOrder::Ascending)
+                    );
+                    assert_eq!(
+                        cmp_packed::<496, Scalar>(&packed, a, c, TieOrder::Descending),
+                        simd_cmp_packed::<496>(&packed, a, c, TieOrder::Descending)
+                    );
+                    assert_eq!(
+                        cmp_bytes::<224, Scalar>(&b, a, c),
+                        simd_cmp_bytes::<224>(&b, a, c)
+                    );
+                    assert_eq!(
+                        cmp_seeds::<112, Scalar>(&seeds, a, c),
+                        simd_cmp::<112>(&seeds, a, c)
+                    );
+                }
+            }
+        }
+    }
 }
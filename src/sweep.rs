@@ -0,0 +1,130 @@
+use std::time::Instant;
+
+use crate::suffix_array::*;
+use crate::tune::*;
+
+/// Context lengths supported by the `_with_ctx` constructors.
+pub const SUPPORTED_CTX: [usize; 4] = [124, 248, 496, 992];
+
+/// Number of evenly spaced pieces a sweep sample is taken from.
+const SAMPLE_PIECES: usize = 16;
+
+/// One `(k, ctx)` configuration of a `Sweep`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SweepPoint {
+    pub k: usize,
+    pub ctx: usize,
+    /// Construction time on the sample, in seconds.
+    pub time: f64,
+    /// Estimated peak memory of the full build, in bytes.
+    pub memory: usize,
+    /// Adjacent suffixes of the sample that are still equal over the whole context.
+    pub ties: usize,
+}
+
+/// A grid of bounded-context builds on a sample of the input, to choose `k` and the
+/// context before the full build.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    pub sample_len: usize,
+    pub points: Vec<SweepPoint>,
+}
+
+impl Sweep {
+    /// Build the suffix array of a sample of about `sample_len` bases of `text` for every `k` in `ks` and context in `ctxs`. The sample consists of
+    /// evenly spaced pieces, so that repeats from the whole input are represented.
+    pub fn run<const BYTES: usize>(
+        text: &[u8],
+        sample_len: usize,
+        ks: &[usize],
+        ctxs: &[usize],
+        bucket_threads: impl Into<Schedule>,
+    ) -> Self {
+        let schedule: Schedule = bucket_threads.into();
+        let sample = sample_text(text, sample_len);
+
+        let mut points = Vec::with_capacity(ks.len() * ctxs.len());
+        for &ctx in ctxs {
+            for &k in ks {
+                let start = Instant::now();
+                let sa = SuffixArray::<BYTES>::new_packed_with_ctx(&sample, ctx, k, &schedule);
+                let time = start.elapsed().as_secs_f64();
+                points.push(SweepPoint {
+                    k,
+                    ctx,
+                    time,
+                    memory: SuffixArray::<BYTES>::estimated_memory(text.len(), k, &schedule),
+                    ties: sa.residual_ties().unwrap(),
+                });
+            }
+        }
+        Self {
+            sample_len: sample.len(),
+            points,
+        }
+    }
+
+    /// The fastest configuration among those leaving the fewest residual ties.
+    pub fn recommended(&self) -> Option<&SweepPoint> {
+        let ties = self.points.iter().map(|p| p.ties).min()?;
+        self.points
+            .iter()
+            .filter(|p| p.ties == ties)
+            .min_by(|a, b| a.time.total_cmp(&b.time))
+    }
+}
+
+/// `len` bases of `text` in `SAMPLE_PIECES` evenly spaced pieces, or all of it.
+fn sample_text(text: &[u8], len: usize) -> Vec<u8> {
+    if text.len() <= len {
+        return text.to_vec();
+    }
+    let piece = len / SAMPLE_PIECES;
+    let stride = (text.len() - piece) / (SAMPLE_PIECES - 1).max(1);
+    (0..SAMPLE_PIECES)
+        .flat_map(|i| &text[i * stride..i * stride + piece])
+        .copied()
+        .collect()
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// See `estimate_memory`.
+    pub fn estimated_memory(n: usize, k: usize, bucket_threads: impl Into<Schedule>) -> usize {
+        estimate_memory(n, k, bucket_threads, BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_sweep() {
+        let mut text = pseudo_dna(2000);
+        // A repeat longer than the smallest context but shorter than the next.
+        text.extend_from_within(500..700);
+        text.push(b'C');
+
+        let sweep = Sweep::run::<5>(&text, 1 << 20, &[3, 4], &[124, 248], 2);
+        assert_eq!(sweep.sample_len, text.len());
+        assert_eq!(sweep.points.len(), 4);
+        for p in &sweep.points {
+            let b = pad_text(&text, p.ctx);
+            let idxs = SuffixArray::<5>::new_packed_with_ctx(&text, p.ctx, p.k, 2)
+                .idxs()
+                .to_usize_vec();
+            let naive = idxs
+                .windows(2)
+                .filter(|w| b[w[0]..w[0] + p.ctx] == b[w[1]..w[1] + p.ctx])
+                .count();
+            assert_eq!(p.ties, naive);
+        }
+        assert!(sweep.points[0].ties > 0);
+        assert_eq!(sweep.recommended().unwrap().ctx, 248);
+
+        let sample = sample_text(&text, 160);
+        assert_eq!(sample.len(), 160);
+        assert_eq!(sample[..10], text[..10]);
+    }
+}
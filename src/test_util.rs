@@ -0,0 +1,13 @@
+/// `n` bases of a fixed pseudo-random DNA sequence, from the top bits of a xorshift
+/// generator.
+pub fn pseudo_dna(n: usize) -> Vec<u8> {
+    let mut x = 0x2545f4914f6cdd1du64;
+    (0..n)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            b"ACGT"[(x >> 62) as usize]
+        })
+        .collect()
+}
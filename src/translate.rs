@@ -0,0 +1,248 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::alphabet::*;
+use crate::compact_vec::*;
+use crate::perf::*;
+use crate::seq_dict::*;
+
+/// Amino acids of the standard genetic code, indexed by the codon in base 4 with
+/// `T = 0, C = 1, A = 2, G = 3`, with `*` for stop codons.
+const CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// Amino acid of a codon, `X` if it has a base other than `ACGTU`.
+pub fn translate_codon(codon: &[u8]) -> u8 {
+    let mut idx = 0;
+    for &b in codon {
+        idx = idx * 4
+            + match b.to_ascii_uppercase() {
+                b'T' | b'U' => 0,
+                b'C' => 1,
+                b'A' => 2,
+                b'G' => 3,
+                _ => return b'X',
+            };
+    }
+    CODE[idx]
+}
+
+/// One of the six reading frames of a sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub seq: usize,
+    /// Offset of the first codon, on the reverse complement for reverse frames.
+    pub offset: usize,
+    pub reverse: bool,
+    /// Range of the translation in the protein text.
+    pub protein: Range<usize>,
+}
+
+/// A protein match mapped back to its sequence, in forward coordinates of the bases
+/// of its codons.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DnaHit {
+    pub seq: usize,
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+}
+
+/// The six-frame translation of all sequences of a text, concatenated.
+pub struct TranslatedText {
+    pub protein: Vec<u8>,
+    pub frames: Vec<Frame>,
+}
+
+impl TranslatedText {
+    pub fn new(bytes: &[u8], dict: &SeqDict) -> Self {
+        let frames = (0..dict.len())
+            .into_par_iter()
+            .flat_map_iter(|id| {
+                let seq = &bytes[dict.start(id)..dict.end(id)];
+                let rc = revcomp(seq);
+                (0..6).map(move |f| {
+                    let (strand, offset) = if f < 3 { (seq, f) } else { (&rc[..], f - 3) };
+                    let protein = strand[offset.min(strand.len())..]
+                        .chunks_exact(3)
+                        .map(translate_codon)
+                        .collect::<Vec<_>>();
+                    (id, offset, f >= 3, protein)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut protein = Vec::with_capacity(frames.iter().map(|f| f.3.len()).sum());
+        let frames = frames
+            .into_iter()
+            .map(|(seq, offset, reverse, p)| {
+                let start = protein.len();
+                protein.extend_from_slice(&p);
+                Frame {
+                    seq,
+                    offset,
+                    reverse,
+                    protein: start..protein.len(),
+                }
+            })
+            .collect();
+        Self { protein, frames }
+    }
+
+    /// The frame containing protein position `pos`.
+    pub fn frame(&self, pos: usize) -> &Frame {
+        &self.frames[self.frames.partition_point(|f| f.protein.end <= pos)]
+    }
+
+    /// The codons of the `len` residues starting at protein position `pos`.
+    pub fn to_dna(&self, dict: &SeqDict, pos: usize, len: usize) -> DnaHit {
+        let f = self.frame(pos);
+        let start = f.offset + 3 * (pos - f.protein.start);
+        let end = start + 3 * len;
+        let (start, end) = if f.reverse {
+            let seq_len = dict.seq_len(f.seq);
+            (seq_len - end, seq_len - start)
+        } else {
+            (start, end)
+        };
+        DnaHit {
+            seq: f.seq,
+            start,
+            end,
+            reverse: f.reverse,
+        }
+    }
+}
+
+/// Suffix array of a six-frame translation, sorted on up to `ctx` residues. The end of
+/// each frame sorts before all residues, and ties are broken by position.
+pub struct ProteinSuffixArray<const BYTES: usize> {
+    idxs: CompactVec<BYTES>,
+    ctx: usize,
+}
+
+impl<const BYTES: usize> ProteinSuffixArray<BYTES> {
+    pub fn new(text: &TranslatedText, ctx: usize) -> Self {
+        let phase = Phase::start("Frame ends");
+        let mut idxs = CompactVec::<BYTES>::new(text.protein.len());
+        idxs.par_iter_mut()
+            .enumerate()
+            .for_each(|(i, e)| e.set_usize(i));
+        let ends = text
+            .frames
+            .iter()
+            .flat_map(|f| f.protein.clone().map(move |_| f.protein.end))
+            .collect::<Vec<_>>();
+        phase.finish();
+
+        let phase = Phase::start("Sort translated suffixes");
+        let suffix = |i: usize| &text.protein[i..(i + ctx).min(ends[i])];
+        idxs.par_sort_unstable_by(|a, b| {
+            let (a, b) = (a.get_usize(), b.get_usize());
+            suffix(a).cmp(suffix(b)).then(a.cmp(&b))
+        });
+        phase.finish();
+
+        Self { idxs, ctx }
+    }
+
+    pub fn idxs(&self) -> &CompactVec<BYTES> {
+        &self.idxs
+    }
+
+    pub fn ctx(&self) -> usize {
+        self.ctx
+    }
+
+    /// Range of suffix array entries whose suffixes within their frame start with
+    /// `peptide`.
+    pub fn range(&self, text: &TranslatedText, peptide: &[u8]) -> Range<usize> {
+        assert!(peptide.len() <= self.ctx);
+        let cmp = |i: &Int<BYTES>| {
+            let i = i.get_usize();
+            let end = text.frame(i).protein.end.min(i + peptide.len());
+            let s = &text.protein[i..end];
+            if s.len() == peptide.len() {
+                s.cmp(peptide)
+            } else {
+                // A frame end sorts before all residues.
+                s.cmp(&peptide[..s.len()]).then(Ordering::Less)
+            }
+        };
+        let start = self.idxs.partition_point(|i| cmp(i) == Ordering::Less);
+        let end = start + self.idxs[start..].partition_point(|i| cmp(i) == Ordering::Equal);
+        start..end
+    }
+
+    /// All occurrences of `peptide` in the six-frame translation, mapped back to DNA.
+    pub fn locate(&self, text: &TranslatedText, dict: &SeqDict, peptide: &[u8]) -> Vec<DnaHit> {
+        self.idxs[self.range(text, peptide)]
+            .iter()
+            .map(|i| text.to_dna(dict, i.get_usize(), peptide.len()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(translate_codon(b"ATG"), b'M');
+        assert_eq!(translate_codon(b"tgg"), b'W');
+        assert_eq!(translate_codon(b"TAA"), b'*');
+        assert_eq!(translate_codon(b"GCN"), b'X');
+
+        // MKW on the forward strand of `a`, and on the reverse strand of `b`.
+        let a = b"CATGAAATGGTT";
+        let b = revcomp(b"GATGAAGTGGATGAAATGGC");
+        let mut dict = SeqDict::new();
+        dict.push("a", a.len());
+        dict.push("b", b.len());
+        let bytes = [&a[..], &b].concat();
+
+        let text = TranslatedText::new(&bytes, &dict);
+        assert_eq!(text.frames.len(), 12);
+        assert_eq!(&text.protein[text.frames[1].protein.clone()], b"MKW");
+        let sa = ProteinSuffixArray::<5>::new(&text, 8);
+
+        let naive = |p: &[u8]| {
+            let mut hits = text
+                .frames
+                .iter()
+                .flat_map(|f| f.protein.clone())
+                .filter(|&i| text.protein[i..text.frame(i).protein.end].starts_with(p))
+                .map(|i| text.to_dna(&dict, i, p.len()))
+                .collect::<Vec<_>>();
+            hits.sort_unstable();
+            hits
+        };
+        for p in [&b"MKW"[..], b"M", b"DE", b"W", b"KWG"] {
+            let mut hits = sa.locate(&text, &dict, p);
+            hits.sort_unstable();
+            assert_eq!(hits, naive(p), "{}", String::from_utf8_lossy(p));
+        }
+
+        // `ATGAAGTGG` and `ATGAAATGG` in frame 1 of the reverse complement of `b`.
+        let hits = sa.locate(&text, &dict, b"MKW");
+        assert_eq!(hits.len(), 3);
+        for (start, codons) in [(1, b"ATGAAGTGG"), (10, b"ATGAAATGG")] {
+            let hit = DnaHit {
+                seq: 1,
+                start: b.len() - start - 9,
+                end: b.len() - start,
+                reverse: true,
+            };
+            assert!(hits.contains(&hit));
+            assert_eq!(&revcomp(&b[hit.start..hit.end]), codons);
+        }
+        assert!(hits.contains(&DnaHit {
+            seq: 0,
+            start: 1,
+            end: 10,
+            reverse: false
+        }));
+    }
+}
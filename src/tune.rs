@@ -0,0 +1,110 @@
+use crate::suffix_array::*;
+
+/// Largest expected bucket size `auto_tune` aims for. Buckets are sorted by comparison,
+/// so larger ones cost more per suffix and balance worse over threads.
+pub const TARGET_BUCKET: usize = 1 << 12;
+
+/// Largest `k` the packed bucketing supports.
+const MAX_K: usize = 16;
+
+/// Peak memory in bytes of `new_packed` on a text of `n` bases with `entry_bytes`-byte
+/// entries: the packed text, the array itself, and per bucket the counters of every
+/// chunk, its bit in the per-chunk touched sets, its start and its pending count. The
+/// caller's copy of the text is not included.
+pub fn estimate_memory(
+    n: usize,
+    k: usize,
+    bucket_threads: impl Into<Schedule>,
+    entry_bytes: usize,
+) -> usize {
+    let buckets = 1usize << (2 * k);
+    let schedule: Schedule = bucket_threads.into();
+    let chunks = schedule.chunk_count();
+    let per_bucket = chunks * entry_bytes + chunks.div_ceil(8) + entry_bytes + size_of::<usize>();
+    n / 4 + n * entry_bytes + buckets * per_bucket
+}
+
+/// Bucketing parameters chosen by `auto_tune`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tuning {
+    pub k: usize,
+    pub bucket_threads: usize,
+    /// Estimated peak memory in bytes, see `estimate_memory`.
+    pub memory: usize,
+}
+
+impl Tuning {
+    /// Expected number of suffixes per bucket of a text of `n` bases.
+    pub fn expected_bucket(&self, n: usize) -> usize {
+        n >> (2 * self.k)
+    }
+}
+
+/// Choose `k` and `bucket_threads` for a text of `n` bases that fit `budget` bytes.
+///
+/// `k` is the smallest that keeps the expected bucket below `TARGET_BUCKET`, and the
+/// most threads up to `max_threads` that fit, at its chunks per thread, are used. If
+/// even one thread does not fit, `k` is lowered, which shrinks the counters at the cost
+/// of larger buckets. `None` if the array itself does not fit.
+pub fn auto_tune(
+    n: usize,
+    entry_bytes: usize,
+    budget: usize,
+    max_threads: impl Into<Schedule>,
+) -> Option<Tuning> {
+    let schedule: Schedule = max_threads.into();
+    let with_threads = |t: usize| Schedule {
+        bucket_threads: t,
+        ..schedule.clone()
+    };
+    let ideal_k = (1..=MAX_K)
+        .find(|&k| n >> (2 * k) <= TARGET_BUCKET)
+        .unwrap_or(MAX_K);
+    (1..=ideal_k).rev().find_map(|k| {
+        // Memory grows with the threads, so the most that fit are found by bisection.
+        let fits = |t: usize| estimate_memory(n, k, with_threads(t), entry_bytes) <= budget;
+        if !fits(1) {
+            return None;
+        }
+        let (mut lo, mut hi) = (1, schedule.bucket_threads.max(1));
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if fits(mid) {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        Some(Tuning {
+            k,
+            bucket_threads: lo,
+            memory: estimate_memory(n, k, with_threads(lo), entry_bytes),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_tune() {
+        const GBP: usize = 1 << 30;
+        let n = 3 * GBP;
+        let t = auto_tune(n, 5, 64 * GBP, 32).unwrap();
+        assert_eq!((t.k, t.bucket_threads), (10, 32));
+        assert!(t.expected_bucket(n) <= TARGET_BUCKET);
+        assert!(t.memory <= 64 * GBP);
+
+        // A tight budget first costs threads, then bucket length.
+        let tight = estimate_memory(n, 10, 1, 5) + (64 << 20);
+        let t = auto_tune(n, 5, tight, 32).unwrap();
+        assert_eq!(t.k, 10);
+        assert!((1..32).contains(&t.bucket_threads));
+        let t = auto_tune(n, 5, n / 4 + n * 5 + (1 << 20), 32).unwrap();
+        assert_eq!(t.k, 7);
+
+        assert_eq!(auto_tune(n, 5, GBP, 32), None);
+        assert_eq!(auto_tune(1000, 4, 1 << 20, 4).unwrap().k, 1);
+    }
+}
@@ -0,0 +1,215 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+use crate::suffix_array::*;
+
+/// The first problem `SuffixArray::verify` found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// Entry `index` holds `pos`, which is not below the number of entries.
+    OutOfRange { index: usize, pos: usize },
+    /// No entry holds the suffix at `pos`, so another suffix is held twice.
+    Missing { pos: usize },
+    /// The suffixes at `a` and `b` of entries `index - 1` and `index` are out of order.
+    Misordered { index: usize, a: usize, b: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfRange { index, pos } => {
+                write!(f, "entry {index} holds out of range position {pos}")
+            }
+            Self::Missing { pos } => write!(f, "no entry holds position {pos}"),
+            Self::Misordered { index, a, b } => write!(
+                f,
+                "entries {} and {index} hold suffixes {a} and {b} out of order",
+                index - 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Summary of how two suffix arrays over the same text differ.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    /// Number of entries compared (the shorter of the two lengths).
+    pub compared: usize,
+    /// Entries holding the same suffix in both arrays.
+    pub identical: usize,
+    /// Entries holding different suffixes that are equal over the compared context,
+    /// i.e. the arrays only disagree on how ties are broken.
+    pub tie_order: usize,
+    /// Entries holding suffixes that differ within the compared context.
+    pub misordered: usize,
+    /// Index of the first misordered entry.
+    pub first_misorder: Option<usize>,
+    pub len_self: usize,
+    pub len_other: usize,
+}
+
+impl ConsistencyReport {
+    /// Whether both arrays are equal up to the order of tied suffixes.
+    pub fn is_consistent(&self) -> bool {
+        self.misordered == 0 && self.len_self == self.len_other
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Compare against another array built over the same `bytes`, e.g. by a different
+    /// backend, using the smaller of the two contexts.
+    pub fn compare<const B: usize>(
+        &self,
+        other: &SuffixArray<B>,
+        bytes: &[u8],
+    ) -> ConsistencyReport {
+        let ctx = self.ctx().min(other.ctx());
+        let packed = RevPacked::new(bytes);
+        let compared = self.idxs().len().min(other.idxs().len());
+
+        let mut report = self.idxs()[..compared]
+            .par_iter()
+            .zip(other.idxs()[..compared].par_iter())
+            .enumerate()
+            .fold(ConsistencyReport::default, |mut r, (i, (a, b))| {
+                let (a, b) = (a.get_usize(), b.get_usize());
+                if a == b {
+                    r.identical += 1;
+                } else if unsafe { packed.lcp(a, b, ctx) } >= ctx {
+                    r.tie_order += 1;
+                } else {
+                    r.misordered += 1;
+                    r.first_misorder = Some(r.first_misorder.map_or(i, |f| f.min(i)));
+                }
+                r
+            })
+            .reduce(ConsistencyReport::default, |mut a, b| {
+                a.identical += b.identical;
+                a.tie_order += b.tie_order;
+                a.misordered += b.misordered;
+                a.first_misorder = match (a.first_misorder, b.first_misorder) {
+                    (Some(x), Some(y)) => Some(x.min(y)),
+                    (x, y) => x.or(y),
+                };
+                a
+            });
+
+        report.compared = compared;
+        report.len_self = self.idxs().len();
+        report.len_other = other.idxs().len();
+        report
+    }
+}
+
+impl<const BYTES: usize> SuffixArray<BYTES> {
+    /// Check in parallel that the array is a permutation of `0..n` for its `n` entries,
+    /// and that adjacent suffixes of the padded `bytes` are in order over the context,
+    /// with ties in the array's tie order. Arrays that are not in lexicographic order
+    /// are only checked within buckets, and ties in exact arrays are not checked.
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), VerifyError> {
+        let idxs = self.idxs();
+        let n = idxs.len();
+
+        if let Some(index) = idxs.par_iter().position_first(|i| i.get_usize() >= n) {
+            let pos = idxs[index].get_usize();
+            return Err(VerifyError::OutOfRange { index, pos });
+        }
+        let seen = (0..n.div_ceil(64))
+            .map(|_| AtomicU64::new(0))
+            .collect::<Vec<_>>();
+        idxs.par_iter().for_each(|i| {
+            let i = i.get_usize();
+            seen[i / 64].fetch_or(1 << (i % 64), AtomicOrdering::Relaxed);
+        });
+        if let Some(pos) = (0..n)
+            .into_par_iter()
+            .find_first(|&p| seen[p / 64].load(AtomicOrdering::Relaxed) & (1 << (p % 64)) == 0)
+        {
+            return Err(VerifyError::Missing { pos });
+        }
+
+        let ctx = self.ctx();
+        let packed = RevPacked::new(bytes);
+        let check_ties = !self.is_exact();
+        let ranges: Vec<Range<usize>> = if self.is_lexicographic() {
+            std::iter::once(0..n).collect()
+        } else {
+            (0..self.bucket_ends().len())
+                .map(|kmer| self.bucket_range(kmer))
+                .collect()
+        };
+        let misordered = |index: usize| {
+            let (a, b) = (idxs[index - 1].get_usize(), idxs[index].get_usize());
+            let l = unsafe { packed.lcp(a, b, ctx) };
+            let ord = if l < ctx {
+                packed.get(a + l).cmp(&packed.get(b + l))
+            } else if !check_ties {
+                Ordering::Less
+            } else {
+                match self.tie_order() {
+                    TieOrder::Ascending => a.cmp(&b),
+                    TieOrder::Descending => b.cmp(&a),
+                }
+            };
+            ord == Ordering::Greater
+        };
+        let first = ranges
+            .par_iter()
+            .filter_map(|r| {
+                (r.start + 1..r.end)
+                    .into_par_iter()
+                    .find_first(|&i| misordered(i))
+            })
+            .min();
+        match first {
+            Some(index) => Err(VerifyError::Misordered {
+                index,
+                a: idxs[index - 1].get_usize(),
+                b: idxs[index].get_usize(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pseudo_dna;
+
+    #[test]
+    fn test_compare() {
+        let mut seq = pseudo_dna(500);
+        seq.extend_from_within(100..400);
+        let text = pad_text(&seq, 124);
+
+        let packed = SuffixArray::<5>::new_packed::<124>(&seq, 3, 2);
+        let bytes = SuffixArray::<5>::new_bytes::<128>(&seq, 2, 2);
+        let report = packed.compare(&bytes, &text);
+        assert!(report.is_consistent());
+        assert!(report.tie_order > 0);
+
+        let mut idxs = packed.idxs().clone();
+        idxs.swap(10, 20);
+        let broken = SuffixArray::from_idxs(idxs, 124);
+        let report = packed.compare(&broken, &text);
+        assert_eq!((report.misordered, report.first_misorder), (2, Some(10)));
+
+        assert_eq!(packed.verify(&text), Ok(()));
+        assert!(matches!(
+            broken.verify(&text),
+            Err(VerifyError::Misordered { .. })
+        ));
+        let mut idxs = packed.idxs().clone();
+        idxs[7] = idxs[8].clone();
+        let pos = packed.idxs()[7].get_usize();
+        let duplicated = SuffixArray::from_idxs(idxs, 124);
+        assert_eq!(duplicated.verify(&text), Err(VerifyError::Missing { pos }));
+    }
+}
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Something a `Watchdog` noticed during construction. Construction carries on
+/// regardless; the callback decides whether to log, alert or abort.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// The phase has been running for longer than its timeout.
+    SlowPhase {
+        phase: &'static str,
+        elapsed: Duration,
+    },
+    /// A bucket holds more suffixes than the limit. `kmer` is its decoded key.
+    LargeBucket { kmer: String, size: usize },
+}
+
+type Callback = Box<dyn Fn(&WatchdogEvent) + Send + Sync>;
+
+/// Per-phase timeouts and a bucket size limit, with a callback for when they are
+/// exceeded, so that pipelines notice pathological inputs. Pass one to a build in its
+/// `Schedule`.
+pub struct Watchdog {
+    timeout: Option<Duration>,
+    phase_timeouts: HashMap<String, Duration>,
+    max_bucket: Option<usize>,
+    callback: Callback,
+}
+
+impl Watchdog {
+    /// A watchdog without limits that reports to `callback`, which may be called from
+    /// any thread.
+    pub fn new(callback: impl Fn(&WatchdogEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            timeout: None,
+            phase_timeouts: HashMap::new(),
+            max_bucket: None,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Report every phase still running after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Report the phase named `phase`, as in the build report (e.g. `Parallel move into
+    /// and sort buckets`), after `timeout` instead.
+    pub fn phase_timeout(mut self, phase: &str, timeout: Duration) -> Self {
+        self.phase_timeouts.insert(phase.to_string(), timeout);
+        self
+    }
+
+    /// Report every bucket of more than `max_bucket` suffixes.
+    pub fn max_bucket(mut self, max_bucket: usize) -> Self {
+        self.max_bucket = Some(max_bucket);
+        self
+    }
+
+    fn timeout_for(&self, phase: &str) -> Option<Duration> {
+        self.phase_timeouts.get(phase).copied().or(self.timeout)
+    }
+
+    fn report(&self, event: &WatchdogEvent) {
+        (self.callback)(event);
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("timeout", &self.timeout)
+            .field("phase_timeouts", &self.phase_timeouts)
+            .field("max_bucket", &self.max_bucket)
+            .finish_non_exhaustive()
+    }
+}
+
+thread_local! {
+    static WATCHDOG: RefCell<Option<Arc<Watchdog>>> = const { RefCell::new(None) };
+}
+
+/// Watch the construction running on the calling thread with `watchdog`, and return
+/// the one watching it before.
+pub(crate) fn replace_watchdog(watchdog: Option<Arc<Watchdog>>) -> Option<Arc<Watchdog>> {
+    WATCHDOG.with(|w| w.replace(watchdog))
+}
+
+/// The watchdog of the construction running on the calling thread.
+pub(crate) fn watchdog() -> Option<Arc<Watchdog>> {
+    WATCHDOG.with(|w| w.borrow().clone())
+}
+
+/// Report the buckets with more suffixes than the watchdog allows. `decode` gives the
+/// key of a bucket.
+pub(crate) fn check_buckets(sizes: impl Iterator<Item = usize>, decode: impl Fn(usize) -> Vec<u8>) {
+    let Some(watchdog) = watchdog() else {
+        return;
+    };
+    let Some(max_bucket) = watchdog.max_bucket else {
+        return;
+    };
+    for (kmer, size) in sizes.enumerate().filter(|&(_, size)| size > max_bucket) {
+        watchdog.report(&WatchdogEvent::LargeBucket {
+            kmer: String::from_utf8_lossy(&decode(kmer)).into_owned(),
+            size,
+        });
+    }
+}
+
+/// A thread that reports the phase once if it is still running after its timeout.
+pub(crate) struct PhaseWatch {
+    done: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PhaseWatch {
+    /// Watch `phase` if the installed watchdog has a timeout for it.
+    pub(crate) fn start(phase: &'static str) -> Option<Self> {
+        let watchdog = watchdog()?;
+        let timeout = watchdog.timeout_for(phase)?;
+        let done = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let done = done.clone();
+            let start = Instant::now();
+            std::thread::spawn(move || {
+                let (lock, cvar) = &*done;
+                let (_guard, wait) = cvar
+                    .wait_timeout_while(lock.lock().unwrap(), timeout, |done| !*done)
+                    .unwrap();
+                if wait.timed_out() {
+                    watchdog.report(&WatchdogEvent::SlowPhase {
+                        phase,
+                        elapsed: start.elapsed(),
+                    });
+                }
+            })
+        };
+        Some(Self {
+            done,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for PhaseWatch {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.done;
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perf::*;
+    use crate::suffix_array::*;
+
+    #[test]
+    fn test_watchdog() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let schedule = Schedule {
+            bucket_threads: 1,
+            watchdog: Some(Arc::new(
+                Watchdog::new(move |e| sink.lock().unwrap().push(e.clone()))
+                    .phase_timeout("Watchdog test phase", Duration::from_millis(1))
+                    .max_bucket(99),
+            )),
+            ..Schedule::default()
+        };
+
+        {
+            let _observed = schedule.observe();
+            let phase = Phase::start("Watchdog test phase");
+            std::thread::sleep(Duration::from_millis(50));
+            phase.finish();
+        }
+        // Builds without the watchdog are not watched.
+        let phase = Phase::start("Watchdog test phase");
+        std::thread::sleep(Duration::from_millis(50));
+        phase.finish();
+
+        const CTX: usize = 124;
+        let b = b"ACGT".repeat(100);
+        SuffixArray::<5>::new_packed::<CTX>(&b, 2, 1);
+        assert_eq!(events.lock().unwrap().len(), 1);
+        SuffixArray::<5>::new_packed::<CTX>(&b, 2, schedule);
+
+        let events = events.lock().unwrap();
+        assert!(events.iter().any(|e| matches!(
+            e,
+            WatchdogEvent::SlowPhase { phase: "Watchdog test phase", elapsed }
+                if *elapsed >= Duration::from_millis(1)
+        )));
+        assert!(events.contains(&WatchdogEvent::LargeBucket {
+            kmer: "AC".to_string(),
+            size: 100,
+        }));
+    }
+}
@@ -0,0 +1,169 @@
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::stats::*;
+use crate::suffix_array::*;
+
+/// Smallest entry width in bytes, at least 4, that holds every position of a text of
+/// `len` bytes.
+pub fn entry_bytes(len: usize) -> usize {
+    let bits = usize::BITS - len.leading_zeros();
+    (bits as usize).div_ceil(8).max(4)
+}
+
+/// A suffix array whose entry width was chosen at run time, see `AnySuffixArray::new`.
+pub enum AnySuffixArray {
+    B4(SuffixArray<4>),
+    B5(SuffixArray<5>),
+    B6(SuffixArray<6>),
+    B8(SuffixArray<8>),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $sa:ident => $e:expr) => {
+        match $self {
+            AnySuffixArray::B4($sa) => $e,
+            AnySuffixArray::B5($sa) => $e,
+            AnySuffixArray::B6($sa) => $e,
+            AnySuffixArray::B8($sa) => $e,
+        }
+    };
+}
+
+impl AnySuffixArray {
+    /// `new_packed_with_ctx` with the narrowest entries that fit the positions of
+    /// `seq`: 4 bytes up to 4 Gbp, 5 bytes up to 1 Tbp, and 6 bytes beyond.
+    pub fn new(seq: &[u8], ctx: usize, k: usize, bucket_threads: impl Into<Schedule>) -> Self {
+        Self::with_entry_bytes(seq, ctx, k, bucket_threads, entry_bytes(seq.len()))
+    }
+
+    /// `new_packed_with_ctx` with entries of `entry_bytes` bytes (4, 5, 6, or 8 for
+    /// `u64`), which must fit the positions of `seq`.
+    pub fn with_entry_bytes(
+        seq: &[u8],
+        ctx: usize,
+        k: usize,
+        bucket_threads: impl Into<Schedule>,
+        entry_bytes: usize,
+    ) -> Self {
+        assert!(
+            entry_bytes >= 8 || seq.len() <= 1 << (8 * entry_bytes),
+            "{entry_bytes}-byte entries cannot hold positions of a text of {} bases",
+            seq.len()
+        );
+        match entry_bytes {
+            4 => Self::B4(SuffixArray::new_packed_with_ctx(
+                seq,
+                ctx,
+                k,
+                bucket_threads,
+            )),
+            5 => Self::B5(SuffixArray::new_packed_with_ctx(
+                seq,
+                ctx,
+                k,
+                bucket_threads,
+            )),
+            6 => Self::B6(SuffixArray::new_packed_with_ctx(
+                seq,
+                ctx,
+                k,
+                bucket_threads,
+            )),
+            8 => Self::B8(SuffixArray::new_packed_with_ctx(
+                seq,
+                ctx,
+                k,
+                bucket_threads,
+            )),
+            b => panic!("Entries of {b} seq are not supported!"),
+        }
+    }
+
+    /// Width of an entry in seq.
+    pub fn entry_bytes(&self) -> usize {
+        match self {
+            Self::B4(_) => 4,
+            Self::B5(_) => 5,
+            Self::B6(_) => 6,
+            Self::B8(_) => 8,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        dispatch!(self, sa => sa.idxs().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Text position of suffix array entry `i`.
+    pub fn get(&self, i: usize) -> usize {
+        dispatch!(self, sa => sa.idxs()[i].get_usize())
+    }
+
+    pub fn k(&self) -> usize {
+        dispatch!(self, sa => sa.k())
+    }
+
+    pub fn ctx(&self) -> usize {
+        dispatch!(self, sa => sa.ctx())
+    }
+
+    /// The entries as little-endian integers of `entry_bytes` seq, back to back.
+    pub fn as_bytes(&self) -> &[u8] {
+        dispatch!(self, sa => sa.idxs().as_bytes())
+    }
+
+    /// See `SuffixArray::range`.
+    pub fn range(&self, seq: &[u8], pattern: &[u8]) -> Range<usize> {
+        dispatch!(self, sa => sa.range(seq, pattern))
+    }
+
+    pub fn to_usize_vec(&self) -> Vec<usize> {
+        dispatch!(self, sa => sa.idxs().to_usize_vec())
+    }
+
+    /// See `SuffixArray::to_u64_vec`.
+    pub fn to_u64_vec(&self) -> Vec<u64> {
+        dispatch!(self, sa => sa.to_u64_vec())
+    }
+
+    /// See `SuffixArray::write_raw`.
+    pub fn write_raw(&self, path: impl AsRef<Path>, width: usize) -> io::Result<()> {
+        dispatch!(self, sa => sa.write_raw(path, width))
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        dispatch!(self, sa => sa.write_to(path))
+    }
+
+    /// See `SuffixArray::stats`.
+    pub fn stats(&self, bytes: &[u8], lcp_cap: Option<usize>) -> LcpStats {
+        dispatch!(self, sa => sa.stats(bytes, lcp_cap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_width() {
+        assert_eq!(entry_bytes(0), 4);
+        assert_eq!(entry_bytes((1 << 32) - 1), 4);
+        assert_eq!(entry_bytes(1 << 32), 5);
+        assert_eq!(entry_bytes(1 << 40), 6);
+
+        const CTX: usize = 124;
+        let b = b"ACGTTGCAGATTACA".repeat(20);
+        let s = AnySuffixArray::new(&b, CTX, 3, 2);
+        assert_eq!(s.entry_bytes(), 4);
+        let t = SuffixArray::<5>::new_packed::<CTX>(&b, 3, 2);
+        assert_eq!(s.to_usize_vec(), t.idxs().to_usize_vec());
+        assert_eq!(s.get(7), t.idxs()[7].get_usize());
+        assert_eq!((s.k(), s.ctx()), (3, CTX));
+    }
+}